@@ -9,11 +9,16 @@ License:        Apache-2.0
 URL:            https://github.com/binarypie-dev/datacube
 Source0:        %{url}/archive/main/%{crate}-main.tar.gz
 
-BuildRequires:  rust >= 1.70
+BuildRequires:  rust >= 1.82
 BuildRequires:  cargo
 BuildRequires:  protobuf-compiler
 BuildRequires:  systemd-rpm-macros
 
+# Used by the (opt-in) currency provider to fetch exchange rates
+Recommends:     curl
+# sqlite3 reads Firefox bookmarks for the (opt-in) bookmarks provider
+Recommends:     sqlite
+
 %description
 Datacube is a background service that provides data to application launchers
 and desktop utilities. It indexes desktop applications, provides calculator
@@ -33,6 +38,15 @@ install -Dm755 target/release/datacube-cli %{buildroot}%{_bindir}/datacube-cli
 # Install systemd user service
 install -Dm644 datacube.service %{buildroot}%{_userunitdir}/datacube.service
 
+# Install shell completions
+for bin in datacube datacube-cli; do
+    if [ "$bin" = datacube ]; then gen="--completions"; else gen="completions"; fi
+    install -d %{buildroot}%{bash_completions_dir} %{buildroot}%{zsh_completions_dir} %{buildroot}%{fish_completions_dir}
+    target/release/$bin $gen bash > %{buildroot}%{bash_completions_dir}/$bin
+    target/release/$bin $gen zsh > %{buildroot}%{zsh_completions_dir}/_$bin
+    target/release/$bin $gen fish > %{buildroot}%{fish_completions_dir}/$bin.fish
+done
+
 %post
 %systemd_user_post %{crate}.service
 
@@ -48,3 +62,9 @@ install -Dm644 datacube.service %{buildroot}%{_userunitdir}/datacube.service
 %{_bindir}/datacube
 %{_bindir}/datacube-cli
 %{_userunitdir}/datacube.service
+%{bash_completions_dir}/datacube
+%{bash_completions_dir}/datacube-cli
+%{zsh_completions_dir}/_datacube
+%{zsh_completions_dir}/_datacube-cli
+%{fish_completions_dir}/datacube.fish
+%{fish_completions_dir}/datacube-cli.fish
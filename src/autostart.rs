@@ -0,0 +1,141 @@
+//! XDG autostart entry for starting the daemon on login
+//!
+//! `datacube --install-autostart` writes `~/.config/autostart/datacube.desktop`
+//! running the current executable, and `--uninstall-autostart` removes it. A
+//! file that differs from the one datacube would write is taken to be
+//! customized, and is only replaced or removed when forced.
+
+use std::path::{Path, PathBuf};
+
+/// Where the autostart entry lives: `$XDG_CONFIG_HOME/autostart/datacube.desktop`
+pub fn autostart_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("autostart/datacube.desktop"))
+}
+
+/// The autostart entry running `exec`
+pub fn desktop_entry(exec: &Path) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Datacube\n\
+         Comment=Data provider service for desktop utilities\n\
+         Exec={}\n\
+         Terminal=false\n\
+         NoDisplay=true\n\
+         X-GNOME-Autostart-enabled=true\n\
+         X-GNOME-Autostart-Delay=0\n",
+        quote_exec_arg(&exec.to_string_lossy())
+    )
+}
+
+/// Write the autostart entry for `exec` to `path`
+///
+/// Fails if `path` holds a different entry, unless `force` is set.
+pub fn install(path: &Path, exec: &Path, force: bool) -> anyhow::Result<()> {
+    let entry = desktop_entry(exec);
+    ensure_not_customized(path, &entry, force, "overwrite")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, entry)?;
+    Ok(())
+}
+
+/// Remove the autostart entry for `exec` at `path`, returning whether there
+/// was one
+///
+/// Fails if `path` holds a different entry, unless `force` is set.
+pub fn uninstall(path: &Path, exec: &Path, force: bool) -> anyhow::Result<bool> {
+    ensure_not_customized(path, &desktop_entry(exec), force, "remove")?;
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Fail if `path` holds something other than `entry`, unless `force` is set
+fn ensure_not_customized(path: &Path, entry: &str, force: bool, verb: &str) -> anyhow::Result<()> {
+    match std::fs::read_to_string(path) {
+        Ok(existing) if existing != entry && !force => anyhow::bail!(
+            "{} has been customized; use --force to {} it",
+            path.display(),
+            verb
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Quote one `Exec` argument as the desktop entry spec requires
+fn quote_exec_arg(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+    let reserved = |c: char| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c);
+    if !arg.contains(reserved) {
+        return arg;
+    }
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing::TempDir;
+
+    #[test]
+    fn exec_arguments_are_quoted() {
+        assert_eq!(quote_exec_arg("/usr/bin/datacube"), "/usr/bin/datacube");
+        assert_eq!(
+            quote_exec_arg("/opt/my apps/data$cube"),
+            r#""/opt/my apps/data\$cube""#
+        );
+        assert_eq!(quote_exec_arg("/opt/100%/datacube"), "/opt/100%%/datacube");
+    }
+
+    #[test]
+    fn installs_and_uninstalls() {
+        let dir = TempDir::new();
+        let path = dir.path.join("autostart/datacube.desktop");
+        let exec = Path::new("/usr/bin/datacube");
+
+        install(&path, exec, false).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.contains("Exec=/usr/bin/datacube\n"));
+        assert!(written.contains("X-GNOME-Autostart-enabled=true\n"));
+        // Rewriting an unchanged entry needs no force.
+        install(&path, exec, false).unwrap();
+
+        assert!(uninstall(&path, exec, false).unwrap());
+        assert!(!uninstall(&path, exec, false).unwrap());
+    }
+
+    #[test]
+    fn customized_entries_need_force() {
+        let dir = TempDir::new();
+        let path = dir.path.join("datacube.desktop");
+        std::fs::create_dir_all(&dir.path).unwrap();
+        std::fs::write(&path, "[Desktop Entry]\nExec=datacube --debug\n").unwrap();
+
+        let exec = Path::new("/usr/bin/datacube");
+        let err = install(&path, exec, false).unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert!(std::fs::read_to_string(&path).unwrap().contains("--debug"));
+
+        let err = uninstall(&path, exec, false).unwrap_err();
+        assert!(err.to_string().contains("--force to remove"));
+        assert!(path.exists());
+        assert!(uninstall(&path, exec, true).unwrap());
+        assert!(!path.exists());
+
+        std::fs::write(&path, "[Desktop Entry]\nExec=datacube --debug\n").unwrap();
+        install(&path, exec, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), desktop_entry(exec));
+    }
+}
@@ -4,15 +4,34 @@
 //!   datacube-cli query "firefox"
 //!   datacube-cli query "=2+2"
 //!   datacube-cli providers
-
-use clap::{Parser, Subcommand};
+//!   datacube-cli configure calculator --priority 10 --prefix "calc "
+//!   datacube-cli log-level debug
+//!   datacube-cli routing "=2+2"
+//!   datacube-cli batch "=2+2" firefox
+//!   datacube-cli item --qid <qid> --id <item id>
+//!   datacube-cli bench --query firefox --iterations 1000
+//!   datacube-cli watch
+//!   datacube-cli completions bash
+
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use datacube::client::{recv_message, send_message};
+use datacube::completions::{self, Shell};
+use datacube::default_socket_path;
+use datacube::msgpack;
 use datacube::proto::{
-    Item, ListProvidersRequest, ListProvidersResponse, QueryRequest, QueryResponse,
+    BatchQueryRequest, BatchQueryResponse, Framing, Hello, HelloResponse, Item,
+    ListProvidersRequest, ListProvidersResponse, QueryRequest, QueryResponse, ResolveItemRequest,
+    ResolveItemResponse, RoutingRequest, RoutingResponse, SetLogLevelRequest, SetLogLevelResponse,
+    SetProviderConfigRequest, SetProviderConfigResponse,
 };
+use datacube::providers::clipboard;
+use datacube::providers::typing::{self, TypingTool};
+use datacube::server::{MessageType, PROTOCOL_VERSION};
 use prost::Message;
-use std::io::{Read, Write};
+use std::io::{BufRead, IsTerminal, Write};
 use std::os::unix::net::UnixStream;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 #[derive(Parser, Debug)]
 #[command(name = "datacube-cli")]
@@ -37,110 +56,315 @@ enum Commands {
         #[arg(short, long, default_value = "10")]
         max: i32,
 
+        /// Skip this many results (for paging with --max)
+        #[arg(long, default_value = "0")]
+        offset: u32,
+
         /// Specific providers to query (comma-separated)
         #[arg(short, long)]
         providers: Option<String>,
 
-        /// Output results as JSON (one object per line)
-        #[arg(short, long)]
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Human)]
+        format: OutputFormat,
+
+        /// Shorthand for --format json
+        #[arg(short, long, conflicts_with = "format")]
         json: bool,
+
+        /// Report how long the query and each provider took (on stderr)
+        #[arg(long)]
+        timings: bool,
+
+        /// Copy the top result's value to the clipboard
+        #[arg(long)]
+        copy: bool,
+
+        /// Type the top result's value into the focused window
+        #[arg(long = "type")]
+        type_text: bool,
+
+        /// Tool to type with (default: wtype under Wayland, xdotool under X11)
+        #[arg(long, value_enum, requires = "type_text")]
+        type_with: Option<TypingTool>,
     },
 
     /// List available providers
-    Providers,
+    Providers {
+        /// Include providers the daemon's config disables
+        #[arg(short, long)]
+        all: bool,
+    },
+
+    /// Override a provider's priority or prefix until the daemon restarts
+    Configure {
+        /// Provider name
+        name: String,
+
+        /// New tie-break priority
+        #[arg(long, allow_negative_numbers = true)]
+        priority: Option<i32>,
+
+        /// New query prefix ("" to handle all queries)
+        #[arg(long)]
+        prefix: Option<String>,
+
+        /// Drop all overrides and restore the configured values
+        #[arg(long, conflicts_with_all = ["priority", "prefix"])]
+        reset: bool,
+    },
+
+    /// Change the daemon's log level until it restarts
+    LogLevel {
+        /// error, warn, info, debug, trace or off
+        #[arg(required_unless_present = "reset")]
+        level: Option<String>,
+
+        /// Go back to the level the daemon started with
+        #[arg(long, conflicts_with = "level")]
+        reset: bool,
+    },
+
+    /// Send several queries in one request and print each one's results
+    Batch {
+        /// Search queries
+        #[arg(required = true)]
+        queries: Vec<String>,
+
+        /// Maximum results per query
+        #[arg(short, long, default_value = "10")]
+        max: i32,
+
+        /// Specific providers to query (comma-separated)
+        #[arg(short, long)]
+        providers: Option<String>,
+    },
+
+    /// Fetch an item from a recent query's results, as the daemon produced it
+    Item {
+        /// The query response's qid
+        #[arg(long)]
+        qid: String,
+
+        /// The item's id
+        #[arg(long)]
+        id: String,
+
+        /// Print the item as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+
+    /// Show which providers a query would be routed to, without running it
+    Routing {
+        /// Query to route
+        query: String,
+    },
+
+    /// Measure query latency over a single connection
+    Bench {
+        /// Query to send
+        #[arg(short, long, required_unless_present = "file")]
+        query: Option<String>,
+
+        /// File with one query per line, sent in order (cycling) to simulate typing
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Number of queries to send
+        #[arg(short, long, default_value = "1000")]
+        iterations: usize,
+
+        /// Maximum results per query
+        #[arg(short, long, default_value = "10")]
+        max: i32,
+    },
+
+    /// Read queries line by line from stdin and print results for each
+    Watch {
+        /// Maximum results
+        #[arg(short, long, default_value = "10")]
+        max: i32,
+
+        /// Specific providers to query (comma-separated)
+        #[arg(short, long)]
+        providers: Option<String>,
+    },
+
+    /// Print a shell completion script
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
 }
 
-/// Message types for the protocol
-#[repr(u8)]
-#[derive(Debug, Clone, Copy)]
-enum MessageType {
-    Query = 1,
-    #[allow(dead_code)]
-    QueryResponse = 2,
-    ListProviders = 5,
-    #[allow(dead_code)]
-    ListProvidersResponse = 6,
+/// How `query` prints its results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable listing
+    Human,
+    /// The whole response (query, qid, items) as one JSON document
+    Json,
+    /// One JSON object per item, one per line
+    Ndjson,
+    /// The whole response as MessagePack (binary)
+    Msgpack,
 }
 
 fn get_socket_path(arg: Option<PathBuf>) -> PathBuf {
-    arg.unwrap_or_else(|| {
-        let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
-            .unwrap_or_else(|_| format!("/run/user/{}", unsafe { libc::getuid() }));
-        PathBuf::from(runtime_dir).join("datacube.sock")
-    })
+    arg.unwrap_or_else(default_socket_path)
 }
 
-fn send_message(
-    stream: &mut UnixStream,
-    msg_type: MessageType,
-    body: &[u8],
-) -> std::io::Result<()> {
-    let mut header = vec![msg_type as u8];
-    header.extend_from_slice(&(body.len() as u32).to_be_bytes());
-    stream.write_all(&header)?;
-    stream.write_all(body)?;
-    stream.flush()
+/// Agree on a protocol version with the daemon, failing if there is none
+fn hello(stream: &mut UnixStream) -> anyhow::Result<()> {
+    let request = Hello {
+        protocol_version: PROTOCOL_VERSION,
+        min_protocol_version: PROTOCOL_VERSION,
+        framing: Framing::FixedHeader.into(),
+    };
+    send_message(stream, MessageType::Hello, &request.encode_to_vec())?;
+
+    // Daemons that predate Hello ignore it rather than reply.
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let (_, body) = recv_message(stream)
+        .map_err(|e| anyhow::anyhow!("No reply to Hello (daemon too old?): {}", e))?;
+    stream.set_read_timeout(None)?;
+    let response = HelloResponse::decode(body.as_slice())?;
+    if !response.error.is_empty() {
+        anyhow::bail!("Incompatible daemon: {}", response.error);
+    }
+    Ok(())
 }
 
-fn recv_message(stream: &mut UnixStream) -> std::io::Result<(u8, Vec<u8>)> {
-    let mut header = [0u8; 5];
-    stream.read_exact(&mut header)?;
-
-    let msg_type = header[0];
-    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
-
-    let mut body = vec![0u8; length];
-    stream.read_exact(&mut body)?;
+/// Send a query and wait for its response
+fn send_query(
+    stream: &mut UnixStream,
+    query: String,
+    max_results: i32,
+    offset: u32,
+    providers: Vec<String>,
+    timings: bool,
+) -> anyhow::Result<QueryResponse> {
+    let request = QueryRequest {
+        query,
+        max_results,
+        providers,
+        exact: false,
+        offset,
+        timings,
+        request_id: 0,
+    };
+
+    send_message(stream, MessageType::Query, &request.encode_to_vec())?;
+
+    let (_, body) = recv_message(stream)?;
+    Ok(QueryResponse::decode(body.as_slice())?)
+}
 
-    Ok((msg_type, body))
+/// Split a comma-separated `--providers` value
+fn parse_providers(providers: Option<String>) -> Vec<String> {
+    providers
+        .map(|p| p.split(',').map(String::from).collect())
+        .unwrap_or_default()
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+
+    // Completions don't need the daemon, so handle them before connecting.
+    if let Commands::Completions { shell } = args.command {
+        completions::generate(
+            shell,
+            &mut Args::command(),
+            "datacube-cli",
+            &mut std::io::stdout(),
+        )?;
+        return Ok(());
+    }
+
     let socket_path = get_socket_path(args.socket);
 
     let mut stream = UnixStream::connect(&socket_path)
         .map_err(|e| anyhow::anyhow!("Failed to connect to {:?}: {}", socket_path, e))?;
+    hello(&mut stream)?;
 
     match args.command {
         Commands::Query {
             query,
             max,
+            offset,
             providers,
+            format,
             json,
+            timings,
+            copy,
+            type_text,
+            type_with,
         } => {
-            let providers_list: Vec<String> = providers
-                .map(|p| p.split(',').map(String::from).collect())
-                .unwrap_or_default();
-
-            let request = QueryRequest {
-                query: query.clone(),
-                max_results: max,
-                providers: providers_list,
-                exact: false,
-            };
+            let response = send_query(
+                &mut stream,
+                query,
+                max,
+                offset,
+                parse_providers(providers),
+                timings,
+            )?;
+            let format = if json { OutputFormat::Json } else { format };
+            warn_unknown_providers(&response);
+            if timings {
+                print_timings(&response);
+            }
+            if copy {
+                copy_top_result(&response)?;
+            }
+            if type_text {
+                type_top_result(&response, type_with)?;
+            }
 
-            send_message(&mut stream, MessageType::Query, &request.encode_to_vec())?;
+            // Machine-readable formats serialize the proto types directly, so
+            // they always match the schema.
+            match format {
+                OutputFormat::Human => print_response(&response),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&response)?),
+                OutputFormat::Ndjson => {
+                    for item in &response.items {
+                        println!("{}", serde_json::to_string(item)?);
+                    }
+                }
+                OutputFormat::Msgpack => {
+                    let mut stdout = std::io::stdout().lock();
+                    stdout.write_all(&msgpack::to_vec(&response)?)?;
+                    stdout.flush()?;
+                }
+            }
+        }
 
-            let (_, body) = recv_message(&mut stream)?;
-            let response = QueryResponse::decode(body.as_slice())?;
+        Commands::Watch { max, providers } => {
+            let providers = parse_providers(providers);
+            let clear_screen = std::io::stdout().is_terminal();
 
-            if json {
-                // Output items directly - serde derives handle all fields automatically
-                println!("{}", serde_json::to_string_pretty(&response.items)?);
-            } else {
-                println!("Query: '{}' (qid: {})", response.query, response.qid);
-                println!("Results: {}", response.items.len());
-                println!();
+            // One connection for the whole session, like a real launcher.
+            for line in std::io::stdin().lock().lines() {
+                let response = send_query(&mut stream, line?, max, 0, providers.clone(), false)?;
 
-                for (i, item) in response.items.iter().enumerate() {
-                    print_item(i + 1, item);
+                if clear_screen {
+                    // Clear the screen and move the cursor to the top-left
+                    print!("\x1b[2J\x1b[H");
                 }
+                print_response(&response);
+                warn_unknown_providers(&response);
+                std::io::stdout().flush()?;
             }
         }
 
-        Commands::Providers => {
-            let request = ListProvidersRequest {};
+        Commands::Completions { .. } => unreachable!("handled before connecting"),
+
+        Commands::Providers { all } => {
+            let request = ListProvidersRequest {
+                request_id: 0,
+                include_disabled: all,
+            };
             send_message(
                 &mut stream,
                 MessageType::ListProviders,
@@ -153,23 +377,317 @@ fn main() -> anyhow::Result<()> {
             println!("Providers:");
             for provider in response.providers {
                 println!(
-                    "  - {} (prefix: '{}', enabled: {})",
+                    "  - {} (prefix: '{}', enabled: {}, priority: {})",
                     provider.name,
                     if provider.prefix.is_empty() {
                         "none"
                     } else {
                         &provider.prefix
                     },
-                    provider.enabled
+                    provider.enabled,
+                    provider.priority
                 );
+                let capabilities: Vec<&str> = [
+                    (provider.requires_prefix, "prefix only"),
+                    (provider.is_network, "network"),
+                    (!provider.supports_activate, "informational"),
+                ]
+                .into_iter()
+                .filter_map(|(set, label)| set.then_some(label))
+                .collect();
+                if !capabilities.is_empty() {
+                    println!("    [{}]", capabilities.join(", "));
+                }
                 println!("    {}", provider.description);
             }
         }
+
+        Commands::Configure {
+            name,
+            priority,
+            prefix,
+            reset,
+        } => {
+            let request = SetProviderConfigRequest {
+                name,
+                priority,
+                prefix,
+                reset,
+                request_id: 0,
+            };
+            send_message(
+                &mut stream,
+                MessageType::SetProviderConfig,
+                &request.encode_to_vec(),
+            )?;
+
+            let (_, body) = recv_message(&mut stream)?;
+            let response = SetProviderConfigResponse::decode(body.as_slice())?;
+            if !response.error.is_empty() {
+                anyhow::bail!(response.error);
+            }
+            if let Some(provider) = response.provider {
+                println!(
+                    "{}: prefix '{}', priority {}",
+                    provider.name,
+                    if provider.prefix.is_empty() {
+                        "none"
+                    } else {
+                        &provider.prefix
+                    },
+                    provider.priority
+                );
+            }
+        }
+
+        Commands::LogLevel { level, reset: _ } => {
+            // Without a level (--reset), the daemon goes back to its own.
+            let request = SetLogLevelRequest {
+                level: level.unwrap_or_default(),
+                request_id: 0,
+            };
+            send_message(
+                &mut stream,
+                MessageType::SetLogLevel,
+                &request.encode_to_vec(),
+            )?;
+
+            let (_, body) = recv_message(&mut stream)?;
+            let response = SetLogLevelResponse::decode(body.as_slice())?;
+            if !response.error.is_empty() {
+                anyhow::bail!(response.error);
+            }
+            println!("Logging at {} level", response.level);
+        }
+
+        Commands::Batch {
+            queries,
+            max,
+            providers,
+        } => {
+            let providers = parse_providers(providers);
+            let request = BatchQueryRequest {
+                queries: queries
+                    .into_iter()
+                    .map(|query| QueryRequest {
+                        query,
+                        max_results: max,
+                        providers: providers.clone(),
+                        exact: false,
+                        offset: 0,
+                        timings: false,
+                        request_id: 0,
+                    })
+                    .collect(),
+                request_id: 0,
+            };
+            send_message(
+                &mut stream,
+                MessageType::BatchQuery,
+                &request.encode_to_vec(),
+            )?;
+
+            let (_, body) = recv_message(&mut stream)?;
+            let response = BatchQueryResponse::decode(body.as_slice())?;
+            for (i, response) in response.responses.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                print_response(response);
+                warn_unknown_providers(response);
+            }
+        }
+
+        Commands::Item { qid, id, json } => {
+            let request = ResolveItemRequest {
+                qid,
+                item_id: id,
+                request_id: 0,
+            };
+            send_message(
+                &mut stream,
+                MessageType::ResolveItem,
+                &request.encode_to_vec(),
+            )?;
+
+            let (_, body) = recv_message(&mut stream)?;
+            let response = ResolveItemResponse::decode(body.as_slice())?;
+            let Some(item) = response.item else {
+                anyhow::bail!(response.error);
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&item)?);
+            } else {
+                print_item(1, &item);
+            }
+        }
+
+        Commands::Routing { query } => {
+            let request = RoutingRequest {
+                query,
+                request_id: 0,
+            };
+            send_message(&mut stream, MessageType::Routing, &request.encode_to_vec())?;
+
+            let (_, body) = recv_message(&mut stream)?;
+            let response = RoutingResponse::decode(body.as_slice())?;
+            if response.routes.is_empty() {
+                println!("No provider handles this query");
+            }
+            for route in response.routes {
+                println!(
+                    "  - {} (prefix: '{}', receives: '{}')",
+                    route.provider,
+                    if route.prefix.is_empty() {
+                        "none"
+                    } else {
+                        &route.prefix
+                    },
+                    route.query
+                );
+            }
+        }
+
+        Commands::Bench {
+            query,
+            file,
+            iterations,
+            max,
+        } => {
+            let queries: Vec<String> = match file {
+                Some(path) => std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read {:?}: {}", path, e))?
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(String::from)
+                    .collect(),
+                None => query.into_iter().collect(),
+            };
+            if queries.is_empty() {
+                anyhow::bail!("No queries to send");
+            }
+
+            let mut latencies = Vec::with_capacity(iterations);
+            let started = Instant::now();
+
+            for query in queries.iter().cycle().take(iterations) {
+                let request = QueryRequest {
+                    query: query.clone(),
+                    max_results: max,
+                    providers: vec![],
+                    exact: false,
+                    offset: 0,
+                    timings: false,
+                    request_id: 0,
+                };
+
+                let sent = Instant::now();
+                send_message(&mut stream, MessageType::Query, &request.encode_to_vec())?;
+                let (_, body) = recv_message(&mut stream)?;
+                latencies.push(sent.elapsed());
+
+                QueryResponse::decode(body.as_slice())?;
+            }
+
+            print_bench_summary(&mut latencies, started.elapsed(), queries.len());
+        }
+    }
+
+    Ok(())
+}
+
+/// Print latency percentiles for a benchmark run
+fn print_bench_summary(latencies: &mut [Duration], total: Duration, distinct: usize) {
+    if latencies.is_empty() {
+        println!("No queries sent");
+        return;
+    }
+    latencies.sort();
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    // Nearest-rank percentile over the sorted samples
+    let percentile = |p: f64| {
+        let rank = ((p / 100.0) * latencies.len() as f64).ceil() as usize;
+        latencies[rank.clamp(1, latencies.len()) - 1]
+    };
+
+    println!(
+        "Benchmark: {} queries ({} distinct)",
+        latencies.len(),
+        distinct
+    );
+    println!(
+        "  total: {:.2}s ({:.0} q/s)",
+        total.as_secs_f64(),
+        latencies.len() as f64 / total.as_secs_f64()
+    );
+    println!("  min:   {:.3} ms", ms(latencies[0]));
+    println!("  p50:   {:.3} ms", ms(percentile(50.0)));
+    println!("  p95:   {:.3} ms", ms(percentile(95.0)));
+    println!("  p99:   {:.3} ms", ms(percentile(99.0)));
+    println!("  max:   {:.3} ms", ms(latencies[latencies.len() - 1]));
+}
+
+/// Report requested providers the daemon doesn't know or has disabled, on
+/// stderr so it doesn't corrupt machine-readable output
+fn warn_unknown_providers(response: &QueryResponse) {
+    for name in &response.unknown_providers {
+        eprintln!("unknown provider: {}", name);
+    }
+    for name in &response.disabled_providers {
+        eprintln!("disabled provider: {}", name);
+    }
+}
+
+/// Print a response's timings on stderr, slowest provider first
+fn print_timings(response: &QueryResponse) {
+    let mut providers: Vec<(&String, &f64)> = response.provider_ms.iter().collect();
+    providers.sort_by(|a, b| b.1.total_cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    eprintln!("total: {:.1} ms", response.elapsed_ms);
+    for (name, ms) in providers {
+        eprintln!("  {}: {:.1} ms", name, ms);
     }
+}
+
+/// Copy the first item's `copy` metadata (or its text, if it has none)
+fn copy_top_result(response: &QueryResponse) -> anyhow::Result<()> {
+    let Some(item) = response.items.first() else {
+        anyhow::bail!("no results to copy");
+    };
+    let text = item
+        .metadata
+        .get(clipboard::METADATA_KEY)
+        .unwrap_or(&item.text);
+    clipboard::copy(text)?;
+    eprintln!("Copied: {}", text);
+    Ok(())
+}
 
+/// Type the first item's `type_text` metadata (or its text, if it has none)
+fn type_top_result(response: &QueryResponse, tool: Option<TypingTool>) -> anyhow::Result<()> {
+    let Some(item) = response.items.first() else {
+        anyhow::bail!("no results to type");
+    };
+    let text = item
+        .metadata
+        .get(typing::METADATA_KEY)
+        .unwrap_or(&item.text);
+    typing::type_text(text, tool)?;
+    eprintln!("Typed: {}", text);
     Ok(())
 }
 
+/// Print a query response in human-readable format
+fn print_response(response: &QueryResponse) {
+    println!("Query: '{}' (qid: {})", response.query, response.qid);
+    println!("Results: {}", response.items.len());
+    println!();
+
+    for (i, item) in response.items.iter().enumerate() {
+        print_item(i + 1, item);
+    }
+}
+
 /// Print an item in human-readable format
 /// Uses serde to iterate fields, ensuring consistency with JSON output
 fn print_item(index: usize, item: &Item) {
@@ -0,0 +1,55 @@
+//! Blocking client-side framing, for the CLI and tests
+//!
+//! Messages use the fixed header: 1 byte message type, 4 byte big-endian
+//! body length, then the protobuf-encoded body.
+
+use crate::server::MessageType;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+
+/// Send one message
+pub fn send_message(
+    stream: &mut UnixStream,
+    msg_type: MessageType,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let mut header = vec![msg_type as u8];
+    header.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    stream.write_all(&header)?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// Receive one message: its type byte and body
+pub fn recv_message(stream: &mut UnixStream) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header)?;
+
+    let msg_type = header[0];
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+
+    let mut body = vec![0u8; length];
+    stream.read_exact(&mut body)?;
+
+    Ok((msg_type, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip() {
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        send_message(&mut a, MessageType::Routing, b"body").unwrap();
+        send_message(&mut a, MessageType::ListProviders, b"").unwrap();
+        assert_eq!(
+            recv_message(&mut b).unwrap(),
+            (MessageType::Routing as u8, b"body".to_vec())
+        );
+        assert_eq!(
+            recv_message(&mut b).unwrap(),
+            (MessageType::ListProviders as u8, Vec::new())
+        );
+    }
+}
@@ -0,0 +1,494 @@
+//! Shell completion script generation
+//!
+//! Walks a `clap::Command` (subcommands, options, possible values and path
+//! hints) and renders a completion script for bash, zsh, fish or elvish. Used
+//! by both `datacube` and `datacube-cli`.
+
+use clap::builder::ValueHint;
+use clap::{Command, ValueEnum};
+use std::io::{self, Write};
+
+/// Shells that completion scripts can be generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Elvish,
+}
+
+/// Write a completion script for `cmd` to `out`
+pub fn generate(
+    shell: Shell,
+    cmd: &mut Command,
+    bin_name: &str,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    cmd.build();
+    let mut specs = Vec::new();
+    collect(cmd, vec![bin_name.to_string()], &mut specs);
+
+    match shell {
+        Shell::Bash => bash(&specs, bin_name, out),
+        Shell::Zsh => zsh(&specs, bin_name, out),
+        Shell::Fish => fish(&specs, bin_name, out),
+        Shell::Elvish => elvish(&specs, bin_name, out),
+    }
+}
+
+/// A command (or subcommand) flattened out of the clap tree
+struct CommandSpec {
+    /// Names from the binary down to this command
+    path: Vec<String>,
+    options: Vec<OptionSpec>,
+    /// (name, description) of each visible subcommand
+    subcommands: Vec<(String, String)>,
+}
+
+struct OptionSpec {
+    short: Option<char>,
+    long: Option<String>,
+    help: String,
+    takes_value: bool,
+    /// Fixed set of accepted values (e.g. from a `ValueEnum`)
+    values: Vec<String>,
+    /// Whether the value is a filesystem path
+    path: bool,
+}
+
+impl OptionSpec {
+    /// All spellings of this option (`-m`, `--max`)
+    fn flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if let Some(short) = self.short {
+            flags.push(format!("-{}", short));
+        }
+        if let Some(long) = &self.long {
+            flags.push(format!("--{}", long));
+        }
+        flags
+    }
+}
+
+fn collect(cmd: &Command, path: Vec<String>, specs: &mut Vec<CommandSpec>) {
+    let options = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .map(|arg| OptionSpec {
+            short: arg.get_short(),
+            long: arg.get_long().map(String::from),
+            help: first_line(arg.get_help().map(|h| h.to_string())),
+            takes_value: arg.get_action().takes_values(),
+            values: arg
+                .get_possible_values()
+                .iter()
+                .filter(|v| !v.is_hide_set())
+                .map(|v| v.get_name().to_string())
+                .collect(),
+            path: matches!(
+                arg.get_value_hint(),
+                ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+            ),
+        })
+        .collect();
+
+    let visible: Vec<_> = cmd.get_subcommands().filter(|s| !s.is_hide_set()).collect();
+    specs.push(CommandSpec {
+        path: path.clone(),
+        options,
+        subcommands: visible
+            .iter()
+            .map(|s| {
+                let about = first_line(s.get_about().map(|a| a.to_string()));
+                (s.get_name().to_string(), about)
+            })
+            .collect(),
+    });
+
+    for sub in visible {
+        let mut sub_path = path.clone();
+        sub_path.push(sub.get_name().to_string());
+        collect(sub, sub_path, specs);
+    }
+}
+
+fn first_line(text: Option<String>) -> String {
+    text.unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or("")
+        .to_string()
+}
+
+/// Shell function name for a command path (`datacube-cli query` -> `_datacube_cli__query`)
+fn function_name(path: &[String]) -> String {
+    format!("_{}", path.join("__").replace('-', "_"))
+}
+
+fn bash(specs: &[CommandSpec], bin_name: &str, out: &mut dyn Write) -> io::Result<()> {
+    let root = function_name(&specs[0].path);
+    writeln!(out, "{}() {{", root)?;
+    writeln!(out, "    local i cur prev cmd opts")?;
+    writeln!(out, "    COMPREPLY=()")?;
+    writeln!(out, "    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(out, "    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(out, "    cmd=\"{}\"", bin_name)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    for i in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do"
+    )?;
+    writeln!(out, "        case \"${{cmd}},${{i}}\" in")?;
+    for spec in specs {
+        for (sub, _) in &spec.subcommands {
+            writeln!(
+                out,
+                "            \"{},{}\") cmd=\"{}__{}\" ;;",
+                spec.path.join("__"),
+                sub,
+                spec.path.join("__"),
+                sub
+            )?;
+        }
+    }
+    writeln!(out, "            *) ;;")?;
+    writeln!(out, "        esac")?;
+    writeln!(out, "    done")?;
+    writeln!(out)?;
+    writeln!(out, "    case \"${{cmd}}\" in")?;
+    for spec in specs {
+        let mut words: Vec<String> = spec.options.iter().flat_map(|o| o.flags()).collect();
+        words.extend(spec.subcommands.iter().map(|(name, _)| name.clone()));
+
+        writeln!(out, "        {})", spec.path.join("__"))?;
+        writeln!(out, "            opts=\"{}\"", words.join(" "))?;
+        writeln!(out, "            case \"${{prev}}\" in")?;
+        for opt in spec.options.iter().filter(|o| o.takes_value) {
+            let reply = if !opt.values.is_empty() {
+                format!(
+                    "COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\"))",
+                    opt.values.join(" ")
+                )
+            } else if opt.path {
+                "COMPREPLY=($(compgen -f -- \"${cur}\"))".to_string()
+            } else {
+                "COMPREPLY=()".to_string()
+            };
+            writeln!(out, "                {})", opt.flags().join("|"))?;
+            writeln!(out, "                    {}", reply)?;
+            writeln!(out, "                    return 0")?;
+            writeln!(out, "                    ;;")?;
+        }
+        writeln!(out, "            esac")?;
+        writeln!(
+            out,
+            "            COMPREPLY=($(compgen -W \"${{opts}}\" -- \"${{cur}}\"))"
+        )?;
+        writeln!(out, "            return 0")?;
+        writeln!(out, "            ;;")?;
+    }
+    writeln!(out, "    esac")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "complete -F {} -o bashdefault -o default {}",
+        root, bin_name
+    )
+}
+
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh(specs: &[CommandSpec], bin_name: &str, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "#compdef {}", bin_name)?;
+
+    for spec in specs {
+        let name = function_name(&spec.path);
+        writeln!(out)?;
+        writeln!(out, "{}() {{", name)?;
+        writeln!(out, "    local curcontext=\"$curcontext\" state line")?;
+        writeln!(out, "    _arguments -C \\")?;
+        for opt in &spec.options {
+            let help = zsh_escape(&opt.help);
+            let action = if !opt.values.is_empty() {
+                format!("({})", opt.values.join(" "))
+            } else if opt.path {
+                "_files".to_string()
+            } else {
+                "_default".to_string()
+            };
+            for flag in opt.flags() {
+                if opt.takes_value {
+                    let sep = if flag.starts_with("--") { "=" } else { "+" };
+                    writeln!(
+                        out,
+                        "        '{}{}[{}]:value:{}' \\",
+                        flag, sep, help, action
+                    )?;
+                } else {
+                    writeln!(out, "        '{}[{}]' \\", flag, help)?;
+                }
+            }
+        }
+        if spec.subcommands.is_empty() {
+            writeln!(out, "        '*::arg:_default'")?;
+            writeln!(out, "}}")?;
+            continue;
+        }
+
+        writeln!(out, "        '1: :{}_commands' \\", name)?;
+        writeln!(out, "        '*:: :->args'")?;
+        writeln!(out, "    case $state in")?;
+        writeln!(out, "        args)")?;
+        writeln!(out, "            case $line[1] in")?;
+        for (sub, _) in &spec.subcommands {
+            writeln!(
+                out,
+                "                {}) {}__{} ;;",
+                sub,
+                name,
+                sub.replace('-', "_")
+            )?;
+        }
+        writeln!(out, "            esac")?;
+        writeln!(out, "            ;;")?;
+        writeln!(out, "    esac")?;
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+        writeln!(out, "{}_commands() {{", name)?;
+        writeln!(out, "    local commands; commands=(")?;
+        for (sub, about) in &spec.subcommands {
+            writeln!(out, "        '{}:{}'", zsh_escape(sub), zsh_escape(about))?;
+        }
+        writeln!(out, "    )")?;
+        writeln!(
+            out,
+            "    _describe -t commands '{} commands' commands",
+            spec.path.join(" ")
+        )?;
+        writeln!(out, "}}")?;
+    }
+
+    let root = function_name(&specs[0].path);
+    writeln!(out)?;
+    writeln!(out, "if [ \"$funcstack[1]\" = \"{}\" ]; then", root)?;
+    writeln!(out, "    {} \"$@\"", root)?;
+    writeln!(out, "else")?;
+    writeln!(out, "    compdef {} {}", root, bin_name)?;
+    writeln!(out, "fi")
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish(specs: &[CommandSpec], bin_name: &str, out: &mut dyn Write) -> io::Result<()> {
+    let has_subcommands = !specs[0].subcommands.is_empty();
+
+    for spec in specs {
+        // Root options apply until a subcommand is typed; subcommand options
+        // once it has been.
+        let condition = match spec.path.len() {
+            1 if has_subcommands => " -n \"__fish_use_subcommand\"".to_string(),
+            1 => String::new(),
+            _ => format!(
+                " -n \"__fish_seen_subcommand_from {}\"",
+                spec.path[spec.path.len() - 1]
+            ),
+        };
+
+        for opt in &spec.options {
+            let mut line = format!("complete -c {}{}", bin_name, condition);
+            if let Some(short) = opt.short {
+                line.push_str(&format!(" -s {}", short));
+            }
+            if let Some(long) = &opt.long {
+                line.push_str(&format!(" -l {}", long));
+            }
+            if !opt.help.is_empty() {
+                line.push_str(&format!(" -d '{}'", fish_escape(&opt.help)));
+            }
+            if opt.takes_value {
+                line.push_str(" -r");
+                if !opt.values.is_empty() {
+                    line.push_str(&format!(" -f -a \"{}\"", opt.values.join(" ")));
+                } else if opt.path {
+                    line.push_str(" -F");
+                }
+            }
+            writeln!(out, "{}", line)?;
+        }
+
+        for (sub, about) in &spec.subcommands {
+            writeln!(
+                out,
+                "complete -c {}{} -f -a \"{}\" -d '{}'",
+                bin_name,
+                condition,
+                sub,
+                fish_escape(about)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn elvish_escape(text: &str) -> String {
+    text.replace('\'', "''")
+}
+
+fn elvish(specs: &[CommandSpec], bin_name: &str, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "use builtin;")?;
+    writeln!(out, "use str;")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "set edit:completion:arg-completer[{}] = {{|@words|",
+        bin_name
+    )?;
+    writeln!(out, "    fn cand {{|text desc|")?;
+    writeln!(
+        out,
+        "        edit:complex-candidate $text &display=$text' '$desc"
+    )?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "    var command = '{}'", bin_name)?;
+    writeln!(out, "    for word $words[1..-1] {{")?;
+    writeln!(out, "        if (str:has-prefix $word '-') {{")?;
+    writeln!(out, "            break")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        set command = $command';'$word")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "    var completions = [")?;
+    for spec in specs {
+        writeln!(
+            out,
+            "        &'{}'= {{",
+            elvish_escape(&spec.path.join(";"))
+        )?;
+        for opt in &spec.options {
+            for flag in opt.flags() {
+                writeln!(
+                    out,
+                    "            cand {} '{}'",
+                    flag,
+                    elvish_escape(&opt.help)
+                )?;
+            }
+        }
+        for (sub, about) in &spec.subcommands {
+            writeln!(out, "            cand {} '{}'", sub, elvish_escape(about))?;
+        }
+        writeln!(out, "        }}")?;
+    }
+    writeln!(out, "    ]")?;
+    writeln!(out, "    $completions[$command]")?;
+    writeln!(out, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Arg, ArgAction};
+
+    fn sample() -> Command {
+        Command::new("tool")
+            .arg(
+                Arg::new("socket")
+                    .short('s')
+                    .long("socket")
+                    .help("Socket path")
+                    .value_parser(clap::value_parser!(std::path::PathBuf)),
+            )
+            .subcommand(
+                Command::new("query")
+                    .about("Query for items")
+                    .arg(Arg::new("query").required(true))
+                    .arg(
+                        Arg::new("max")
+                            .short('m')
+                            .long("max")
+                            .help("Maximum results"),
+                    )
+                    .arg(
+                        Arg::new("format")
+                            .long("format")
+                            .help("Output format")
+                            .value_parser(["json", "ndjson"]),
+                    )
+                    .arg(
+                        Arg::new("json")
+                            .long("json")
+                            .help("Output JSON")
+                            .action(ArgAction::SetTrue),
+                    ),
+            )
+    }
+
+    fn render(shell: Shell) -> String {
+        let mut out = Vec::new();
+        generate(shell, &mut sample(), "tool", &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn bash_completes_subcommands_options_and_values() {
+        let script = render(Shell::Bash);
+        assert!(script.contains("complete -F _tool -o bashdefault -o default tool"));
+        assert!(script.contains("\"tool,query\") cmd=\"tool__query\""));
+        assert!(script.contains("-m --max"));
+        assert!(script.contains("compgen -W \"json ndjson\""));
+        // PathBuf options complete file names.
+        assert!(script.contains("-s|--socket)\n                    COMPREPLY=($(compgen -f"));
+    }
+
+    #[test]
+    fn zsh_defines_a_function_per_command() {
+        let script = render(Shell::Zsh);
+        assert!(script.starts_with("#compdef tool"));
+        assert!(script.contains("_tool__query() {"));
+        assert!(script.contains("'--max=[Maximum results]:value:_default'"));
+        assert!(script.contains("'--format=[Output format]:value:(json ndjson)'"));
+        assert!(script.contains("'query:Query for items'"));
+    }
+
+    #[test]
+    fn fish_scopes_options_to_subcommands() {
+        let script = render(Shell::Fish);
+        assert!(script.contains(
+            "complete -c tool -n \"__fish_seen_subcommand_from query\" -s m -l max -d 'Maximum results' -r"
+        ));
+        assert!(script.contains(
+            "complete -c tool -n \"__fish_use_subcommand\" -f -a \"query\" -d 'Query for items'"
+        ));
+        assert!(script.contains("-s s -l socket -d 'Socket path' -r -F"));
+    }
+
+    #[test]
+    fn elvish_lists_candidates_per_command() {
+        let script = render(Shell::Elvish);
+        assert!(script.contains("set edit:completion:arg-completer[tool]"));
+        assert!(script.contains("&'tool;query'= {"));
+        assert!(script.contains("cand --max 'Maximum results'"));
+    }
+
+    #[test]
+    fn flat_command_has_no_subcommand_dispatch() {
+        let mut cmd = Command::new("daemon").arg(
+            Arg::new("debug")
+                .short('d')
+                .long("debug")
+                .action(ArgAction::SetTrue),
+        );
+        let mut out = Vec::new();
+        generate(Shell::Fish, &mut cmd, "daemon", &mut out).unwrap();
+        let script = String::from_utf8(out).unwrap();
+        assert!(script.contains("complete -c daemon -s d -l debug"));
+        assert!(!script.contains("__fish_use_subcommand"));
+    }
+}
@@ -1,8 +1,13 @@
 //! Configuration management for datacube
 
+use crate::providers::websearch::{default_engines, SearchEngine};
+use crate::providers::CustomEntry;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tracing::info;
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
 
 /// Main configuration struct
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,15 +16,119 @@ pub struct Config {
     #[serde(default = "default_socket_path")]
     pub socket_path: PathBuf,
 
-    /// Maximum results per provider
+    /// File holding the running daemon's pid, which keeps a second daemon
+    /// from starting (default: $XDG_RUNTIME_DIR/datacube.pid)
+    #[serde(default = "default_pid_file")]
+    pub pid_file: PathBuf,
+
+    /// Most results taken from each provider for a query
     #[serde(default = "default_max_results")]
-    pub max_results: usize,
+    pub provider_max_results: usize,
+
+    /// Results per query, after merging, when the request doesn't say
+    /// (formerly `max_results`)
+    #[serde(default = "default_max_results", alias = "max_results")]
+    pub total_max_results: usize,
+
+    /// Drop results scoring below this (0-1; default 0 keeps everything),
+    /// except those their provider marks as always shown
+    #[serde(default)]
+    pub min_score: f32,
+
+    /// Maximum simultaneous client connections; further clients wait to be
+    /// accepted until one disconnects
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+
+    /// Close connections that send nothing for this many seconds (0 = never)
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+
+    /// Hold each query this many milliseconds before running it, and drop
+    /// it unanswered if the same connection sends a newer query before it
+    /// finishes (0 = run every query)
+    #[serde(default)]
+    pub debounce_ms: u64,
+
+    /// Let each connection run at most this many queries per second, on
+    /// average; faster queries are held back until their turn (0 = no limit)
+    #[serde(default)]
+    pub max_queries_per_sec: u32,
+
+    /// Keep the results of this many recent queries, so clients can fetch an
+    /// item back by qid and id (0 = off)
+    #[serde(default = "default_result_cache_size")]
+    pub result_cache_size: usize,
+
+    /// Forget cached query results after this many seconds
+    #[serde(default = "default_result_cache_secs")]
+    pub result_cache_secs: u64,
+
+    /// Rewrite themed icon names to absolute paths before sending results,
+    /// for frontends that can't look icons up themselves
+    #[serde(default)]
+    pub resolve_icons: bool,
+
+    /// Icon size in pixels used when resolving icons (default: 48)
+    #[serde(default = "default_icon_size")]
+    pub icon_size: u16,
+
+    /// Icon theme searched when resolving icons (default: the GTK theme)
+    #[serde(default)]
+    pub icon_theme: Option<String>,
+
+    /// Log output format ("human" or "json")
+    #[serde(default)]
+    pub log_format: LogFormat,
+
+    /// How query results are ordered
+    #[serde(default)]
+    pub result_order: ResultOrder,
+
+    /// Provider order for `result_order = "provider_groups"`; providers not
+    /// listed come last
+    #[serde(default)]
+    pub provider_groups: Vec<String>,
+
+    /// The providers answering an empty query (the launcher's initial view),
+    /// whose results are grouped in this order; unset, every provider that
+    /// accepts the empty query does
+    #[serde(default)]
+    pub empty_query_providers: Option<Vec<String>>,
+
+    /// Shortcuts rewriting the start of a query before it is routed, e.g.
+    /// `ff` -> `firefox`
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
 
     /// Provider-specific configuration
     #[serde(default)]
     pub providers: ProvidersConfig,
 }
 
+/// Log output format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines (default)
+    #[default]
+    Human,
+    /// One JSON object per line, for log aggregation
+    Json,
+}
+
+/// Order of the results of a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultOrder {
+    /// Highest score first, whichever provider produced it (default)
+    #[default]
+    Score,
+    /// Grouped by provider in the `provider_groups` order, by score within
+    /// each group
+    ProviderGroups,
+}
+
 /// Provider-specific configuration
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ProvidersConfig {
@@ -30,6 +139,94 @@ pub struct ProvidersConfig {
     /// Calculator provider config
     #[serde(default)]
     pub calculator: CalculatorConfig,
+
+    /// Currency provider config
+    #[serde(default)]
+    pub currency: CurrencyConfig,
+
+    /// Time provider config
+    #[serde(default)]
+    pub time: TimeConfig,
+
+    /// Color provider config
+    #[serde(default)]
+    pub color: ColorConfig,
+
+    /// Hash provider config
+    #[serde(default)]
+    pub hash: HashConfig,
+
+    /// Generate provider config
+    #[serde(default)]
+    pub generate: GenerateConfig,
+
+    /// Recent files provider config
+    #[serde(default)]
+    pub recent: RecentFilesConfig,
+
+    /// Bookmarks provider config
+    #[serde(default)]
+    pub bookmarks: BookmarksConfig,
+
+    /// Systemd provider config
+    #[serde(default)]
+    pub systemd: SystemdConfig,
+
+    /// Process provider config
+    #[serde(default)]
+    pub process: ProcessConfig,
+
+    /// Trash provider config
+    #[serde(default)]
+    pub trash: TrashConfig,
+
+    /// Custom entries provider config
+    #[serde(default)]
+    pub custom: CustomConfig,
+
+    /// Web provider config
+    #[serde(default)]
+    pub web: WebConfig,
+
+    /// Web search provider config
+    #[serde(default)]
+    pub websearch: WebSearchConfig,
+
+    /// External providers, each running its own command
+    #[serde(default)]
+    pub external: Vec<ExternalConfig>,
+}
+
+impl ProvidersConfig {
+    /// Each provider's `min_query_len` by provider name, for the manager
+    pub fn min_query_lens(&self) -> HashMap<String, usize> {
+        let builtin = [
+            ("applications", self.applications.min_query_len),
+            ("calculator", self.calculator.min_query_len),
+            ("currency", self.currency.min_query_len),
+            ("time", self.time.min_query_len),
+            ("color", self.color.min_query_len),
+            ("hash", self.hash.min_query_len),
+            ("generate", self.generate.min_query_len),
+            ("recent", self.recent.min_query_len),
+            ("bookmarks", self.bookmarks.min_query_len),
+            ("systemd", self.systemd.min_query_len),
+            ("process", self.process.min_query_len),
+            ("trash", self.trash.min_query_len),
+            ("custom", self.custom.min_query_len),
+            ("web", self.web.min_query_len),
+            ("websearch", self.websearch.min_query_len),
+        ];
+        builtin
+            .into_iter()
+            .map(|(name, len)| (name.to_string(), len))
+            .chain(
+                self.external
+                    .iter()
+                    .map(|external| (external.name.clone(), external.min_query_len)),
+            )
+            .collect()
+    }
 }
 
 /// Applications provider configuration
@@ -46,6 +243,25 @@ pub struct ApplicationsConfig {
     /// Additional directories to search for .desktop files
     #[serde(default)]
     pub extra_dirs: Vec<PathBuf>,
+
+    /// Reload every application this often, in seconds, as well as watching
+    /// for changes (default: 0, never)
+    #[serde(default)]
+    pub rescan_interval_secs: u64,
+
+    /// Also match names within this many typos of the query, where fuzzy
+    /// matching finds nothing (default: 0, off)
+    #[serde(default)]
+    pub max_typos: usize,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
 }
 
 impl Default for ApplicationsConfig {
@@ -54,6 +270,10 @@ impl Default for ApplicationsConfig {
             enabled: true,
             terminal: default_terminal(),
             extra_dirs: Vec::new(),
+            rescan_interval_secs: 0,
+            max_typos: 0,
+            priority: 0,
+            min_query_len: 0,
         }
     }
 }
@@ -68,6 +288,40 @@ pub struct CalculatorConfig {
     /// Prefix to trigger calculator (default: "=")
     #[serde(default = "default_calc_prefix")]
     pub prefix: String,
+
+    /// Decimal places shown for non-integer results (trailing zeros trimmed)
+    #[serde(default = "default_calc_precision")]
+    pub precision: usize,
+
+    /// Also show results close to a simple fraction as that fraction
+    #[serde(default = "default_true")]
+    pub fractions: bool,
+
+    /// Separate thousands with commas (1,000,000)
+    #[serde(default)]
+    pub grouping: bool,
+
+    /// Show results from 10^scientific_above up in scientific notation
+    #[serde(default = "default_calc_scientific_above")]
+    pub scientific_above: i32,
+
+    /// Show non-zero results below 10^scientific_below in scientific notation
+    #[serde(default = "default_calc_scientific_below")]
+    pub scientific_below: i32,
+
+    /// Constants usable in expressions, e.g. `g = 9.81`, alongside the
+    /// built-in `pi`, `e` and `tau`
+    #[serde(default)]
+    pub constants: HashMap<String, f64>,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 1)
+    #[serde(default = "default_calc_min_query_len")]
+    pub min_query_len: usize,
 }
 
 impl Default for CalculatorConfig {
@@ -75,21 +329,612 @@ impl Default for CalculatorConfig {
         Self {
             enabled: true,
             prefix: default_calc_prefix(),
+            precision: default_calc_precision(),
+            fractions: true,
+            grouping: false,
+            scientific_above: default_calc_scientific_above(),
+            scientific_below: default_calc_scientific_below(),
+            constants: HashMap::new(),
+            priority: 0,
+            min_query_len: default_calc_min_query_len(),
         }
     }
 }
 
-// Default value functions for serde
-fn default_socket_path() -> PathBuf {
-    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
-        .unwrap_or_else(|_| format!("/run/user/{}", unsafe { libc::getuid() }));
-    PathBuf::from(runtime_dir).join("datacube.sock")
+/// Currency provider configuration
+///
+/// Disabled by default because it fetches exchange rates over the network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurrencyConfig {
+    /// Whether this provider is enabled (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Prefix to trigger currency conversion (default: "$")
+    #[serde(default = "default_currency_prefix")]
+    pub prefix: String,
+
+    /// Rate endpoint; `{base}` and `{api_key}` are substituted
+    #[serde(default = "default_currency_endpoint")]
+    pub endpoint: String,
+
+    /// API key for endpoints that need one
+    #[serde(default)]
+    pub api_key: Option<String>,
+
+    /// Base currency requested from the endpoint (default: "USD")
+    #[serde(default = "default_currency_base")]
+    pub base: String,
+
+    /// How long cached rates are used before refreshing, in hours (default: 12)
+    #[serde(default = "default_currency_ttl_hours")]
+    pub ttl_hours: u64,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for CurrencyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix: default_currency_prefix(),
+            endpoint: default_currency_endpoint(),
+            api_key: None,
+            base: default_currency_base(),
+            ttl_hours: default_currency_ttl_hours(),
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Time provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeConfig {
+    /// Whether this provider is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Prefix to trigger time lookups (default: "time ")
+    #[serde(default = "default_time_prefix")]
+    pub prefix: String,
+
+    /// Zones shown when the query is just the prefix (default: ["UTC"])
+    #[serde(default = "default_time_pinned")]
+    pub pinned: Vec<String>,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for TimeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: default_time_prefix(),
+            pinned: default_time_pinned(),
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Color provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorConfig {
+    /// Whether this provider is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Prefix that enables CSS color names (default: "color "); hex codes,
+    /// rgb() and hsl() are recognized without it
+    #[serde(default = "default_color_prefix")]
+    pub prefix: String,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for ColorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: default_color_prefix(),
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Hash provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HashConfig {
+    /// Whether this provider is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Prefix to trigger hashing and encoding (default: "hash ")
+    #[serde(default = "default_hash_prefix")]
+    pub prefix: String,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: default_hash_prefix(),
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Generate provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerateConfig {
+    /// Whether this provider is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Prefix to trigger generation (default: "gen ")
+    #[serde(default = "default_generate_prefix")]
+    pub prefix: String,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for GenerateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: default_generate_prefix(),
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Recent files provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentFilesConfig {
+    /// Whether this provider is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Only search recent files after this prefix (default: none, so recent
+    /// files are mixed into every non-empty query)
+    #[serde(default)]
+    pub prefix: Option<String>,
+
+    /// Recent files store (default: $XDG_DATA_HOME/recently-used.xbel)
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for RecentFilesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: None,
+            path: None,
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Bookmarks provider configuration
+///
+/// Disabled by default since it reads browser profile data.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BookmarksConfig {
+    /// Whether this provider is enabled (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Only search bookmarks after this prefix (default: none)
+    #[serde(default)]
+    pub prefix: Option<String>,
+
+    /// Firefox profile directory (default: the profile marked default in
+    /// ~/.mozilla/firefox/profiles.ini)
+    #[serde(default)]
+    pub firefox_profile: Option<PathBuf>,
+
+    /// Chromium-family profile directory (default: the first of Chromium,
+    /// Chrome and Brave's "Default" profile that has bookmarks)
+    #[serde(default)]
+    pub chromium_profile: Option<PathBuf>,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+/// Systemd provider configuration
+///
+/// Disabled by default since it exposes service management.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemdConfig {
+    /// Whether this provider is enabled (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Prefix to trigger the provider (default: "sys ")
+    #[serde(default = "default_systemd_prefix")]
+    pub prefix: String,
+
+    /// List the user's units (`systemctl --user`)
+    #[serde(default = "default_true")]
+    pub user: bool,
+
+    /// List system units
+    #[serde(default = "default_true")]
+    pub system: bool,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for SystemdConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix: default_systemd_prefix(),
+            user: true,
+            system: true,
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Process provider configuration
+///
+/// Disabled by default since its results are for ending processes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessConfig {
+    /// Whether this provider is enabled (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Prefix to trigger the provider (default: "kill ")
+    #[serde(default = "default_process_prefix")]
+    pub prefix: String,
+
+    /// List other users' processes too, not only the current user's
+    #[serde(default)]
+    pub all_users: bool,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix: default_process_prefix(),
+            all_users: false,
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Trash provider configuration
+///
+/// Disabled by default since its results are for restoring or deleting files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashConfig {
+    /// Whether this provider is enabled (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Prefix to trigger the provider (default: "trash ")
+    #[serde(default = "default_trash_prefix")]
+    pub prefix: String,
+
+    /// Trash directory (default: $XDG_DATA_HOME/Trash)
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            prefix: default_trash_prefix(),
+            path: None,
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Custom entries provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomConfig {
+    /// Whether this provider is enabled (default: true; it has nothing to
+    /// show until entries are added)
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Prefix to trigger the provider (default: none)
+    #[serde(default)]
+    pub prefix: Option<String>,
+
+    /// The entries, as `[[providers.custom.entries]]` tables
+    #[serde(default)]
+    pub entries: Vec<CustomEntry>,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for CustomConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            prefix: None,
+            entries: Vec::new(),
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Web provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebConfig {
+    /// Whether this provider is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Web search provider configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebSearchConfig {
+    /// Whether this provider is enabled
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Engines by the keyword that picks them (default: `g` Google, `ddg`
+    /// DuckDuckGo, `w` Wikipedia); setting any replaces all of them
+    #[serde(default = "default_engines")]
+    pub engines: HashMap<String, SearchEngine>,
+
+    /// Keyword of the engine searching queries that pick none (default:
+    /// "ddg"); empty, those queries get no search
+    #[serde(default = "default_search_engine")]
+    pub default_engine: String,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+impl Default for WebSearchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            engines: default_engines(),
+            default_engine: default_search_engine(),
+            priority: 0,
+            min_query_len: 0,
+        }
+    }
+}
+
+/// Configuration of one external provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalConfig {
+    /// Provider name, used in listings and `--providers`
+    pub name: String,
+
+    /// Program and arguments; the query is appended as the last argument
+    pub command: Vec<String>,
+
+    /// Whether this provider is enabled (default: true)
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Prefix to trigger the provider (default: none)
+    #[serde(default)]
+    pub prefix: Option<String>,
+
+    /// Description shown in provider listings
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Kill the command after this many milliseconds
+    #[serde(default = "default_external_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// Tie-break priority when results have equal scores (higher sorts first)
+    #[serde(default)]
+    pub priority: i32,
+
+    /// Skip the provider for queries shorter than this many characters,
+    /// prefix included (default: 0)
+    #[serde(default)]
+    pub min_query_len: usize,
+}
+
+/// The socket path used when none is configured, shared by the daemon and
+/// the CLI so they agree
+///
+/// `$XDG_RUNTIME_DIR/datacube.sock`, or `/run/user/<uid>/datacube.sock`
+/// without the variable. Where that directory is missing or not writable, as
+/// in some containers and CI, it's `/tmp/datacube-<uid>.sock` instead, with a
+/// warning.
+pub fn default_socket_path() -> PathBuf {
+    runtime_path("sock")
+}
+
+/// The pid file used when none is configured: `datacube.pid` alongside the
+/// default socket, with the same `/tmp` fallback
+fn default_pid_file() -> PathBuf {
+    runtime_path("pid")
 }
 
+fn runtime_path(extension: &str) -> PathBuf {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR");
+    resolve_runtime_path(runtime_dir.as_deref(), unsafe { libc::getuid() }, extension)
+}
+
+/// `datacube.<extension>` in the runtime dir for a given `XDG_RUNTIME_DIR`
+/// value and user, or `/tmp/datacube-<uid>.<extension>`
+fn resolve_runtime_path(runtime_dir: Option<&OsStr>, uid: u32, extension: &str) -> PathBuf {
+    let runtime_dir = runtime_dir
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(format!("/run/user/{}", uid)));
+    if is_writable_dir(&runtime_dir) {
+        return runtime_dir.join(format!("datacube.{}", extension));
+    }
+    let fallback = PathBuf::from(format!("/tmp/datacube-{}.{}", uid, extension));
+    warn!(
+        "{} is not a writable directory; using {}",
+        runtime_dir.display(),
+        fallback.display()
+    );
+    fallback
+}
+
+fn is_writable_dir(dir: &Path) -> bool {
+    let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    dir.is_dir() && unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0
+}
+
+// Default value functions for serde
+
 fn default_max_results() -> usize {
     50
 }
 
+fn default_max_connections() -> usize {
+    64
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    300
+}
+
+fn default_result_cache_size() -> usize {
+    64
+}
+
+fn default_result_cache_secs() -> u64 {
+    300
+}
+
+fn default_icon_size() -> u16 {
+    48
+}
+
 fn default_true() -> bool {
     true
 }
@@ -102,17 +947,110 @@ fn default_calc_prefix() -> String {
     "=".to_string()
 }
 
+fn default_calc_min_query_len() -> usize {
+    1
+}
+
+fn default_calc_precision() -> usize {
+    crate::providers::calculator::DEFAULT_PRECISION
+}
+
+fn default_calc_scientific_above() -> i32 {
+    crate::providers::calculator::DEFAULT_SCIENTIFIC_ABOVE
+}
+
+fn default_calc_scientific_below() -> i32 {
+    crate::providers::calculator::DEFAULT_SCIENTIFIC_BELOW
+}
+
+fn default_currency_prefix() -> String {
+    "$".to_string()
+}
+
+fn default_currency_endpoint() -> String {
+    crate::providers::currency::DEFAULT_ENDPOINT.to_string()
+}
+
+fn default_currency_base() -> String {
+    "USD".to_string()
+}
+
+fn default_currency_ttl_hours() -> u64 {
+    12
+}
+
+fn default_time_prefix() -> String {
+    "time ".to_string()
+}
+
+fn default_time_pinned() -> Vec<String> {
+    vec!["UTC".to_string()]
+}
+
+fn default_search_engine() -> String {
+    crate::providers::websearch::DEFAULT_ENGINE.to_string()
+}
+
+fn default_color_prefix() -> String {
+    "color ".to_string()
+}
+
+fn default_hash_prefix() -> String {
+    "hash ".to_string()
+}
+
+fn default_generate_prefix() -> String {
+    "gen ".to_string()
+}
+
+fn default_systemd_prefix() -> String {
+    "sys ".to_string()
+}
+
+fn default_trash_prefix() -> String {
+    "trash ".to_string()
+}
+
+fn default_external_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_process_prefix() -> String {
+    "kill ".to_string()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             socket_path: default_socket_path(),
-            max_results: default_max_results(),
+            pid_file: default_pid_file(),
+            provider_max_results: default_max_results(),
+            total_max_results: default_max_results(),
+            min_score: 0.0,
+            max_connections: default_max_connections(),
+            idle_timeout_secs: default_idle_timeout_secs(),
+            debounce_ms: 0,
+            max_queries_per_sec: 0,
+            result_cache_size: default_result_cache_size(),
+            result_cache_secs: default_result_cache_secs(),
+            resolve_icons: false,
+            icon_size: default_icon_size(),
+            icon_theme: None,
+            log_format: LogFormat::default(),
+            result_order: ResultOrder::default(),
+            provider_groups: Vec::new(),
+            empty_query_providers: None,
+            aliases: HashMap::new(),
             providers: ProvidersConfig::default(),
         }
     }
 }
 
 impl Config {
+    /// A commented config file setting every option to its default, for
+    /// `datacube --print-default-config`
+    pub const DEFAULT_TEMPLATE: &'static str = include_str!("default-config.toml");
+
     /// Load configuration from file or use defaults
     pub fn load() -> Self {
         let config_path = Self::config_path();
@@ -150,23 +1088,118 @@ impl Config {
 
         config_dir.join("datacube").join("config.toml")
     }
+
+    /// Get the directory for cached data (e.g. exchange rates)
+    pub fn cache_dir() -> PathBuf {
+        let cache_dir = std::env::var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| {
+                dirs::home_dir()
+                    .unwrap_or_else(|| PathBuf::from("/"))
+                    .join(".cache")
+            });
+
+        cache_dir.join("datacube")
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn socket_path_follows_the_runtime_dir() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            resolve_runtime_path(Some(dir.as_os_str()), 1000, "sock"),
+            dir.join("datacube.sock")
+        );
+
+        let run_user = Path::new("/run/user/1000");
+        let expected = if is_writable_dir(run_user) {
+            run_user.join("datacube.sock")
+        } else {
+            PathBuf::from("/tmp/datacube-1000.sock")
+        };
+        assert_eq!(resolve_runtime_path(None, 1000, "sock"), expected);
+        assert_eq!(
+            resolve_runtime_path(Some(OsStr::new("")), 1000, "sock"),
+            expected
+        );
+    }
+
+    #[test]
+    fn socket_falls_back_to_tmp_without_a_usable_runtime_dir() {
+        assert_eq!(
+            resolve_runtime_path(Some(OsStr::new("/nonexistent/run/user/1000")), 1000, "sock"),
+            PathBuf::from("/tmp/datacube-1000.sock")
+        );
+    }
+
+    #[test]
+    fn pid_file_sits_next_to_the_socket() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            resolve_runtime_path(Some(dir.as_os_str()), 1000, "pid"),
+            dir.join("datacube.pid")
+        );
+        assert_eq!(
+            resolve_runtime_path(Some(OsStr::new("/nonexistent")), 1000, "pid"),
+            PathBuf::from("/tmp/datacube-1000.pid")
+        );
+        let config = Config::default();
+        assert_eq!(config.pid_file.parent(), config.socket_path.parent());
+    }
+
     #[test]
     fn defaults_are_sane() {
         let config = Config::default();
-        assert_eq!(config.max_results, 50);
+        assert_eq!(config.provider_max_results, 50);
+        assert_eq!(config.total_max_results, 50);
+        assert_eq!(config.max_connections, 64);
+        assert_eq!(config.idle_timeout_secs, 300);
+        assert_eq!(config.debounce_ms, 0);
+        assert_eq!(config.max_queries_per_sec, 0);
+        assert_eq!(config.result_order, ResultOrder::Score);
+        assert_eq!(config.result_cache_size, 64);
+        assert_eq!(config.result_cache_secs, 300);
+        assert!(!config.resolve_icons);
+        assert_eq!(config.icon_size, 48);
         assert!(config.providers.applications.enabled);
         assert!(config.providers.calculator.enabled);
         assert_eq!(config.providers.calculator.prefix, "=");
-        assert!(config
-            .socket_path
-            .to_string_lossy()
-            .ends_with("datacube.sock"));
+        assert_eq!(config.providers.calculator.precision, 10);
+        assert!(config.providers.calculator.fractions);
+        assert!(!config.providers.calculator.grouping);
+        assert_eq!(config.providers.calculator.scientific_above, 15);
+        assert_eq!(config.providers.calculator.scientific_below, -6);
+        assert_eq!(config.providers.calculator.min_query_len, 1);
+        assert!(config.providers.calculator.constants.is_empty());
+        assert_eq!(config.providers.applications.min_query_len, 0);
+        assert_eq!(config.providers.applications.rescan_interval_secs, 0);
+        assert!(!config.providers.currency.enabled);
+        assert_eq!(config.providers.currency.ttl_hours, 12);
+        assert!(config.providers.time.enabled);
+        assert_eq!(config.providers.time.pinned, vec!["UTC"]);
+        assert!(config.providers.color.enabled);
+        assert_eq!(config.providers.color.prefix, "color ");
+        assert_eq!(config.providers.hash.prefix, "hash ");
+        assert_eq!(config.providers.generate.prefix, "gen ");
+        assert!(config.providers.recent.enabled);
+        assert!(config.providers.recent.prefix.is_none());
+        assert!(!config.providers.bookmarks.enabled);
+        assert!(!config.providers.systemd.enabled);
+        assert_eq!(config.providers.systemd.prefix, "sys ");
+        assert!(!config.providers.process.enabled);
+        assert!(!config.providers.process.all_users);
+        assert!(!config.providers.trash.enabled);
+        assert_eq!(config.providers.trash.prefix, "trash ");
+        assert!(config.providers.custom.enabled);
+        assert!(config.providers.custom.entries.is_empty());
+        assert!(config.providers.external.is_empty());
+        // datacube.sock, or datacube-<uid>.sock without a runtime dir
+        let socket_name = config.socket_path.file_name().unwrap().to_string_lossy();
+        assert!(socket_name.starts_with("datacube") && socket_name.ends_with(".sock"));
     }
 
     #[test]
@@ -174,7 +1207,7 @@ mod tests {
         let config = Config::default();
         let serialized = toml::to_string(&config).expect("serialize");
         let parsed: Config = toml::from_str(&serialized).expect("deserialize");
-        assert_eq!(parsed.max_results, config.max_results);
+        assert_eq!(parsed.total_max_results, config.total_max_results);
         assert_eq!(
             parsed.providers.calculator.prefix,
             config.providers.calculator.prefix
@@ -184,17 +1217,154 @@ mod tests {
 
     #[test]
     fn partial_config_uses_defaults() {
-        // Only override max_results; everything else should fall back to defaults.
-        let parsed: Config = toml::from_str("max_results = 7").expect("deserialize");
-        assert_eq!(parsed.max_results, 7);
+        // Only override the result limit; everything else should fall back to
+        // defaults.
+        let parsed: Config = toml::from_str("total_max_results = 7").expect("deserialize");
+        assert_eq!(parsed.total_max_results, 7);
+        assert_eq!(parsed.provider_max_results, 50);
         assert!(parsed.providers.applications.enabled);
         assert_eq!(parsed.providers.calculator.prefix, "=");
     }
 
+    #[test]
+    fn log_format_parses_lowercase() {
+        let parsed: Config = toml::from_str("log_format = \"json\"").expect("deserialize");
+        assert_eq!(parsed.log_format, LogFormat::Json);
+        assert_eq!(Config::default().log_format, LogFormat::Human);
+    }
+
+    #[test]
+    fn custom_entries_are_listed_as_tables() {
+        let parsed: Config = toml::from_str(
+            r#"
+            [[providers.custom.entries]]
+            name = "Start VPN"
+            exec = "nmcli con up work"
+            keywords = ["network"]
+
+            [[providers.custom.entries]]
+            name = "Mount NAS"
+            exec = "mount /mnt/nas"
+            terminal = true
+            "#,
+        )
+        .expect("deserialize");
+        let custom = &parsed.providers.custom;
+        assert!(custom.enabled);
+        assert_eq!(custom.entries.len(), 2);
+        assert_eq!(custom.entries[0].keywords, ["network"]);
+        assert!(custom.entries[0].icon.is_empty());
+        assert!(custom.entries[1].terminal);
+    }
+
+    #[test]
+    fn external_providers_are_listed_as_tables() {
+        let parsed: Config = toml::from_str(
+            r#"
+            [[providers.external]]
+            name = "notes"
+            command = ["notes-search", "--json"]
+            prefix = "n "
+
+            [[providers.external]]
+            name = "emoji"
+            command = ["emoji-lookup"]
+            timeout_ms = 500
+            "#,
+        )
+        .expect("deserialize");
+        let external = &parsed.providers.external;
+        assert_eq!(external.len(), 2);
+        assert_eq!(external[0].command, ["notes-search", "--json"]);
+        assert_eq!(external[0].prefix.as_deref(), Some("n "));
+        assert_eq!(external[0].timeout_ms, 5000);
+        assert!(external[1].enabled);
+        assert!(external[1].prefix.is_none());
+        assert_eq!(external[1].timeout_ms, 500);
+    }
+
+    #[test]
+    fn min_query_lens_cover_every_provider() {
+        let parsed: Config = toml::from_str(
+            r#"
+            [providers.recent]
+            min_query_len = 3
+
+            [[providers.external]]
+            name = "notes"
+            command = ["notes-search"]
+            min_query_len = 2
+            "#,
+        )
+        .expect("deserialize");
+        let lens = parsed.providers.min_query_lens();
+        assert_eq!(lens["recent"], 3);
+        assert_eq!(lens["notes"], 2);
+        assert_eq!(lens["calculator"], 1);
+        assert_eq!(lens["applications"], 0);
+        assert_eq!(lens.len(), 16);
+    }
+
     #[test]
     fn empty_config_is_all_defaults() {
         let parsed: Config = toml::from_str("").expect("deserialize");
-        assert_eq!(parsed.max_results, 50);
+        assert_eq!(parsed.total_max_results, 50);
         assert!(parsed.providers.applications.enabled);
     }
+
+    #[test]
+    fn max_results_is_the_total_limit() {
+        let parsed: Config =
+            toml::from_str("max_results = 10\nprovider_max_results = 20").expect("deserialize");
+        assert_eq!(parsed.total_max_results, 10);
+        assert_eq!(parsed.provider_max_results, 20);
+    }
+
+    /// Every key path in `value`, with tables flattened ("providers.time.prefix")
+    fn key_paths(value: &toml::Value, path: &str, out: &mut Vec<String>) {
+        match value {
+            toml::Value::Table(table) if !table.is_empty() => {
+                for (key, value) in table {
+                    let path = match path {
+                        "" => key.clone(),
+                        _ => format!("{}.{}", path, key),
+                    };
+                    key_paths(value, &path, out);
+                }
+            }
+            _ => out.push(path.to_string()),
+        }
+    }
+
+    #[test]
+    fn default_template_matches_the_defaults() {
+        let parsed: Config = toml::from_str(Config::DEFAULT_TEMPLATE).expect("template parses");
+        assert_eq!(
+            toml::Value::try_from(&parsed).unwrap(),
+            toml::Value::try_from(Config::default()).unwrap()
+        );
+
+        // Every option is mentioned, set or commented out.
+        let lines: Vec<&str> = Config::DEFAULT_TEMPLATE
+            .lines()
+            .map(|line| line.trim_start_matches('#').trim_start())
+            .collect();
+        let mut paths = Vec::new();
+        key_paths(
+            &toml::Value::try_from(Config::default()).unwrap(),
+            "",
+            &mut paths,
+        );
+        for path in paths {
+            let (table, key) = path.rsplit_once('.').unwrap_or(("", &path));
+            let mentioned = lines.iter().any(|line| {
+                line.starts_with(&format!("{} =", key)) || *line == format!("[[{}.{}]]", table, key)
+            });
+            assert!(
+                mentioned,
+                "{} is missing from the default config template",
+                path
+            );
+        }
+    }
 }
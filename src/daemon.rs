@@ -0,0 +1,348 @@
+//! Detaching the daemon from its terminal, and its pidfile
+//!
+//! Unless run with `--foreground` (as systemd's `Type=simple` expects),
+//! `datacube` double-forks: the first fork lets the launching process return,
+//! `setsid` drops the controlling terminal, and the second fork keeps the
+//! daemon from ever acquiring another. The daemon then moves to `/` so it
+//! doesn't keep the launch directory's mount busy, and its std streams point
+//! at `/dev/null`, so logs are only visible in the foreground.
+//!
+//! The launching process doesn't return until the daemon reports through a
+//! [`Readiness`] that it is listening, or why it couldn't start; it exits
+//! non-zero with that error, since the daemon's own stderr goes nowhere.
+//!
+//! Forking only copies the calling thread, so this must run before the tokio
+//! runtime (or anything else that starts threads) exists.
+//!
+//! Either way the daemon's pid goes in `Config::pid_file`. A daemon starting
+//! while the pid there is still alive refuses to, rather than take over the
+//! running one's socket. Without a usable runtime dir the pidfile is in the
+//! shared `/tmp`, so it's never written through a symlink, or when another
+//! user owns it.
+
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::FromRawFd;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use tracing::warn;
+
+/// What a daemon that started writes to the launching process
+const READY: &[u8] = b"\0";
+
+/// Tells the process that launched the daemon how startup went
+///
+/// Only the first report counts. A daemon that exits without one counts as
+/// having failed. In the foreground (the default value) there is no one to
+/// tell and reports go nowhere.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    pipe: Arc<Mutex<Option<File>>>,
+}
+
+impl Readiness {
+    fn new(pipe: File) -> Self {
+        Self {
+            pipe: Arc::new(Mutex::new(Some(pipe))),
+        }
+    }
+
+    /// Report that the daemon is listening, letting the launching process
+    /// exit successfully
+    pub fn ready(&self) {
+        self.report(READY);
+    }
+
+    /// Report that the daemon failed to start, for the launching process to
+    /// print
+    pub fn failed(&self, error: &anyhow::Error) {
+        self.report(format!("{:#}", error).as_bytes());
+    }
+
+    fn report(&self, message: &[u8]) {
+        // Closing the pipe afterwards is what ends the launcher's read.
+        let pipe = self
+            .pipe
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.write_all(message);
+        }
+    }
+}
+
+/// Detach from the terminal
+///
+/// Returns in the daemon, with the [`Readiness`] to report startup through.
+/// The intermediate child exits, and the original process waits for that
+/// report and exits with it.
+pub fn daemonize() -> anyhow::Result<Readiness> {
+    let mut fds = [0; 2];
+    // Close-on-exec, so the providers' subprocesses don't hold the launcher
+    // up by inheriting the write end.
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        anyhow::bail!("pipe failed: {}", std::io::Error::last_os_error());
+    }
+    let (read_end, write_end) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    match unsafe { libc::fork() } {
+        -1 => anyhow::bail!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => drop(read_end),
+        _ => {
+            drop(write_end);
+            wait_for_daemon(read_end)
+        }
+    }
+
+    let readiness = Readiness::new(write_end);
+    let detached = detach();
+    if let Err(e) = &detached {
+        readiness.failed(e);
+    }
+    detached.map(|()| readiness)
+}
+
+/// The rest of [`daemonize`], in the first child
+fn detach() -> anyhow::Result<()> {
+    if unsafe { libc::setsid() } < 0 {
+        anyhow::bail!("setsid failed: {}", std::io::Error::last_os_error());
+    }
+    fork_and_exit_parent()?;
+    std::env::set_current_dir("/")?;
+    redirect_std_streams()
+}
+
+/// In the launching process: wait for the daemon's report and exit with it
+fn wait_for_daemon(mut pipe: File) -> ! {
+    let mut report = Vec::new();
+    let _ = pipe.read_to_end(&mut report);
+    match startup_outcome(&report) {
+        Ok(()) => unsafe { libc::_exit(0) },
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            unsafe { libc::_exit(1) }
+        }
+    }
+}
+
+/// What the launching process makes of the daemon's report
+fn startup_outcome(report: &[u8]) -> Result<(), String> {
+    match report {
+        READY => Ok(()),
+        [] => Err("the daemon exited while starting".to_string()),
+        error => Err(String::from_utf8_lossy(error).into_owned()),
+    }
+}
+
+/// Fail if `pidfile` names a running process; a stale one is ignored
+pub fn check_pidfile(pidfile: &Path) -> anyhow::Result<()> {
+    let Ok(contents) = std::fs::read_to_string(pidfile) else {
+        return Ok(());
+    };
+    match contents.trim().parse::<i32>() {
+        Ok(pid) if is_alive(pid) => anyhow::bail!(
+            "datacube is already running (pid {}, from {}); stop it first",
+            pid,
+            pidfile.display()
+        ),
+        _ => {
+            warn!("Ignoring stale pid file {}", pidfile.display());
+            Ok(())
+        }
+    }
+}
+
+/// Write this process's pid to `pidfile`
+pub fn write_pidfile(pidfile: &Path) -> anyhow::Result<()> {
+    write_pid(pidfile, std::process::id())
+}
+
+/// Remove the pidfile written by [`write_pidfile`], if it's still ours
+pub fn remove_pidfile(pidfile: &Path) {
+    let ours = std::fs::read_to_string(pidfile)
+        .is_ok_and(|pid| pid.trim() == std::process::id().to_string());
+    if ours {
+        let _ = std::fs::remove_file(pidfile);
+    }
+}
+
+fn fork_and_exit_parent() -> anyhow::Result<()> {
+    match unsafe { libc::fork() } {
+        -1 => anyhow::bail!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => Ok(()),
+        // _exit skips the parent's atexit handlers and buffered output, which
+        // the child still owns.
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+/// Point stdin, stdout and stderr at `/dev/null`
+fn redirect_std_streams() -> anyhow::Result<()> {
+    let dev_null = CString::new("/dev/null")?;
+    let fd = unsafe { libc::open(dev_null.as_ptr(), libc::O_RDWR) };
+    if fd < 0 {
+        anyhow::bail!(
+            "failed to open /dev/null: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    for target in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(fd, target) } < 0 {
+            anyhow::bail!("dup2 failed: {}", std::io::Error::last_os_error());
+        }
+    }
+    if fd > libc::STDERR_FILENO {
+        unsafe { libc::close(fd) };
+    }
+    Ok(())
+}
+
+/// Whether a process with `pid` exists, as `kill -0` tells
+fn is_alive(pid: i32) -> bool {
+    // 0 and negative pids address process groups, not a process.
+    if pid <= 0 {
+        return false;
+    }
+    // EPERM: it exists, but belongs to someone else.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+fn write_pid(pidfile: &Path, pid: u32) -> anyhow::Result<()> {
+    if let Some(parent) = pidfile.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let write = || -> std::io::Result<()> {
+        // Not truncated until we know the file is ours.
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(pidfile)?;
+        let metadata = file.metadata()?;
+        if metadata.uid() != unsafe { libc::getuid() } || metadata.nlink() != 1 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                "it belongs to another user or is linked elsewhere",
+            ));
+        }
+        file.set_len(0)?;
+        file.write_all(format!("{}\n", pid).as_bytes())
+    };
+    write().map_err(|e| anyhow::anyhow!("failed to write pidfile {:?}: {}", pidfile, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_pidfile() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("datacube-pid-{}", uuid::Uuid::new_v4()))
+            .join("datacube.pid")
+    }
+
+    fn pipe() -> (File, File) {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+    }
+
+    fn reported(read_end: &mut File) -> Result<(), String> {
+        let mut report = Vec::new();
+        read_end.read_to_end(&mut report).unwrap();
+        startup_outcome(&report)
+    }
+
+    #[test]
+    fn only_the_first_startup_report_counts() {
+        let (mut read_end, write_end) = pipe();
+        let readiness = Readiness::new(write_end);
+        readiness.clone().ready();
+        readiness.failed(&anyhow::anyhow!("too late"));
+        assert_eq!(reported(&mut read_end), Ok(()));
+
+        let (mut read_end, write_end) = pipe();
+        let readiness = Readiness::new(write_end);
+        readiness.failed(&anyhow::anyhow!("bind failed").context("starting"));
+        assert_eq!(
+            reported(&mut read_end),
+            Err("starting: bind failed".to_string())
+        );
+
+        // Exiting without a report
+        let (mut read_end, write_end) = pipe();
+        drop(Readiness::new(write_end));
+        assert!(reported(&mut read_end).unwrap_err().contains("exited"));
+
+        // In the foreground reports go nowhere.
+        Readiness::default().ready();
+    }
+
+    #[test]
+    fn only_our_own_pidfile_is_removed() {
+        let pidfile = temp_pidfile();
+
+        write_pid(&pidfile, 1).unwrap();
+        remove_pidfile(&pidfile);
+        assert_eq!(std::fs::read_to_string(&pidfile).unwrap(), "1\n");
+
+        write_pidfile(&pidfile).unwrap();
+        remove_pidfile(&pidfile);
+        assert!(!pidfile.exists());
+
+        let _ = std::fs::remove_dir_all(pidfile.parent().unwrap());
+    }
+
+    #[test]
+    fn pidfile_is_private_and_never_followed() {
+        let pidfile = temp_pidfile();
+        write_pid(&pidfile, 1).unwrap();
+        let mode = std::fs::metadata(&pidfile).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Shorter contents leave nothing of the old ones behind.
+        write_pid(&pidfile, 22).unwrap();
+        write_pid(&pidfile, 3).unwrap();
+        assert_eq!(std::fs::read_to_string(&pidfile).unwrap(), "3\n");
+
+        let victim = pidfile.with_file_name("victim");
+        std::fs::write(&victim, "precious").unwrap();
+        let link = pidfile.with_file_name("link.pid");
+        std::os::unix::fs::symlink(&victim, &link).unwrap();
+        assert!(write_pid(&link, 1).is_err());
+        let hard_link = pidfile.with_file_name("hard.pid");
+        std::fs::hard_link(&victim, &hard_link).unwrap();
+        assert!(write_pid(&hard_link, 1).is_err());
+        assert_eq!(std::fs::read_to_string(&victim).unwrap(), "precious");
+
+        let _ = std::fs::remove_dir_all(pidfile.parent().unwrap());
+    }
+
+    #[test]
+    fn live_pids_block_startup() {
+        let pidfile = temp_pidfile();
+        assert!(check_pidfile(&pidfile).is_ok());
+
+        write_pidfile(&pidfile).unwrap();
+        let err = check_pidfile(&pidfile).unwrap_err();
+        assert!(err.to_string().contains("already running"));
+
+        // A process that has exited leaves a stale file.
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        write_pid(&pidfile, pid).unwrap();
+        assert!(check_pidfile(&pidfile).is_ok());
+
+        std::fs::write(&pidfile, "garbage").unwrap();
+        assert!(check_pidfile(&pidfile).is_ok());
+        assert!(!is_alive(0));
+
+        let _ = std::fs::remove_dir_all(pidfile.parent().unwrap());
+    }
+}
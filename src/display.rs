@@ -0,0 +1,117 @@
+//! Which display server the session runs
+//!
+//! Copying, typing and anything else that talks to the desktop has to pick
+//! tools for Wayland or X11. [`current`] works that out once from the
+//! environment (`WAYLAND_DISPLAY`, `DISPLAY`, then `XDG_SESSION_TYPE`), so
+//! callers branch on a [`DisplayServer`] rather than re-reading variables.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The display server of the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayServer {
+    /// A Wayland compositor, with XWayland for X11 clients if `xwayland`
+    Wayland {
+        xwayland: bool,
+    },
+    X11,
+    /// No display, e.g. over SSH or in a service started before the session
+    None,
+}
+
+impl DisplayServer {
+    pub fn is_wayland(self) -> bool {
+        matches!(self, DisplayServer::Wayland { .. })
+    }
+
+    /// Whether X11 clients can connect, natively or through XWayland
+    pub fn has_x11(self) -> bool {
+        matches!(
+            self,
+            DisplayServer::X11 | DisplayServer::Wayland { xwayland: true }
+        )
+    }
+}
+
+impl fmt::Display for DisplayServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DisplayServer::Wayland { xwayland: true } => "Wayland (with XWayland)",
+            DisplayServer::Wayland { xwayland: false } => "Wayland",
+            DisplayServer::X11 => "X11",
+            DisplayServer::None => "none",
+        })
+    }
+}
+
+/// The session's display server, detected on first use
+pub fn current() -> DisplayServer {
+    static CURRENT: OnceLock<DisplayServer> = OnceLock::new();
+    *CURRENT.get_or_init(|| {
+        detect(
+            std::env::var_os("WAYLAND_DISPLAY").is_some(),
+            std::env::var_os("DISPLAY").is_some(),
+            std::env::var("XDG_SESSION_TYPE").ok().as_deref(),
+        )
+    })
+}
+
+/// The display server for the given environment
+///
+/// The display variables are what clients connect with, so they win;
+/// `XDG_SESSION_TYPE` only decides when neither is set.
+fn detect(wayland_display: bool, display: bool, session_type: Option<&str>) -> DisplayServer {
+    if wayland_display {
+        DisplayServer::Wayland { xwayland: display }
+    } else if display {
+        DisplayServer::X11
+    } else {
+        match session_type {
+            Some("wayland") => DisplayServer::Wayland { xwayland: false },
+            Some("x11") => DisplayServer::X11,
+            _ => DisplayServer::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_variables_decide_first() {
+        assert_eq!(
+            detect(true, true, Some("x11")),
+            DisplayServer::Wayland { xwayland: true }
+        );
+        assert_eq!(
+            detect(true, false, None),
+            DisplayServer::Wayland { xwayland: false }
+        );
+        assert_eq!(detect(false, true, Some("wayland")), DisplayServer::X11);
+    }
+
+    #[test]
+    fn session_type_is_the_fallback() {
+        assert_eq!(
+            detect(false, false, Some("wayland")),
+            DisplayServer::Wayland { xwayland: false }
+        );
+        assert_eq!(detect(false, false, Some("x11")), DisplayServer::X11);
+        assert_eq!(detect(false, false, Some("tty")), DisplayServer::None);
+        assert_eq!(detect(false, false, None), DisplayServer::None);
+    }
+
+    #[test]
+    fn x11_clients_need_x11_or_xwayland() {
+        assert!(DisplayServer::X11.has_x11());
+        assert!(DisplayServer::Wayland { xwayland: true }.has_x11());
+        assert!(!DisplayServer::Wayland { xwayland: false }.has_x11());
+        assert!(!DisplayServer::None.has_x11());
+        assert_eq!(
+            DisplayServer::Wayland { xwayland: true }.to_string(),
+            "Wayland (with XWayland)"
+        );
+    }
+}
@@ -3,7 +3,14 @@
 //! A backend service that aggregates data from multiple sources to power
 //! application launchers and desktop utilities.
 
+pub mod autostart;
+pub mod client;
+pub mod completions;
 pub mod config;
+pub mod daemon;
+pub mod display;
+pub mod logging;
+pub mod msgpack;
 pub mod providers;
 pub mod server;
 
@@ -12,6 +19,12 @@ pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/datacube.rs"));
 }
 
-pub use config::Config;
-pub use providers::{ApplicationsProvider, CalculatorProvider, Item, Provider, ProviderManager};
+pub use config::{default_socket_path, Config};
+pub use providers::{
+    ApplicationsProvider, BookmarkSource, BookmarksProvider, CalculatorProvider, ColorProvider,
+    CurrencyProvider, CustomEntry, CustomProvider, ExternalItem, ExternalProvider,
+    GenerateProvider, HashProvider, IconKind, Item, ItemStream, ProcessProvider, Provider,
+    ProviderManager, ProviderOverrides, RecentFilesProvider, Route, SystemdProvider, SystemdScope,
+    TimeProvider, TrashProvider, WebProvider, WebSearchProvider,
+};
 pub use server::Server;
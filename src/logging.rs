@@ -0,0 +1,308 @@
+//! Logging setup for the datacube daemon
+//!
+//! Supports the default human-readable output and a JSON-lines format for log
+//! aggregation. In JSON mode every line is a single object; the fields of all
+//! enclosing spans (e.g. the `qid` of a query) are flattened into it so that
+//! related lines can be correlated.
+//!
+//! The level sits in a reloadable filter, so a running daemon can be switched
+//! to debug logging (and back) through its [`LogLevel`] handle.
+
+use crate::config::LogFormat;
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::{FormatEvent, FormatFields, Writer};
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormattedFields, MakeWriter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, FmtSubscriber, Layer, Registry};
+
+/// Install the global subscriber for the given format and level
+///
+/// Returns the handle for changing the level later.
+pub fn init(format: LogFormat, level: Level) -> anyhow::Result<LogLevel> {
+    let (subscriber, log_level) = subscriber(format, level, std::io::stdout);
+    tracing::subscriber::set_global_default(subscriber)?;
+    Ok(log_level)
+}
+
+/// A subscriber writing to `writer`, and the handle to its level
+pub(crate) fn subscriber<W>(
+    format: LogFormat,
+    level: Level,
+    writer: W,
+) -> (impl Subscriber + Send + Sync, LogLevel)
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let initial = LevelFilter::from_level(level);
+    let (filter, handle) = reload::Layer::new(initial);
+    let layer = tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .with_writer(writer);
+    let layer = match format {
+        LogFormat::Human => layer.boxed(),
+        LogFormat::Json => layer
+            .fmt_fields(JsonFields)
+            .event_format(JsonFormat)
+            .boxed(),
+    };
+    let subscriber = Registry::default().with(filter).with(layer);
+    (subscriber, LogLevel { handle, initial })
+}
+
+/// Changes the level of the subscriber installed by [`init`]
+#[derive(Clone)]
+pub struct LogLevel {
+    handle: reload::Handle<LevelFilter, Registry>,
+    initial: LevelFilter,
+}
+
+impl LogLevel {
+    /// The level currently logged at
+    pub fn current(&self) -> anyhow::Result<LevelFilter> {
+        self.handle
+            .clone_current()
+            .ok_or_else(|| anyhow::anyhow!("the subscriber is gone"))
+    }
+
+    /// Log at `level` from now on
+    pub fn set(&self, level: LevelFilter) -> anyhow::Result<()> {
+        self.handle.reload(level)?;
+        Ok(())
+    }
+
+    /// Go back to the level logging started at
+    pub fn reset(&self) -> anyhow::Result<()> {
+        self.set(self.initial)
+    }
+}
+
+/// A warn-level human subscriber for use before the configured one exists
+///
+/// The log format comes from the config file, so the config has to be loaded
+/// before logging is initialized. Loading it under this subscriber keeps
+/// warnings about a broken config file visible.
+pub fn bootstrap_subscriber() -> impl Subscriber + Send + Sync {
+    FmtSubscriber::builder()
+        .with_max_level(Level::WARN)
+        .with_target(false)
+        .finish()
+}
+
+/// Collects recorded fields into a JSON object
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            Value::from(format!("{:?}", value)),
+        );
+    }
+}
+
+/// Field formatter that stores span fields as a JSON object string
+///
+/// `JsonFormat` parses these back when flattening span fields into an event.
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: tracing_subscriber::field::RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        let json = serde_json::to_string(&visitor.0).map_err(|_| fmt::Error)?;
+        writer.write_str(&json)
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(parse_object(&current.fields));
+        fields.record(&mut visitor);
+        current.fields = serde_json::to_string(&visitor.0).map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
+
+/// Event formatter that writes one JSON object per line
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+
+        let mut object = Map::new();
+        object.insert("timestamp".to_string(), Value::from(timestamp));
+        object.insert(
+            "level".to_string(),
+            Value::from(event.metadata().level().as_str()),
+        );
+        object.insert("target".to_string(), Value::from(event.metadata().target()));
+
+        // Span fields first (outermost to innermost) so event fields win.
+        if let Some(scope) = ctx.event_scope() {
+            let mut spans = Vec::new();
+            for span in scope.from_root() {
+                spans.push(Value::from(span.name()));
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    object.extend(parse_object(&fields.fields));
+                }
+            }
+            object.insert("spans".to_string(), Value::Array(spans));
+        }
+
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        object.extend(visitor.0);
+
+        let json = serde_json::to_string(&object).map_err(|_| fmt::Error)?;
+        writeln!(writer, "{}", json)
+    }
+}
+
+/// Parse a JSON object produced by `JsonFields`, ignoring anything else
+fn parse_object(fields: &str) -> Map<String, Value> {
+    match serde_json::from_str(fields) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tracing::info_span;
+
+    /// A `MakeWriter` that captures output into a shared buffer.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture_json(f: impl FnOnce()) -> Vec<Value> {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = FmtSubscriber::builder()
+            .with_max_level(Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .fmt_fields(JsonFields)
+            .event_format(JsonFormat)
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+            .collect()
+    }
+
+    #[test]
+    fn json_lines_include_level_message_and_fields() {
+        let lines = capture_json(|| tracing::info!(results = 3, "Query complete"));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["message"], "Query complete");
+        assert_eq!(lines[0]["results"], 3);
+        assert!(lines[0]["timestamp"].is_string());
+    }
+
+    #[test]
+    fn json_lines_flatten_span_fields() {
+        let lines = capture_json(|| {
+            let span = info_span!("query", qid = "abc");
+            let _guard = span.enter();
+            tracing::debug!("inside");
+        });
+        assert_eq!(lines[0]["qid"], "abc");
+        assert_eq!(lines[0]["spans"], serde_json::json!(["query"]));
+    }
+
+    #[test]
+    fn json_fields_merge_recorded_span_values() {
+        let lines = capture_json(|| {
+            let span = info_span!("query", qid = "abc", latency_ms = tracing::field::Empty);
+            span.record("latency_ms", 12u64);
+            let _guard = span.enter();
+            tracing::info!("done");
+        });
+        assert_eq!(lines[0]["qid"], "abc");
+        assert_eq!(lines[0]["latency_ms"], 12);
+    }
+
+    #[test]
+    fn level_can_be_changed_and_reset() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let (subscriber, level) = subscriber(LogFormat::Json, Level::INFO, move || writer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("hidden");
+            level.set(LevelFilter::DEBUG).unwrap();
+            assert_eq!(level.current().unwrap(), LevelFilter::DEBUG);
+            tracing::debug!("shown");
+            level.reset().unwrap();
+            assert_eq!(level.current().unwrap(), LevelFilter::INFO);
+            tracing::debug!("hidden again");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let messages: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap()["message"].clone())
+            .collect();
+        assert_eq!(messages, ["shown"]);
+    }
+}
@@ -3,11 +3,17 @@
 //! A backend service that aggregates data from multiple sources to power
 //! application launchers and desktop utilities.
 
-use clap::Parser;
-use datacube::{ApplicationsProvider, CalculatorProvider, Config, ProviderManager, Server};
+use clap::{CommandFactory, Parser};
+use datacube::completions::{self, Shell};
+use datacube::config::{LogFormat, ResultOrder};
+use datacube::daemon::{self, Readiness};
+use datacube::logging::{self, LogLevel};
+use datacube::providers::builtin;
+use datacube::{autostart, display};
+use datacube::{Config, ProviderManager, Server};
 use std::path::PathBuf;
-use tracing::{info, Level};
-use tracing_subscriber::FmtSubscriber;
+use std::sync::Arc;
+use tracing::{error, info, Level};
 
 #[derive(Parser, Debug)]
 #[command(name = "datacube")]
@@ -25,68 +31,186 @@ struct Args {
     #[arg(short, long)]
     debug: bool,
 
-    /// Run in foreground (don't daemonize)
+    /// Log output format (overrides config)
+    #[arg(long, value_enum)]
+    log_format: Option<LogFormat>,
+
+    /// Print a shell completion script and exit
+    #[arg(long, value_enum, value_name = "SHELL")]
+    completions: Option<Shell>,
+
+    /// Start datacube on login: write an XDG autostart entry and exit
+    #[arg(long, group = "autostart", conflicts_with = "uninstall_autostart")]
+    install_autostart: bool,
+
+    /// Remove the XDG autostart entry and exit
+    #[arg(long, group = "autostart")]
+    uninstall_autostart: bool,
+
+    /// Print a commented config file with every option at its default, and
+    /// exit
+    #[arg(long)]
+    print_default_config: bool,
+
+    /// Take over from a datacube already running on the socket
+    #[arg(long)]
+    replace: bool,
+
+    /// Overwrite or remove a customized autostart entry
+    #[arg(long, requires = "autostart")]
+    force: bool,
+
+    /// Stay attached to the terminal instead of daemonizing (for systemd's
+    /// Type=simple, or to see logs)
     #[arg(short, long)]
     foreground: bool,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
+    if let Some(shell) = args.completions {
+        completions::generate(
+            shell,
+            &mut Args::command(),
+            "datacube",
+            &mut std::io::stdout(),
+        )?;
+        return Ok(());
+    }
+
+    if args.print_default_config {
+        print!("{}", Config::DEFAULT_TEMPLATE);
+        return Ok(());
+    }
+
+    if args.install_autostart || args.uninstall_autostart {
+        let path = autostart::autostart_path()
+            .ok_or_else(|| anyhow::anyhow!("no config directory to autostart from"))?;
+        if args.install_autostart {
+            autostart::install(&path, &std::env::current_exe()?, args.force)?;
+            println!("Wrote {}", path.display());
+        } else if autostart::uninstall(&path, &std::env::current_exe()?, args.force)? {
+            println!("Removed {}", path.display());
+        } else {
+            println!("No autostart entry at {}", path.display());
+        }
+        return Ok(());
+    }
+
+    // Load configuration (before logging, since it selects the log format)
+    let mut config: Config =
+        tracing::subscriber::with_default(logging::bootstrap_subscriber(), || {
+            anyhow::Ok(if let Some(config_path) = &args.config {
+                match std::fs::read_to_string(config_path) {
+                    Ok(content) => toml::from_str(&content)?,
+                    Err(e) => {
+                        tracing::warn!("Failed to load config from {:?}: {}", config_path, e);
+                        Config::default()
+                    }
+                }
+            } else {
+                Config::load()
+            })
+        })?;
+
     // Initialize logging
     let log_level = if args.debug {
         Level::DEBUG
     } else {
         Level::INFO
     };
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(log_level)
-        .with_target(false)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)?;
-
-    info!("datacube v{} starting...", env!("CARGO_PKG_VERSION"));
-
-    // Load configuration
-    let mut config = if let Some(config_path) = args.config {
-        match std::fs::read_to_string(&config_path) {
-            Ok(content) => toml::from_str(&content)?,
-            Err(e) => {
-                tracing::warn!("Failed to load config from {:?}: {}", config_path, e);
-                Config::default()
-            }
-        }
-    } else {
-        Config::load()
-    };
+    if let Some(log_format) = args.log_format {
+        config.log_format = log_format;
+    }
+    let log_level = logging::init(config.log_format, log_level)?;
 
     // Override socket path if specified
     if let Some(socket) = args.socket {
         config.socket_path = socket;
     }
+    // The daemon runs from /, so relative paths must be resolved first.
+    config.socket_path = std::path::absolute(&config.socket_path)?;
+    config.pid_file = std::path::absolute(&config.pid_file)?;
+
+    // Refuse to take over a running daemon's socket, while errors still
+    // reach the terminal
+    if !args.replace {
+        daemon::check_pidfile(&config.pid_file)?;
+    }
+
+    // Detach before the runtime starts its threads; forking copies only this
+    // one.
+    let readiness = if args.foreground {
+        Readiness::default()
+    } else {
+        daemon::daemonize()?
+    };
+    let pid_file = config.pid_file.clone();
+    let result = daemon::write_pidfile(&pid_file).and_then(|()| {
+        let result = tokio::runtime::Runtime::new()?.block_on(run(
+            config,
+            log_level,
+            args.replace,
+            readiness.clone(),
+        ));
+        daemon::remove_pidfile(&pid_file);
+        result
+    });
+    // Only reaches the launching process if the server never got to listen.
+    if let Err(e) = &result {
+        readiness.failed(e);
+    }
+    result
+}
+
+/// Register the configured providers and serve until shutdown
+async fn run(
+    config: Config,
+    log_level: LogLevel,
+    replace: bool,
+    readiness: Readiness,
+) -> anyhow::Result<()> {
+    info!("datacube v{} starting...", env!("CARGO_PKG_VERSION"));
+    info!("Display server: {}", display::current());
 
     // Create provider manager and register providers
-    let manager = ProviderManager::new();
+    let manager = ProviderManager::new()
+        .with_min_query_lens(config.providers.min_query_lens())
+        .with_provider_max_results(config.provider_max_results)
+        .with_min_score(config.min_score)
+        .with_aliases(config.aliases.clone());
+    let manager = match config.result_order {
+        ResultOrder::Score => manager,
+        ResultOrder::ProviderGroups => manager.with_provider_groups(config.provider_groups.clone()),
+    };
+    let manager = match &config.empty_query_providers {
+        Some(providers) => manager.with_empty_query_providers(providers.clone()),
+        None => manager,
+    };
 
-    if config.providers.applications.enabled {
-        let extra_dirs = config.providers.applications.extra_dirs.clone();
+    let mut registered = 0;
+    for provider in builtin::configured(Arc::new(config.clone())) {
         manager
-            .register(ApplicationsProvider::with_extra_dirs(extra_dirs))
+            .add_factory(&provider.name, Arc::clone(&provider.factory))
             .await;
+        if !provider.enabled {
+            manager.add_known(provider.info).await;
+            continue;
+        }
+        match manager.register_boxed((provider.factory)()).await {
+            Ok(()) => registered += 1,
+            Err(e) => error!("Failed to register provider: {}", e),
+        }
     }
 
-    if config.providers.calculator.enabled {
-        manager.register(CalculatorProvider::new()).await;
-    }
-
-    info!(
-        "Registered {} providers",
-        manager.list_providers().await.len()
-    );
+    info!("Registered {} providers", registered);
 
     // Create and run server
-    let server = Server::new(config, manager);
+    let server = Server::new(config, manager)
+        .with_replace(replace)
+        .with_log_level(log_level)
+        .with_readiness(readiness);
     server.run().await?;
 
     Ok(())
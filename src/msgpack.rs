@@ -0,0 +1,168 @@
+//! Minimal MessagePack encoder
+//!
+//! Encodes any `serde::Serialize` value by way of `serde_json::Value`, which
+//! covers everything the proto types produce (maps, arrays, strings, numbers
+//! and booleans). Used by the CLI's `--format msgpack` output.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Encode `value` as MessagePack
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> serde_json::Result<Vec<u8>> {
+    let value = serde_json::to_value(value)?;
+    let mut out = Vec::new();
+    encode(&value, &mut out);
+    Ok(out)
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                encode_uint(u, out);
+            } else if let Some(i) = n.as_i64() {
+                encode_int(i, out);
+            } else {
+                out.push(0xcb);
+                out.extend_from_slice(&n.as_f64().unwrap_or(0.0).to_be_bytes());
+            }
+        }
+        Value::String(s) => {
+            let len = s.len();
+            match len {
+                0..=31 => out.push(0xa0 | len as u8),
+                32..=0xff => out.extend_from_slice(&[0xd9, len as u8]),
+                0x100..=0xffff => {
+                    out.push(0xda);
+                    out.extend_from_slice(&(len as u16).to_be_bytes());
+                }
+                _ => {
+                    out.push(0xdb);
+                    out.extend_from_slice(&(len as u32).to_be_bytes());
+                }
+            }
+            out.extend_from_slice(s.as_bytes());
+        }
+        Value::Array(items) => {
+            encode_len(items.len(), 0x90, 0xdc, out);
+            for item in items {
+                encode(item, out);
+            }
+        }
+        Value::Object(map) => {
+            encode_len(map.len(), 0x80, 0xde, out);
+            for (key, value) in map {
+                encode(&Value::from(key.as_str()), out);
+                encode(value, out);
+            }
+        }
+    }
+}
+
+/// Write an array/map header: a fix-type for up to 15 entries, then the
+/// 16-bit (`marker16`) or 32-bit (`marker16 + 1`) form
+fn encode_len(len: usize, fix: u8, marker16: u8, out: &mut Vec<u8>) {
+    if len <= 15 {
+        out.push(fix | len as u8);
+    } else if len <= 0xffff {
+        out.push(marker16);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(marker16 + 1);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn encode_uint(u: u64, out: &mut Vec<u8>) {
+    match u {
+        0..=0x7f => out.push(u as u8),
+        0x80..=0xff => out.extend_from_slice(&[0xcc, u as u8]),
+        0x100..=0xffff => {
+            out.push(0xcd);
+            out.extend_from_slice(&(u as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(u as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xcf);
+            out.extend_from_slice(&u.to_be_bytes());
+        }
+    }
+}
+
+/// Encode a negative integer (non-negative ones go through `encode_uint`)
+fn encode_int(i: i64, out: &mut Vec<u8>) {
+    match i {
+        -32..=-1 => out.push(i as u8),
+        -0x80..=-33 => out.extend_from_slice(&[0xd0, i as u8]),
+        -0x8000..=-0x81 => {
+            out.push(0xd1);
+            out.extend_from_slice(&(i as i16).to_be_bytes());
+        }
+        -0x8000_0000..=-0x8001 => {
+            out.push(0xd2);
+            out.extend_from_slice(&(i as i32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xd3);
+            out.extend_from_slice(&i.to_be_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encoded(value: Value) -> Vec<u8> {
+        to_vec(&value).unwrap()
+    }
+
+    #[test]
+    fn encodes_scalars() {
+        assert_eq!(encoded(json!(null)), [0xc0]);
+        assert_eq!(encoded(json!(true)), [0xc3]);
+        assert_eq!(encoded(json!(5)), [0x05]);
+        assert_eq!(encoded(json!(200)), [0xcc, 200]);
+        assert_eq!(encoded(json!(-1)), [0xff]);
+        assert_eq!(encoded(json!(-200)), [0xd1, 0xff, 0x38]);
+        assert_eq!(encoded(json!(1.5)), [0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(encoded(json!("hi")), [0xa2, b'h', b'i']);
+    }
+
+    #[test]
+    fn encodes_containers() {
+        assert_eq!(encoded(json!([1, 2])), [0x92, 0x01, 0x02]);
+        assert_eq!(encoded(json!({"a": 1})), [0x81, 0xa1, b'a', 0x01]);
+
+        let long: Vec<u8> = (0..20).collect();
+        let bytes = encoded(json!(long));
+        assert_eq!(&bytes[..3], [0xdc, 0x00, 20]);
+        assert_eq!(bytes.len(), 3 + 20);
+    }
+
+    #[test]
+    fn encodes_long_strings() {
+        let s = "x".repeat(40);
+        let bytes = encoded(json!(s));
+        assert_eq!(&bytes[..2], [0xd9, 40]);
+        assert_eq!(bytes.len(), 2 + 40);
+    }
+
+    #[test]
+    fn encodes_proto_items() {
+        let item = crate::proto::Item {
+            text: "Firefox".to_string(),
+            ..Default::default()
+        };
+        let bytes = to_vec(&item).unwrap();
+        // A map with one entry per proto field.
+        assert_eq!(bytes[0], 0x8c);
+    }
+}
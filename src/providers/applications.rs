@@ -2,8 +2,10 @@
 //!
 //! Uses incremental updates for efficient file watching - only the changed
 //! .desktop file is parsed/removed rather than reloading all applications.
+//! Optionally the whole list is also reloaded on an interval, which catches
+//! changes the watcher misses.
 
-use super::{Item, Provider};
+use super::{IconKind, Item, Provider};
 use freedesktop_desktop_entry::DesktopEntry;
 use freedesktop_icons::lookup;
 use fuzzy_matcher::skim::SkimMatcherV2;
@@ -16,8 +18,9 @@ use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::sync::Arc;
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, TryLockError, Weak};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
 use tracing::{debug, error, info, warn};
 
 /// Standard icon sizes to search (largest first)
@@ -40,6 +43,18 @@ impl AppSource {
         }
     }
 
+    /// Determine the source from the keys flatpak and snapd add to the
+    /// desktop files they export, falling back to the file's path
+    fn detect(path: &Path, entry: &DesktopEntry) -> Self {
+        if entry.flatpak().is_some() {
+            AppSource::Flatpak
+        } else if entry.desktop_entry("X-SnapInstanceName").is_some() {
+            AppSource::Snap
+        } else {
+            Self::from_path(path)
+        }
+    }
+
     /// Determine the source based on the .desktop file path
     fn from_path(path: &Path) -> Self {
         let path_str = path.to_string_lossy();
@@ -74,17 +89,26 @@ struct AppEntry {
     keywords: Vec<String>,
     /// Whether this is a terminal app
     terminal: bool,
+    /// Working directory to launch in (`Path=`)
+    working_dir: Option<String>,
     /// Launch count for ranking
     launch_count: u32,
     /// Source of the application (native, flatpak, snap)
     source: AppSource,
 }
 
+/// Application entries by Desktop Entry ID, published as immutable snapshots
+///
+/// Queries clone the inner `Arc` and drop the lock straight away, so they
+/// never wait on a reload: the full loader builds a new map and swaps it in,
+/// and watcher updates copy-on-write with `Arc::make_mut`.
+type AppCache = RwLock<Arc<HashMap<String, AppEntry>>>;
+
 /// Provider for installed applications
 pub struct ApplicationsProvider {
     /// Cached application entries, keyed by Desktop Entry ID for XDG deduplication
     /// Per XDG spec, entries with the same ID from higher-priority directories override lower ones
-    apps: Arc<RwLock<HashMap<String, AppEntry>>>,
+    apps: Arc<AppCache>,
     /// Reverse lookup: path -> Desktop Entry ID (for efficient file watcher updates)
     #[allow(dead_code)]
     path_to_id: Arc<RwLock<HashMap<PathBuf, String>>>,
@@ -96,6 +120,15 @@ pub struct ApplicationsProvider {
     /// Keep watcher alive - dropping it stops watching
     #[allow(dead_code)]
     watcher: Option<RecommendedWatcher>,
+    /// Tie-break priority when sorting results
+    priority: i32,
+    /// How often to reload every application, if at all
+    rescan_interval: Option<Duration>,
+    /// Most typos (edits) a query may have from a name that fuzzy matching
+    /// misses (0: no typo tolerance)
+    max_typos: usize,
+    /// Held for the duration of a full load, so loads never overlap
+    reload_lock: Arc<Mutex<()>>,
 }
 
 impl ApplicationsProvider {
@@ -104,36 +137,51 @@ impl ApplicationsProvider {
     }
 
     pub fn with_extra_dirs(extra_dirs: Vec<PathBuf>) -> Self {
-        let apps = Arc::new(RwLock::new(HashMap::new()));
+        let apps = Arc::new(RwLock::new(Arc::new(HashMap::new())));
         let path_to_id = Arc::new(RwLock::new(HashMap::new()));
 
         // Set up file watching first so changes that happen during the initial
         // load are not missed.
         let watcher = Self::start_watching(Arc::clone(&apps), Arc::clone(&path_to_id), &extra_dirs);
 
-        // Load applications in a background thread so the daemon can bind its
-        // socket and start serving immediately. The initial load - and icon
-        // resolution in particular - is filesystem-heavy and would otherwise
-        // delay startup by seconds, blocking clients (e.g. quickshell) from
-        // searching until the whole index was built.
-        {
-            let apps = Arc::clone(&apps);
-            let path_to_id = Arc::clone(&path_to_id);
-            let extra_dirs = extra_dirs.clone();
-            std::thread::spawn(move || {
-                Self::load_applications_into(&apps, &path_to_id, &extra_dirs);
-            });
-        }
-
+        // Applications are loaded by `warmup`, once the daemon has bound its
+        // socket.
         Self {
             apps,
             path_to_id,
             matcher: SkimMatcherV2::default(),
             extra_dirs,
             watcher,
+            priority: 0,
+            rescan_interval: None,
+            max_typos: 0,
+            reload_lock: Arc::new(Mutex::new(())),
         }
     }
 
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Reload every application this often, in the background (default:
+    /// never; a zero interval also disables it)
+    pub fn with_rescan_interval(mut self, interval: Duration) -> Self {
+        self.rescan_interval = Some(interval).filter(|interval| !interval.is_zero());
+        self
+    }
+
+    /// Also match names within this many edits of the query (insertions,
+    /// deletions or substitutions; a swap of two letters is two), for typos
+    /// fuzzy matching misses such as `fierfox` (default: 0, off)
+    ///
+    /// Short queries get fewer: one edit per three characters.
+    pub fn with_max_typos(mut self, max_typos: usize) -> Self {
+        self.max_typos = max_typos;
+        self
+    }
+
     /// Get directories in XDG precedence order (highest priority first)
     ///
     /// Per the XDG Base Directory Specification:
@@ -145,7 +193,7 @@ impl ApplicationsProvider {
     /// 4. Flatpak system directory (/var/lib/flatpak/exports/share/applications)
     /// 5. Snap directory (/var/lib/snapd/desktop/applications)
     /// 6. Extra directories from config (lowest priority)
-    fn get_directories_in_precedence_order(extra_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    pub(super) fn get_directories_in_precedence_order(extra_dirs: &[PathBuf]) -> Vec<PathBuf> {
         let mut dirs = Vec::new();
         let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
 
@@ -286,11 +334,9 @@ impl ApplicationsProvider {
     fn resolve_icon_path(icon: &str) -> Option<String> {
         // If it's already an absolute path, use it directly
         let icon_path = Path::new(icon);
-        if icon_path.is_absolute() {
-            if icon_path.exists() {
-                return Some(icon.to_string());
-            }
-            // Absolute path but doesn't exist - try theme lookup anyway
+        // (an absolute path that doesn't exist falls through to the theme lookup)
+        if icon_path.is_absolute() && icon_path.exists() {
+            return Some(icon.to_string());
         }
 
         // Try to find SVG first (scalable)
@@ -398,9 +444,7 @@ impl ApplicationsProvider {
         let name = entry.name(locales)?.to_string();
 
         // Skip entries without an exec command (not launchable)
-        if entry.exec().is_none() {
-            return None;
-        }
+        entry.exec()?;
 
         // Get the desktop file ID (filename without extension)
         let id = path
@@ -416,7 +460,7 @@ impl ApplicationsProvider {
         // Icon path is resolved separately via `resolve_entry_icon`. Resolving
         // an icon involves many filesystem lookups, so we keep parsing cheap and
         // resolve icons in the background during the initial bulk load.
-        let source = AppSource::from_path(path);
+        let source = AppSource::detect(path, &entry);
 
         Some(AppEntry {
             id,
@@ -431,6 +475,10 @@ impl ApplicationsProvider {
                 .map(|k| k.into_iter().map(String::from).collect())
                 .unwrap_or_default(),
             terminal: entry.terminal(),
+            working_dir: entry
+                .desktop_entry("Path")
+                .filter(|dir| !dir.is_empty())
+                .map(String::from),
             launch_count: 0,
             source,
         })
@@ -448,7 +496,7 @@ impl ApplicationsProvider {
     /// Add a single desktop entry to the cache, respecting XDG override policy
     /// Only adds if no higher-priority entry with the same ID exists
     fn add_entry(
-        apps: &Arc<RwLock<HashMap<String, AppEntry>>>,
+        apps: &Arc<AppCache>,
         path_to_id: &Arc<RwLock<HashMap<PathBuf, String>>>,
         path: &Path,
         extra_dirs: &[PathBuf],
@@ -458,106 +506,107 @@ impl ApplicationsProvider {
             let id = entry.id.clone();
             let new_priority = Self::get_directory_priority(path, extra_dirs);
 
-            if let (Ok(mut apps_guard), Ok(mut path_guard)) = (apps.write(), path_to_id.write()) {
-                // Check if an entry with this ID already exists
-                if let Some(existing) = apps_guard.get(&id) {
-                    let existing_priority =
-                        Self::get_directory_priority(&existing.path, extra_dirs);
-
-                    // Only replace if new entry has higher priority (lower number)
-                    match (new_priority, existing_priority) {
-                        (Some(new_p), Some(existing_p)) if new_p < existing_p => {
-                            debug!(
-                                "Overriding {} from {:?} (priority {}) with {:?} (priority {})",
-                                entry.name, existing.path, existing_p, path, new_p
-                            );
-                            // Remove old path mapping
-                            path_guard.remove(&existing.path);
-                        }
-                        (Some(new_p), Some(existing_p)) => {
-                            debug!(
-                                "Skipping {} from {:?} (priority {}), higher priority entry exists at {:?} (priority {})",
-                                entry.name, path, new_p, existing.path, existing_p
-                            );
-                            // Still track this path for file watcher purposes
-                            path_guard.insert(path.to_path_buf(), id);
-                            return;
-                        }
-                        _ => {
-                            // If we can't determine priority, use existing behavior (first wins)
-                            debug!(
-                                "Skipping {} from {:?}, entry already exists from {:?}",
-                                entry.name, path, existing.path
-                            );
-                            path_guard.insert(path.to_path_buf(), id);
-                            return;
-                        }
+            let mut apps_lock = write_lock(apps);
+            let apps_guard = Arc::make_mut(&mut apps_lock);
+            let mut path_guard = write_lock(path_to_id);
+            // Check if an entry with this ID already exists
+            if let Some(existing) = apps_guard.get(&id) {
+                let existing_priority = Self::get_directory_priority(&existing.path, extra_dirs);
+
+                // Only replace if new entry has higher priority (lower number)
+                match (new_priority, existing_priority) {
+                    (Some(new_p), Some(existing_p)) if new_p < existing_p => {
+                        debug!(
+                            "Overriding {} from {:?} (priority {}) with {:?} (priority {})",
+                            entry.name, existing.path, existing_p, path, new_p
+                        );
+                        // Remove old path mapping
+                        path_guard.remove(&existing.path);
+                    }
+                    (Some(new_p), Some(existing_p)) => {
+                        debug!(
+                            "Skipping {} from {:?} (priority {}), higher priority entry exists at {:?} (priority {})",
+                            entry.name, path, new_p, existing.path, existing_p
+                        );
+                        // Still track this path for file watcher purposes
+                        path_guard.insert(path.to_path_buf(), id);
+                        return;
+                    }
+                    _ => {
+                        // If we can't determine priority, use existing behavior (first wins)
+                        debug!(
+                            "Skipping {} from {:?}, entry already exists from {:?}",
+                            entry.name, path, existing.path
+                        );
+                        path_guard.insert(path.to_path_buf(), id);
+                        return;
                     }
                 }
-
-                debug!("Adding application: {} from {:?}", entry.name, path);
-                path_guard.insert(path.to_path_buf(), id.clone());
-                apps_guard.insert(id, entry);
             }
+
+            debug!("Adding application: {} from {:?}", entry.name, path);
+            path_guard.insert(path.to_path_buf(), id.clone());
+            apps_guard.insert(id, entry);
         }
     }
 
     /// Remove a single entry from the cache by path
     /// If a lower-priority entry exists with the same ID, it will be promoted
     fn remove_entry(
-        apps: &Arc<RwLock<HashMap<String, AppEntry>>>,
+        apps: &Arc<AppCache>,
         path_to_id: &Arc<RwLock<HashMap<PathBuf, String>>>,
         path: &Path,
         extra_dirs: &[PathBuf],
     ) {
-        if let (Ok(mut apps_guard), Ok(mut path_guard)) = (apps.write(), path_to_id.write()) {
-            if let Some(id) = path_guard.remove(path) {
-                // Only remove from apps if this was the active entry for this ID
-                if let Some(entry) = apps_guard.get(&id) {
-                    if entry.path == path {
-                        let entry_name = entry.name.clone();
-                        apps_guard.remove(&id);
-                        debug!("Removed application: {} from {:?}", entry_name, path);
-
-                        // Look for a lower-priority entry to promote
-                        // This handles the case where a user override is removed and
-                        // the system entry should become active again
-                        let candidates: Vec<_> = path_guard
-                            .iter()
-                            .filter(|(_, entry_id)| *entry_id == &id)
-                            .map(|(p, _)| p.clone())
-                            .collect();
-
-                        if !candidates.is_empty() {
-                            // Find the highest priority candidate
-                            let mut best_path: Option<PathBuf> = None;
-                            let mut best_priority: Option<usize> = None;
-
-                            for candidate_path in candidates {
-                                let priority =
-                                    Self::get_directory_priority(&candidate_path, extra_dirs);
-                                match (priority, best_priority) {
-                                    (Some(p), None) => {
-                                        best_path = Some(candidate_path);
-                                        best_priority = Some(p);
-                                    }
-                                    (Some(p), Some(bp)) if p < bp => {
-                                        best_path = Some(candidate_path);
-                                        best_priority = Some(p);
-                                    }
-                                    _ => {}
+        let mut apps_lock = write_lock(apps);
+        let apps_guard = Arc::make_mut(&mut apps_lock);
+        let mut path_guard = write_lock(path_to_id);
+        if let Some(id) = path_guard.remove(path) {
+            // Only remove from apps if this was the active entry for this ID
+            if let Some(entry) = apps_guard.get(&id) {
+                if entry.path == path {
+                    let entry_name = entry.name.clone();
+                    apps_guard.remove(&id);
+                    debug!("Removed application: {} from {:?}", entry_name, path);
+
+                    // Look for a lower-priority entry to promote
+                    // This handles the case where a user override is removed and
+                    // the system entry should become active again
+                    let candidates: Vec<_> = path_guard
+                        .iter()
+                        .filter(|(_, entry_id)| *entry_id == &id)
+                        .map(|(p, _)| p.clone())
+                        .collect();
+
+                    if !candidates.is_empty() {
+                        // Find the highest priority candidate
+                        let mut best_path: Option<PathBuf> = None;
+                        let mut best_priority: Option<usize> = None;
+
+                        for candidate_path in candidates {
+                            let priority =
+                                Self::get_directory_priority(&candidate_path, extra_dirs);
+                            match (priority, best_priority) {
+                                (Some(p), None) => {
+                                    best_path = Some(candidate_path);
+                                    best_priority = Some(p);
+                                }
+                                (Some(p), Some(bp)) if p < bp => {
+                                    best_path = Some(candidate_path);
+                                    best_priority = Some(p);
                                 }
+                                _ => {}
                             }
+                        }
 
-                            if let Some(promote_path) = best_path {
-                                if let Some(mut entry) = Self::parse_desktop_file(&promote_path) {
-                                    Self::resolve_entry_icon(&mut entry);
-                                    debug!(
-                                        "Promoting {} from {:?} after removal of higher-priority entry",
-                                        entry.name, promote_path
-                                    );
-                                    apps_guard.insert(id, entry);
-                                }
+                        if let Some(promote_path) = best_path {
+                            if let Some(mut entry) = Self::parse_desktop_file(&promote_path) {
+                                Self::resolve_entry_icon(&mut entry);
+                                debug!(
+                                    "Promoting {} from {:?} after removal of higher-priority entry",
+                                    entry.name, promote_path
+                                );
+                                apps_guard.insert(id, entry);
                             }
                         }
                     }
@@ -568,7 +617,7 @@ impl ApplicationsProvider {
 
     /// Update an existing entry (re-parse and potentially update)
     fn update_entry(
-        apps: &Arc<RwLock<HashMap<String, AppEntry>>>,
+        apps: &Arc<AppCache>,
         path_to_id: &Arc<RwLock<HashMap<PathBuf, String>>>,
         path: &Path,
         extra_dirs: &[PathBuf],
@@ -577,36 +626,37 @@ impl ApplicationsProvider {
             Self::resolve_entry_icon(&mut entry);
             let id = entry.id.clone();
 
-            if let (Ok(mut apps_guard), Ok(mut path_guard)) = (apps.write(), path_to_id.write()) {
-                // Check if this path is the active entry for this ID
-                if let Some(existing) = apps_guard.get(&id) {
-                    if existing.path == path {
-                        debug!("Updated application: {} from {:?}", entry.name, path);
-                        apps_guard.insert(id.clone(), entry);
-                        path_guard.insert(path.to_path_buf(), id);
-                        return;
-                    }
+            let mut apps_lock = write_lock(apps);
+            let apps_guard = Arc::make_mut(&mut apps_lock);
+            let mut path_guard = write_lock(path_to_id);
+            // Check if this path is the active entry for this ID
+            if let Some(existing) = apps_guard.get(&id) {
+                if existing.path == path {
+                    debug!("Updated application: {} from {:?}", entry.name, path);
+                    apps_guard.insert(id.clone(), entry);
+                    path_guard.insert(path.to_path_buf(), id);
+                    return;
                 }
+            }
 
-                // Path is not the active entry - just update path_to_id mapping
-                // and check if we should override the existing entry
-                let new_priority = Self::get_directory_priority(path, extra_dirs);
-                let existing_priority = apps_guard
-                    .get(&id)
-                    .and_then(|e| Self::get_directory_priority(&e.path, extra_dirs));
-
-                match (new_priority, existing_priority) {
-                    (Some(new_p), Some(existing_p)) if new_p < existing_p => {
-                        debug!(
-                            "Updated entry now has higher priority, promoting: {} from {:?}",
-                            entry.name, path
-                        );
-                        apps_guard.insert(id.clone(), entry);
-                    }
-                    _ => {}
+            // Path is not the active entry - just update path_to_id mapping
+            // and check if we should override the existing entry
+            let new_priority = Self::get_directory_priority(path, extra_dirs);
+            let existing_priority = apps_guard
+                .get(&id)
+                .and_then(|e| Self::get_directory_priority(&e.path, extra_dirs));
+
+            match (new_priority, existing_priority) {
+                (Some(new_p), Some(existing_p)) if new_p < existing_p => {
+                    debug!(
+                        "Updated entry now has higher priority, promoting: {} from {:?}",
+                        entry.name, path
+                    );
+                    apps_guard.insert(id.clone(), entry);
                 }
-                path_guard.insert(path.to_path_buf(), id);
+                _ => {}
             }
+            path_guard.insert(path.to_path_buf(), id);
         } else {
             // If parsing fails (e.g., now hidden), remove it
             Self::remove_entry(apps, path_to_id, path, extra_dirs);
@@ -628,7 +678,7 @@ impl ApplicationsProvider {
 
     /// Scan a directory for .desktop files and add them to the cache
     fn scan_directory(
-        apps: &Arc<RwLock<HashMap<String, AppEntry>>>,
+        apps: &Arc<AppCache>,
         path_to_id: &Arc<RwLock<HashMap<PathBuf, String>>>,
         dir: &Path,
         extra_dirs: &[PathBuf],
@@ -645,7 +695,7 @@ impl ApplicationsProvider {
 
     /// Start watching application directories for changes with incremental updates
     fn start_watching(
-        apps: Arc<RwLock<HashMap<String, AppEntry>>>,
+        apps: Arc<AppCache>,
         path_to_id: Arc<RwLock<HashMap<PathBuf, String>>>,
         extra_dirs: &[PathBuf],
     ) -> Option<RecommendedWatcher> {
@@ -666,19 +716,11 @@ impl ApplicationsProvider {
                     Ok(event) => {
                         // Check if a new "applications" directory was created (e.g., first flatpak install)
                         for path in &event.paths {
-                            if Self::is_applications_dir(path) {
-                                match event.kind {
-                                    EventKind::Create(_) => {
-                                        info!("New applications directory detected: {:?}", path);
-                                        Self::scan_directory(
-                                            &apps,
-                                            &path_to_id,
-                                            path,
-                                            &extra_dirs_owned,
-                                        );
-                                    }
-                                    _ => {}
-                                }
+                            if Self::is_applications_dir(path)
+                                && matches!(event.kind, EventKind::Create(_))
+                            {
+                                info!("New applications directory detected: {:?}", path);
+                                Self::scan_directory(&apps, &path_to_id, path, &extra_dirs_owned);
                             }
                         }
 
@@ -743,9 +785,7 @@ impl ApplicationsProvider {
                             }
                             EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
                                 // Both paths in event.paths: [old, new]
-                                if event.paths.len() >= 2 {
-                                    let old_path = &event.paths[0];
-                                    let new_path = &event.paths[1];
+                                if let [old_path, new_path, ..] = event.paths.as_slice() {
                                     if Self::is_desktop_file(old_path) {
                                         debug!("Desktop file renamed from: {:?}", old_path);
                                         Self::remove_entry(
@@ -858,17 +898,40 @@ impl ApplicationsProvider {
         }
     }
 
+    /// Load every application unless a load is already running, returning
+    /// whether this one ran
+    fn reload(
+        apps: &Arc<AppCache>,
+        path_to_id: &Arc<RwLock<HashMap<PathBuf, String>>>,
+        extra_dirs: &[PathBuf],
+        reload_lock: &Mutex<()>,
+    ) -> bool {
+        // A panic mid-load poisons the lock; the next load replaces the
+        // whole list anyway.
+        let _guard = match reload_lock.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                debug!("Applications are already being loaded; skipping");
+                return false;
+            }
+        };
+        Self::load_applications_into(apps, path_to_id, extra_dirs);
+        true
+    }
+
     /// Load all desktop entries from XDG directories into the provided cache
     /// Processes directories in XDG precedence order so higher-priority entries override lower ones
     ///
     /// This runs in two phases so applications become searchable as fast as
     /// possible:
-    /// 1. Parse all `.desktop` files (cheap) and publish them - apps are now
-    ///    searchable, just without resolved icon paths.
+    /// 1. Parse all `.desktop` files (cheap), publishing what has been parsed
+    ///    after each directory - apps are searchable as soon as their directory
+    ///    is done, just without resolved icon paths.
     /// 2. Resolve icon paths (filesystem-heavy) in the background and patch them
     ///    into the cache.
     fn load_applications_into(
-        apps: &Arc<RwLock<HashMap<String, AppEntry>>>,
+        apps: &Arc<AppCache>,
         path_to_id: &Arc<RwLock<HashMap<PathBuf, String>>>,
         extra_dirs: &[PathBuf],
     ) {
@@ -887,7 +950,7 @@ impl ApplicationsProvider {
         }
 
         // Phase 1: parse metadata (no icon resolution) - first directory wins for each ID
-        for dir in &ordered_dirs {
+        for (i, dir) in ordered_dirs.iter().enumerate() {
             if let Ok(read_dir) = std::fs::read_dir(dir) {
                 for entry in read_dir.flatten() {
                     let path = entry.path();
@@ -899,26 +962,39 @@ impl ApplicationsProvider {
                             path_map.insert(path.clone(), id.clone());
 
                             // Only insert if no higher-priority entry exists
-                            if !entries.contains_key(&id) {
-                                debug!("Adding {} from {:?}", app.name, path);
-                                entries.insert(id, app);
-                            } else {
-                                debug!(
-                                    "Skipping {} from {:?} - higher priority entry already exists",
-                                    app.name, path
-                                );
+                            match entries.entry(id) {
+                                std::collections::hash_map::Entry::Vacant(slot) => {
+                                    debug!("Adding {} from {:?}", app.name, path);
+                                    slot.insert(app);
+                                }
+                                std::collections::hash_map::Entry::Occupied(_) => {
+                                    debug!(
+                                        "Skipping {} from {:?} - higher priority entry already exists",
+                                        app.name, path
+                                    );
+                                }
                             }
                         }
                     }
                 }
             }
+
+            // Directories are scanned highest priority first, so the entries
+            // so far are already the right ones for their IDs; let queries
+            // see them while the remaining directories are read.
+            if i + 1 < ordered_dirs.len() {
+                *write_lock(apps) = Arc::new(entries.clone());
+            }
         }
 
         let app_count = entries.len();
 
-        // Publish metadata immediately - applications are now searchable.
-        if let (Ok(mut apps_guard), Ok(mut path_guard)) = (apps.write(), path_to_id.write()) {
-            *apps_guard = entries;
+        // Publish the complete metadata - every application is now searchable.
+        // The new map is swapped in whole rather than copied into the old one.
+        {
+            let mut apps_guard = write_lock(apps);
+            let mut path_guard = write_lock(path_to_id);
+            *apps_guard = Arc::new(entries);
             *path_guard = path_map;
         }
 
@@ -928,114 +1004,99 @@ impl ApplicationsProvider {
         );
 
         // Phase 2: resolve icon paths. Snapshot (id, icon) first so the lock is
-        // not held during the filesystem lookups, then patch each entry in.
-        let to_resolve: Vec<(String, String)> = match apps.read() {
-            Ok(guard) => guard
-                .values()
-                .map(|a| (a.id.clone(), a.icon.clone()))
-                .collect(),
-            Err(_) => return,
-        };
+        // not held during the filesystem lookups, then patch them all in at
+        // once (each patch copies the map if a query holds a snapshot).
+        let to_resolve: Vec<(String, String)> = read_lock(apps)
+            .values()
+            .map(|a| (a.id.clone(), a.icon.clone()))
+            .collect();
+        let resolved: Vec<(String, String, String)> = to_resolve
+            .into_iter()
+            .filter_map(|(id, icon)| {
+                let path = Self::resolve_icon_path(&icon)?;
+                Some((id, icon, path))
+            })
+            .collect();
 
-        for (id, icon) in to_resolve {
-            let resolved = Self::resolve_icon_path(&icon);
-            // Skip the write lock entirely if there is nothing to store.
-            if resolved.is_none() {
-                continue;
-            }
-            if let Ok(mut guard) = apps.write() {
-                // The entry may have been replaced/removed by the watcher in the
-                // meantime; only patch it if it still matches.
-                if let Some(app) = guard.get_mut(&id) {
-                    if app.icon == icon && app.icon_path.is_none() {
-                        app.icon_path = resolved;
-                    }
+        let mut apps_lock = write_lock(apps);
+        let apps_guard = Arc::make_mut(&mut apps_lock);
+        for (id, icon, path) in resolved {
+            // The entry may have been replaced/removed by the watcher in the
+            // meantime; only patch it if it still matches.
+            if let Some(app) = apps_guard.get_mut(&id) {
+                if app.icon == icon && app.icon_path.is_none() {
+                    app.icon_path = Some(path);
                 }
             }
         }
+        drop(apps_lock);
 
         info!("Finished resolving icons for {} applications", app_count);
     }
 
-    /// Calculate a search score for an app against a query
-    fn score_app(&self, app: &AppEntry, query: &str) -> Option<i64> {
+    /// The strongest field of an app a query matches, with the fuzzy match
+    /// score there
+    fn score_app(&self, app: &AppEntry, query: &str) -> Option<(MatchField, i64)> {
         let query_lower = query.to_lowercase();
 
-        // Try matching against name first (highest priority)
-        if let Some(score) = self
-            .matcher
-            .fuzzy_match(&app.name.to_lowercase(), &query_lower)
-        {
-            return Some(score + 1000); // Boost name matches
-        }
-
-        // Try desktop entry ID (e.g., "org.mozilla.firefox" for flatpak apps)
-        if let Some(score) = self
-            .matcher
-            .fuzzy_match(&app.id.to_lowercase(), &query_lower)
-        {
-            return Some(score + 750);
-        }
-
-        // Try generic name
-        if let Some(ref generic) = app.generic_name {
-            if let Some(score) = self
-                .matcher
-                .fuzzy_match(&generic.to_lowercase(), &query_lower)
-            {
-                return Some(score + 500);
-            }
-        }
-
-        // Try keywords
-        for keyword in &app.keywords {
-            if let Some(score) = self
-                .matcher
-                .fuzzy_match(&keyword.to_lowercase(), &query_lower)
-            {
-                return Some(score + 250);
-            }
-        }
+        // Desktop entry ids matter for e.g. "org.mozilla.firefox" flatpaks.
+        let fields: [(MatchField, Vec<&str>); 5] = [
+            (MatchField::Name, vec![&app.name]),
+            (MatchField::Id, vec![&app.id]),
+            (
+                MatchField::GenericName,
+                app.generic_name.iter().map(String::as_str).collect(),
+            ),
+            (
+                MatchField::Keyword,
+                app.keywords.iter().map(String::as_str).collect(),
+            ),
+            (
+                MatchField::Comment,
+                app.comment.iter().map(String::as_str).collect(),
+            ),
+        ];
+        fields
+            .into_iter()
+            .find_map(|(field, values)| {
+                let score = values
+                    .into_iter()
+                    .filter_map(|value| {
+                        self.matcher
+                            .fuzzy_match(&value.to_lowercase(), &query_lower)
+                    })
+                    .max()?;
+                Some((field, score))
+            })
+            .or_else(|| {
+                let typos = self.typos(app, &query_lower)?;
+                Some((MatchField::Typo, TYPO_MATCH_SCORE / typos as i64))
+            })
+    }
 
-        // Try comment/description
-        if let Some(ref comment) = app.comment {
-            if let Some(score) = self
-                .matcher
-                .fuzzy_match(&comment.to_lowercase(), &query_lower)
-            {
-                return Some(score);
-            }
+    /// The fewest edits turning the app's name, or a word of it, into
+    /// `query`, if within the typo tolerance
+    fn typos(&self, app: &AppEntry, query: &str) -> Option<usize> {
+        let bound = self.max_typos.min(query.chars().count() / 3);
+        if bound == 0 {
+            return None;
         }
-
-        None
+        let name = app.name.to_lowercase();
+        std::iter::once(name.as_str())
+            .chain(name.split_whitespace())
+            .filter_map(|candidate| levenshtein_within(candidate, query, bound))
+            .min()
     }
 
     fn query_impl(&self, query: &str, max_results: usize) -> Vec<Item> {
-        let apps = match self.apps.read() {
-            Ok(guard) => guard,
-            Err(_) => return Vec::new(),
-        };
+        let apps = Arc::clone(&read_lock(&self.apps));
 
         if query.is_empty() {
             // Return most frequently used apps when query is empty
             let mut items: Vec<_> = apps
                 .values()
                 .take(max_results)
-                .map(|app| {
-                    Item::new(&app.name, "applications")
-                        .with_subtext(
-                            app.comment
-                                .as_deref()
-                                .or(app.generic_name.as_deref())
-                                .unwrap_or(""),
-                        )
-                        .with_icon(&app.icon)
-                        .with_icon_path(app.icon_path.as_deref().unwrap_or(""))
-                        .with_score(app.launch_count as f32 / 100.0)
-                        .with_metadata("desktop_id", &app.id)
-                        .with_metadata("terminal", if app.terminal { "true" } else { "false" })
-                        .with_source(app.source.as_str())
-                })
+                .map(|app| app_item(app, app.launch_count as f32 / 100.0))
                 .collect();
 
             items.sort_by(|a, b| {
@@ -1052,41 +1113,166 @@ impl ApplicationsProvider {
             .filter_map(|app| self.score_app(app, query).map(|score| (app, score)))
             .collect();
 
-        // Sort by score (highest first)
-        scored.sort_by(|a, b| b.1.cmp(&a.1));
+        // Sort by matched field, then fuzzy score (highest first)
+        scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
 
-        // Convert to Items
         scored
             .into_iter()
             .take(max_results)
-            .map(|(app, score)| {
-                // Normalize score to 0.0-1.0 range
-                let normalized_score = (score as f32 / 2000.0).min(1.0).max(0.0);
-
-                Item::new(&app.name, "applications")
-                    .with_subtext(
-                        app.comment
-                            .as_deref()
-                            .or(app.generic_name.as_deref())
-                            .unwrap_or(""),
-                    )
-                    .with_icon(&app.icon)
-                    .with_icon_path(app.icon_path.as_deref().unwrap_or(""))
-                    .with_score(normalized_score)
-                    .with_metadata("desktop_id", &app.id)
-                    .with_metadata("terminal", if app.terminal { "true" } else { "false" })
-                    .with_source(app.source.as_str())
-            })
+            .map(|(app, (field, score))| app_item(app, match_score(field, score)))
             .collect()
     }
 }
 
+/// Reload every application each `interval` for as long as the provider
+/// exists
+async fn rescan_loop(
+    apps: Weak<AppCache>,
+    path_to_id: Weak<RwLock<HashMap<PathBuf, String>>>,
+    extra_dirs: Vec<PathBuf>,
+    reload_lock: Arc<Mutex<()>>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        let (Some(apps), Some(path_to_id)) = (apps.upgrade(), path_to_id.upgrade()) else {
+            return;
+        };
+        let extra_dirs = extra_dirs.clone();
+        let reload_lock = Arc::clone(&reload_lock);
+        let rescan = tokio::task::spawn_blocking(move || {
+            ApplicationsProvider::reload(&apps, &path_to_id, &extra_dirs, &reload_lock)
+        });
+        match rescan.await {
+            Ok(true) => debug!("Rescanned applications"),
+            Ok(false) => {}
+            Err(e) => error!("Rescanning applications failed: {}", e),
+        }
+    }
+}
+
 impl Default for ApplicationsProvider {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Fields of a desktop entry a query can match, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchField {
+    /// A name within the typo tolerance, which fuzzy matching missed
+    Typo,
+    Comment,
+    Keyword,
+    GenericName,
+    Id,
+    Name,
+}
+
+/// Fuzzy match score at which a match fills half its field's band
+const HALF_BAND_SCORE: f32 = 100.0;
+
+/// Fuzzy match score a name one typo from the query counts as, divided by
+/// the number of typos; typo matches have the weakest band to themselves
+const TYPO_MATCH_SCORE: i64 = 50;
+
+/// The Levenshtein distance between `a` and `b`, if it is at most `bound`
+///
+/// Gives up as soon as every alignment so far costs more than `bound`.
+fn levenshtein_within(a: &str, b: &str, bound: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > bound {
+        return None;
+    }
+    // previous[j]: edits turning the first i chars of `a` into the first j
+    // of `b`
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitute = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitute.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        // A row's minimum never decreases in the next row.
+        if current.iter().all(|&d| d > bound) {
+            return None;
+        }
+        previous = current;
+    }
+    Some(previous[b.len()]).filter(|&d| d <= bound)
+}
+
+/// Map a match to 0.0 - 1.0
+///
+/// Each field gets an equal band, stronger fields higher, so any name match
+/// outranks any keyword match; the fuzzy score places the match within its
+/// band without reaching the next one.
+fn match_score(field: MatchField, score: i64) -> f32 {
+    let bands = MatchField::Name as usize + 1;
+    let score = score.max(0) as f32;
+    let within = score / (score + HALF_BAND_SCORE);
+    (field as usize as f32 + within) / bands as f32
+}
+
+/// The result item for an application
+///
+/// The metadata is what a frontend needs to launch it: the desktop id and
+/// file, whether it runs in a terminal, and the working directory, if any.
+fn app_item(app: &AppEntry, score: f32) -> Item {
+    let mut item = Item::new(&app.name, "applications")
+        .with_stable_id(&app.id)
+        .with_subtext(
+            app.comment
+                .as_deref()
+                .or(app.generic_name.as_deref())
+                .unwrap_or(""),
+        )
+        .with_icon(&app.icon)
+        .with_icon_kind(icon_kind(&app.icon))
+        .with_icon_path(app.icon_path.as_deref().unwrap_or(""))
+        .with_score(score)
+        .with_metadata("desktop_id", &app.id)
+        .with_metadata("desktop_file", app.path.to_string_lossy())
+        .with_metadata("terminal", if app.terminal { "true" } else { "false" })
+        .with_metadata("source", app.source.as_str())
+        .with_source(app.source.as_str());
+    if let Some(dir) = &app.working_dir {
+        item = item.with_metadata("working_dir", dir);
+    }
+    item
+}
+
+/// Desktop entries give either a theme icon name or an absolute path
+fn icon_kind(icon: &str) -> IconKind {
+    if icon.contains('/') {
+        IconKind::AbsolutePath
+    } else {
+        IconKind::ThemedName
+    }
+}
+
+/// Lock the cache for reading, recovering it if a panic poisoned the lock
+///
+/// Every cache update leaves the maps consistent, so their contents are still
+/// usable after a panic; refusing them would disable search until restart.
+fn read_lock<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| {
+        warn!("Application cache lock was poisoned by a panic; recovering");
+        lock.clear_poison();
+        e.into_inner()
+    })
+}
+
+/// Lock the cache for writing, recovering it like [`read_lock`]
+fn write_lock<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| {
+        warn!("Application cache lock was poisoned by a panic; recovering");
+        lock.clear_poison();
+        e.into_inner()
+    })
+}
+
 impl Provider for ApplicationsProvider {
     fn name(&self) -> &str {
         "applications"
@@ -1096,6 +1282,42 @@ impl Provider for ApplicationsProvider {
         "Search installed applications"
     }
 
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn spawn_background(&self, handle: tokio::runtime::Handle) {
+        // The first load is warmup's; rescans follow it at the interval.
+        if let Some(interval) = self.rescan_interval {
+            handle.spawn(rescan_loop(
+                Arc::downgrade(&self.apps),
+                Arc::downgrade(&self.path_to_id),
+                self.extra_dirs.clone(),
+                Arc::clone(&self.reload_lock),
+                interval,
+            ));
+        }
+    }
+
+    /// Load applications off the async runtime. The initial load - and icon
+    /// resolution in particular - is filesystem-heavy and would otherwise delay
+    /// startup by seconds, blocking clients (e.g. quickshell) from searching
+    /// until the whole index was built.
+    fn warmup(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        let apps = Arc::clone(&self.apps);
+        let path_to_id = Arc::clone(&self.path_to_id);
+        let extra_dirs = self.extra_dirs.clone();
+        let reload_lock = Arc::clone(&self.reload_lock);
+        Box::pin(async move {
+            let load = tokio::task::spawn_blocking(move || {
+                Self::reload(&apps, &path_to_id, &extra_dirs, &reload_lock);
+            });
+            if let Err(e) = load.await {
+                error!("Loading applications failed: {}", e);
+            }
+        })
+    }
+
     fn query(
         &self,
         query: &str,
@@ -1109,32 +1331,7 @@ impl Provider for ApplicationsProvider {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-
-    /// A self-cleaning temporary directory (avoids pulling in a dev-dependency).
-    struct TempDir {
-        path: PathBuf,
-    }
-
-    impl TempDir {
-        fn new() -> Self {
-            let path = std::env::temp_dir().join(format!("datacube-test-{}", uuid::Uuid::new_v4()));
-            fs::create_dir_all(&path).unwrap();
-            Self { path }
-        }
-
-        fn write(&self, name: &str, contents: &str) -> PathBuf {
-            let p = self.path.join(name);
-            fs::write(&p, contents).unwrap();
-            p
-        }
-    }
-
-    impl Drop for TempDir {
-        fn drop(&mut self) {
-            let _ = fs::remove_dir_all(&self.path);
-        }
-    }
+    use crate::providers::testing::{texts, TempDir};
 
     fn make_entry(id: &str, name: &str) -> AppEntry {
         AppEntry {
@@ -1147,6 +1344,7 @@ mod tests {
             icon_path: None,
             keywords: Vec::new(),
             terminal: false,
+            working_dir: None,
             launch_count: 0,
             source: AppSource::Native,
         }
@@ -1158,11 +1356,15 @@ mod tests {
         let map: HashMap<String, AppEntry> =
             entries.into_iter().map(|e| (e.id.clone(), e)).collect();
         ApplicationsProvider {
-            apps: Arc::new(RwLock::new(map)),
+            apps: Arc::new(RwLock::new(Arc::new(map))),
             path_to_id: Arc::new(RwLock::new(HashMap::new())),
             matcher: SkimMatcherV2::default(),
             extra_dirs: Vec::new(),
             watcher: None,
+            priority: 0,
+            rescan_interval: None,
+            max_typos: 0,
+            reload_lock: Arc::new(Mutex::new(())),
         }
     }
 
@@ -1184,6 +1386,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn app_source_from_desktop_entry_keys() {
+        let dir = TempDir::new();
+        let entry = |name: &str, extra: &str| {
+            let path = dir.write(
+                name,
+                &format!(
+                    "[Desktop Entry]\nType=Application\nName=X\nExec=x\n{}",
+                    extra
+                ),
+            );
+            ApplicationsProvider::parse_desktop_file(&path).expect("should parse")
+        };
+
+        assert_eq!(
+            entry("org.x.desktop", "X-Flatpak=org.x\n").source,
+            AppSource::Flatpak
+        );
+        assert_eq!(
+            entry("x_x.desktop", "X-SnapInstanceName=x\n").source,
+            AppSource::Snap
+        );
+        assert_eq!(entry("plain.desktop", "").source, AppSource::Native);
+    }
+
     #[test]
     fn app_source_as_str() {
         assert_eq!(AppSource::Native.as_str(), "native");
@@ -1228,10 +1455,27 @@ mod tests {
         assert_eq!(entry.icon, "firefox");
         assert!(entry.keywords.iter().any(|k| k == "browser"));
         assert!(!entry.terminal);
+        assert_eq!(entry.working_dir, None);
         // Icon resolution is deferred - parse leaves it unset.
         assert!(entry.icon_path.is_none());
     }
 
+    #[test]
+    fn working_directory_is_passed_on_in_metadata() {
+        let dir = TempDir::new();
+        let path = dir.write(
+            "game.desktop",
+            "[Desktop Entry]\nType=Application\nName=Game\nExec=./run.sh\nPath=/opt/game\n",
+        );
+        let entry = ApplicationsProvider::parse_desktop_file(&path).expect("should parse");
+        assert_eq!(entry.working_dir.as_deref(), Some("/opt/game"));
+
+        let item = app_item(&entry, 1.0);
+        assert_eq!(item.metadata["working_dir"], "/opt/game");
+        let item = app_item(&make_entry("x", "X"), 1.0);
+        assert!(!item.metadata.contains_key("working_dir"));
+    }
+
     #[test]
     fn parse_desktop_file_skips_nodisplay_and_no_exec() {
         let dir = TempDir::new();
@@ -1258,15 +1502,70 @@ mod tests {
             "[Desktop Entry]\nType=Application\nName=Datacube Unit Test App\nExec=/bin/true\nIcon=x\n",
         );
 
-        let apps = Arc::new(RwLock::new(HashMap::new()));
+        let apps = Arc::new(RwLock::new(Arc::new(HashMap::new())));
         let path_to_id = Arc::new(RwLock::new(HashMap::new()));
-        ApplicationsProvider::load_applications_into(&apps, &path_to_id, &[dir.path.clone()]);
+        ApplicationsProvider::load_applications_into(
+            &apps,
+            &path_to_id,
+            std::slice::from_ref(&dir.path),
+        );
 
         let guard = apps.read().unwrap();
         let entry = guard.get(unique).expect("temp app should be loaded");
         assert_eq!(entry.name, "Datacube Unit Test App");
     }
 
+    #[test]
+    fn reloads_never_overlap() {
+        let dir = TempDir::new();
+        let unique = "datacube-unit-test-rescan-xyz";
+        dir.write(
+            &format!("{unique}.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Rescanned App\nExec=/bin/true\n",
+        );
+        let apps = Arc::new(RwLock::new(Arc::new(HashMap::new())));
+        let path_to_id = Arc::new(RwLock::new(HashMap::new()));
+        let extra_dirs = std::slice::from_ref(&dir.path);
+        let reload_lock = Mutex::new(());
+
+        // A load already in progress makes another one a no-op.
+        let held = reload_lock.lock().unwrap();
+        assert!(!ApplicationsProvider::reload(
+            &apps,
+            &path_to_id,
+            extra_dirs,
+            &reload_lock
+        ));
+        assert!(!apps.read().unwrap().contains_key(unique));
+        drop(held);
+
+        assert!(ApplicationsProvider::reload(
+            &apps,
+            &path_to_id,
+            extra_dirs,
+            &reload_lock
+        ));
+        assert!(apps.read().unwrap().contains_key(unique));
+    }
+
+    #[test]
+    fn zero_rescan_interval_disables_rescans() {
+        let provider = provider_with(Vec::new());
+        assert_eq!(
+            provider
+                .with_rescan_interval(Duration::from_secs(60))
+                .rescan_interval,
+            Some(Duration::from_secs(60))
+        );
+        let provider = provider_with(Vec::new());
+        assert_eq!(
+            provider
+                .with_rescan_interval(Duration::ZERO)
+                .rescan_interval,
+            None
+        );
+    }
+
     #[test]
     fn query_matches_by_name() {
         let provider = provider_with(vec![
@@ -1281,6 +1580,34 @@ mod tests {
         assert_eq!(results[0].provider, "applications");
     }
 
+    #[test]
+    fn snapshots_are_unaffected_by_later_updates() {
+        let provider = provider_with(vec![make_entry("firefox", "Firefox")]);
+        let snapshot = Arc::clone(&read_lock(&provider.apps));
+
+        // Updating while a snapshot is held copies rather than blocking.
+        Arc::make_mut(&mut write_lock(&provider.apps)).clear();
+
+        assert_eq!(snapshot.len(), 1);
+        assert!(provider.query_impl("firefox", 10).is_empty());
+    }
+
+    #[test]
+    fn query_survives_a_poisoned_cache_lock() {
+        let provider = provider_with(vec![make_entry("firefox", "Firefox")]);
+        let apps = Arc::clone(&provider.apps);
+        let _ = std::thread::spawn(move || {
+            let _guard = apps.write().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(provider.apps.is_poisoned());
+
+        let results = provider.query_impl("firefox", 10);
+        assert_eq!(results[0].text, "Firefox");
+        assert!(!provider.apps.is_poisoned());
+    }
+
     #[test]
     fn query_matches_by_id() {
         let mut entry = make_entry("org.mozilla.firefox", "Firefox");
@@ -1290,6 +1617,103 @@ mod tests {
         let results = provider.query_impl("mozilla", 10);
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].text, "Firefox");
+        assert_eq!(results[0].metadata["source"], "flatpak");
+        assert_eq!(
+            results[0].metadata["desktop_file"],
+            "/usr/share/applications/org.mozilla.firefox.desktop"
+        );
+    }
+
+    #[test]
+    fn name_matches_outrank_keyword_matches() {
+        // A weak, scattered name match against a strong, exact keyword match
+        let mut editor = make_entry("gedit", "Gedit");
+        editor.keywords = vec!["txt".to_string()];
+        let mut terminal = make_entry("term", "Terminal Tool Xyz Tester");
+        terminal.keywords = vec!["shell".to_string()];
+        let provider = provider_with(vec![editor, terminal]);
+
+        let results = provider.query_impl("txt", 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].text, "Terminal Tool Xyz Tester");
+        assert_eq!(results[1].text, "Gedit");
+        let by_name = match_score(MatchField::Name, 0);
+        let by_keyword = match_score(MatchField::Keyword, i64::MAX);
+        assert!(by_name >= by_keyword);
+        for item in &results {
+            assert!((0.0..1.0).contains(&item.score));
+        }
+    }
+
+    #[test]
+    fn scores_order_by_field_then_fuzzy_score() {
+        let fields = [
+            MatchField::Typo,
+            MatchField::Comment,
+            MatchField::Keyword,
+            MatchField::GenericName,
+            MatchField::Id,
+            MatchField::Name,
+        ];
+        let scores: Vec<f32> = fields
+            .iter()
+            .flat_map(|&field| [0, 50, 10_000].map(|score| match_score(field, score)))
+            .collect();
+        assert!(
+            scores.windows(2).all(|pair| pair[0] < pair[1]),
+            "{scores:?}"
+        );
+        assert_eq!(scores[0], 0.0);
+        assert!(*scores.last().unwrap() < 1.0);
+    }
+
+    #[test]
+    fn levenshtein_distance_is_bounded() {
+        assert_eq!(levenshtein_within("firefox", "firefox", 2), Some(0));
+        assert_eq!(levenshtein_within("firefox", "firefix", 2), Some(1));
+        assert_eq!(levenshtein_within("firefox", "firfox", 2), Some(1));
+        assert_eq!(levenshtein_within("firefox", "fireefox", 2), Some(1));
+        // A transposition is two edits
+        assert_eq!(levenshtein_within("firefox", "fierfox", 2), Some(2));
+        assert_eq!(levenshtein_within("firefox", "fierfox", 1), None);
+        assert_eq!(levenshtein_within("firefox", "chromium", 2), None);
+        assert_eq!(levenshtein_within("gimp", "gimp-2.10", 2), None);
+        assert_eq!(levenshtein_within("", "ab", 2), Some(2));
+    }
+
+    #[test]
+    fn typos_match_names_when_allowed() {
+        let provider = provider_with(vec![
+            make_entry("firefox", "Firefox Web Browser"),
+            make_entry("files", "Files"),
+            make_entry("vim", "Vim"),
+        ]);
+        // Fuzzy matching misses the swap
+        assert!(provider.query_impl("fierfox", 10).is_empty());
+
+        let provider = provider.with_max_typos(2);
+        let swapped = provider.query_impl("fierfox", 10);
+        assert_eq!(swapped.len(), 1);
+        assert_eq!(swapped[0].text, "Firefox Web Browser");
+        let substituted = provider.query_impl("firefix", 10);
+        assert_eq!(substituted[0].text, "Firefox Web Browser");
+
+        // Fewer typos score higher, but below any real match
+        assert!(substituted[0].score > swapped[0].score);
+        assert!(substituted[0].score < match_score(MatchField::Comment, 0));
+
+        // Short queries allow fewer typos: "vum" is one edit from "vim"
+        assert_eq!(provider.query_impl("vum", 10)[0].text, "Vim");
+        assert!(provider.query_impl("vxx", 10).is_empty());
+
+        // A weak fuzzy name match still beats a close typo
+        let provider = provider_with(vec![
+            make_entry("thunderbird", "Thunderbird"),
+            make_entry("tbh", "Tbh"),
+        ])
+        .with_max_typos(2);
+        let results = provider.query_impl("tbd", 10);
+        assert_eq!(texts(&results), ["Thunderbird", "Tbh"]);
     }
 
     #[test]
@@ -1309,4 +1733,25 @@ mod tests {
         assert_eq!(provider.query_impl("", 10).len(), 3);
         assert_eq!(provider.query_impl("", 2).len(), 2);
     }
+
+    #[test]
+    fn query_ids_are_stable_across_queries() {
+        let provider = provider_with(vec![make_entry("firefox", "Firefox")]);
+        let first = provider.query_impl("fir", 10);
+        let second = provider.query_impl("firefox", 10);
+        assert_eq!(first[0].id, second[0].id);
+        assert_eq!(first[0].id, provider.query_impl("", 10)[0].id);
+    }
+
+    #[test]
+    fn icon_kind_follows_the_desktop_entry_icon() {
+        let mut custom = make_entry("tool", "Tool");
+        custom.icon = "/opt/tool/tool.png".to_string();
+        let provider = provider_with(vec![make_entry("firefox", "Firefox"), custom]);
+
+        let items = provider.query_impl("firefox", 10);
+        assert_eq!(items[0].icon_kind, IconKind::ThemedName);
+        let items = provider.query_impl("tool", 10);
+        assert_eq!(items[0].icon_kind, IconKind::AbsolutePath);
+    }
 }
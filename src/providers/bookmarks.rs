@@ -0,0 +1,519 @@
+//! Bookmarks provider - searches Firefox and Chromium bookmarks
+//!
+//! Chromium keeps bookmarks in a JSON `Bookmarks` file in the profile
+//! directory. Firefox keeps them in `places.sqlite`, which is read with the
+//! `sqlite3` command-line tool (rather than linking SQLite into the daemon),
+//! opened read-only with `immutable=1` so a running Firefox is never blocked.
+//! Immutable mode ignores the write-ahead log, so bookmarks Firefox hasn't
+//! checkpointed yet appear a little later.
+//!
+//! Each source is re-read when its file's modification time changes.
+
+use super::{Item, Provider};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+/// Bookmarks with a URL, excluding Firefox's smart folders (`place:` URLs)
+const FIREFOX_QUERY: &str = "SELECT b.title AS title, p.url AS url \
+     FROM moz_bookmarks b JOIN moz_places p ON p.id = b.fk \
+     WHERE b.type = 1 AND p.url NOT LIKE 'place:%'";
+
+/// Where to read bookmarks from
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookmarkSource {
+    /// A Firefox profile directory (containing `places.sqlite`)
+    Firefox(PathBuf),
+    /// A Chromium-family profile directory (containing `Bookmarks`)
+    Chromium(PathBuf),
+}
+
+impl BookmarkSource {
+    fn browser(&self) -> &'static str {
+        match self {
+            BookmarkSource::Firefox(_) => "firefox",
+            BookmarkSource::Chromium(_) => "chromium",
+        }
+    }
+
+    /// The file holding this source's bookmarks
+    fn file(&self) -> PathBuf {
+        match self {
+            BookmarkSource::Firefox(profile) => profile.join("places.sqlite"),
+            BookmarkSource::Chromium(profile) => profile.join("Bookmarks"),
+        }
+    }
+
+    fn load(&self) -> anyhow::Result<Vec<Bookmark>> {
+        let file = self.file();
+        let mut bookmarks = match self {
+            BookmarkSource::Firefox(_) => load_firefox(&file)?,
+            BookmarkSource::Chromium(_) => parse_chromium(&std::fs::read(&file)?)?,
+        };
+        for bookmark in &mut bookmarks {
+            bookmark.browser = self.browser();
+        }
+        Ok(bookmarks)
+    }
+
+    /// The default Firefox profile, from `~/.mozilla/firefox/profiles.ini`
+    pub fn default_firefox() -> Option<Self> {
+        let root = dirs::home_dir()?.join(".mozilla/firefox");
+        let ini = std::fs::read_to_string(root.join("profiles.ini")).ok()?;
+        let profile = default_firefox_profile(&ini)?;
+        let path = if profile.is_absolute() {
+            profile
+        } else {
+            root.join(profile)
+        };
+        Some(BookmarkSource::Firefox(path))
+    }
+
+    /// The first Chromium-family default profile that has bookmarks
+    pub fn default_chromium() -> Option<Self> {
+        let config = dirs::config_dir()?;
+        ["chromium", "google-chrome", "BraveSoftware/Brave-Browser"]
+            .iter()
+            .map(|dir| config.join(dir).join("Default"))
+            .find(|profile| profile.join("Bookmarks").is_file())
+            .map(BookmarkSource::Chromium)
+    }
+}
+
+/// A single bookmark
+#[derive(Debug, Clone, PartialEq)]
+struct Bookmark {
+    title: String,
+    url: String,
+    /// Folder path, e.g. "Bookmarks bar/Work" (Chromium only)
+    folder: Option<String>,
+    browser: &'static str,
+}
+
+/// Loaded bookmarks per source, keyed by the mtime they were read at
+struct Cache {
+    mtimes: Vec<Option<SystemTime>>,
+    bookmarks: Vec<Vec<Bookmark>>,
+}
+
+/// Provider for browser bookmarks
+pub struct BookmarksProvider {
+    sources: Vec<BookmarkSource>,
+    prefix: Option<String>,
+    priority: i32,
+    cache: Mutex<Cache>,
+    matcher: SkimMatcherV2,
+}
+
+impl BookmarksProvider {
+    pub fn new(sources: Vec<BookmarkSource>) -> Self {
+        let count = sources.len();
+        Self {
+            sources,
+            prefix: None,
+            priority: 0,
+            cache: Mutex::new(Cache {
+                mtimes: vec![None; count],
+                bookmarks: vec![Vec::new(); count],
+            }),
+            matcher: SkimMatcherV2::default(),
+        }
+    }
+
+    /// Only answer queries starting with `prefix`; an empty query after the
+    /// prefix then lists all bookmarks
+    pub fn with_prefix(mut self, prefix: Option<String>) -> Self {
+        self.prefix = prefix.filter(|p| !p.is_empty());
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// All bookmarks, re-reading any source whose file changed
+    ///
+    /// Sources are read on the blocking pool (Firefox's through sqlite3),
+    /// off the async workers.
+    async fn bookmarks(&self) -> Vec<Bookmark> {
+        let changed: Vec<_> = {
+            let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            self.sources
+                .iter()
+                .enumerate()
+                .filter_map(|(i, source)| {
+                    let mtime = std::fs::metadata(source.file())
+                        .and_then(|m| m.modified())
+                        .ok();
+                    (mtime != cache.mtimes[i]).then(|| (i, source.clone(), mtime))
+                })
+                .collect()
+        };
+
+        for (i, source, mtime) in changed {
+            let bookmarks = match mtime {
+                Some(_) => {
+                    let loading = source.clone();
+                    match tokio::task::spawn_blocking(move || loading.load()).await {
+                        Ok(Ok(bookmarks)) => {
+                            debug!("Loaded {} bookmarks from {:?}", bookmarks.len(), source);
+                            bookmarks
+                        }
+                        Ok(Err(e)) => {
+                            warn!("Failed to read bookmarks from {:?}: {}", source, e);
+                            Vec::new()
+                        }
+                        Err(e) => {
+                            warn!("Reading bookmarks from {:?} failed: {}", source, e);
+                            Vec::new()
+                        }
+                    }
+                }
+                None => Vec::new(),
+            };
+            // Each source's entry is replaced wholesale, never left
+            // half-updated.
+            let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            cache.bookmarks[i] = bookmarks;
+            cache.mtimes[i] = mtime;
+        }
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .bookmarks
+            .concat()
+    }
+
+    fn query_impl(&self, bookmarks: Vec<Bookmark>, query: &str, max_results: usize) -> Vec<Item> {
+        let query = match &self.prefix {
+            Some(prefix) => match query.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.trim(),
+                None => return Vec::new(),
+            },
+            None => query.trim(),
+        };
+        // Without a prefix an empty query belongs to the applications list.
+        if query.is_empty() && self.prefix.is_none() {
+            return Vec::new();
+        }
+
+        let mut scored: Vec<(Bookmark, i64)> = bookmarks
+            .into_iter()
+            .filter_map(|bookmark| {
+                if query.is_empty() {
+                    return Some((bookmark, 0));
+                }
+                let by_title = self.matcher.fuzzy_match(&bookmark.title, query);
+                let by_url = self.matcher.fuzzy_match(&bookmark.url, query);
+                let score = by_title.max(by_url)?;
+                Some((bookmark, score))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.title.cmp(&b.0.title)));
+
+        scored
+            .into_iter()
+            .take(max_results)
+            .map(|(bookmark, score)| {
+                let score = if query.is_empty() {
+                    0.5
+                } else {
+                    (score as f32 / 2000.0).clamp(0.0, 1.0)
+                };
+                bookmark_item(bookmark).with_score(score)
+            })
+            .collect()
+    }
+}
+
+impl Provider for BookmarksProvider {
+    fn name(&self) -> &str {
+        "bookmarks"
+    }
+
+    fn description(&self) -> &str {
+        "Browser bookmarks"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let query = query.to_string();
+        Box::pin(async move {
+            let bookmarks = self.bookmarks().await;
+            self.query_impl(bookmarks, &query, max_results)
+        })
+    }
+}
+
+fn bookmark_item(bookmark: Bookmark) -> Item {
+    let title = if bookmark.title.is_empty() {
+        bookmark.url.clone()
+    } else {
+        bookmark.title
+    };
+    let mut item = Item::new(title, "bookmarks")
+        .with_stable_id(format!("{}\0{}", bookmark.browser, bookmark.url))
+        .with_subtext(&bookmark.url)
+        .with_icon("user-bookmarks")
+        .with_source(bookmark.browser)
+        .with_metadata("url", &bookmark.url);
+    if let Some(folder) = bookmark.folder {
+        item = item.with_metadata("folder", folder);
+    }
+    item
+}
+
+/// Read bookmarks from a Firefox `places.sqlite` with the sqlite3 CLI
+fn load_firefox(places: &Path) -> anyhow::Result<Vec<Bookmark>> {
+    let output = std::process::Command::new("sqlite3")
+        .args(["-readonly", "-json"])
+        .arg(immutable_uri(places))
+        .arg(FIREFOX_QUERY)
+        .output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "sqlite3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse_firefox(&output.stdout)
+}
+
+/// A SQLite URI opening `path` read-only without taking any locks
+fn immutable_uri(path: &Path) -> String {
+    let path = path
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    format!("file:{}?mode=ro&immutable=1", path)
+}
+
+/// Parse `sqlite3 -json` output (empty when there are no rows)
+fn parse_firefox(output: &[u8]) -> anyhow::Result<Vec<Bookmark>> {
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    let rows: Vec<serde_json::Value> = serde_json::from_slice(output)?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            Some(Bookmark {
+                title: row.get("title")?.as_str().unwrap_or("").to_string(),
+                url: row.get("url")?.as_str()?.to_string(),
+                folder: None,
+                browser: "firefox",
+            })
+        })
+        .collect())
+}
+
+/// Parse a Chromium `Bookmarks` file
+fn parse_chromium(data: &[u8]) -> anyhow::Result<Vec<Bookmark>> {
+    let json: serde_json::Value = serde_json::from_slice(data)?;
+    let roots = json
+        .get("roots")
+        .and_then(|r| r.as_object())
+        .ok_or_else(|| anyhow::anyhow!("bookmarks file has no 'roots' object"))?;
+
+    let mut bookmarks = Vec::new();
+    for root in roots.values() {
+        collect_chromium(root, None, &mut bookmarks);
+    }
+    Ok(bookmarks)
+}
+
+fn collect_chromium(node: &serde_json::Value, folder: Option<&str>, out: &mut Vec<Bookmark>) {
+    let name = node.get("name").and_then(|n| n.as_str()).unwrap_or("");
+    match node.get("type").and_then(|t| t.as_str()) {
+        Some("url") => {
+            if let Some(url) = node.get("url").and_then(|u| u.as_str()) {
+                out.push(Bookmark {
+                    title: name.to_string(),
+                    url: url.to_string(),
+                    folder: folder.map(String::from),
+                    browser: "chromium",
+                });
+            }
+        }
+        Some("folder") => {
+            let path = match folder {
+                Some(parent) => format!("{}/{}", parent, name),
+                None => name.to_string(),
+            };
+            for child in node
+                .get("children")
+                .and_then(|c| c.as_array())
+                .into_iter()
+                .flatten()
+            {
+                collect_chromium(child, Some(&path), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The default profile's path from a Firefox `profiles.ini`
+///
+/// Newer Firefox marks the default per install (`[Install...]` `Default=`);
+/// older versions mark a `[Profile..]` section with `Default=1`.
+fn default_firefox_profile(ini: &str) -> Option<PathBuf> {
+    let mut install_default = None;
+    let mut flagged = None;
+    let mut first = None;
+
+    let mut section = "";
+    let mut path = None;
+    let mut is_default = false;
+    let mut finish = |path: Option<&str>, is_default: bool| {
+        if let Some(path) = path {
+            first.get_or_insert_with(|| path.to_string());
+            if is_default {
+                flagged.get_or_insert_with(|| path.to_string());
+            }
+        }
+    };
+
+    for line in ini.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if section.starts_with("Profile") {
+                finish(path.take(), is_default);
+            }
+            section = name;
+            is_default = false;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        match (section, key.trim()) {
+            (s, "Default") if s.starts_with("Install") => {
+                install_default.get_or_insert_with(|| value.trim().to_string());
+            }
+            (s, "Path") if s.starts_with("Profile") => path = Some(value.trim()),
+            (s, "Default") if s.starts_with("Profile") => is_default = value.trim() == "1",
+            _ => {}
+        }
+    }
+    if section.starts_with("Profile") {
+        finish(path, is_default);
+    }
+
+    install_default.or(flagged).or(first).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing::TempDir;
+
+    const CHROMIUM_BOOKMARKS: &str = r#"{
+        "roots": {
+            "bookmark_bar": {
+                "type": "folder", "name": "Bookmarks bar",
+                "children": [
+                    {"type": "url", "name": "Rust docs", "url": "https://doc.rust-lang.org/"},
+                    {"type": "folder", "name": "Work", "children": [
+                        {"type": "url", "name": "Tracker", "url": "https://issues.example.com/"}
+                    ]}
+                ]
+            },
+            "other": {"type": "folder", "name": "Other bookmarks", "children": []}
+        },
+        "version": 1
+    }"#;
+
+    #[test]
+    fn parses_chromium_bookmarks_with_folders() {
+        let bookmarks = parse_chromium(CHROMIUM_BOOKMARKS.as_bytes()).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        let tracker = bookmarks.iter().find(|b| b.title == "Tracker").unwrap();
+        assert_eq!(tracker.folder.as_deref(), Some("Bookmarks bar/Work"));
+        assert!(parse_chromium(b"{}").is_err());
+    }
+
+    #[test]
+    fn parses_sqlite_json_output() {
+        let output = br#"[{"title":"Rust","url":"https://www.rust-lang.org/"},
+                          {"title":null,"url":"https://example.com/"}]"#;
+        let bookmarks = parse_firefox(output).unwrap();
+        assert_eq!(bookmarks.len(), 2);
+        assert_eq!(bookmarks[1].title, "");
+        assert!(parse_firefox(b"\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn builds_immutable_uris() {
+        assert_eq!(
+            immutable_uri(Path::new("/home/me/.mozilla/a b#1/places.sqlite")),
+            "file:/home/me/.mozilla/a b%231/places.sqlite?mode=ro&immutable=1"
+        );
+    }
+
+    #[test]
+    fn finds_the_default_firefox_profile() {
+        let modern = "[Install4F96D1932A9F858E]\nDefault=abc.default-release\nLocked=1\n\n\
+                      [Profile0]\nName=default\nIsRelative=1\nPath=xyz.default\nDefault=1\n";
+        assert_eq!(
+            default_firefox_profile(modern),
+            Some(PathBuf::from("abc.default-release"))
+        );
+
+        let legacy = "[General]\nStartWithLastProfile=1\n\n[Profile0]\nPath=one\n\n\
+                      [Profile1]\nPath=two\nDefault=1\n";
+        assert_eq!(default_firefox_profile(legacy), Some(PathBuf::from("two")));
+
+        assert_eq!(
+            default_firefox_profile("[Profile0]\nPath=only\n"),
+            Some(PathBuf::from("only"))
+        );
+        assert_eq!(default_firefox_profile("[General]\n"), None);
+    }
+
+    #[tokio::test]
+    async fn query_matches_titles_and_urls() {
+        let dir = TempDir::new();
+        std::fs::write(dir.path.join("Bookmarks"), CHROMIUM_BOOKMARKS).unwrap();
+        let provider = BookmarksProvider::new(vec![BookmarkSource::Chromium(dir.path.clone())]);
+
+        let items = provider.query("rust docs", 10).await;
+        assert_eq!(items[0].text, "Rust docs");
+        assert_eq!(items[0].metadata["url"], "https://doc.rust-lang.org/");
+        assert_eq!(items[0].source, "chromium");
+
+        let items = provider.query("issues.example", 10).await;
+        assert_eq!(items[0].text, "Tracker");
+        assert_eq!(items[0].metadata["folder"], "Bookmarks bar/Work");
+
+        assert!(provider.query("", 10).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_sources_give_no_results() {
+        let provider = BookmarksProvider::new(vec![
+            BookmarkSource::Firefox(PathBuf::from("/nonexistent/profile")),
+            BookmarkSource::Chromium(PathBuf::from("/nonexistent/profile")),
+        ])
+        .with_prefix(Some("bm ".to_string()));
+        assert!(provider.query("bm ", 10).await.is_empty());
+    }
+}
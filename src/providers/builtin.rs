@@ -0,0 +1,567 @@
+//! Built-in providers by name, constructed from the config
+//!
+//! The daemon registers the providers the config enables, and hands the
+//! manager a factory for every one, so the disabled ones can be instantiated
+//! on demand. Disabled ones are described (listed as disabled, for a settings
+//! UI) from [`Builtin::info`] without being built.
+
+use super::{
+    ApplicationsProvider, BookmarkSource, BookmarksProvider, CalculatorProvider, ColorProvider,
+    CurrencyProvider, CustomProvider, ExternalProvider, GenerateProvider, HashProvider,
+    ProcessProvider, Provider, ProviderFactory, ProviderInfo, RecentFilesProvider, SystemdProvider,
+    SystemdScope, TimeProvider, TrashProvider, WebProvider, WebSearchProvider,
+};
+use crate::config::{Config, ExternalConfig};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+/// Builds a provider from the config
+pub type Constructor = fn(&Config) -> Box<dyn Provider>;
+
+/// A built-in provider type
+pub struct Builtin {
+    /// The name its providers register under
+    pub name: &'static str,
+    /// Whether the config enables it
+    pub enabled: fn(&Config) -> bool,
+    /// What the built provider would report as its info, without building
+    /// it (which may start watchers or read caches)
+    pub info: fn(&Config) -> ProviderInfo,
+    pub build: Constructor,
+}
+
+/// Every built-in provider, in registration order
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "applications",
+        enabled: |config| config.providers.applications.enabled,
+        info: |config| {
+            describe(
+                "applications",
+                "Search installed applications",
+                None,
+                config.providers.applications.priority,
+            )
+        },
+        build: applications,
+    },
+    Builtin {
+        name: "calculator",
+        enabled: |config| config.providers.calculator.enabled,
+        info: |config| ProviderInfo {
+            supports_activate: false,
+            ..describe(
+                "calculator",
+                "Evaluate mathematical expressions",
+                Some(&config.providers.calculator.prefix),
+                config.providers.calculator.priority,
+            )
+        },
+        build: calculator,
+    },
+    Builtin {
+        name: "currency",
+        enabled: |config| config.providers.currency.enabled,
+        info: |config| ProviderInfo {
+            supports_activate: false,
+            is_network: true,
+            ..describe(
+                "currency",
+                "Convert between currencies",
+                Some(&config.providers.currency.prefix),
+                config.providers.currency.priority,
+            )
+        },
+        build: currency,
+    },
+    Builtin {
+        name: "time",
+        enabled: |config| config.providers.time.enabled,
+        info: |config| ProviderInfo {
+            supports_activate: false,
+            ..describe(
+                "time",
+                "Current time in other timezones",
+                Some(&config.providers.time.prefix),
+                config.providers.time.priority,
+            )
+        },
+        build: time,
+    },
+    Builtin {
+        name: "color",
+        enabled: |config| config.providers.color.enabled,
+        info: |config| ProviderInfo {
+            supports_activate: false,
+            // Color codes are recognized without the prefix
+            requires_prefix: false,
+            ..describe(
+                "color",
+                "Color code conversion",
+                Some(&config.providers.color.prefix),
+                config.providers.color.priority,
+            )
+        },
+        build: color,
+    },
+    Builtin {
+        name: "hash",
+        enabled: |config| config.providers.hash.enabled,
+        info: |config| ProviderInfo {
+            supports_activate: false,
+            ..describe(
+                "hash",
+                "Hashes and encodings of text",
+                Some(&config.providers.hash.prefix),
+                config.providers.hash.priority,
+            )
+        },
+        build: hash,
+    },
+    Builtin {
+        name: "generate",
+        enabled: |config| config.providers.generate.enabled,
+        info: |config| ProviderInfo {
+            supports_activate: false,
+            ..describe(
+                "generate",
+                "Random UUIDs, hex strings and passwords",
+                Some(&config.providers.generate.prefix),
+                config.providers.generate.priority,
+            )
+        },
+        build: generate,
+    },
+    Builtin {
+        name: "recent",
+        enabled: |config| config.providers.recent.enabled,
+        info: |config| {
+            describe(
+                "recent",
+                "Recently used files",
+                config.providers.recent.prefix.as_deref(),
+                config.providers.recent.priority,
+            )
+        },
+        build: recent,
+    },
+    Builtin {
+        name: "bookmarks",
+        enabled: |config| config.providers.bookmarks.enabled,
+        info: |config| {
+            describe(
+                "bookmarks",
+                "Browser bookmarks",
+                config.providers.bookmarks.prefix.as_deref(),
+                config.providers.bookmarks.priority,
+            )
+        },
+        build: bookmarks,
+    },
+    Builtin {
+        name: "systemd",
+        enabled: |config| config.providers.systemd.enabled,
+        info: |config| {
+            describe(
+                "systemd",
+                "Systemd units and their state",
+                Some(&config.providers.systemd.prefix),
+                config.providers.systemd.priority,
+            )
+        },
+        build: systemd,
+    },
+    Builtin {
+        name: "process",
+        enabled: |config| config.providers.process.enabled,
+        info: |config| {
+            describe(
+                "process",
+                "Running processes, to terminate or kill",
+                Some(&config.providers.process.prefix),
+                config.providers.process.priority,
+            )
+        },
+        build: process,
+    },
+    Builtin {
+        name: "trash",
+        enabled: |config| config.providers.trash.enabled,
+        info: |config| {
+            describe(
+                "trash",
+                "Files in the trash, to restore or delete",
+                Some(&config.providers.trash.prefix),
+                config.providers.trash.priority,
+            )
+        },
+        build: trash,
+    },
+    // Without entries there is nothing to search.
+    Builtin {
+        name: "custom",
+        enabled: |config| {
+            config.providers.custom.enabled && !config.providers.custom.entries.is_empty()
+        },
+        info: |config| {
+            describe(
+                "custom",
+                "Launcher entries from the config file",
+                config.providers.custom.prefix.as_deref(),
+                config.providers.custom.priority,
+            )
+        },
+        build: custom,
+    },
+    Builtin {
+        name: "web",
+        enabled: |config| config.providers.web.enabled,
+        info: |config| {
+            describe(
+                "web",
+                "Open typed URLs and domains",
+                None,
+                config.providers.web.priority,
+            )
+        },
+        build: web,
+    },
+    Builtin {
+        name: "websearch",
+        enabled: |config| config.providers.websearch.enabled,
+        info: |config| {
+            describe(
+                "websearch",
+                "Search the web, choosing the engine by keyword",
+                None,
+                config.providers.websearch.priority,
+            )
+        },
+        build: websearch,
+    },
+];
+
+/// Info for a built-in provider, as [`Provider::info`] reports it by default
+fn describe(name: &str, description: &str, prefix: Option<&str>, priority: i32) -> ProviderInfo {
+    ProviderInfo {
+        name: name.to_string(),
+        description: description.to_string(),
+        prefix: prefix.map(String::from),
+        enabled: true,
+        priority,
+        supports_activate: true,
+        is_network: false,
+        requires_prefix: prefix.is_some(),
+    }
+}
+
+/// The built-in provider named `name`
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+/// A provider the config describes
+pub struct Configured {
+    pub name: String,
+    /// Whether the config enables it
+    pub enabled: bool,
+    /// Its info, for listing it while it's disabled
+    pub info: ProviderInfo,
+    /// Builds it from the config
+    pub factory: ProviderFactory,
+}
+
+/// Every provider the config describes, built-in then external
+pub fn configured(config: Arc<Config>) -> Vec<Configured> {
+    let builtins = BUILTINS.iter().map(|builtin| {
+        let enabled = (builtin.enabled)(&config);
+        let config = Arc::clone(&config);
+        Configured {
+            name: builtin.name.to_string(),
+            enabled,
+            info: (builtin.info)(&config),
+            factory: Arc::new(move || (builtin.build)(&config)),
+        }
+    });
+    let externals = config.providers.external.iter().map(|external_config| {
+        let external_config = external_config.clone();
+        Configured {
+            name: external_config.name.clone(),
+            enabled: external_config.enabled,
+            // Building one only records its command
+            info: external(&external_config).info(),
+            factory: Arc::new(move || external(&external_config)),
+        }
+    });
+    builtins.chain(externals).collect()
+}
+
+fn applications(config: &Config) -> Box<dyn Provider> {
+    let applications_config = &config.providers.applications;
+    Box::new(
+        ApplicationsProvider::with_extra_dirs(applications_config.extra_dirs.clone())
+            .with_priority(applications_config.priority)
+            .with_rescan_interval(Duration::from_secs(
+                applications_config.rescan_interval_secs,
+            ))
+            .with_max_typos(applications_config.max_typos),
+    )
+}
+
+fn calculator(config: &Config) -> Box<dyn Provider> {
+    let calculator_config = &config.providers.calculator;
+    Box::new(
+        CalculatorProvider::new()
+            .with_prefix(&calculator_config.prefix)
+            .with_precision(calculator_config.precision)
+            .with_fractions(calculator_config.fractions)
+            .with_grouping(calculator_config.grouping)
+            .with_scientific_range(
+                calculator_config.scientific_below,
+                calculator_config.scientific_above,
+            )
+            .with_constants(calculator_config.constants.clone())
+            .with_priority(calculator_config.priority),
+    )
+}
+
+fn currency(config: &Config) -> Box<dyn Provider> {
+    let currency_config = &config.providers.currency;
+    Box::new(
+        CurrencyProvider::new(Config::cache_dir().join("currency-rates.json"))
+            .with_prefix(&currency_config.prefix)
+            .with_endpoint(&currency_config.endpoint)
+            .with_api_key(currency_config.api_key.clone())
+            .with_base(&currency_config.base)
+            .with_ttl(Duration::from_secs(currency_config.ttl_hours * 60 * 60))
+            .with_priority(currency_config.priority),
+    )
+}
+
+fn time(config: &Config) -> Box<dyn Provider> {
+    let time_config = &config.providers.time;
+    Box::new(
+        TimeProvider::new()
+            .with_prefix(&time_config.prefix)
+            .with_pinned(time_config.pinned.clone())
+            .with_priority(time_config.priority),
+    )
+}
+
+fn color(config: &Config) -> Box<dyn Provider> {
+    Box::new(
+        ColorProvider::new(Config::cache_dir().join("swatches"))
+            .with_prefix(&config.providers.color.prefix)
+            .with_priority(config.providers.color.priority),
+    )
+}
+
+fn hash(config: &Config) -> Box<dyn Provider> {
+    Box::new(
+        HashProvider::new()
+            .with_prefix(&config.providers.hash.prefix)
+            .with_priority(config.providers.hash.priority),
+    )
+}
+
+fn generate(config: &Config) -> Box<dyn Provider> {
+    Box::new(
+        GenerateProvider::new()
+            .with_prefix(&config.providers.generate.prefix)
+            .with_priority(config.providers.generate.priority),
+    )
+}
+
+fn recent(config: &Config) -> Box<dyn Provider> {
+    let recent_config = &config.providers.recent;
+    let path = recent_config
+        .path
+        .clone()
+        .unwrap_or_else(RecentFilesProvider::default_path);
+    Box::new(
+        RecentFilesProvider::new(path)
+            .with_prefix(recent_config.prefix.clone())
+            .with_priority(recent_config.priority),
+    )
+}
+
+fn bookmarks(config: &Config) -> Box<dyn Provider> {
+    let bookmarks_config = &config.providers.bookmarks;
+    let firefox = match &bookmarks_config.firefox_profile {
+        Some(profile) => Some(BookmarkSource::Firefox(profile.clone())),
+        None => BookmarkSource::default_firefox(),
+    };
+    let chromium = match &bookmarks_config.chromium_profile {
+        Some(profile) => Some(BookmarkSource::Chromium(profile.clone())),
+        None => BookmarkSource::default_chromium(),
+    };
+    let sources: Vec<_> = firefox.into_iter().chain(chromium).collect();
+    if sources.is_empty() && bookmarks_config.enabled {
+        warn!("Bookmarks enabled but no browser profile was found");
+    }
+    Box::new(
+        BookmarksProvider::new(sources)
+            .with_prefix(bookmarks_config.prefix.clone())
+            .with_priority(bookmarks_config.priority),
+    )
+}
+
+fn systemd(config: &Config) -> Box<dyn Provider> {
+    let systemd_config = &config.providers.systemd;
+    let scopes: Vec<_> = [
+        (systemd_config.user, SystemdScope::User),
+        (systemd_config.system, SystemdScope::System),
+    ]
+    .into_iter()
+    .filter_map(|(listed, scope)| listed.then_some(scope))
+    .collect();
+    Box::new(
+        SystemdProvider::new(scopes)
+            .with_prefix(&systemd_config.prefix)
+            .with_priority(systemd_config.priority),
+    )
+}
+
+fn process(config: &Config) -> Box<dyn Provider> {
+    let process_config = &config.providers.process;
+    Box::new(
+        ProcessProvider::new()
+            .with_prefix(&process_config.prefix)
+            .with_all_users(process_config.all_users)
+            .with_priority(process_config.priority),
+    )
+}
+
+fn trash(config: &Config) -> Box<dyn Provider> {
+    let trash_config = &config.providers.trash;
+    let path = trash_config
+        .path
+        .clone()
+        .unwrap_or_else(TrashProvider::default_path);
+    Box::new(
+        TrashProvider::new(path)
+            .with_prefix(&trash_config.prefix)
+            .with_priority(trash_config.priority),
+    )
+}
+
+fn web(config: &Config) -> Box<dyn Provider> {
+    Box::new(WebProvider::new().with_priority(config.providers.web.priority))
+}
+
+fn websearch(config: &Config) -> Box<dyn Provider> {
+    let websearch_config = &config.providers.websearch;
+    Box::new(
+        WebSearchProvider::new()
+            .with_engines(websearch_config.engines.clone())
+            .with_default_engine(
+                Some(websearch_config.default_engine.clone()).filter(|k| !k.is_empty()),
+            )
+            .with_priority(websearch_config.priority),
+    )
+}
+
+fn external(external_config: &ExternalConfig) -> Box<dyn Provider> {
+    let mut external =
+        ExternalProvider::new(&external_config.name, external_config.command.clone())
+            .with_prefix(external_config.prefix.clone())
+            .with_timeout(Duration::from_millis(external_config.timeout_ms))
+            .with_priority(external_config.priority);
+    if let Some(description) = &external_config.description {
+        external = external.with_description(description);
+    }
+    Box::new(external)
+}
+
+fn custom(config: &Config) -> Box<dyn Provider> {
+    let custom_config = &config.providers.custom;
+    Box::new(
+        CustomProvider::new(custom_config.entries.clone())
+            .with_prefix(custom_config.prefix.clone())
+            .with_priority(custom_config.priority),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_are_named_after_their_providers() {
+        let config = Config::default();
+        for builtin in BUILTINS {
+            // Building it would start watching the host's application dirs.
+            if builtin.name == "applications" {
+                continue;
+            }
+            assert_eq!((builtin.build)(&config).name(), builtin.name);
+        }
+        assert!(builtin("trash").is_some());
+        assert!(builtin("nope").is_none());
+    }
+
+    #[test]
+    fn builtin_info_matches_the_built_provider() {
+        let mut config = Config::default();
+        config.providers.recent.prefix = Some("r ".to_string());
+        config.providers.calculator.priority = 3;
+        for builtin in BUILTINS {
+            if builtin.name == "applications" {
+                continue;
+            }
+            assert_eq!(
+                (builtin.info)(&config),
+                (builtin.build)(&config).info(),
+                "{}",
+                builtin.name
+            );
+        }
+        let activates = |name: &str| (builtin(name).unwrap().info)(&config).supports_activate;
+        assert!(!activates("time"));
+        assert!(activates("trash"));
+    }
+
+    #[test]
+    fn calculator_prefix_follows_the_config() {
+        let mut config = Config::default();
+        config.providers.calculator.prefix = "calc ".to_string();
+        assert_eq!(calculator(&config).prefix(), Some("calc "));
+    }
+
+    #[test]
+    fn configured_providers_follow_the_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [providers.applications]
+            enabled = false
+
+            [providers.trash]
+            enabled = true
+
+            [[providers.external]]
+            name = "notes"
+            command = ["notes-search"]
+            enabled = false
+            "#,
+        )
+        .unwrap();
+        let configured = configured(Arc::new(config));
+        let enabled = |name: &str| {
+            configured
+                .iter()
+                .find(|provider| provider.name == name)
+                .map(|provider| provider.enabled)
+        };
+        assert_eq!(enabled("applications"), Some(false));
+        assert_eq!(enabled("trash"), Some(true));
+        assert_eq!(enabled("calculator"), Some(true));
+        // No entries, so nothing to enable
+        assert_eq!(enabled("custom"), Some(false));
+        assert_eq!(enabled("notes"), Some(false));
+        assert_eq!(configured.len(), BUILTINS.len() + 1);
+
+        let notes = configured.iter().find(|p| p.name == "notes").unwrap();
+        assert_eq!((notes.factory)().name(), "notes");
+    }
+}
@@ -1,66 +1,347 @@
 //! Calculator provider - evaluates mathematical expressions
+//!
+//! `ans` refers to the previous result and `name = expr` assigns a variable.
+//! Both live for the daemon's lifetime only.
+//!
+//! Launchers query on every keystroke, so a result only becomes `ans` (and an
+//! assignment only takes effect) once the calculation is finished: when the
+//! next expression is not an edit of it (neither is a prefix of the other), or
+//! when the input is cleared back to the bare prefix.
+//!
+//! Results that are (within rounding) a simple fraction, such as `0.25`, are
+//! also offered as that fraction (`1/4`).
+//!
+//! An expression that fails only for want of up to three closing parentheses,
+//! as while typing `sqrt(2`, is evaluated with them added.
+//!
+//! Numbers followed by a time unit (`s`, `min`, `h`, `d` and their longer
+//! forms) are durations: `3h + 45m` is `3.75 h`, and `90min in hours` is
+//! `1.5 h`. Without an `in`/`to` unit the result keeps the unit used, or
+//! with mixed units the largest one it's at least 1 of.
 
 use super::{Item, Provider};
 use evalexpr::{
-    eval_with_context, ContextWithMutableFunctions, ContextWithMutableVariables, Function,
+    eval_with_context, Context, ContextWithMutableFunctions, ContextWithMutableVariables, Function,
     HashMapContext, Value,
 };
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use tracing::debug;
+use std::sync::{Mutex, PoisonError};
+use tracing::{debug, warn};
+
+/// Names that can't be assigned to: `ans` and the built-in constants
+const RESERVED_NAMES: &[&str] = &["ans", "pi", "e", "tau"];
+
+/// Decimal places shown by default
+pub const DEFAULT_PRECISION: usize = 10;
+
+/// Results from 10^15 up are shown in scientific notation by default
+pub const DEFAULT_SCIENTIFIC_ABOVE: i32 = 15;
+
+/// Non-zero results below 10^-6 are shown in scientific notation by default
+pub const DEFAULT_SCIENTIFIC_BELOW: i32 = -6;
+
+/// Most closing parentheses added to an expression that's missing some
+const MAX_AUTO_CLOSE: usize = 3;
+
+/// Largest denominator offered for the fraction form of a result
+const MAX_DENOMINATOR: i64 = 1000;
+
+/// Time units durations are written in: accepted names, the name shown, and
+/// length in seconds, largest first
+const DURATION_UNITS: &[(&[&str], &str, f64)] = &[
+    (&["d", "day", "days"], "d", 86_400.0),
+    (&["h", "hr", "hrs", "hour", "hours"], "h", 3_600.0),
+    (&["m", "min", "mins", "minute", "minutes"], "min", 60.0),
+    (&["s", "sec", "secs", "second", "seconds"], "s", 1.0),
+];
 
 /// Provider for mathematical calculations
-pub struct CalculatorProvider;
+pub struct CalculatorProvider {
+    prefix: String,
+    /// Tie-break priority when sorting results
+    priority: i32,
+    /// How results are written out
+    format: NumberFormat,
+    /// Whether to also offer results as simplified fractions
+    fractions: bool,
+    /// User-defined constants, on top of the built-in ones
+    constants: HashMap<String, f64>,
+    /// Variables and previous results
+    session: Mutex<Session>,
+}
+
+/// How numeric results are written out
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NumberFormat {
+    /// Decimal places shown (trailing zeros trimmed)
+    precision: usize,
+    /// Separate thousands with commas (1,000,000)
+    grouping: bool,
+    /// Use scientific notation from 10^scientific_above up...
+    scientific_above: i32,
+    /// ...and for non-zero values below 10^scientific_below
+    scientific_below: i32,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            precision: DEFAULT_PRECISION,
+            grouping: false,
+            scientific_above: DEFAULT_SCIENTIFIC_ABOVE,
+            scientific_below: DEFAULT_SCIENTIFIC_BELOW,
+        }
+    }
+}
+
+/// Calculator state carried between queries
+#[derive(Debug, Default)]
+struct Session {
+    /// Variables from finished assignments
+    variables: HashMap<String, f64>,
+    /// Result of the last finished calculation
+    ans: Option<f64>,
+    /// The calculation currently being typed
+    pending: Option<Pending>,
+}
+
+/// A successfully evaluated calculation that may still be edited
+#[derive(Debug)]
+struct Pending {
+    expr: String,
+    result: f64,
+    /// Variable the result is assigned to, if any
+    variable: Option<String>,
+}
+
+impl Session {
+    /// Finish the pending calculation: it becomes `ans` and its assignment
+    /// takes effect
+    fn commit(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.ans = Some(pending.result);
+            if let Some(name) = pending.variable {
+                self.variables.insert(name, pending.result);
+            }
+        }
+    }
+
+    /// Finish the pending calculation unless `expr` is an edit of it
+    fn commit_unless_edit_of(&mut self, expr: &str) {
+        let is_edit = self
+            .pending
+            .as_ref()
+            .is_some_and(|p| p.expr.starts_with(expr) || expr.starts_with(p.expr.as_str()));
+        if !is_edit {
+            self.commit();
+        }
+    }
+}
 
 impl CalculatorProvider {
     pub fn new() -> Self {
-        Self
+        Self {
+            prefix: "=".to_string(),
+            priority: 0,
+            format: NumberFormat::default(),
+            fractions: true,
+            constants: HashMap::new(),
+            session: Mutex::new(Session::default()),
+        }
+    }
+
+    /// Set the query prefix (default: "=")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the number of decimal places shown (default: 10); trailing zeros
+    /// are trimmed either way
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.format.precision = precision;
+        self
+    }
+
+    /// Separate thousands with commas, e.g. 1,000,000 (default: off)
+    pub fn with_grouping(mut self, grouping: bool) -> Self {
+        self.format.grouping = grouping;
+        self
+    }
+
+    /// Show results in scientific notation (1.23e15) when they are at least
+    /// 10^`above`, or non-zero and below 10^`below` (default: -6 and 15)
+    pub fn with_scientific_range(mut self, below: i32, above: i32) -> Self {
+        self.format.scientific_below = below;
+        self.format.scientific_above = above;
+        self
+    }
+
+    /// Offer results close to a simple fraction as that fraction too
+    /// (default: on)
+    pub fn with_fractions(mut self, fractions: bool) -> Self {
+        self.fractions = fractions;
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Define constants usable in every expression, e.g. `g = 9.81`
+    ///
+    /// Names that aren't identifiers or would shadow a built-in constant,
+    /// function or keyword are skipped with a warning.
+    pub fn with_constants(mut self, constants: HashMap<String, f64>) -> Self {
+        for (name, value) in constants {
+            match check_constant_name(&name) {
+                Ok(()) => {
+                    self.constants.insert(name, value);
+                }
+                Err(e) => warn!("Skipping calculator constant '{}': {}", name, e),
+            }
+        }
+        self
     }
 
     fn query_impl(&self, query: &str, _max_results: usize) -> Vec<Item> {
         // Remove the prefix if present
-        let expr = query.strip_prefix('=').unwrap_or(query).trim();
+        let expr = query
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(query)
+            .trim();
+
+        // The session holds plain data that is never left half-updated, so a
+        // poisoned lock is still safe to use.
+        let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
 
         if expr.is_empty() {
+            session.commit();
             return vec![Item::new("Enter an expression (e.g., 2+2)", "calculator")
+                .with_stable_id("hint")
                 .with_subtext(
                     "Supports: +, -, *, /, ^, %, sqrt(), sin(), cos(), tan(), \
-                     log(), ln(), and constants pi, e",
+                     log(), ln(), constants pi, e, ans, x = ... variables, \
+                     and durations (3h + 45m, 90min in h)",
                 )
                 .with_icon("accessories-calculator")
                 .with_score(1.0)];
         }
 
-        // evalexpr uses integer division for integer operands (5/2 == 2), which
-        // is surprising for a calculator. Coerce bare integer literals to floats
-        // so arithmetic behaves like a calculator (5/2 == 2.5).
-        let prepared = floatify_int_literals(expr);
-        let context = build_context();
+        session.commit_unless_edit_of(expr);
+
+        let (variable, rhs) = match split_assignment(expr) {
+            Some((name, _))
+                if RESERVED_NAMES.contains(&name) || self.constants.contains_key(name) =>
+            {
+                return vec![Item::new("Invalid assignment", "calculator")
+                    .with_stable_id("error")
+                    .with_subtext(format!("Error: '{}' is reserved", name))
+                    .with_icon("dialog-error")
+                    .with_score(0.5)];
+            }
+            Some((name, rhs)) => (Some(name), rhs),
+            None => (None, expr),
+        };
+
+        let mut context = build_context();
+        for (name, value) in self.constants.iter().chain(&session.variables) {
+            let _ = context.set_value(name.clone(), Value::Float(*value));
+        }
+        if let Some(ans) = session.ans {
+            let _ = context.set_value("ans".into(), Value::Float(ans));
+        }
 
-        // Try to evaluate the expression
-        match eval_with_context(&prepared, &context) {
-            Ok(value) => match format_value(&value) {
+        if variable.is_none() {
+            if let Some(items) = self.duration_items(expr, rhs, &context) {
+                return items;
+            }
+        }
+
+        // Try to evaluate the expression, and failing that, the expression
+        // with its open parentheses closed, as while typing `sqrt(2`
+        let mut evaluated = eval_with_context(&prepare(rhs), &context);
+        let mut rhs = rhs.to_string();
+        let mut note = "";
+        if evaluated.is_err() {
+            if let Some(closed) = auto_close(&rhs) {
+                if let Ok(value) = eval_with_context(&prepare(&closed), &context) {
+                    evaluated = Ok(value);
+                    rhs = closed;
+                    note = " (auto-closed)";
+                }
+            }
+        }
+        let rhs = rhs.as_str();
+
+        match evaluated {
+            Ok(value) => match format_value(&value, &self.format) {
                 Some(result_str) => {
                     debug!("Calculator: {} = {}", expr, result_str);
 
-                    vec![Item::new(&result_str, "calculator")
-                        .with_subtext(format!("{} =", expr))
+                    if let Ok(result) = value.as_number() {
+                        session.pending = Some(Pending {
+                            expr: expr.to_string(),
+                            result,
+                            variable: variable.map(String::from),
+                        });
+                    }
+
+                    let item = match variable {
+                        Some(name) => Item::new(format!("{} = {}", name, result_str), "calculator")
+                            .with_subtext(format!("Assign {} ={}", rhs, note))
+                            .with_metadata("variable", name),
+                        None => Item::new(&result_str, "calculator")
+                            .with_subtext(format!("{} ={}", rhs, note)),
+                    };
+                    let mut items = vec![item
+                        .with_stable_id(expr)
                         .with_icon("accessories-calculator")
                         .with_score(1.0)
-                        .with_metadata("expression", expr)
-                        .with_metadata("result", &result_str)]
+                        .with_metadata("expression", rhs)
+                        .with_metadata("result", &result_str)
+                        .with_copy(&result_str)];
+
+                    let fraction = match value {
+                        Value::Float(f) if self.fractions => as_fraction(f),
+                        _ => None,
+                    };
+                    if let Some((numerator, denominator)) = fraction {
+                        let fraction = format!("{}/{}", numerator, denominator);
+                        items.push(
+                            Item::new(&fraction, "calculator")
+                                .with_stable_id(format!("fraction:{}", expr))
+                                .with_subtext(format!("{} as a fraction", result_str))
+                                .with_icon("accessories-calculator")
+                                .with_score(0.99)
+                                .with_metadata("expression", rhs)
+                                .with_metadata("result", &fraction)
+                                .with_copy(&fraction),
+                        );
+                    }
+                    items
                 }
                 None => {
                     debug!("Calculator: unsupported result type for '{}'", expr);
                     vec![Item::new("Invalid expression", "calculator")
+                        .with_stable_id("error")
                         .with_subtext("Error: unsupported result type")
                         .with_icon("dialog-error")
                         .with_score(0.5)]
                 }
             },
+            Err(e) if !looks_like_math(rhs, &context) => {
+                debug!("Calculator: '{}' isn't an expression ({})", expr, e);
+                Vec::new()
+            }
             Err(e) => {
                 debug!("Calculator error for '{}': {}", expr, e);
                 vec![Item::new("Invalid expression", "calculator")
+                    .with_stable_id("error")
                     .with_subtext(format!("Error: {}", e))
                     .with_icon("dialog-error")
                     .with_score(0.5)]
@@ -69,6 +350,51 @@ impl CalculatorProvider {
     }
 }
 
+impl CalculatorProvider {
+    /// Items for `rhs` as a duration calculation, if it is one
+    fn duration_items(&self, expr: &str, rhs: &str, context: &HashMapContext) -> Option<Vec<Item>> {
+        let (body, target) = match split_duration_target(rhs) {
+            (body, Some(word)) => (body, Some(duration_unit(word)?)),
+            (body, None) => (body, None),
+        };
+        let (seconds_expr, units) = rewrite_durations(body)?;
+        let seconds = eval_with_context(&prepare(&seconds_expr), context)
+            .ok()?
+            .as_number()
+            .ok()?;
+        let (unit, unit_seconds) = target.unwrap_or_else(|| normalized_unit(seconds, &units));
+        let result = format!(
+            "{} {}",
+            format_number(seconds / unit_seconds, &self.format),
+            unit
+        );
+        debug!("Calculator: {} = {}", expr, result);
+
+        let mut items = vec![Item::new(&result, "calculator")
+            .with_stable_id(expr)
+            .with_subtext(format!("{} =", rhs))
+            .with_icon("accessories-calculator")
+            .with_score(1.0)
+            .with_metadata("expression", rhs)
+            .with_metadata("result", &result)
+            .with_metadata("seconds", seconds.to_string())
+            .with_copy(&result)];
+        if let Some(parts) = duration_parts(seconds).filter(|parts| *parts != result) {
+            items.push(
+                Item::new(&parts, "calculator")
+                    .with_stable_id(format!("duration:{}", expr))
+                    .with_subtext(format!("{} in parts", result))
+                    .with_icon("accessories-calculator")
+                    .with_score(0.99)
+                    .with_metadata("expression", rhs)
+                    .with_metadata("result", &parts)
+                    .with_copy(&parts),
+            );
+        }
+        Some(items)
+    }
+}
+
 impl Default for CalculatorProvider {
     fn default() -> Self {
         Self::new()
@@ -85,7 +411,16 @@ impl Provider for CalculatorProvider {
     }
 
     fn prefix(&self) -> Option<&str> {
-        Some("=")
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Results are values to read or copy; activating one does nothing more
+    fn supports_activate(&self) -> bool {
+        false
     }
 
     fn query(
@@ -98,11 +433,58 @@ impl Provider for CalculatorProvider {
     }
 }
 
+type UnaryFn = fn(f64) -> f64;
+
+/// Unary f64 -> f64 functions
+const UNARY_FUNCTIONS: &[(&str, UnaryFn)] = &[
+    ("sqrt", f64::sqrt),
+    ("cbrt", f64::cbrt),
+    ("sin", f64::sin),
+    ("cos", f64::cos),
+    ("tan", f64::tan),
+    ("asin", f64::asin),
+    ("acos", f64::acos),
+    ("atan", f64::atan),
+    ("sinh", f64::sinh),
+    ("cosh", f64::cosh),
+    ("tanh", f64::tanh),
+    ("ln", f64::ln),
+    ("log10", f64::log10),
+    ("log2", f64::log2),
+    ("exp", f64::exp),
+    ("abs", f64::abs),
+    ("floor", f64::floor),
+    ("ceil", f64::ceil),
+    ("round", f64::round),
+    ("deg", f64::to_degrees),
+    ("rad", f64::to_radians),
+];
+
+/// Functions of two arguments
+const BINARY_FUNCTIONS: &[(&str, BinaryFn)] = &[
+    ("pow", f64::powf),
+    ("hypot", f64::hypot),
+    ("atan2", f64::atan2),
+    ("min", f64::min),
+    ("max", f64::max),
+];
+
+type BinaryFn = fn(f64, f64) -> f64;
+
+/// Names that evalexpr itself gives a meaning
+const KEYWORDS: &[&str] = &["true", "false"];
+
 /// Build an evaluation context exposing common math functions and constants
-/// under bare names (e.g. `sqrt`, `pi`) for a familiar calculator experience.
+/// under bare names (e.g. `sqrt`, `pi`) for a familiar calculator experience
 ///
 /// evalexpr only ships these under a `math::` namespace and provides no math
-/// constants, so we register the friendly names ourselves.
+/// constants, so we register the friendly names ourselves. The built-in set:
+///
+/// - constants `pi`, `e` and `tau`
+/// - the functions of one argument in [`UNARY_FUNCTIONS`], including `deg`
+///   and `rad` to convert radians to degrees and back
+/// - `log(x)` (base 10) and `log(base, x)`
+/// - `pow`, `hypot`, `atan2`, `min` and `max` of two arguments
 fn build_context() -> HashMapContext {
     let mut ctx = HashMapContext::new();
 
@@ -111,30 +493,7 @@ fn build_context() -> HashMapContext {
     let _ = ctx.set_value("e".into(), Value::Float(std::f64::consts::E));
     let _ = ctx.set_value("tau".into(), Value::Float(std::f64::consts::TAU));
 
-    // Unary f64 -> f64 functions
-    type UnaryFn = fn(f64) -> f64;
-    let unary: &[(&str, UnaryFn)] = &[
-        ("sqrt", f64::sqrt),
-        ("cbrt", f64::cbrt),
-        ("sin", f64::sin),
-        ("cos", f64::cos),
-        ("tan", f64::tan),
-        ("asin", f64::asin),
-        ("acos", f64::acos),
-        ("atan", f64::atan),
-        ("sinh", f64::sinh),
-        ("cosh", f64::cosh),
-        ("tanh", f64::tanh),
-        ("ln", f64::ln),
-        ("log10", f64::log10),
-        ("log2", f64::log2),
-        ("exp", f64::exp),
-        ("abs", f64::abs),
-        ("floor", f64::floor),
-        ("ceil", f64::ceil),
-        ("round", f64::round),
-    ];
-    for &(name, f) in unary {
+    for &(name, f) in UNARY_FUNCTIONS {
         let _ = ctx.set_function(
             name.into(),
             Function::new(move |arg| {
@@ -160,26 +519,87 @@ fn build_context() -> HashMapContext {
         }),
     );
 
-    // pow(base, exp)
-    let _ = ctx.set_function(
-        "pow".into(),
-        Function::new(|arg| {
-            let tuple = arg.as_fixed_len_tuple(2)?;
-            let base: f64 = tuple[0].as_number()?;
-            let exp: f64 = tuple[1].as_number()?;
-            Ok(Value::Float(base.powf(exp)))
-        }),
-    );
+    for &(name, f) in BINARY_FUNCTIONS {
+        let _ = ctx.set_function(
+            name.into(),
+            Function::new(move |arg| {
+                let tuple = arg.as_fixed_len_tuple(2)?;
+                let x: f64 = tuple[0].as_number()?;
+                let y: f64 = tuple[1].as_number()?;
+                Ok(Value::Float(f(x, y)))
+            }),
+        );
+    }
 
     ctx
 }
 
+/// Check that a user-defined constant's name is an identifier that doesn't
+/// shadow anything built in
+fn check_constant_name(name: &str) -> anyhow::Result<()> {
+    let mut chars = name.chars();
+    let is_identifier = chars
+        .next()
+        .is_some_and(|first| first.is_alphabetic() || first == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    if !is_identifier {
+        anyhow::bail!("not a valid name");
+    }
+    let function = UNARY_FUNCTIONS
+        .iter()
+        .map(|&(function, _)| function)
+        .chain(BINARY_FUNCTIONS.iter().map(|&(function, _)| function))
+        .chain(["log"])
+        .any(|function| function == name);
+    if function || RESERVED_NAMES.contains(&name) || KEYWORDS.contains(&name) {
+        anyhow::bail!("'{}' is built in", name);
+    }
+    Ok(())
+}
+
+/// Whether input that failed to evaluate was meant as a calculation at all
+///
+/// Plain words (say, an app name typed after the prefix) contain no digits,
+/// operators, functions or known names, and get no error item.
+fn looks_like_math(expr: &str, context: &HashMapContext) -> bool {
+    if expr
+        .chars()
+        .any(|c| c.is_ascii_digit() || "+-*/^%()=<>!&|,.".contains(c))
+    {
+        return true;
+    }
+    expr.split_whitespace().any(|word| {
+        context.get_value(word).is_some()
+            || matches!(word, "log" | "pow")
+            || UNARY_FUNCTIONS.iter().any(|(name, _)| *name == word)
+    })
+}
+
+/// Split `name = expr` into its variable name and expression
+///
+/// Returns `None` unless the left-hand side is a plain identifier, so
+/// comparisons such as `a == b` or `a <= b` are not mistaken for assignments.
+fn split_assignment(expr: &str) -> Option<(&str, &str)> {
+    let (name, rhs) = expr.split_once('=')?;
+    let name = name.trim();
+    if rhs.starts_with('=') {
+        return None;
+    }
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    if !(first.is_alphabetic() || first == '_') || !chars.all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some((name, rhs.trim()))
+}
+
 /// Convert an evaluation result into a display string.
 /// Returns `None` for result types that have no meaningful textual form here
 /// (empty value, tuples).
-fn format_value(value: &Value) -> Option<String> {
+fn format_value(value: &Value, format: &NumberFormat) -> Option<String> {
     match value {
-        Value::Float(f) => Some(format_result(*f)),
+        Value::Float(f) => Some(format_number(*f, format)),
+        Value::Int(i) if format.grouping => Some(group_thousands(&i.to_string())),
         Value::Int(i) => Some(i.to_string()),
         Value::Boolean(b) => Some(b.to_string()),
         Value::String(s) => Some(s.clone()),
@@ -187,6 +607,228 @@ fn format_value(value: &Value) -> Option<String> {
     }
 }
 
+/// `expr` with its unclosed parentheses closed, if it has between one and
+/// [`MAX_AUTO_CLOSE`] of them and no stray closing ones
+fn auto_close(expr: &str) -> Option<String> {
+    let mut open = 0usize;
+    for c in expr.chars() {
+        match c {
+            '(' => open += 1,
+            ')' => open = open.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    (1..=MAX_AUTO_CLOSE)
+        .contains(&open)
+        .then(|| format!("{}{}", expr, ")".repeat(open)))
+}
+
+/// The display name and length in seconds of a time unit
+fn duration_unit(word: &str) -> Option<(&'static str, f64)> {
+    let word = word.to_lowercase();
+    DURATION_UNITS
+        .iter()
+        .find(|(names, _, _)| names.contains(&word.as_str()))
+        .map(|&(_, unit, seconds)| (unit, seconds))
+}
+
+/// Split `expr in <unit>` (or `to <unit>`) into the expression and unit word
+fn split_duration_target(expr: &str) -> (&str, Option<&str>) {
+    [" in ", " to "]
+        .iter()
+        .filter_map(|separator| expr.rsplit_once(separator))
+        .max_by_key(|(body, _)| body.len())
+        .map_or((expr, None), |(body, unit)| {
+            (body.trim(), Some(unit.trim()))
+        })
+}
+
+/// `expr` with each duration (`3h`, `45 min`) replaced by its length in
+/// seconds, and the units used, or `None` if it has no durations
+fn rewrite_durations(expr: &str) -> Option<(String, Vec<(&'static str, f64)>)> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut out = String::with_capacity(expr.len());
+    let mut units = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let starts_number = chars[i].is_ascii_digit()
+            && (i == 0 || !(chars[i - 1].is_alphanumeric() || matches!(chars[i - 1], '_' | '.')));
+        if !starts_number {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let mut end = i;
+        while end < chars.len() && (chars[end].is_ascii_digit() || chars[end] == '.') {
+            end += 1;
+        }
+        let number: String = chars[i..end].iter().collect();
+        let word_start = skip_spaces(&chars, end);
+        let mut word_end = word_start;
+        while word_end < chars.len() && chars[word_end].is_alphabetic() {
+            word_end += 1;
+        }
+        let word: String = chars[word_start..word_end].iter().collect();
+        // A unit stands alone: not `3h2`, nor the function call `3 min(1, 2)`.
+        let stands_alone = !chars
+            .get(word_end)
+            .is_some_and(|&c| c.is_alphanumeric() || c == '_')
+            && chars.get(skip_spaces(&chars, word_end)) != Some(&'(');
+        match duration_unit(&word).filter(|_| stands_alone) {
+            Some((unit, seconds)) => {
+                out.push_str(&format!("({}*{})", number, seconds));
+                units.push((unit, seconds));
+                i = word_end;
+            }
+            None => {
+                out.push_str(&number);
+                i = end;
+            }
+        }
+    }
+    (!units.is_empty()).then_some((out, units))
+}
+
+/// The unit to show a result in: the one used throughout, or with mixed
+/// units the largest the result is at least 1 of
+fn normalized_unit(seconds: f64, units: &[(&'static str, f64)]) -> (&'static str, f64) {
+    if units.iter().all(|unit| unit.0 == units[0].0) {
+        return units[0];
+    }
+    DURATION_UNITS
+        .iter()
+        .find(|(_, _, unit_seconds)| seconds.abs() >= *unit_seconds)
+        .map_or(("s", 1.0), |&(_, unit, unit_seconds)| (unit, unit_seconds))
+}
+
+/// A whole, positive number of seconds in days, hours, minutes and seconds
+/// ("3 h 45 min"), if that takes more than one unit
+fn duration_parts(seconds: f64) -> Option<String> {
+    if !((1.0..1e15).contains(&seconds) && seconds.fract() == 0.0) {
+        return None;
+    }
+    let mut rest = seconds as u64;
+    let mut parts = Vec::new();
+    for &(_, unit, unit_seconds) in DURATION_UNITS {
+        let count = rest / unit_seconds as u64;
+        if count > 0 {
+            parts.push(format!("{} {}", count, unit));
+            rest %= unit_seconds as u64;
+        }
+    }
+    (parts.len() > 1).then(|| parts.join(" "))
+}
+
+/// Rewrite calculator syntax into something evalexpr can evaluate
+///
+/// Percentages are expanded first. Then, because evalexpr uses integer
+/// division for integer operands (5/2 == 2), which is surprising for a
+/// calculator, bare integer literals are coerced to floats (5/2 == 2.5).
+fn prepare(expr: &str) -> String {
+    floatify_int_literals(&expand_percentages(expr))
+}
+
+/// Expand percentages into plain arithmetic
+///
+/// - `X% of Y` becomes `X/100*Y`
+/// - `Y + X%` and `Y - X%` become `(Y)*(1+X/100)` and `(Y)*(1-X/100)`, where
+///   `Y` is everything to the left within the same parentheses, so
+///   `100 + 10% - 5%` applies both changes in turn
+/// - any other `X%` becomes `(X/100)`
+///
+/// A `%` followed by an operand (`10 % 3`) is still the modulo operator.
+fn expand_percentages(expr: &str) -> String {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut out = String::with_capacity(expr.len());
+    // Start offsets in `out` of the enclosing parenthesised groups
+    let mut groups = vec![0];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let starts_literal = c.is_ascii_digit()
+            && (i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_'));
+        if !starts_literal {
+            match c {
+                '(' => groups.push(out.len() + 1),
+                ')' if groups.len() > 1 => {
+                    groups.pop();
+                }
+                _ => {}
+            }
+            out.push(c);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+            i += 1;
+        }
+        let number: String = chars[start..i].iter().collect();
+
+        let after_number = skip_spaces(&chars, i);
+        if chars.get(after_number) != Some(&'%') {
+            out.push_str(&number);
+            continue;
+        }
+        let after_percent = skip_spaces(&chars, after_number + 1);
+        let is_of = chars.get(after_percent) == Some(&'o')
+            && chars.get(after_percent + 1) == Some(&'f')
+            && chars
+                .get(after_percent + 2)
+                .is_none_or(|c| !(c.is_alphanumeric() || *c == '_'));
+        let is_percent = is_of
+            || chars
+                .get(after_percent)
+                .is_none_or(|c| matches!(c, '+' | '-' | '*' | '/' | '^' | ')' | ','));
+        if !is_percent {
+            // Modulo: leave `%` for the main loop to copy
+            out.push_str(&number);
+            continue;
+        }
+
+        if is_of {
+            out.push_str(&format!("{}/100*", number));
+            i = after_percent + 2;
+            continue;
+        }
+        i = after_number + 1;
+
+        // `Y + X%` / `Y - X%`: scale everything to the left in this group
+        let group_start = *groups.last().unwrap_or(&0);
+        let before = out[group_start..].trim_end();
+        let op = before.chars().last();
+        let left = before[..before.len() - op.map_or(0, char::len_utf8)].trim();
+        match op {
+            Some(op @ ('+' | '-')) if ends_with_operand(left) => {
+                let scaled = format!("({})*(1{}{}/100)", left, op, number);
+                out.truncate(group_start);
+                out.push_str(&scaled);
+            }
+            _ => out.push_str(&format!("({}/100)", number)),
+        }
+    }
+
+    out
+}
+
+/// Index of the first non-space char at or after `i`
+fn skip_spaces(chars: &[char], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+/// Whether `s` ends in a value, so a following `+`/`-` is binary
+fn ends_with_operand(s: &str) -> bool {
+    s.chars()
+        .last()
+        .is_some_and(|c| c.is_alphanumeric() || matches!(c, ')' | '.' | '_'))
+}
+
 /// Append `.0` to standalone integer literals so evalexpr performs floating
 /// point arithmetic (e.g. `5/2` -> `5.0/2.0` -> `2.5`).
 ///
@@ -235,8 +877,69 @@ fn floatify_int_literals(expr: &str) -> String {
     out
 }
 
-/// Format a floating point result nicely
-fn format_result(value: f64) -> String {
+/// Format a result, switching to scientific notation outside the format's
+/// range and grouping thousands if asked to
+fn format_number(value: f64, format: &NumberFormat) -> String {
+    let magnitude = value.abs();
+    let scientific = value.is_finite()
+        && value != 0.0
+        && (magnitude >= 10f64.powi(format.scientific_above)
+            || magnitude < 10f64.powi(format.scientific_below));
+    if scientific {
+        return format_scientific(value, format.precision);
+    }
+    let formatted = format_result(value, format.precision);
+    if format.grouping {
+        group_thousands(&formatted)
+    } else {
+        formatted
+    }
+}
+
+/// Scientific notation with at most `precision` decimals in the mantissa:
+/// "1.23e15", "5e-9"
+fn format_scientific(value: f64, precision: usize) -> String {
+    let formatted = format!("{:.*e}", precision, value);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{}e{}", mantissa, exponent)
+}
+
+/// Insert commas between groups of three digits in the integer part
+fn group_thousands(number: &str) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", number),
+    };
+    let (integer, fraction) = match unsigned.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (unsigned, None),
+    };
+    // Leave "Infinity" and "NaN" alone.
+    if !integer.bytes().all(|b| b.is_ascii_digit()) {
+        return number.to_string();
+    }
+
+    let mut out = String::from(sign);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        out.push('.');
+        out.push_str(fraction);
+    }
+    out
+}
+
+/// Format a floating point result with at most `precision` decimal places
+fn format_result(value: f64, precision: usize) -> String {
     if value.is_infinite() {
         if value.is_sign_positive() {
             "Infinity".to_string()
@@ -249,32 +952,144 @@ fn format_result(value: f64) -> String {
         // Display as integer if it's a whole number
         format!("{}", value as i64)
     } else {
-        // Display with reasonable precision
-        let formatted = format!("{:.10}", value);
+        let formatted = format!("{:.*}", precision, value);
         // Remove trailing zeros
         let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
-        trimmed.to_string()
+        match trimmed {
+            // Rounded away entirely, e.g. 0.0001 with precision 2
+            "-0" => "0".to_string(),
+            _ => trimmed.to_string(),
+        }
+    }
+}
+
+/// `value` as a simplified `(numerator, denominator)`, if it is within
+/// rounding error of a fraction with a denominator up to `MAX_DENOMINATOR`
+///
+/// Walks the continued fraction expansion, whose convergents are the best
+/// rational approximations. Whole numbers return `None`.
+fn as_fraction(value: f64) -> Option<(i64, i64)> {
+    if !value.is_finite() || value.fract() == 0.0 || value.abs() >= 1e12 {
+        return None;
+    }
+    let target = value.abs();
+    let tolerance = 1e-9 * target.max(1.0);
+
+    // Previous two convergents h/k, seeded with 1/0 and 0/1
+    let (mut h_prev, mut h) = (0i64, 1i64);
+    let (mut k_prev, mut k) = (1i64, 0i64);
+    let mut x = target;
+    loop {
+        let a = x.floor() as i64;
+        let h_next = a.checked_mul(h)?.checked_add(h_prev)?;
+        let k_next = a.checked_mul(k)?.checked_add(k_prev)?;
+        if k_next > MAX_DENOMINATOR {
+            return None;
+        }
+        (h_prev, h, k_prev, k) = (h, h_next, k, k_next);
+
+        if (h as f64 / k as f64 - target).abs() <= tolerance {
+            return (k > 1).then(|| (if value < 0.0 { -h } else { h }, k));
+        }
+        let rest = x - x.floor();
+        if rest == 0.0 {
+            return None;
+        }
+        x = 1.0 / rest;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::providers::testing;
 
     fn eval(expr: &str) -> Option<String> {
-        let prepared = floatify_int_literals(expr);
+        let prepared = prepare(expr);
         let context = build_context();
         evalexpr::eval_with_context(&prepared, &context)
             .ok()
-            .and_then(|v| format_value(&v))
+            .and_then(|v| format_value(&v, &NumberFormat::default()))
     }
 
     #[test]
     fn test_format_result() {
-        assert_eq!(format_result(42.0), "42");
-        assert_eq!(format_result(3.14159), "3.14159");
-        assert_eq!(format_result(f64::INFINITY), "Infinity");
-        assert_eq!(format_result(f64::NEG_INFINITY), "-Infinity");
+        assert_eq!(format_result(42.0, 10), "42");
+        assert_eq!(format_result(1.23456, 10), "1.23456");
+        assert_eq!(format_result(f64::INFINITY, 10), "Infinity");
+        assert_eq!(format_result(f64::NEG_INFINITY, 10), "-Infinity");
+    }
+
+    #[test]
+    fn precision_limits_decimals_and_trims_zeros() {
+        assert_eq!(format_result(std::f64::consts::PI, 2), "3.14");
+        assert_eq!(format_result(2.5, 4), "2.5");
+        assert_eq!(format_result(1.999, 2), "2");
+        assert_eq!(format_result(-0.0001, 2), "0");
+
+        let calc = CalculatorProvider::new().with_precision(3);
+        assert_eq!(calc.query_impl("=sqrt(2)", 10)[0].text, "1.414");
+    }
+
+    #[test]
+    fn groups_thousands() {
+        assert_eq!(group_thousands("1000000"), "1,000,000");
+        assert_eq!(group_thousands("-12345.678"), "-12,345.678");
+        assert_eq!(group_thousands("999"), "999");
+        assert_eq!(group_thousands("Infinity"), "Infinity");
+
+        let calc = CalculatorProvider::new().with_grouping(true);
+        assert_eq!(calc.query_impl("=1000*1000", 10)[0].text, "1,000,000");
+        assert_eq!(calc.query_impl("=1234.5", 10)[0].text, "1,234.5");
+    }
+
+    #[test]
+    fn very_large_and_small_values_use_scientific_notation() {
+        let format = NumberFormat::default();
+        assert_eq!(format_number(1.23e15, &format), "1.23e15");
+        assert_eq!(format_number(-4e20, &format), "-4e20");
+        assert_eq!(format_number(5e-9, &format), "5e-9");
+        assert_eq!(format_number(0.0, &format), "0");
+        // Whole numbers within range stay integers.
+        assert_eq!(
+            format_number(999_999_999_999_999.0, &format),
+            "999999999999999"
+        );
+        assert_eq!(format_number(0.000001, &format), "0.000001");
+
+        let calc = CalculatorProvider::new().with_scientific_range(-3, 6);
+        assert_eq!(calc.query_impl("=2^20", 10)[0].text, "1.048576e6");
+        assert_eq!(calc.query_impl("=1/4096", 10)[0].text, "2.44140625e-4");
+        assert_eq!(calc.query_impl("=2^10", 10)[0].text, "1024");
+    }
+
+    #[test]
+    fn test_as_fraction() {
+        assert_eq!(as_fraction(0.25), Some((1, 4)));
+        assert_eq!(as_fraction(1.0 / 3.0), Some((1, 3)));
+        assert_eq!(as_fraction(-2.5), Some((-5, 2)));
+        assert_eq!(as_fraction(0.1 + 0.2), Some((3, 10)));
+        assert_eq!(as_fraction(3.0), None);
+        assert_eq!(as_fraction(std::f64::consts::PI), None);
+        assert_eq!(as_fraction(2f64.sqrt()), None);
+        assert_eq!(as_fraction(f64::NAN), None);
+    }
+
+    #[test]
+    fn fraction_is_offered_after_the_decimal() {
+        let items = CalculatorProvider::new().query_impl("=1/8", 10);
+        assert_eq!(items[0].text, "0.125");
+        assert_eq!(items[1].text, "1/8");
+        assert_eq!(items[1].metadata["result"], "1/8");
+        assert_eq!(items[1].metadata["copy"], "1/8");
+
+        let items = CalculatorProvider::new().query_impl("=sqrt(2)", 10);
+        assert_eq!(items.len(), 1);
+
+        let items = CalculatorProvider::new()
+            .with_fractions(false)
+            .query_impl("=1/8", 10);
+        assert_eq!(items.len(), 1);
     }
 
     #[test]
@@ -308,6 +1123,206 @@ mod tests {
         assert_eq!(eval("round(sin(pi))").as_deref(), Some("0"));
     }
 
+    #[test]
+    fn test_percent_of() {
+        assert_eq!(eval("20% of 80").as_deref(), Some("16"));
+        assert_eq!(eval("12.5% of 200").as_deref(), Some("25"));
+        assert_eq!(eval("10% of (50 + 50)").as_deref(), Some("10"));
+    }
+
+    #[test]
+    fn test_percent_increase_and_decrease() {
+        assert_eq!(eval("80 + 15%").as_deref(), Some("92"));
+        assert_eq!(eval("80 - 25%").as_deref(), Some("60"));
+        assert_eq!(eval("100 + 10% - 5%").as_deref(), Some("104.5"));
+        assert_eq!(eval("(50 + 10%) * 2").as_deref(), Some("110"));
+        assert_eq!(eval("2 * (100 - 50%)").as_deref(), Some("100"));
+    }
+
+    #[test]
+    fn test_bare_percent_and_modulo() {
+        assert_eq!(eval("50%").as_deref(), Some("0.5"));
+        assert_eq!(eval("200 * 10%").as_deref(), Some("20"));
+        assert_eq!(eval("-10%").as_deref(), Some("-0.1"));
+        // `%` followed by an operand is still modulo.
+        assert_eq!(eval("10%3").as_deref(), Some("1"));
+        assert_eq!(eval("10 % 3").as_deref(), Some("1"));
+        assert_eq!(expand_percentages("2+2"), "2+2");
+    }
+
+    #[test]
+    fn durations_are_added_and_converted() {
+        let calc = CalculatorProvider::new();
+        let texts = |input: &str| -> Vec<String> {
+            calc.query_impl(input, 10)
+                .into_iter()
+                .map(|item| item.text)
+                .collect()
+        };
+
+        assert_eq!(texts("=3h + 45m"), ["3.75 h", "3 h 45 min"]);
+        assert_eq!(texts("=90min in hours"), ["1.5 h", "1 h 30 min"]);
+        assert_eq!(texts("=2 days - 12h to min"), ["2160 min", "1 d 12 h"]);
+        assert_eq!(texts("=90 min"), ["90 min", "1 h 30 min"]);
+        assert_eq!(texts("=1h * 3"), ["3 h"]);
+        assert_eq!(texts("=30s + 15s"), ["45 s"]);
+        assert_eq!(
+            calc.query_impl("=3h + 45m", 10)[0].metadata["seconds"],
+            "13500"
+        );
+    }
+
+    #[test]
+    fn plain_arithmetic_is_not_a_duration() {
+        assert_eq!(rewrite_durations("min(1, 2) + 3"), None);
+        assert_eq!(rewrite_durations("3 min(1, 2)"), None);
+        assert_eq!(rewrite_durations("log10(100) * 2"), None);
+        assert_eq!(rewrite_durations("3h2"), None);
+        let calc = CalculatorProvider::new();
+        assert_eq!(run(&calc, &["=max(2, 3) * 2"]), "6");
+        assert_eq!(run(&calc, &["=2 + 2"]), "4");
+    }
+
+    #[test]
+    fn answers_through_the_provider_trait() {
+        let items = testing::query(&CalculatorProvider::new(), "=6*7", 10);
+        assert_eq!(items[0].text, "42");
+        assert_eq!(items[0].provider, "calculator");
+    }
+
+    #[test]
+    fn prefix_is_configurable() {
+        let calc = CalculatorProvider::new().with_prefix("calc ");
+        assert_eq!(calc.prefix(), Some("calc "));
+        assert_eq!(run(&calc, &["calc 6*7"]), "42");
+    }
+
+    /// Evaluate a sequence of inputs through one provider, as if typed
+    fn run(calc: &CalculatorProvider, inputs: &[&str]) -> String {
+        let mut last = String::new();
+        for input in inputs {
+            // Half-typed names ("=an") produce no items at all.
+            last = calc
+                .query_impl(input, 10)
+                .first()
+                .map(|item| item.text.clone())
+                .unwrap_or_default();
+        }
+        last
+    }
+
+    #[test]
+    fn test_split_assignment() {
+        assert_eq!(split_assignment("x = 5"), Some(("x", "5")));
+        assert_eq!(split_assignment("rate_2=0.5*3"), Some(("rate_2", "0.5*3")));
+        assert_eq!(split_assignment("x == 5"), None);
+        assert_eq!(split_assignment("x <= 5"), None);
+        assert_eq!(split_assignment("2 = 5"), None);
+        assert_eq!(split_assignment("2+2"), None);
+    }
+
+    #[test]
+    fn ans_refers_to_previous_finished_result() {
+        let calc = CalculatorProvider::new();
+        assert_eq!(run(&calc, &["=5", "=5*", "=5*3"]), "15");
+        // Typing `ans+10` keystroke by keystroke: each edit sees the same ans.
+        assert_eq!(
+            run(
+                &calc,
+                &["=", "=a", "=an", "=ans", "=ans+", "=ans+1", "=ans+10"]
+            ),
+            "25"
+        );
+        assert_eq!(run(&calc, &["=ans*2"]), "50");
+    }
+
+    #[test]
+    fn variables_can_be_assigned_and_referenced() {
+        let calc = CalculatorProvider::new();
+        let items = calc.query_impl("=x = 5", 10);
+        assert_eq!(items[0].text, "x = 5");
+        assert_eq!(items[0].metadata["variable"], "x");
+
+        assert_eq!(run(&calc, &["=x * 2"]), "10");
+        // Self-referencing assignments are evaluated once, not per keystroke.
+        assert_eq!(run(&calc, &["=x = x", "=x = x +", "=x = x + 1"]), "x = 6");
+        assert_eq!(run(&calc, &["=", "=x"]), "6");
+    }
+
+    #[test]
+    fn reserved_names_cannot_be_assigned() {
+        let calc = CalculatorProvider::new();
+        assert_eq!(run(&calc, &["=pi = 3"]), "Invalid assignment");
+        assert_eq!(run(&calc, &["=ans = 3"]), "Invalid assignment");
+    }
+
+    #[test]
+    fn plain_words_get_no_error_item() {
+        let calc = CalculatorProvider::new();
+        assert!(calc.query_impl("=firefox", 10).is_empty());
+        assert!(calc.query_impl("=hello world", 10).is_empty());
+        for input in ["=2 +", "=sqrt", "=notafunc(2)", "=pi pi"] {
+            assert_eq!(run(&calc, &[input]), "Invalid expression", "{}", input);
+        }
+
+        run(&calc, &["=rate = 2"]);
+        run(&calc, &["="]);
+        assert_eq!(run(&calc, &["=rate rate"]), "Invalid expression");
+    }
+
+    #[test]
+    fn unclosed_parentheses_are_closed_as_a_fallback() {
+        let calc = CalculatorProvider::new();
+        let items = calc.query_impl("=sqrt(16", 10);
+        assert_eq!(items[0].text, "4");
+        assert_eq!(items[0].subtext, "sqrt(16) = (auto-closed)");
+        assert_eq!(items[0].metadata["expression"], "sqrt(16)");
+
+        let items = calc.query_impl("=x = (1 + (2", 10);
+        assert_eq!(items[0].text, "x = 3");
+        assert_eq!(items[0].subtext, "Assign (1 + (2)) = (auto-closed)");
+
+        assert_eq!(calc.query_impl("=(2+3)", 10)[0].subtext, "(2+3) =");
+        assert_eq!(run(&calc, &["=sqrt(2 +"]), "Invalid expression");
+    }
+
+    #[test]
+    fn auto_closing_is_capped() {
+        assert_eq!(auto_close("((1").as_deref(), Some("((1))"));
+        assert_eq!(auto_close("(1)"), None);
+        assert_eq!(auto_close("1)("), None);
+        assert_eq!(auto_close("((((1"), None);
+    }
+
+    #[test]
+    fn extra_functions_are_available() {
+        assert_eq!(eval("hypot(3, 4)").as_deref(), Some("5"));
+        assert_eq!(eval("max(2, 7)").as_deref(), Some("7"));
+        assert_eq!(eval("min(2, 7)").as_deref(), Some("2"));
+        assert_eq!(eval("deg(pi)").as_deref(), Some("180"));
+        assert_eq!(eval("pow(2, 10)").as_deref(), Some("1024"));
+    }
+
+    #[test]
+    fn user_constants_are_usable_and_protected() {
+        let constants = HashMap::from([
+            ("r".to_string(), 2.0),
+            ("g".to_string(), 9.81),
+            ("sqrt".to_string(), 1.0),
+            ("pi".to_string(), 3.0),
+            ("true".to_string(), 1.0),
+            ("2x".to_string(), 1.0),
+        ]);
+        let calc = CalculatorProvider::new().with_constants(constants);
+        let mut names: Vec<_> = calc.constants.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["g", "r"]);
+
+        assert_eq!(run(&calc, &["=2*pi*r"]), "12.5663706144");
+        assert_eq!(run(&calc, &["=g*2"]), "19.62");
+        assert_eq!(run(&calc, &["=g = 10"]), "Invalid assignment");
+    }
+
     #[test]
     fn test_invalid() {
         // Unbound functions / unparseable input yield no result.
@@ -0,0 +1,116 @@
+//! Copying to the clipboard
+//!
+//! Items whose value is worth copying (a calculation, a hash, a generated
+//! password) carry it under the `copy` metadata key, set with
+//! [`Item::with_copy`](super::Item::with_copy), so a frontend can copy any of
+//! them without knowing which provider made it. [`copy`] does the copying
+//! with whichever tool suits the session: `wl-copy` under Wayland, `xclip` or
+//! `xsel` under X11.
+
+use crate::display::{self, DisplayServer};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The metadata key holding the text to copy when an item is activated
+pub const METADATA_KEY: &str = "copy";
+
+/// A clipboard tool and the arguments that make it read the clipboard's new
+/// contents from stdin
+type Tool = (&'static str, &'static [&'static str]);
+
+const WAYLAND_TOOLS: &[Tool] = &[("wl-copy", &[])];
+const X11_TOOLS: &[Tool] = &[
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+/// Put `text` on the clipboard
+///
+/// Fails if the session has no display, or none of its tools is installed.
+pub fn copy(text: &str) -> anyhow::Result<()> {
+    let tools = tools(display::current());
+    if tools.is_empty() {
+        anyhow::bail!("no display to copy to (neither WAYLAND_DISPLAY nor DISPLAY is set)");
+    }
+    for (program, args) in &tools {
+        match pipe_to(program, args, text) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            result => return result.map_err(|e| anyhow::anyhow!("{} failed: {}", program, e)),
+        }
+    }
+    let names: Vec<_> = tools.iter().map(|(program, _)| *program).collect();
+    anyhow::bail!("no clipboard tool found (tried {})", names.join(", "))
+}
+
+/// The tools to try, in order, on `server`
+///
+/// X11 tools are a fallback under Wayland, through XWayland.
+fn tools(server: DisplayServer) -> Vec<Tool> {
+    let mut tools = Vec::new();
+    if server.is_wayland() {
+        tools.extend_from_slice(WAYLAND_TOOLS);
+    }
+    if server.has_x11() {
+        tools.extend_from_slice(X11_TOOLS);
+    }
+    tools
+}
+
+/// Run `program` with `text` on its stdin, failing if it exits unsuccessfully
+///
+/// Its output is discarded: the clipboard tools fork a child that keeps
+/// serving the selection, which must not hold our pipes open.
+pub(super) fn pipe_to(program: &str, args: &[&str], text: &str) -> std::io::Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("exited with {}", status)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tools_follow_the_session() {
+        let names = |server| -> Vec<&str> {
+            tools(server)
+                .into_iter()
+                .map(|(program, _)| program)
+                .collect()
+        };
+        assert_eq!(
+            names(DisplayServer::Wayland { xwayland: false }),
+            ["wl-copy"]
+        );
+        assert_eq!(names(DisplayServer::X11), ["xclip", "xsel"]);
+        assert_eq!(
+            names(DisplayServer::Wayland { xwayland: true }),
+            ["wl-copy", "xclip", "xsel"]
+        );
+        assert!(names(DisplayServer::None).is_empty());
+    }
+
+    #[test]
+    fn text_is_piped_to_the_tool() {
+        let path = std::env::temp_dir().join(format!("datacube-copy-{}", uuid::Uuid::new_v4()));
+        let script = format!("cat > '{}'", path.display());
+        pipe_to("sh", &["-c", &script], "3 h 45 min").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "3 h 45 min");
+        let _ = std::fs::remove_file(&path);
+
+        assert!(pipe_to("false", &[], "x").is_err());
+        let missing = pipe_to("datacube-no-such-tool", &[], "x").unwrap_err();
+        assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,663 @@
+//! Color provider - parses color codes and converts between notations
+//!
+//! Hex codes (`#f00`, `#ff000080`), `rgb()`/`rgba()` and `hsl()`/`hsla()` are
+//! recognized anywhere; CSS color names need the prefix (`color rebeccapurple`)
+//! so ordinary words don't turn into colors. Each result carries a small SVG
+//! swatch written to the swatch directory on first use.
+
+use super::{Item, Provider};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use tracing::warn;
+
+/// CSS named colors (CSS Color Module Level 4)
+///
+/// Where two names share a value the first is used when naming a color.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+/// An sRGB color with alpha
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+    /// Opacity from 0.0 to 1.0
+    a: f64,
+}
+
+impl Color {
+    fn from_rgb(rgb: u32) -> Self {
+        Self {
+            r: (rgb >> 16) as u8,
+            g: (rgb >> 8) as u8,
+            b: rgb as u8,
+            a: 1.0,
+        }
+    }
+
+    fn rgb(&self) -> u32 {
+        u32::from(self.r) << 16 | u32::from(self.g) << 8 | u32::from(self.b)
+    }
+
+    fn is_opaque(&self) -> bool {
+        self.a >= 1.0
+    }
+
+    /// The CSS name for this exact color, if it has one
+    fn name(&self) -> Option<&'static str> {
+        if !self.is_opaque() {
+            return None;
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(_, rgb)| *rgb == self.rgb())
+            .map(|(name, _)| *name)
+    }
+
+    /// Hue in degrees, saturation and lightness in percent
+    fn hsl(&self) -> (f64, f64, f64) {
+        let [r, g, b] = [self.r, self.g, self.b].map(|c| f64::from(c) / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        if d == 0.0 {
+            return (0.0, 0.0, l * 100.0);
+        }
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / d + 2.0
+        } else {
+            (r - g) / d + 4.0
+        };
+        (h * 60.0, s * 100.0, l * 100.0)
+    }
+
+    fn from_hsl(h: f64, s: f64, l: f64, a: f64) -> Self {
+        let (s, l) = (s.clamp(0.0, 100.0) / 100.0, l.clamp(0.0, 100.0) / 100.0);
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        let channel = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Self {
+            r: channel(r),
+            g: channel(g),
+            b: channel(b),
+            a,
+        }
+    }
+
+    fn to_hex(self) -> String {
+        let hex = format!("#{:06x}", self.rgb());
+        if self.is_opaque() {
+            hex
+        } else {
+            format!("{}{:02x}", hex, (self.a * 255.0).round() as u8)
+        }
+    }
+
+    fn to_rgb_string(self) -> String {
+        if self.is_opaque() {
+            format!("rgb({}, {}, {})", self.r, self.g, self.b)
+        } else {
+            format!(
+                "rgba({}, {}, {}, {})",
+                self.r,
+                self.g,
+                self.b,
+                format_alpha(self.a)
+            )
+        }
+    }
+
+    fn to_hsl_string(self) -> String {
+        let (h, s, l) = self.hsl();
+        let (h, s, l) = (h.round() as u32 % 360, s.round(), l.round());
+        if self.is_opaque() {
+            format!("hsl({}, {}%, {}%)", h, s, l)
+        } else {
+            format!("hsla({}, {}%, {}%, {})", h, s, l, format_alpha(self.a))
+        }
+    }
+}
+
+fn format_alpha(a: f64) -> String {
+    let s = format!("{:.2}", a);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Parse a hex code, `rgb()`/`rgba()` or `hsl()`/`hsla()` notation
+fn parse_color(input: &str) -> Option<Color> {
+    let input = input.trim().to_ascii_lowercase();
+    if let Some(hex) = input.strip_prefix('#') {
+        return parse_hex(hex);
+    }
+
+    let (func, args) = input.strip_suffix(')')?.split_once('(')?;
+    let args: Vec<&str> = args
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .collect();
+    if args.len() != 3 && args.len() != 4 {
+        return None;
+    }
+    let alpha = match args.get(3) {
+        Some(a) => parse_fraction(a, 1.0)?.clamp(0.0, 1.0),
+        None => 1.0,
+    };
+
+    match func.trim() {
+        "rgb" | "rgba" => {
+            let mut channels = [0u8; 3];
+            for (channel, arg) in channels.iter_mut().zip(&args) {
+                *channel = parse_fraction(arg, 255.0)?.round().clamp(0.0, 255.0) as u8;
+            }
+            let [r, g, b] = channels;
+            Some(Color { r, g, b, a: alpha })
+        }
+        "hsl" | "hsla" => {
+            let h: f64 = args[0].trim_end_matches("deg").parse().ok()?;
+            let s: f64 = args[1].trim_end_matches('%').parse().ok()?;
+            let l: f64 = args[2].trim_end_matches('%').parse().ok()?;
+            Some(Color::from_hsl(h, s, l, alpha))
+        }
+        _ => None,
+    }
+}
+
+/// A plain number, or a percentage of `scale`
+fn parse_fraction(arg: &str, scale: f64) -> Option<f64> {
+    match arg.strip_suffix('%') {
+        Some(pct) => Some(pct.parse::<f64>().ok()? / 100.0 * scale),
+        None => arg.parse().ok(),
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digits: Vec<u8> = match hex.len() {
+        3 | 4 => hex
+            .chars()
+            .map(|c| u8::from_str_radix(&format!("{c}{c}"), 16))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        _ => return None,
+    };
+    Some(Color {
+        r: digits[0],
+        g: digits[1],
+        b: digits[2],
+        a: digits.get(3).map_or(1.0, |&a| f64::from(a) / 255.0),
+    })
+}
+
+fn named_color(name: &str) -> Option<Color> {
+    let name = name.replace(' ', "").to_ascii_lowercase();
+    NAMED_COLORS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, rgb)| Color::from_rgb(*rgb))
+}
+
+/// Provider for color code conversion
+pub struct ColorProvider {
+    prefix: String,
+    swatch_dir: PathBuf,
+    priority: i32,
+}
+
+impl ColorProvider {
+    /// Create a provider that writes swatch icons to `swatch_dir`
+    pub fn new(swatch_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            prefix: "color ".to_string(),
+            swatch_dir: swatch_dir.into(),
+            priority: 0,
+        }
+    }
+
+    /// Set the prefix that enables CSS color names (default: "color ")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Path of an SVG swatch for `color`, writing it if needed
+    fn swatch(&self, color: Color) -> Option<PathBuf> {
+        let path = self
+            .swatch_dir
+            .join(format!("{}.svg", color.to_hex().trim_start_matches('#')));
+        if !path.exists() {
+            if let Err(e) = write_swatch(&path, color) {
+                warn!("Failed to write swatch {:?}: {}", path, e);
+                return None;
+            }
+        }
+        Some(path)
+    }
+
+    fn query_impl(&self, query: &str, max_results: usize) -> Vec<Item> {
+        let (input, prefixed) = match query.strip_prefix(self.prefix.as_str()) {
+            Some(rest) => (rest.trim(), true),
+            None => (query.trim(), false),
+        };
+
+        if let Some(color) =
+            parse_color(input).or_else(|| prefixed.then(|| named_color(input)).flatten())
+        {
+            return self.conversions(color);
+        }
+
+        // With the prefix, suggest names starting with what was typed.
+        if !prefixed || input.is_empty() {
+            return Vec::new();
+        }
+        let partial = input.replace(' ', "").to_ascii_lowercase();
+        NAMED_COLORS
+            .iter()
+            .filter(|(name, _)| name.starts_with(&partial))
+            .take(max_results)
+            .map(|(name, rgb)| {
+                let color = Color::from_rgb(*rgb);
+                self.item(name.to_string(), color.to_hex(), color)
+                    .with_score(0.5)
+            })
+            .collect()
+    }
+
+    /// One item per notation for `color`
+    fn conversions(&self, color: Color) -> Vec<Item> {
+        let mut notations = vec![
+            (color.to_hex(), "Hex"),
+            (color.to_rgb_string(), "RGB"),
+            (color.to_hsl_string(), "HSL"),
+        ];
+        if let Some(name) = color.name() {
+            notations.push((name.to_string(), "CSS name"));
+        }
+
+        notations
+            .into_iter()
+            .enumerate()
+            .map(|(i, (text, label))| {
+                self.item(text, label.to_string(), color)
+                    .with_score(1.0 - i as f32 * 0.01)
+            })
+            .collect()
+    }
+
+    fn item(&self, text: String, subtext: String, color: Color) -> Item {
+        let mut item = Item::new(text.as_str(), "color")
+            .with_stable_id(&text)
+            .with_subtext(subtext)
+            .with_icon("color-select")
+            .with_metadata("hex", color.to_hex())
+            .with_metadata("rgb", color.to_rgb_string())
+            .with_metadata("hsl", color.to_hsl_string())
+            .with_copy(&text);
+        if let Some(name) = color.name() {
+            item = item.with_metadata("name", name);
+        }
+        if let Some(swatch) = self.swatch(color) {
+            item = item.with_icon_path(swatch.to_string_lossy());
+        }
+        item
+    }
+}
+
+fn write_swatch(path: &Path, color: Color) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"64\" height=\"64\">\
+         <rect width=\"64\" height=\"64\" rx=\"12\" fill=\"#{:06x}\" fill-opacity=\"{}\"/></svg>\n",
+        color.rgb(),
+        format_alpha(color.a)
+    );
+    // Write then rename so a concurrent reader never sees half a file.
+    let tmp = path.with_extension("svg.tmp");
+    std::fs::write(&tmp, svg)?;
+    std::fs::rename(tmp, path)
+}
+
+impl Provider for ColorProvider {
+    fn name(&self) -> &str {
+        "color"
+    }
+
+    fn description(&self) -> &str {
+        "Color code conversion"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Results are values to read or copy; activating one does nothing more
+    fn supports_activate(&self) -> bool {
+        false
+    }
+
+    /// Color codes are recognized without the prefix; names need it
+    fn requires_prefix(&self) -> bool {
+        false
+    }
+
+    fn can_handle(&self, query: &str) -> bool {
+        query.starts_with(self.prefix.as_str()) || parse_color(query).is_some()
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let result = self.query_impl(query, max_results);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct SwatchDir(PathBuf);
+
+    impl SwatchDir {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("datacube-swatches-{}", uuid::Uuid::new_v4())))
+        }
+    }
+
+    impl Drop for SwatchDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn texts(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|i| i.text.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_hex_codes() {
+        assert_eq!(parse_color("#f00"), Some(Color::from_rgb(0xff0000)));
+        assert_eq!(parse_color("#FF8000"), Some(Color::from_rgb(0xff8000)));
+        let translucent = parse_color("#ff000080").unwrap();
+        assert!((translucent.a - 128.0 / 255.0).abs() < 1e-9);
+        assert_eq!(parse_color("#ff00"), parse_color("#ffff0000"));
+        assert!(parse_color("#ggg").is_none());
+        assert!(parse_color("#12345").is_none());
+    }
+
+    #[test]
+    fn parses_functional_notation() {
+        assert_eq!(
+            parse_color("rgb(255, 0, 0)"),
+            Some(Color::from_rgb(0xff0000))
+        );
+        assert_eq!(
+            parse_color("rgb(100% 50% 0%)"),
+            Some(Color::from_rgb(0xff8000))
+        );
+        assert_eq!(parse_color("rgba(0,0,255,0.5)").unwrap().a, 0.5);
+        assert_eq!(
+            parse_color("hsl(120, 100%, 25%)"),
+            Some(Color::from_rgb(0x008000))
+        );
+        assert_eq!(
+            parse_color("hsl(270deg 50% 40%)"),
+            Some(Color::from_rgb(0x663399))
+        );
+        assert!(parse_color("rgb(1, 2)").is_none());
+        assert!(parse_color("cmyk(0, 0, 0, 0)").is_none());
+    }
+
+    #[test]
+    fn converts_to_every_notation() {
+        let color = Color::from_rgb(0x663399);
+        assert_eq!(color.to_hex(), "#663399");
+        assert_eq!(color.to_rgb_string(), "rgb(102, 51, 153)");
+        assert_eq!(color.to_hsl_string(), "hsl(270, 50%, 40%)");
+        assert_eq!(color.name(), Some("rebeccapurple"));
+
+        let translucent = Color { a: 0.5, ..color };
+        assert_eq!(translucent.to_hex(), "#66339980");
+        assert_eq!(translucent.to_rgb_string(), "rgba(102, 51, 153, 0.5)");
+        assert_eq!(translucent.name(), None);
+    }
+
+    #[test]
+    fn hsl_round_trips() {
+        for &(_, rgb) in NAMED_COLORS {
+            let color = Color::from_rgb(rgb);
+            let (h, s, l) = color.hsl();
+            assert_eq!(Color::from_hsl(h, s, l, 1.0), color, "{:06x}", rgb);
+        }
+    }
+
+    #[test]
+    fn detects_codes_without_prefix_but_names_need_it() {
+        let dir = SwatchDir::new();
+        let provider = ColorProvider::new(&dir.0);
+
+        assert!(provider.can_handle("#ff0000"));
+        assert!(provider.can_handle("rgb(1, 2, 3)"));
+        assert!(!provider.can_handle("red"));
+        assert!(provider.can_handle("color red"));
+        assert!(!provider.info().requires_prefix);
+
+        let items = provider.query_impl("#ff0000", 10);
+        assert_eq!(
+            texts(&items),
+            vec!["#ff0000", "rgb(255, 0, 0)", "hsl(0, 100%, 50%)", "red"]
+        );
+        assert_eq!(items[1].metadata["copy"], "rgb(255, 0, 0)");
+        assert_eq!(items[1].metadata["hex"], "#ff0000");
+
+        let items = provider.query_impl("color Rebecca Purple", 10);
+        assert_eq!(items[0].text, "#663399");
+    }
+
+    #[test]
+    fn suggests_names_from_a_partial_prefix() {
+        let dir = SwatchDir::new();
+        let provider = ColorProvider::new(&dir.0);
+
+        let items = provider.query_impl("color lightsl", 10);
+        assert_eq!(texts(&items), vec!["lightslategray", "lightslategrey"]);
+        assert_eq!(items[0].subtext, "#778899");
+        assert!(provider.query_impl("lightsl", 10).is_empty());
+    }
+
+    #[test]
+    fn writes_swatch_icons() {
+        let dir = SwatchDir::new();
+        let provider = ColorProvider::new(&dir.0);
+
+        let items = provider.query_impl("#00ff00", 10);
+        let path = PathBuf::from(&items[0].icon_path);
+        assert_eq!(path, dir.0.join("00ff00.svg"));
+        let svg = std::fs::read_to_string(path).unwrap();
+        assert!(svg.contains("fill=\"#00ff00\""));
+    }
+}
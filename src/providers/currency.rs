@@ -0,0 +1,644 @@
+//! Currency provider - converts amounts using cached exchange rates
+//!
+//! Rates are fetched from a configurable endpoint and cached on disk, so
+//! queries never wait on the network. A background task refreshes them once
+//! they are older than the TTL; a query that finds them missing or stale also
+//! kicks off a refresh, and answers from whatever rates are on hand.
+//!
+//! The endpoint must return JSON with a `rates` object mapping currency codes
+//! to their value in the base currency (the format used by most free rate
+//! APIs). Rates are fetched with `curl` rather than pulling an HTTP/TLS stack
+//! into the daemon.
+
+use super::{Item, Provider};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::runtime::Handle;
+use tracing::{debug, info, warn};
+
+/// Default rate endpoint; `{base}` and `{api_key}` are substituted
+pub const DEFAULT_ENDPOINT: &str = "https://open.er-api.com/v6/latest/{base}";
+
+/// Longest wait between background staleness checks
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Exchange rates relative to a base currency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Rates {
+    /// Currency the rates are relative to
+    base: String,
+    /// Currency code -> units per one `base`
+    rates: HashMap<String, f64>,
+    /// When the rates were fetched (seconds since the Unix epoch)
+    fetched_at: u64,
+}
+
+impl Rates {
+    /// Units of `to` per one unit of `from`
+    fn rate(&self, from: &str, to: &str) -> Option<f64> {
+        let per_base = |code: &str| {
+            if code == self.base {
+                Some(1.0)
+            } else {
+                self.rates.get(code).copied()
+            }
+        };
+        Some(per_base(to)? / per_base(from)?)
+    }
+
+    fn age(&self) -> Duration {
+        Duration::from_secs(unix_now().saturating_sub(self.fetched_at))
+    }
+}
+
+/// A parsed conversion request: `<amount> <from> [to|in] <to>`
+#[derive(Debug, PartialEq)]
+struct Conversion {
+    amount: f64,
+    from: String,
+    to: Option<String>,
+}
+
+/// Provider for currency conversion
+pub struct CurrencyProvider {
+    prefix: String,
+    endpoint: String,
+    api_key: Option<String>,
+    base: String,
+    ttl: Duration,
+    cache_path: PathBuf,
+    priority: i32,
+    state: Arc<RateState>,
+}
+
+/// Rates shared between queries and refresh tasks
+#[derive(Default)]
+struct RateState {
+    rates: RwLock<Option<Rates>>,
+    /// Set while a refresh is in flight, so keystrokes don't pile up fetches
+    refreshing: AtomicBool,
+}
+
+/// Where and how often to fetch rates
+#[derive(Clone)]
+struct RateSource {
+    url: String,
+    base: String,
+    cache_path: PathBuf,
+    ttl: Duration,
+}
+
+impl RateSource {
+    /// Start a background refresh if the rates are missing or stale
+    fn refresh_if_stale(&self, state: &Arc<RateState>) {
+        let stale = match state.rates.read() {
+            Ok(guard) => guard.as_ref().is_none_or(|r| r.age() >= self.ttl),
+            Err(_) => true,
+        };
+        if !stale || state.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let source = self.clone();
+        let state = Arc::clone(state);
+        tokio::spawn(async move {
+            match fetch_rates(&source.url, &source.base).await {
+                Ok(fetched) => {
+                    info!("Fetched {} exchange rates", fetched.rates.len());
+                    if let Err(e) = save_cache(&source.cache_path, &fetched) {
+                        warn!("Failed to cache exchange rates: {}", e);
+                    }
+                    if let Ok(mut guard) = state.rates.write() {
+                        *guard = Some(fetched);
+                    }
+                }
+                Err(e) => warn!("Failed to fetch exchange rates: {}", e),
+            }
+            state.refreshing.store(false, Ordering::Release);
+        });
+    }
+}
+
+impl CurrencyProvider {
+    /// Create a provider caching rates at `cache_path`
+    ///
+    /// Any previously cached rates are loaded immediately.
+    pub fn new(cache_path: impl Into<PathBuf>) -> Self {
+        let cache_path = cache_path.into();
+        let rates = load_cache(&cache_path);
+        Self {
+            prefix: "$".to_string(),
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            api_key: None,
+            base: "USD".to_string(),
+            ttl: Duration::from_secs(12 * 60 * 60),
+            cache_path,
+            priority: 0,
+            state: Arc::new(RateState {
+                rates: RwLock::new(rates),
+                refreshing: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Set the query prefix (default: "$")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the rate endpoint URL; `{base}` and `{api_key}` are substituted
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    /// Set the API key substituted into the endpoint
+    pub fn with_api_key(mut self, api_key: Option<String>) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    /// Set the base currency requested from the endpoint (default: USD)
+    pub fn with_base(mut self, base: impl Into<String>) -> Self {
+        self.base = base.into().to_uppercase();
+        self
+    }
+
+    /// Set how long cached rates are used before refreshing (default: 12h)
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// The endpoint with placeholders filled in
+    fn url(&self) -> String {
+        self.endpoint
+            .replace("{base}", &self.base)
+            .replace("{api_key}", self.api_key.as_deref().unwrap_or(""))
+    }
+
+    fn source(&self) -> RateSource {
+        RateSource {
+            url: self.url(),
+            base: self.base.clone(),
+            cache_path: self.cache_path.clone(),
+            ttl: self.ttl,
+        }
+    }
+
+    fn query_impl(&self, query: &str) -> Vec<Item> {
+        let input = query.strip_prefix(self.prefix.as_str()).unwrap_or(query);
+
+        let Some(conversion) = parse_conversion(input) else {
+            return vec![
+                Item::new("Convert currency (e.g., 100 usd to eur)", "currency")
+                    .with_stable_id("hint")
+                    .with_icon("accessories-calculator")
+                    .with_score(1.0),
+            ];
+        };
+        let Some(to) = conversion.to else {
+            return vec![Item::new(
+                format!(
+                    "{} {} to ...",
+                    format_amount(conversion.amount),
+                    conversion.from
+                ),
+                "currency",
+            )
+            .with_stable_id("hint")
+            .with_subtext("Enter a target currency")
+            .with_icon("accessories-calculator")
+            .with_score(1.0)];
+        };
+
+        let rates = match self.state.rates.read() {
+            Ok(guard) => guard.clone(),
+            Err(_) => None,
+        };
+        let Some(rates) = rates else {
+            return vec![Item::new("Fetching exchange rates...", "currency")
+                .with_stable_id("fetching")
+                .with_subtext("Try again in a moment")
+                .with_icon("view-refresh")
+                .with_score(0.5)];
+        };
+
+        let Some(rate) = rates.rate(&conversion.from, &to) else {
+            return vec![Item::new("Unknown currency", "currency")
+                .with_stable_id("error")
+                .with_subtext(format!("No rate for {} to {}", conversion.from, to))
+                .with_icon("dialog-error")
+                .with_score(0.5)];
+        };
+
+        let result = conversion.amount * rate;
+        debug!(
+            "Currency: {} {} = {} {}",
+            conversion.amount, conversion.from, result, to
+        );
+
+        let age = rates.age();
+        vec![
+            Item::new(format!("{} {}", format_amount(result), to), "currency")
+                .with_stable_id(format!("{} {} {}", conversion.amount, conversion.from, to))
+                .with_subtext(format!(
+                    "{} {} at 1 {} = {} {} (rates {})",
+                    format_amount(conversion.amount),
+                    conversion.from,
+                    conversion.from,
+                    format_rate(rate),
+                    to,
+                    format_age(age)
+                ))
+                .with_icon("accessories-calculator")
+                .with_score(1.0)
+                .with_metadata("amount", conversion.amount.to_string())
+                .with_metadata("from", &conversion.from)
+                .with_metadata("to", &to)
+                .with_metadata("rate", rate.to_string())
+                .with_metadata("result", format_amount(result))
+                .with_copy(format_amount(result))
+                // Stale once the rates are due for a refresh
+                .with_ttl(self.ttl.saturating_sub(age))
+                .with_metadata("rates_age_secs", age.as_secs().to_string()),
+        ]
+    }
+}
+
+impl Provider for CurrencyProvider {
+    fn name(&self) -> &str {
+        "currency"
+    }
+
+    fn description(&self) -> &str {
+        "Convert between currencies"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Results are values to read or copy; activating one does nothing more
+    fn supports_activate(&self) -> bool {
+        false
+    }
+
+    fn is_network(&self) -> bool {
+        true
+    }
+
+    fn spawn_background(&self, handle: Handle) {
+        // Fetch at startup if needed, then re-check periodically. The task
+        // holds only a weak reference so it ends once the provider is dropped.
+        let source = self.source();
+        let state = Arc::downgrade(&self.state);
+        let interval = source.ttl.min(MAX_CHECK_INTERVAL);
+        handle.spawn(refresh_loop(source, state, interval));
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        _max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        self.source().refresh_if_stale(&self.state);
+        let result = self.query_impl(query);
+        Box::pin(async move { result })
+    }
+}
+
+/// Refresh stale rates every `interval` for as long as the provider exists
+async fn refresh_loop(source: RateSource, state: Weak<RateState>, interval: Duration) {
+    loop {
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        source.refresh_if_stale(&state);
+        drop(state);
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Parse `<amount> <from> [to|in] <to>`, e.g. `100 usd to eur` or `5eur gbp`
+fn parse_conversion(input: &str) -> Option<Conversion> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .unwrap_or(input.len());
+    let amount: f64 = input[..split].replace(',', "").parse().ok()?;
+
+    let mut words = input[split..].split_whitespace();
+    let from = words.next().filter(|w| is_currency_code(w))?.to_uppercase();
+    let to = match words.next() {
+        Some(w) if w.eq_ignore_ascii_case("to") || w.eq_ignore_ascii_case("in") => words.next(),
+        other => other,
+    };
+    let to = match to {
+        Some(code) if is_currency_code(code) => Some(code.to_uppercase()),
+        Some(_) => return None,
+        None => None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+
+    Some(Conversion { amount, from, to })
+}
+
+fn is_currency_code(word: &str) -> bool {
+    word.len() == 3 && word.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+/// Fetch rates from `url` with curl and parse the response
+///
+/// The URL may hold the API key, so it goes to curl as a config file on
+/// stdin rather than on its command line, where other users can read it.
+async fn fetch_rates(url: &str, base: &str) -> anyhow::Result<Rates> {
+    let mut child = tokio::process::Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location"])
+        .args(["--max-time", "15"])
+        .args(["--config", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(curl_config_url(url).as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "curl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse_rates(&output.stdout, base)
+}
+
+/// A curl config file line setting `url`, quoted and escaped
+fn curl_config_url(url: &str) -> String {
+    let mut line = String::from("url = \"");
+    for c in url.chars() {
+        match c {
+            '\\' => line.push_str("\\\\"),
+            '"' => line.push_str("\\\""),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            '\t' => line.push_str("\\t"),
+            c => line.push(c),
+        }
+    }
+    line.push_str("\"\n");
+    line
+}
+
+/// Parse a rate API response, falling back to `base` if it doesn't name one
+fn parse_rates(body: &[u8], base: &str) -> anyhow::Result<Rates> {
+    let json: serde_json::Value = serde_json::from_slice(body)?;
+    let rates: HashMap<String, f64> = json
+        .get("rates")
+        .and_then(|r| r.as_object())
+        .ok_or_else(|| anyhow::anyhow!("response has no 'rates' object"))?
+        .iter()
+        .filter_map(|(code, value)| Some((code.to_uppercase(), value.as_f64()?)))
+        .collect();
+    let base = ["base", "base_code", "source"]
+        .iter()
+        .find_map(|key| json.get(*key).and_then(|b| b.as_str()))
+        .unwrap_or(base)
+        .to_uppercase();
+
+    Ok(Rates {
+        base,
+        rates,
+        fetched_at: unix_now(),
+    })
+}
+
+fn load_cache(path: &PathBuf) -> Option<Rates> {
+    let content = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(rates) => Some(rates),
+        Err(e) => {
+            warn!("Ignoring unreadable rate cache {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn save_cache(path: &PathBuf, rates: &Rates) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string(rates)?)?;
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn format_amount(value: f64) -> String {
+    format!("{:.2}", value)
+}
+
+/// Format a rate with enough precision for small-unit currencies
+fn format_rate(rate: f64) -> String {
+    let formatted = format!("{:.6}", rate);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => "just updated".to_string(),
+        60..3600 => format!("{}m old", secs / 60),
+        3600..86400 => format!("{}h old", secs / 3600),
+        _ => format!("{}d old", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rates(fetched_at: u64) -> Rates {
+        Rates {
+            base: "USD".to_string(),
+            rates: HashMap::from([("EUR".to_string(), 0.5), ("JPY".to_string(), 150.0)]),
+            fetched_at,
+        }
+    }
+
+    fn provider_with(rates: Rates) -> CurrencyProvider {
+        let path = std::env::temp_dir().join(format!("datacube-rates-{}", uuid::Uuid::new_v4()));
+        let provider = CurrencyProvider::new(path);
+        *provider.state.rates.write().unwrap() = Some(rates);
+        provider
+    }
+
+    #[test]
+    fn parses_conversions() {
+        let parsed = parse_conversion("100 usd to eur").unwrap();
+        assert_eq!(parsed.amount, 100.0);
+        assert_eq!(parsed.from, "USD");
+        assert_eq!(parsed.to.as_deref(), Some("EUR"));
+
+        assert_eq!(
+            parse_conversion("1,250.5EUR in jpy").unwrap().amount,
+            1250.5
+        );
+        assert_eq!(
+            parse_conversion("5 eur gbp").unwrap().to.as_deref(),
+            Some("GBP")
+        );
+        assert_eq!(parse_conversion("5 eur").unwrap().to, None);
+        assert_eq!(parse_conversion("usd to eur"), None);
+        assert_eq!(parse_conversion("5 euros to yen"), None);
+    }
+
+    #[test]
+    fn converts_through_the_base_currency() {
+        let provider = provider_with(rates(unix_now() - 3 * 3600));
+
+        let items = provider.query_impl("$100 usd to eur");
+        assert_eq!(items[0].text, "50.00 EUR");
+        assert_eq!(items[0].metadata["copy"], "50.00");
+        // Rates fetched 3 hours into a 12 hour ttl
+        let ttl = items[0].ttl.unwrap();
+        assert!(ttl <= Duration::from_secs(9 * 3600) && ttl > Duration::from_secs(8 * 3600));
+        assert!(items[0].subtext.contains("3h old"));
+
+        let items = provider.query_impl("$1 eur to jpy");
+        assert_eq!(items[0].text, "300.00 JPY");
+        assert_eq!(items[0].metadata["rate"], "300");
+    }
+
+    #[test]
+    fn unknown_currency_and_missing_rates_are_reported() {
+        let provider = provider_with(rates(unix_now()));
+        assert_eq!(
+            provider.query_impl("$1 usd to xyz")[0].text,
+            "Unknown currency"
+        );
+
+        let path = std::env::temp_dir().join(format!("datacube-rates-{}", uuid::Uuid::new_v4()));
+        let empty = CurrencyProvider::new(path);
+        assert_eq!(
+            empty.query_impl("$1 usd to eur")[0].text,
+            "Fetching exchange rates..."
+        );
+    }
+
+    #[test]
+    fn parses_common_response_shapes() {
+        let body = br#"{"result":"success","base_code":"EUR","rates":{"usd":1.1,"EUR":1}}"#;
+        let parsed = parse_rates(body, "USD").unwrap();
+        assert_eq!(parsed.base, "EUR");
+        assert_eq!(parsed.rates["USD"], 1.1);
+
+        assert!(parse_rates(br#"{"error":"bad key"}"#, "USD").is_err());
+    }
+
+    #[test]
+    fn cache_round_trips_through_disk() {
+        let path = std::env::temp_dir()
+            .join(format!("datacube-rates-{}", uuid::Uuid::new_v4()))
+            .join("currency.json");
+        save_cache(&path, &rates(42)).unwrap();
+
+        let provider = CurrencyProvider::new(&path);
+        let loaded = provider.state.rates.read().unwrap().clone().unwrap();
+        assert_eq!(loaded.fetched_at, 42);
+        assert_eq!(loaded.rates["EUR"], 0.5);
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn endpoint_placeholders_are_filled() {
+        let path = std::env::temp_dir().join("datacube-unused-rates.json");
+        let provider = CurrencyProvider::new(path)
+            .with_endpoint("https://example.com/{api_key}/latest/{base}")
+            .with_api_key(Some("secret".to_string()))
+            .with_base("eur");
+        assert_eq!(provider.url(), "https://example.com/secret/latest/EUR");
+    }
+
+    #[test]
+    fn curl_config_quotes_the_url() {
+        assert_eq!(
+            curl_config_url("https://example.com/latest?key=a b"),
+            "url = \"https://example.com/latest?key=a b\"\n"
+        );
+        assert_eq!(
+            curl_config_url("https://x/\"q\\\n"),
+            "url = \"https://x/\\\"q\\\\\\n\"\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn background_task_fetches_and_stops_with_the_provider() {
+        let dir = std::env::temp_dir().join(format!("datacube-rates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let response = dir.join("response.json");
+        std::fs::write(&response, r#"{"base":"USD","rates":{"EUR":0.5}}"#).unwrap();
+
+        let provider = CurrencyProvider::new(dir.join("cache.json"))
+            .with_endpoint(format!("file://{}", response.display()));
+        provider.spawn_background(Handle::current());
+
+        let state = Arc::downgrade(&provider.state);
+        let mut fetched = false;
+        for _ in 0..200 {
+            if provider.state.rates.read().unwrap().is_some() {
+                fetched = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // Without curl in the environment there is nothing to fetch with.
+        if which_curl() {
+            assert!(fetched, "background task should fetch rates");
+        }
+
+        drop(provider);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(state.upgrade().is_none(), "task must not keep state alive");
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    fn which_curl() -> bool {
+        std::process::Command::new("curl")
+            .arg("--version")
+            .output()
+            .is_ok()
+    }
+}
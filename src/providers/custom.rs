@@ -0,0 +1,220 @@
+//! Custom provider - launcher entries defined in the config file
+//!
+//! For shortcuts such as "start VPN" that don't warrant a desktop file. Each
+//! entry has a name, a command line and optionally an icon, keywords and a
+//! comment; queries are fuzzy-matched against the name, then the keywords.
+//!
+//! Items carry the command line in their `exec` metadata, plus `terminal`,
+//! for the frontend to run the way it runs applications.
+
+use super::{IconKind, Item, Provider};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+/// One configured entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomEntry {
+    /// Name shown and matched against
+    pub name: String,
+
+    /// Command line to run
+    pub exec: String,
+
+    /// Icon theme name or absolute path
+    #[serde(default)]
+    pub icon: String,
+
+    /// Further words the entry is found by
+    #[serde(default)]
+    pub keywords: Vec<String>,
+
+    /// Secondary text shown under the name
+    #[serde(default)]
+    pub comment: Option<String>,
+
+    /// Whether the command runs in a terminal
+    #[serde(default)]
+    pub terminal: bool,
+}
+
+/// Provider for entries from the config file
+pub struct CustomProvider {
+    entries: Vec<CustomEntry>,
+    prefix: Option<String>,
+    priority: i32,
+    matcher: SkimMatcherV2,
+}
+
+impl CustomProvider {
+    pub fn new(entries: Vec<CustomEntry>) -> Self {
+        Self {
+            entries,
+            prefix: None,
+            priority: 0,
+            matcher: SkimMatcherV2::default(),
+        }
+    }
+
+    /// Only answer queries starting with `prefix` (default: none)
+    pub fn with_prefix(mut self, prefix: Option<String>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Match score of an entry: name matches rank above keyword matches
+    fn score(&self, entry: &CustomEntry, query: &str) -> Option<i64> {
+        if let Some(score) = self.matcher.fuzzy_match(&entry.name, query) {
+            return Some(score + 1000);
+        }
+        entry
+            .keywords
+            .iter()
+            .filter_map(|keyword| self.matcher.fuzzy_match(keyword, query))
+            .max()
+    }
+
+    fn query_impl(&self, query: &str, max_results: usize) -> Vec<Item> {
+        let query = match &self.prefix {
+            Some(prefix) => match query.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.trim(),
+                None => return Vec::new(),
+            },
+            None => query.trim(),
+        };
+        // Without a prefix an empty query belongs to the applications list;
+        // with one, every entry is listed.
+        if query.is_empty() {
+            return match self.prefix {
+                Some(_) => self
+                    .entries
+                    .iter()
+                    .take(max_results)
+                    .map(|entry| custom_item(entry, 0.5))
+                    .collect(),
+                None => Vec::new(),
+            };
+        }
+
+        let mut scored: Vec<(&CustomEntry, i64)> = self
+            .entries
+            .iter()
+            .filter_map(|entry| Some((entry, self.score(entry, query)?)))
+            .collect();
+        scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+
+        scored
+            .into_iter()
+            .take(max_results)
+            .map(|(entry, score)| custom_item(entry, (score as f32 / 2000.0).clamp(0.0, 1.0)))
+            .collect()
+    }
+}
+
+impl Provider for CustomProvider {
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    fn description(&self) -> &str {
+        "Launcher entries from the config file"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let result = self.query_impl(query, max_results);
+        Box::pin(async move { result })
+    }
+}
+
+fn custom_item(entry: &CustomEntry, score: f32) -> Item {
+    let icon_kind = if entry.icon.starts_with('/') {
+        IconKind::AbsolutePath
+    } else {
+        IconKind::ThemedName
+    };
+    Item::new(&entry.name, "custom")
+        .with_stable_id(&entry.name)
+        .with_subtext(entry.comment.as_deref().unwrap_or(&entry.exec))
+        .with_icon(&entry.icon)
+        .with_icon_kind(icon_kind)
+        .with_score(score)
+        .with_metadata("exec", &entry.exec)
+        .with_metadata("terminal", if entry.terminal { "true" } else { "false" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing::{query, texts};
+
+    fn entry(name: &str, exec: &str, keywords: &[&str]) -> CustomEntry {
+        CustomEntry {
+            name: name.to_string(),
+            exec: exec.to_string(),
+            icon: "network-vpn".to_string(),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            comment: None,
+            terminal: false,
+        }
+    }
+
+    fn provider() -> CustomProvider {
+        CustomProvider::new(vec![
+            entry("Start VPN", "nmcli con up work", &["network", "tunnel"]),
+            entry("Mount NAS", "mount /mnt/nas", &["storage"]),
+        ])
+    }
+
+    #[test]
+    fn matches_names_before_keywords() {
+        let provider = provider();
+        let items = query(&provider, "vpn", 10);
+        assert_eq!(texts(&items), ["Start VPN"]);
+        assert_eq!(items[0].metadata["exec"], "nmcli con up work");
+        assert_eq!(items[0].subtext, "nmcli con up work");
+
+        assert_eq!(texts(&query(&provider, "storage", 10)), ["Mount NAS"]);
+        assert!(query(&provider, "Mount", 10)[0].score > query(&provider, "storage", 10)[0].score);
+    }
+
+    #[test]
+    fn empty_query_lists_entries_only_with_a_prefix() {
+        assert!(query(&provider(), "", 10).is_empty());
+
+        let prefixed = provider().with_prefix(Some("c ".to_string()));
+        assert_eq!(
+            texts(&query(&prefixed, "c ", 10)),
+            ["Start VPN", "Mount NAS"]
+        );
+        assert!(query(&prefixed, "vpn", 10).is_empty());
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        let provider = provider();
+        assert_eq!(
+            query(&provider, "vpn", 10)[0].id,
+            query(&provider, "start", 10)[0].id
+        );
+    }
+}
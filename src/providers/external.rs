@@ -0,0 +1,370 @@
+//! External provider - results from a program run for each query
+//!
+//! Lets data sources be added without recompiling datacube. The configured
+//! command is run with the query (prefix removed) as its last argument, and
+//! prints one [`ExternalItem`] per line on stdout as JSON:
+//!
+//! ```text
+//! {"text": "Dark mode", "provider": "theme", "subtext": "Toggle", "score": 0.8, "metadata": {"exec": "toggle-theme"}}
+//! ```
+//!
+//! The format is versioned by [`SCHEMA_VERSION`], which commands receive in
+//! the `DATACUBE_SCHEMA_VERSION` environment variable. Items that fail
+//! [`ExternalItem::validate`] or don't parse are skipped with a warning; so is
+//! a command that fails or runs past its timeout, which is killed. Like every
+//! other provider, external ones don't activate anything: frontends act on the
+//! items' metadata.
+
+use super::{IconKind, Item, Provider};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::time::Duration;
+use tracing::warn;
+
+/// Version of the [`ExternalItem`] format this build reads
+///
+/// Bumped only for changes that older commands or readers would
+/// misunderstand; new optional fields don't change it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// One item printed by an external command, a line of JSON
+///
+/// The fields mirror the protocol's `Item`. `text` and `provider` are
+/// required; the rest default to empty (and `score` to 0). The icon kind
+/// isn't sent: a leading `/` marks a path and `scheme://` or `data:` a URI,
+/// anything else is a theme name. Commands written in Rust can build these
+/// and print them with `serde_json::to_string`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExternalItem {
+    /// Format version the item was written for (default: 1)
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    /// Primary display text
+    #[serde(default)]
+    pub text: String,
+    /// Name of the provider the item is for; items are always attributed to
+    /// the configured provider, whatever this says
+    #[serde(default)]
+    pub provider: String,
+    /// Key for a stable item id; without one the id is random per query
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    /// Secondary display text
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub subtext: String,
+    /// Icon theme name, absolute path or URI
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub icon: String,
+    /// Resolved icon file path
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub icon_path: String,
+    /// Relevance score, 0.0 - 1.0
+    #[serde(default)]
+    pub score: f32,
+    /// Data for the frontend to act on
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub metadata: HashMap<String, String>,
+    /// Where the item comes from
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub source: String,
+    /// How long the item stays accurate, in milliseconds (default: no
+    /// expiry)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_ms: Option<u64>,
+}
+
+impl ExternalItem {
+    /// An item with the given text, for `provider`, at the current version
+    pub fn new(text: impl Into<String>, provider: impl Into<String>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            text: text.into(),
+            provider: provider.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Check the item can be used: required fields present, a known version
+    /// and a score in range
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.schema_version == 0 || self.schema_version > SCHEMA_VERSION {
+            anyhow::bail!("unsupported schema_version {}", self.schema_version);
+        }
+        if self.text.is_empty() {
+            anyhow::bail!("missing text");
+        }
+        if self.provider.is_empty() {
+            anyhow::bail!("missing provider");
+        }
+        if !(0.0..=1.0).contains(&self.score) {
+            anyhow::bail!("score {} is outside 0.0 - 1.0", self.score);
+        }
+        Ok(())
+    }
+}
+
+fn default_schema_version() -> u32 {
+    1
+}
+
+/// Provider backed by an external program
+pub struct ExternalProvider {
+    name: String,
+    description: String,
+    /// Program and its leading arguments; the query is appended
+    command: Vec<String>,
+    prefix: Option<String>,
+    priority: i32,
+    timeout: Duration,
+}
+
+impl ExternalProvider {
+    /// Create a provider named `name` that runs `command` (program first)
+    pub fn new(name: impl Into<String>, command: Vec<String>) -> Self {
+        let name = name.into();
+        Self {
+            description: format!("Results from {}", command.join(" ")),
+            name,
+            command,
+            prefix: None,
+            priority: 0,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Set the description shown in provider listings
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = description.into();
+        self
+    }
+
+    /// Only answer queries starting with `prefix` (default: none, so the
+    /// command runs for every query that matches no prefix)
+    pub fn with_prefix(mut self, prefix: Option<String>) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Kill the command if it runs longer than this (default: 5s)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    async fn query_impl(&self, query: &str, max_results: usize) -> Vec<Item> {
+        let query = match &self.prefix {
+            Some(prefix) => match query.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.trim(),
+                None => return Vec::new(),
+            },
+            None => query.trim(),
+        };
+        // Without a prefix an empty query belongs to the applications list.
+        if query.is_empty() && self.prefix.is_none() {
+            return Vec::new();
+        }
+
+        match self.run(query).await {
+            Ok(stdout) => parse_items(&self.name, &stdout, max_results),
+            Err(e) => {
+                warn!(provider = %self.name, "External command failed: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Run the command for `query` and return its stdout
+    async fn run(&self, query: &str) -> anyhow::Result<String> {
+        let Some((program, args)) = self.command.split_first() else {
+            anyhow::bail!("no command configured");
+        };
+        // kill_on_drop also ends the command when the query is superseded.
+        let child = tokio::process::Command::new(program)
+            .args(args)
+            .arg(query)
+            .env("DATACUBE_SCHEMA_VERSION", SCHEMA_VERSION.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(self.timeout, child)
+            .await
+            .map_err(|_| anyhow::anyhow!("timed out after {:?}", self.timeout))??;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Provider for ExternalProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let query = query.to_string();
+        Box::pin(async move { self.query_impl(&query, max_results).await })
+    }
+}
+
+/// Parse a command's NDJSON output into items from `provider`
+fn parse_items(provider: &str, stdout: &str, max_results: usize) -> Vec<Item> {
+    stdout
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let parsed = serde_json::from_str::<ExternalItem>(line)
+                .map_err(anyhow::Error::from)
+                .and_then(|item| item.validate().map(|()| item));
+            match parsed {
+                Ok(item) => Some(external_item(provider, item)),
+                Err(e) => {
+                    warn!(provider, "Skipping invalid item {:?}: {}", line, e);
+                    None
+                }
+            }
+        })
+        .take(max_results)
+        .collect()
+}
+
+fn external_item(provider: &str, external: ExternalItem) -> Item {
+    let mut item = Item::new(external.text, provider)
+        .with_subtext(external.subtext)
+        .with_icon_path(external.icon_path)
+        .with_score(external.score)
+        .with_source(external.source);
+    if !external.id.is_empty() {
+        item = item.with_stable_id(&external.id);
+    }
+    if !external.icon.is_empty() {
+        item = item
+            .with_icon_kind(icon_kind(&external.icon))
+            .with_icon(external.icon);
+    }
+    item.metadata = external.metadata;
+    item.ttl = external.ttl_ms.map(Duration::from_millis);
+    item
+}
+
+/// Commands may name a theme icon, or give a path or URI
+fn icon_kind(icon: &str) -> IconKind {
+    if icon.starts_with('/') {
+        IconKind::AbsolutePath
+    } else if icon.contains("://") || icon.starts_with("data:") {
+        IconKind::Uri
+    } else {
+        IconKind::ThemedName
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing::texts;
+
+    /// A provider running a shell script, which sees the query as `$1`
+    fn script(script: &str) -> ExternalProvider {
+        let command = ["sh", "-c", script, "sh"].map(String::from).to_vec();
+        ExternalProvider::new("ext", command).with_prefix(Some("ext ".to_string()))
+    }
+
+    #[test]
+    fn parses_items_and_skips_invalid_ones() {
+        let stdout = concat!(
+            r#"{"text": "one", "provider": "ext", "id": "1", "score": 0.5, "metadata": {"k": "v"}, "ttl_ms": 1500}"#,
+            "\n\nnot json\n",
+            r#"{"provider": "ext", "subtext": "no text"}"#,
+            "\n",
+            r#"{"text": "no provider"}"#,
+            "\n",
+            r#"{"text": "too good", "provider": "ext", "score": 7}"#,
+            "\n",
+            r#"{"text": "future", "provider": "ext", "schema_version": 2}"#,
+            "\n",
+            r#"{"text": "two", "provider": "other", "schema_version": 1}"#,
+            "\n",
+        );
+        let items = parse_items("ext", stdout, 10);
+        assert_eq!(texts(&items), ["one", "two"]);
+        assert_eq!(items[0].id, parse_items("ext", stdout, 1)[0].id);
+        assert_eq!(items[0].metadata["k"], "v");
+        assert_eq!(items[0].ttl, Some(Duration::from_millis(1500)));
+        assert_eq!(items[1].provider, "ext");
+        assert_eq!(items[1].ttl, None);
+        assert_eq!(parse_items("ext", stdout, 1).len(), 1);
+    }
+
+    #[test]
+    fn helper_items_round_trip() {
+        let mut item = ExternalItem::new("Dark mode", "theme");
+        item.score = 0.8;
+        item.metadata.insert("exec".into(), "toggle-theme".into());
+        let line = serde_json::to_string(&item).unwrap();
+        assert!(line.contains(r#""schema_version":1"#));
+        assert!(!line.contains("subtext"));
+        assert_eq!(serde_json::from_str::<ExternalItem>(&line).unwrap(), item);
+        assert!(item.validate().is_ok());
+        assert!(ExternalItem::default().validate().is_err());
+    }
+
+    #[test]
+    fn icon_kinds_are_detected() {
+        assert_eq!(icon_kind("/usr/share/icons/x.svg"), IconKind::AbsolutePath);
+        assert_eq!(icon_kind("file:///tmp/x.png"), IconKind::Uri);
+        assert_eq!(icon_kind("firefox"), IconKind::ThemedName);
+    }
+
+    #[tokio::test]
+    async fn runs_the_command_with_the_query() {
+        let provider = script(
+            r#"printf '{"text": "got %s v%s", "provider": "ext"}\n' "$1" "$DATACUBE_SCHEMA_VERSION""#,
+        );
+        let items = provider.query("ext hello world", 10).await;
+        assert_eq!(texts(&items), ["got hello world v1"]);
+        assert!(provider.query("hello", 10).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failing_and_slow_commands_give_no_results() {
+        let failing = script(r#"echo '{"text": "x", "provider": "ext"}'; exit 1"#);
+        assert!(failing.query("ext q", 10).await.is_empty());
+
+        let slow = script("sleep 5").with_timeout(Duration::from_millis(50));
+        assert!(slow.query("ext q", 10).await.is_empty());
+
+        let missing = ExternalProvider::new("ext", vec!["/nonexistent/cmd".to_string()]);
+        assert!(missing.query("q", 10).await.is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+//! Generate provider - fresh UUIDs and random tokens
+//!
+//! `gen uuid`, `gen hex 16`, `gen password 20`. Every query generates new
+//! values, so typing the same query twice gives different results; item ids
+//! name the kind of value rather than the value itself.
+
+use super::{Item, Provider};
+use std::future::Future;
+use std::pin::Pin;
+use tracing::warn;
+
+/// Longest token that can be generated
+const MAX_LENGTH: usize = 256;
+
+const DEFAULT_HEX_LENGTH: usize = 32;
+const DEFAULT_PASSWORD_LENGTH: usize = 20;
+
+const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const SYMBOLS: &[u8] = b"!#$%&*+-.:;=?@^_~";
+
+/// Provider for generated identifiers and tokens
+pub struct GenerateProvider {
+    prefix: String,
+    priority: i32,
+}
+
+impl GenerateProvider {
+    pub fn new() -> Self {
+        Self {
+            prefix: "gen ".to_string(),
+            priority: 0,
+        }
+    }
+
+    /// Set the query prefix (default: "gen ")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn query_impl(&self, query: &str) -> Vec<Item> {
+        let input = query.strip_prefix(self.prefix.as_str()).unwrap_or(query);
+        let mut words = input.split_whitespace();
+        let kind = words.next().unwrap_or("").to_ascii_lowercase();
+        let length = words.next();
+
+        let generated = match kind.as_str() {
+            "uuid" | "uuid4" => Ok(uuid_values()),
+            "hex" => parse_length(length, DEFAULT_HEX_LENGTH).and_then(hex_values),
+            "password" | "pw" => {
+                parse_length(length, DEFAULT_PASSWORD_LENGTH).and_then(password_values)
+            }
+            _ => {
+                return vec![Item::new("Generate a value", "generate")
+                    .with_stable_id("hint")
+                    .with_subtext("uuid, hex [length], password [length]")
+                    .with_icon("view-refresh")
+                    .with_score(1.0)];
+            }
+        };
+
+        match generated {
+            Ok(values) => values
+                .into_iter()
+                .enumerate()
+                .map(|(i, (key, label, value))| {
+                    Item::new(value.as_str(), "generate")
+                        .with_stable_id(key)
+                        .with_subtext(label)
+                        .with_icon("view-refresh")
+                        .with_copy(&value)
+                        .with_type_text(&value)
+                        .with_score(1.0 - i as f32 * 0.01)
+                })
+                .collect(),
+            Err(e) => vec![Item::new("Cannot generate value", "generate")
+                .with_stable_id("error")
+                .with_subtext(format!("Error: {}", e))
+                .with_icon("dialog-error")
+                .with_score(0.5)],
+        }
+    }
+}
+
+impl Default for GenerateProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider for GenerateProvider {
+    fn name(&self) -> &str {
+        "generate"
+    }
+
+    fn description(&self) -> &str {
+        "Random UUIDs, hex strings and passwords"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Results are values to read or copy; activating one does nothing more
+    fn supports_activate(&self) -> bool {
+        false
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        _max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let result = self.query_impl(query);
+        Box::pin(async move { result })
+    }
+}
+
+/// A generated value: (stable id key, subtext, value)
+type Generated = (&'static str, String, String);
+
+fn parse_length(arg: Option<&str>, default: usize) -> Result<usize, String> {
+    let Some(arg) = arg else {
+        return Ok(default);
+    };
+    match arg.parse::<usize>() {
+        Ok(n) if (1..=MAX_LENGTH).contains(&n) => Ok(n),
+        _ => Err(format!("length must be between 1 and {}", MAX_LENGTH)),
+    }
+}
+
+fn uuid_values() -> Vec<Generated> {
+    let uuid = uuid::Uuid::new_v4();
+    vec![
+        ("uuid", "UUID v4".to_string(), uuid.hyphenated().to_string()),
+        (
+            "uuid-simple",
+            "UUID v4 without hyphens".to_string(),
+            uuid.simple().to_string(),
+        ),
+    ]
+}
+
+fn hex_values(length: usize) -> Result<Vec<Generated>, String> {
+    let bytes = random_bytes(length.div_ceil(2))?;
+    let mut hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.truncate(length);
+    Ok(vec![("hex", format!("{} random hex digits", length), hex)])
+}
+
+fn password_values(length: usize) -> Result<Vec<Generated>, String> {
+    let with_symbols: Vec<u8> = ALPHANUMERIC.iter().chain(SYMBOLS).copied().collect();
+    Ok(vec![
+        (
+            "password",
+            format!("{}-character password", length),
+            random_string(&with_symbols, length)?,
+        ),
+        (
+            "password-alphanumeric",
+            format!("{}-character password, letters and digits only", length),
+            random_string(ALPHANUMERIC, length)?,
+        ),
+    ])
+}
+
+/// `length` characters drawn uniformly from `alphabet`
+fn random_string(alphabet: &[u8], length: usize) -> Result<String, String> {
+    // Reject bytes past the largest multiple of the alphabet size so every
+    // character is equally likely.
+    let limit = 256 - 256 % alphabet.len();
+    let mut out = String::with_capacity(length);
+    while out.len() < length {
+        for byte in random_bytes(length)? {
+            if usize::from(byte) < limit && out.len() < length {
+                out.push(alphabet[usize::from(byte) % alphabet.len()] as char);
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn random_bytes(n: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0; n];
+    getrandom::fill(&mut bytes).map_err(|e| {
+        warn!("System random number generator failed: {}", e);
+        format!("no randomness available ({})", e)
+    })?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing;
+
+    #[test]
+    fn generates_uuids() {
+        let items = GenerateProvider::new().query_impl("gen uuid");
+        assert_eq!(items.len(), 2);
+        let uuid = uuid::Uuid::parse_str(&items[0].text).unwrap();
+        assert_eq!(uuid.get_version_num(), 4);
+        assert_eq!(items[1].text, items[0].text.replace('-', ""));
+        assert_eq!(items[0].metadata["copy"], items[0].text);
+        assert_eq!(items[0].metadata["type_text"], items[0].text);
+    }
+
+    #[test]
+    fn each_query_generates_new_values_under_the_same_id() {
+        let provider = GenerateProvider::new();
+        let first = provider.query_impl("gen uuid4");
+        let second = provider.query_impl("gen uuid4");
+        assert_ne!(first[0].text, second[0].text);
+        assert_eq!(first[0].id, second[0].id);
+    }
+
+    #[test]
+    fn generates_hex_of_the_requested_length() {
+        let provider = GenerateProvider::new();
+        let items = provider.query_impl("gen hex 15");
+        assert_eq!(items[0].text.len(), 15);
+        assert!(items[0].text.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let items = provider.query_impl("gen hex");
+        assert_eq!(items[0].text.len(), DEFAULT_HEX_LENGTH);
+    }
+
+    #[test]
+    fn generates_passwords() {
+        let items = GenerateProvider::new().query_impl("gen password 24");
+        assert_eq!(items[0].text.len(), 24);
+        assert!(items[0]
+            .text
+            .bytes()
+            .all(|b| ALPHANUMERIC.contains(&b) || SYMBOLS.contains(&b)));
+        assert!(items[1].text.bytes().all(|b| b.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn rejects_out_of_range_lengths() {
+        let provider = GenerateProvider::new();
+        for query in ["gen hex 0", "gen password 1000", "gen hex lots"] {
+            let items = provider.query_impl(query);
+            assert_eq!(items[0].text, "Cannot generate value", "{}", query);
+        }
+    }
+
+    #[test]
+    fn answers_through_the_provider_trait() {
+        let items = testing::query(&GenerateProvider::new(), "gen uuid", 10);
+        assert_eq!(
+            testing::texts(&items)[1],
+            items[0].text.replace('-', "").as_str()
+        );
+        assert!(items.iter().all(|item| item.provider == "generate"));
+    }
+
+    #[test]
+    fn unknown_kind_shows_a_hint() {
+        let items = GenerateProvider::new().query_impl("gen ");
+        assert_eq!(items[0].text, "Generate a value");
+    }
+}
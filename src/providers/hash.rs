@@ -0,0 +1,579 @@
+//! Hash provider - digests and encodings of a string
+//!
+//! `hash sha256 hello`, `hash base64 decode aGVsbG8=`, `hash url encode a b`.
+//! A query without a known command shows every digest of the input. The
+//! digests and codecs are implemented here because the sha2, md-5 and base64
+//! crates aren't available to the build.
+
+use super::{Item, Provider};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Longest input accepted, in bytes
+const MAX_INPUT_BYTES: usize = 16 * 1024;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Provider for hashing and encoding utilities
+pub struct HashProvider {
+    prefix: String,
+    priority: i32,
+}
+
+/// A digest or codec the provider can apply
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operation {
+    Md5,
+    Sha1,
+    Sha256,
+    Base64Encode,
+    Base64Decode,
+    UrlEncode,
+    UrlDecode,
+}
+
+impl Operation {
+    const DIGESTS: [Operation; 3] = [Operation::Md5, Operation::Sha1, Operation::Sha256];
+
+    fn label(self) -> &'static str {
+        match self {
+            Operation::Md5 => "MD5",
+            Operation::Sha1 => "SHA-1",
+            Operation::Sha256 => "SHA-256",
+            Operation::Base64Encode => "Base64 encoded",
+            Operation::Base64Decode => "Base64 decoded",
+            Operation::UrlEncode => "URL encoded",
+            Operation::UrlDecode => "URL decoded",
+        }
+    }
+
+    fn apply(self, input: &str) -> Result<String, String> {
+        match self {
+            Operation::Md5 => Ok(to_hex(&md5(input.as_bytes()))),
+            Operation::Sha1 => Ok(to_hex(&sha1(input.as_bytes()))),
+            Operation::Sha256 => Ok(to_hex(&sha256(input.as_bytes()))),
+            Operation::Base64Encode => Ok(base64_encode(input.as_bytes())),
+            Operation::Base64Decode => {
+                let bytes = base64_decode(input)?;
+                String::from_utf8(bytes).map_err(|e| {
+                    format!("decoded data is not UTF-8 ({} bytes)", e.as_bytes().len())
+                })
+            }
+            Operation::UrlEncode => Ok(url_encode(input)),
+            Operation::UrlDecode => url_decode(input),
+        }
+    }
+}
+
+/// Split `<command> <input>` into the operation and the input, which is kept
+/// exactly as typed after the separating space
+fn parse_command(input: &str) -> Option<(Operation, &str)> {
+    let (command, rest) = input.split_once(' ').unwrap_or((input, ""));
+    let command = command.to_ascii_lowercase();
+    let op = match command.as_str() {
+        "md5" => Operation::Md5,
+        "sha1" => Operation::Sha1,
+        "sha256" => Operation::Sha256,
+        "base64" | "b64" | "url" => {
+            let (direction, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            let op = match (command.as_str(), direction.to_ascii_lowercase().as_str()) {
+                ("url", "encode") => Operation::UrlEncode,
+                ("url", "decode") => Operation::UrlDecode,
+                (_, "encode") => Operation::Base64Encode,
+                (_, "decode") => Operation::Base64Decode,
+                _ => return None,
+            };
+            return Some((op, rest));
+        }
+        _ => return None,
+    };
+    Some((op, rest))
+}
+
+impl HashProvider {
+    pub fn new() -> Self {
+        Self {
+            prefix: "hash ".to_string(),
+            priority: 0,
+        }
+    }
+
+    /// Set the query prefix (default: "hash ")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn query_impl(&self, query: &str) -> Vec<Item> {
+        let input = query.strip_prefix(self.prefix.as_str()).unwrap_or(query);
+
+        if input.trim().is_empty() {
+            return vec![Item::new("Enter text to hash or encode", "hash")
+                .with_stable_id("hint")
+                .with_subtext(
+                    "md5, sha1, sha256 <text>, base64 encode|decode <text>, \
+                     url encode|decode <text>",
+                )
+                .with_icon("dialog-password")
+                .with_score(1.0)];
+        }
+        if input.len() > MAX_INPUT_BYTES {
+            return vec![error_item(format!(
+                "input is longer than {} KiB",
+                MAX_INPUT_BYTES / 1024
+            ))];
+        }
+
+        let (ops, text): (Vec<Operation>, &str) = match parse_command(input) {
+            Some((op, text)) => (vec![op], text),
+            None => (Operation::DIGESTS.to_vec(), input),
+        };
+
+        ops.into_iter()
+            .enumerate()
+            .map(|(i, op)| match op.apply(text) {
+                Ok(result) => Item::new(result.as_str(), "hash")
+                    .with_stable_id(op.label())
+                    .with_subtext(format!("{} of \"{}\"", op.label(), preview(text)))
+                    .with_icon("dialog-password")
+                    .with_metadata("operation", op.label())
+                    .with_copy(&result)
+                    .with_score(1.0 - i as f32 * 0.01),
+                Err(e) => error_item(format!("Invalid input for {}: {}", op.label(), e)),
+            })
+            .collect()
+    }
+}
+
+impl Default for HashProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider for HashProvider {
+    fn name(&self) -> &str {
+        "hash"
+    }
+
+    fn description(&self) -> &str {
+        "Hashes and encodings of text"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Results are values to read or copy; activating one does nothing more
+    fn supports_activate(&self) -> bool {
+        false
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        _max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let result = self.query_impl(query);
+        Box::pin(async move { result })
+    }
+}
+
+fn error_item(message: String) -> Item {
+    Item::new("Invalid input", "hash")
+        .with_stable_id("error")
+        .with_subtext(format!("Error: {}", message))
+        .with_icon("dialog-error")
+        .with_score(0.5)
+}
+
+/// The input shortened for display in a subtext
+fn preview(text: &str) -> String {
+    const MAX_CHARS: usize = 40;
+    match text.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard or URL-safe base64; padding and whitespace are optional
+fn base64_decode(input: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut padding = false;
+
+    for c in input.chars().filter(|c| !c.is_whitespace()) {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            '=' => {
+                padding = true;
+                continue;
+            }
+            _ => return Err(format!("unexpected character '{}'", c)),
+        };
+        if padding {
+            return Err("data after padding".to_string());
+        }
+        buffer = buffer << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    // Leftover bits must be a partial group of 2 or 3 characters.
+    if bits >= 6 {
+        return Err("truncated input".to_string());
+    }
+    Ok(out)
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters
+pub(crate) fn url_encode(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn url_decode(input: &str) -> Result<String, String> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = input
+                .get(i + 1..i + 3)
+                .ok_or_else(|| "incomplete escape".to_string())?;
+            let byte =
+                u8::from_str_radix(hex, 16).map_err(|_| format!("invalid escape '%{}'", hex))?;
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| "decoded data is not UTF-8".to_string())
+}
+
+/// Merkle-Damgard padding: 0x80, zeros, then the bit length
+fn pad(data: &[u8], big_endian: bool) -> Vec<u8> {
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    if big_endian {
+        msg.extend_from_slice(&bit_len.to_be_bytes());
+    } else {
+        msg.extend_from_slice(&bit_len.to_le_bytes());
+    }
+    msg
+}
+
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    let k: Vec<u32> = (1..=64)
+        .map(|i| (f64::from(i).sin().abs() * 4_294_967_296.0) as u32)
+        .collect();
+
+    let mut h: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+    for block in pad(data, false).chunks_exact(64) {
+        let m: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = h;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(k[i])
+                .wrapping_add(m[g])
+                .rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0; 16];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    for block in pad(data, true).chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            (a, b, c, d, e) = (temp, a, b.rotate_left(30), c, d);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0; 20];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    for block in pad(data, true).chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+            (hh, g, f, e, d, c, b, a) = (
+                g,
+                f,
+                e,
+                d.wrapping_add(temp1),
+                c,
+                b,
+                a,
+                temp1.wrapping_add(temp2),
+            );
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+
+    let mut out = [0; 32];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_match_known_vectors() {
+        assert_eq!(to_hex(&md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(to_hex(&md5(b"hello")), "5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(
+            to_hex(&sha1(b"hello")),
+            "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+        );
+        assert_eq!(
+            to_hex(&sha256(b"hello")),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        // Spans two blocks after padding.
+        let long = "a".repeat(100);
+        assert_eq!(
+            to_hex(&sha256(long.as_bytes())),
+            "2816597888e4a0d3a36b82b83316ab32680eb8f00f8cd3b904d681246d285a0e"
+        );
+        // Around the padding boundaries: 55 bytes still pad into one block,
+        // 56 need a second, 64 fill one exactly.
+        let vectors = [
+            (
+                55,
+                "ef1772b6dff9a122358552954ad0df65",
+                "c1c8bbdc22796e28c0e15163d20899b65621d65a",
+            ),
+            (
+                56,
+                "3b0c8ac703f828b04c6c197006d17218",
+                "c2db330f6083854c99d4b5bfb6e8f29f201be699",
+            ),
+            (
+                64,
+                "014842d480b571495a4a0363793f7367",
+                "0098ba824b5c16427bd7a1122a5a442a25ec644d",
+            ),
+            (
+                100,
+                "36a92cc94a9e0fa21f625f8bfb007adf",
+                "7f9000257a4918d7072655ea468540cdcbd42e0c",
+            ),
+        ];
+        for (len, md5_hex, sha1_hex) in vectors {
+            let input = "a".repeat(len);
+            assert_eq!(to_hex(&md5(input.as_bytes())), md5_hex, "md5 of {len}");
+            assert_eq!(to_hex(&sha1(input.as_bytes())), sha1_hex, "sha1 of {len}");
+        }
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        assert_eq!(base64_encode(b"hello"), "aGVsbG8=");
+        assert_eq!(base64_encode(b"hi!"), "aGkh");
+        assert_eq!(base64_encode(b"h"), "aA==");
+        assert_eq!(base64_decode("aGVsbG8=").unwrap(), b"hello");
+        assert_eq!(base64_decode("aGVsbG8").unwrap(), b"hello");
+        assert_eq!(base64_decode("_-8").unwrap(), [0xff, 0xef]);
+        assert!(base64_decode("aGVs*G8=").is_err());
+        assert!(base64_decode("a").is_err());
+        assert!(base64_decode("aA==aA").is_err());
+    }
+
+    #[test]
+    fn url_encoding_round_trips() {
+        assert_eq!(url_encode("a b&c=ü"), "a%20b%26c%3D%C3%BC");
+        assert_eq!(url_decode("a%20b%26c%3D%C3%BC").unwrap(), "a b&c=ü");
+        assert!(url_decode("100%").is_err());
+        assert!(url_decode("%zz").is_err());
+    }
+
+    #[test]
+    fn query_runs_the_named_operation() {
+        let provider = HashProvider::new();
+
+        let items = provider.query_impl("hash sha256 hello");
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].text,
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+        assert_eq!(items[0].metadata["copy"], items[0].text);
+
+        let items = provider.query_impl("hash base64 decode aGVsbG8=");
+        assert_eq!(items[0].text, "hello");
+
+        let items = provider.query_impl("hash URL Encode a b");
+        assert_eq!(items[0].text, "a%20b");
+    }
+
+    #[test]
+    fn query_without_command_shows_every_digest() {
+        let items = HashProvider::new().query_impl("hash hello");
+        let ops: Vec<_> = items
+            .iter()
+            .map(|i| i.metadata["operation"].as_str())
+            .collect();
+        assert_eq!(ops, vec!["MD5", "SHA-1", "SHA-256"]);
+    }
+
+    #[test]
+    fn invalid_input_gives_an_error_item() {
+        let provider = HashProvider::new();
+
+        let items = provider.query_impl("hash base64 decode not*base64");
+        assert_eq!(items[0].text, "Invalid input");
+        assert!(items[0].subtext.contains("unexpected character '*'"));
+
+        let long = format!("hash sha256 {}", "x".repeat(MAX_INPUT_BYTES + 1));
+        let items = provider.query_impl(&long);
+        assert_eq!(items[0].text, "Invalid input");
+    }
+}
@@ -1,84 +1,806 @@
 //! Provider manager - orchestrates all providers
 
-use super::{Item, Provider, ProviderInfo};
-use std::sync::Arc;
+use super::{Item, ItemStream, Provider, ProviderInfo};
+use futures::StreamExt;
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::RwLock;
-use tracing::{debug, info};
+use tracing::{debug, debug_span, error, info, warn, Instrument};
+
+/// Builds a provider on demand, e.g. from the config
+pub type ProviderFactory = Arc<dyn Fn() -> Box<dyn Provider> + Send + Sync>;
+
+/// The registered providers, in registration order and indexed by name
+///
+/// Both views live behind the same lock so they are always updated together.
+#[derive(Default)]
+struct Registry {
+    /// Registration order (used for listing)
+    ordered: Vec<Arc<dyn Provider>>,
+    /// Name -> provider, for lookups by name
+    by_name: HashMap<String, Arc<dyn Provider>>,
+    /// Name -> runtime overrides of the provider's compiled-in settings
+    overrides: HashMap<String, ProviderOverrides>,
+    /// Providers known but not registered (disabled in the config)
+    known: Vec<ProviderInfo>,
+    /// Name -> how to build the provider, registered or not
+    factories: HashMap<String, ProviderFactory>,
+}
+
+impl Registry {
+    /// The provider's info with any overrides applied
+    fn effective_info(&self, provider: &dyn Provider) -> ProviderInfo {
+        let mut info = provider.info();
+        if let Some(overrides) = self.overrides.get(provider.name()) {
+            if let Some(priority) = overrides.priority {
+                info.priority = priority;
+            }
+            if let Some(prefix) = &overrides.prefix {
+                // An overridden prefix alone decides routing.
+                info.prefix = prefix.clone();
+                info.requires_prefix = prefix.is_some();
+            }
+        }
+        info
+    }
+
+    /// The query to hand to `provider`, if it should be routed there
+    ///
+    /// Without a prefix override this is just `can_handle`. With one, the
+    /// effective prefix decides routing and is swapped for the compiled-in
+    /// prefix, so the provider sees the query in the form it expects.
+    fn route(&self, provider: &dyn Provider, query: &str) -> Option<String> {
+        let Some(prefix) = self
+            .overrides
+            .get(provider.name())
+            .and_then(|o| o.prefix.as_ref())
+        else {
+            return provider.can_handle(query).then(|| query.to_string());
+        };
+        let rest = match prefix {
+            Some(prefix) => query.strip_prefix(prefix.as_str())?,
+            None => query,
+        };
+        Some(format!("{}{}", provider.prefix().unwrap_or(""), rest))
+    }
+
+    /// The enabled providers a query is routed to, in registration order
+    ///
+    /// When several providers' prefixes match, only those with the longest
+    /// matching prefix are kept, so `==` takes a query away from `=`.
+    /// Providers that accept the query without a prefix match (no prefix, or
+    /// a `can_handle` that recognizes the query itself) only receive it when
+    /// no prefix matched.
+    fn routes(&self, query: &str) -> Vec<Route> {
+        let candidates: Vec<Route> = self
+            .ordered
+            .iter()
+            .filter(|p| p.enabled())
+            .filter_map(|p| {
+                let routed = self.route(p.as_ref(), query)?;
+                let prefix = self
+                    .effective_info(p.as_ref())
+                    .prefix
+                    .filter(|prefix| query.starts_with(prefix.as_str()));
+                Some(Route {
+                    provider: p.name().to_string(),
+                    prefix,
+                    query: routed,
+                })
+            })
+            .collect();
+
+        let longest = candidates
+            .iter()
+            .filter_map(|route| route.prefix.as_ref().map(String::len))
+            .max();
+        candidates
+            .into_iter()
+            .filter(|route| match &route.prefix {
+                Some(prefix) => Some(prefix.len()) == longest,
+                None => longest.is_none(),
+            })
+            .collect()
+    }
+
+    /// Names of enabled providers other than `name` whose effective prefix is
+    /// `prefix`
+    fn sharing_prefix(&self, name: &str, prefix: &str) -> Vec<String> {
+        self.ordered
+            .iter()
+            .filter(|p| p.enabled() && p.name() != name)
+            .filter(|p| self.effective_info(p.as_ref()).prefix.as_deref() == Some(prefix))
+            .map(|p| p.name().to_string())
+            .collect()
+    }
+}
+
+/// Where a query goes: a provider and the query it is handed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// Provider name
+    pub provider: String,
+    /// The provider's effective prefix, if the query matched on it
+    pub prefix: Option<String>,
+    /// The query as the provider sees it (differs when its prefix is
+    /// overridden)
+    pub query: String,
+}
+
+/// Runtime overrides for a provider's compiled-in settings
+///
+/// Overrides live only as long as the daemon; they are not written back to
+/// the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProviderOverrides {
+    /// Replacement tie-break priority
+    pub priority: Option<i32>,
+    /// Replacement prefix; `Some(None)` makes the provider handle all queries
+    pub prefix: Option<Option<String>>,
+}
 
 /// Manages all registered providers
 pub struct ProviderManager {
-    providers: RwLock<Vec<Arc<dyn Provider>>>,
+    providers: RwLock<Registry>,
+    /// Provider order to group results by, if not sorting purely by score
+    groups: Option<Vec<String>>,
+    /// Name -> shortest query (in characters) the provider is asked
+    min_query_lens: HashMap<String, usize>,
+    /// Most results taken from any one provider for a query
+    provider_max_results: usize,
+    /// The providers answering an empty query, in result order, if not all
+    empty_query_providers: Option<Vec<String>>,
+    /// Items scoring below this are dropped, unless marked always shown
+    min_score: f32,
+    /// Alias -> what it stands for at the start of a query
+    aliases: HashMap<String, String>,
 }
 
 impl ProviderManager {
     pub fn new() -> Self {
         Self {
-            providers: RwLock::new(Vec::new()),
+            providers: RwLock::new(Registry::default()),
+            groups: None,
+            min_query_lens: HashMap::new(),
+            provider_max_results: usize::MAX,
+            empty_query_providers: None,
+            min_score: 0.0,
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// Group query results by provider, in this order (unlisted providers
+    /// last), sorting by score only within each group
+    pub fn with_provider_groups(mut self, order: Vec<String>) -> Self {
+        self.groups = Some(order);
+        self
+    }
+
+    /// Skip providers for queries shorter than their minimum length
+    ///
+    /// Lengths are counted in characters of the whole query, prefix
+    /// included; providers not listed get every query.
+    pub fn with_min_query_lens(mut self, min_query_lens: HashMap<String, usize>) -> Self {
+        self.min_query_lens = min_query_lens;
+        self
+    }
+
+    /// Take at most this many results from each provider (default: as many
+    /// as the page can show), however many the whole query asks for
+    pub fn with_provider_max_results(mut self, provider_max_results: usize) -> Self {
+        self.provider_max_results = provider_max_results;
+        self
+    }
+
+    /// Drop items scoring below `min_score` (default: 0, keeping every item),
+    /// except those marked with [`Item::with_always_show`]
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Rewrite queries that are an alias, or start with one followed by
+    /// whitespace, to what it stands for, before routing
+    ///
+    /// Aliases are expanded before prefixes are matched, so an alias can
+    /// stand for a prefix (`calc` -> `=`). The longest matching alias wins,
+    /// and its expansion isn't expanded again.
+    pub fn with_aliases(mut self, aliases: HashMap<String, String>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Route empty (or blank) queries only to these providers, grouping
+    /// their results in this order
+    ///
+    /// Providers a request names are still queried.
+    pub fn with_empty_query_providers(mut self, providers: Vec<String>) -> Self {
+        self.empty_query_providers = Some(providers);
+        self
+    }
+
+    /// The providers an empty query is limited to, if `query` is empty and
+    /// they are configured
+    fn empty_query_order(&self, query: &str) -> Option<&[String]> {
+        match &self.empty_query_providers {
+            Some(providers) if query.trim().is_empty() => Some(providers),
+            _ => None,
+        }
+    }
+
+    /// Whether `query` is routed to the named provider, as far as the
+    /// manager's own settings go (query length, empty query providers)
+    fn accepts(&self, name: &str, query: &str) -> bool {
+        let listed = self
+            .empty_query_order(query)
+            .is_none_or(|providers| providers.iter().any(|p| p == name));
+        listed && self.long_enough(name, query)
+    }
+
+    /// Whether `query` is long enough for the named provider
+    fn long_enough(&self, name: &str, query: &str) -> bool {
+        match self.min_query_lens.get(name) {
+            Some(&min) => query.chars().count() >= min,
+            None => true,
         }
     }
 
     /// Register a new provider
-    pub async fn register<P: Provider + 'static>(&self, provider: P) {
+    ///
+    /// Fails if the name is invalid or already taken: names identify providers
+    /// in `QueryRequest::providers` and on items, so they must be unique. On
+    /// success the provider's background work is started with
+    /// [`Provider::spawn_background`].
+    pub async fn register<P: Provider + 'static>(&self, provider: P) -> anyhow::Result<()> {
+        self.register_boxed(Box::new(provider)).await
+    }
+
+    /// Register a provider built at runtime, as [`register`](Self::register)
+    pub async fn register_boxed(&self, provider: Box<dyn Provider>) -> anyhow::Result<()> {
         let name = provider.name().to_string();
-        self.providers.write().await.push(Arc::new(provider));
+        validate_name(&name)?;
+
+        let mut registry = self.providers.write().await;
+        if registry.by_name.contains_key(&name) {
+            anyhow::bail!("a provider named '{}' is already registered", name);
+        }
+        if let (true, Some(prefix)) = (provider.enabled(), provider.prefix()) {
+            for other in registry.sharing_prefix(&name, prefix) {
+                warn!(
+                    "Providers {} and {} share the prefix '{}'; both receive its queries",
+                    other, name, prefix
+                );
+            }
+        }
+        let provider: Arc<dyn Provider> = Arc::from(provider);
+        registry.ordered.push(Arc::clone(&provider));
+        registry.by_name.insert(name.clone(), Arc::clone(&provider));
+        drop(registry);
+
+        provider.spawn_background(tokio::runtime::Handle::current());
+
         info!("Registered provider: {}", name);
+        Ok(())
     }
 
-    /// List all registered providers
-    pub async fn list_providers(&self) -> Vec<ProviderInfo> {
-        self.providers
-            .read()
-            .await
+    /// Record how to build the provider named `name`, for
+    /// [`instantiate`](Self::instantiate)
+    pub async fn add_factory(&self, name: impl Into<String>, factory: ProviderFactory) {
+        let mut registry = self.providers.write().await;
+        registry.factories.insert(name.into(), factory);
+    }
+
+    /// Build and register the provider named `name` from its factory
+    ///
+    /// Returns its info; a provider already registered is left as it is.
+    /// Fails if there is no factory for `name`.
+    pub async fn instantiate(&self, name: &str) -> anyhow::Result<ProviderInfo> {
+        let factory = {
+            let registry = self.providers.read().await;
+            if let Some(provider) = registry.by_name.get(name) {
+                return Ok(registry.effective_info(provider.as_ref()));
+            }
+            registry
+                .factories
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("no provider named '{}' is known", name))?
+        };
+        let provider = factory();
+        if provider.name() != name {
+            anyhow::bail!(
+                "the factory for '{}' built a provider named '{}'",
+                name,
+                provider.name()
+            );
+        }
+        self.register_boxed(provider).await?;
+        let registry = self.providers.read().await;
+        let provider = &registry.by_name[name];
+        Ok(registry.effective_info(provider.as_ref()))
+    }
+
+    /// Remove a provider by name, returning whether it was registered
+    pub async fn unregister(&self, name: &str) -> bool {
+        let mut registry = self.providers.write().await;
+        if registry.by_name.remove(name).is_none() {
+            return false;
+        }
+        registry.ordered.retain(|p| p.name() != name);
+        registry.overrides.remove(name);
+
+        info!("Unregistered provider: {}", name);
+        true
+    }
+
+    /// Look up a registered provider by name
+    pub async fn get(&self, name: &str) -> Option<Arc<dyn Provider>> {
+        self.providers.read().await.by_name.get(name).cloned()
+    }
+
+    /// The names in `names` that don't match a registered provider
+    ///
+    /// `query` skips these silently; callers use this to report typos.
+    pub async fn unknown_providers(&self, names: &[String]) -> Vec<String> {
+        let registry = self.providers.read().await;
+        let mut seen = HashSet::new();
+        names
             .iter()
-            .map(|p| p.info())
+            .filter(|name| !registry.by_name.contains_key(name.as_str()))
+            .filter(|name| !registry.known.iter().any(|info| &info.name == *name))
+            .filter(|name| seen.insert(name.as_str()))
+            .cloned()
             .collect()
     }
 
-    /// Query all applicable providers
-    pub async fn query(&self, query: &str, max_results: usize, providers: &[String]) -> Vec<Item> {
-        let all_providers = self.providers.read().await;
+    /// The names in `names` of providers that are disabled, registered or
+    /// only known
+    ///
+    /// `query` skips these even when they're asked for by name.
+    pub async fn disabled_providers(&self, names: &[String]) -> Vec<String> {
+        let registry = self.providers.read().await;
+        let mut seen = HashSet::new();
+        names
+            .iter()
+            .filter(|name| match registry.by_name.get(name.as_str()) {
+                Some(p) => !p.enabled(),
+                None => registry.known.iter().any(|info| &info.name == *name),
+            })
+            .filter(|name| seen.insert(name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// List all registered providers, with overrides applied
+    pub async fn list_providers(&self) -> Vec<ProviderInfo> {
+        let registry = self.providers.read().await;
+        registry
+            .ordered
+            .iter()
+            .map(|p| registry.effective_info(p.as_ref()))
+            .collect()
+    }
 
-        // Filter to requested providers, or all if empty
-        let applicable: Vec<_> = all_providers
+    /// List all registered providers, then the known but unregistered ones
+    pub async fn list_all_providers(&self) -> Vec<ProviderInfo> {
+        let registry = self.providers.read().await;
+        let registered = registry
+            .ordered
             .iter()
-            .filter(|p| {
-                if !providers.is_empty() {
-                    providers.iter().any(|name| name == p.name())
-                } else {
-                    p.can_handle(query) && p.enabled()
+            .map(|p| registry.effective_info(p.as_ref()));
+        let known = registry
+            .known
+            .iter()
+            .filter(|info| !registry.by_name.contains_key(&info.name))
+            .cloned();
+        registered.chain(known).collect()
+    }
+
+    /// Record a provider that exists but isn't registered, e.g. because the
+    /// config disables it
+    ///
+    /// It is listed by [`list_all_providers`](Self::list_all_providers), as
+    /// disabled, and reported as disabled rather than unknown when queried
+    /// by name.
+    pub async fn add_known(&self, mut info: ProviderInfo) {
+        info.enabled = false;
+        let mut registry = self.providers.write().await;
+        registry.known.retain(|known| known.name != info.name);
+        registry.known.push(info);
+    }
+
+    /// Override a provider's priority and/or prefix at runtime
+    ///
+    /// Fields left as `None` keep their current value (overridden or not).
+    /// Returns the provider's effective info afterwards.
+    pub async fn set_overrides(
+        &self,
+        name: &str,
+        overrides: ProviderOverrides,
+    ) -> anyhow::Result<ProviderInfo> {
+        if let Some(Some(prefix)) = &overrides.prefix {
+            if prefix.trim().is_empty() {
+                anyhow::bail!("prefix must not be blank");
+            }
+        }
+
+        let mut registry = self.providers.write().await;
+        let provider = registry
+            .by_name
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no provider named '{}'", name))?;
+
+        let current = registry.overrides.entry(name.to_string()).or_default();
+        if overrides.priority.is_some() {
+            current.priority = overrides.priority;
+        }
+        if overrides.prefix.is_some() {
+            current.prefix = overrides.prefix;
+        }
+
+        let info = registry.effective_info(provider.as_ref());
+        if let (true, Some(prefix)) = (info.enabled, &info.prefix) {
+            for other in registry.sharing_prefix(name, prefix) {
+                warn!(
+                    "Providers {} and {} share the prefix '{}'; both receive its queries",
+                    other, name, prefix
+                );
+            }
+        }
+        info!(
+            "Overrode provider {}: priority={}, prefix={:?}",
+            name, info.priority, info.prefix
+        );
+        Ok(info)
+    }
+
+    /// Drop all overrides for a provider, restoring its compiled-in settings
+    pub async fn reset_overrides(&self, name: &str) -> anyhow::Result<ProviderInfo> {
+        let mut registry = self.providers.write().await;
+        let provider = registry
+            .by_name
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no provider named '{}'", name))?;
+        registry.overrides.remove(name);
+
+        info!("Reset overrides for provider {}", name);
+        Ok(provider.info())
+    }
+
+    /// Run every registered provider's [`Provider::warmup`] in the background
+    ///
+    /// Returns as soon as the tasks are spawned. Providers registered later
+    /// are not warmed up.
+    pub async fn warmup(&self) {
+        let providers = self.providers.read().await.ordered.clone();
+        for provider in providers {
+            let span = debug_span!("provider", provider = %provider.name());
+            tokio::spawn(
+                async move {
+                    let started = Instant::now();
+                    provider.warmup().await;
+                    debug!(
+                        latency_ms = started.elapsed().as_millis() as u64,
+                        "Warmup finished"
+                    );
                 }
+                .instrument(span),
+            );
+        }
+    }
+
+    /// Report which providers `query` would be routed to
+    ///
+    /// This is the routing `query` uses when no providers are named.
+    pub async fn routes(&self, query: &str) -> Vec<Route> {
+        let query = self.expand_alias(query);
+        let mut routes = self.providers.read().await.routes(&query);
+        routes.retain(|route| self.accepts(&route.provider, &query));
+        routes
+    }
+
+    /// `query` with the alias it starts with expanded, if any
+    fn expand_alias<'a>(&self, query: &'a str) -> Cow<'a, str> {
+        let expansion = self
+            .aliases
+            .iter()
+            .filter(|(alias, _)| !alias.is_empty())
+            .filter_map(|(alias, value)| {
+                let rest = query.strip_prefix(alias.as_str())?;
+                let whole_word = rest.is_empty() || rest.starts_with(char::is_whitespace);
+                whole_word.then_some((alias, value, rest))
             })
+            .max_by_key(|(alias, _, _)| alias.len());
+        match expansion {
+            Some((alias, value, rest)) => {
+                debug!("Expanding alias '{}' in '{}'", alias, query);
+                Cow::Owned(format!("{}{}", value, rest))
+            }
+            None => Cow::Borrowed(query),
+        }
+    }
+
+    /// Query all applicable providers
+    ///
+    /// Results are merged and ordered by, in turn: score (highest first), the
+    /// producing provider's priority (highest first), then provider name
+    /// (alphabetical) so equal results have a stable order. A provider that
+    /// panics contributes an error item in place of its remaining results.
+    pub async fn query(&self, query: &str, max_results: usize, providers: &[String]) -> Vec<Item> {
+        self.query_page(query, 0, max_results, providers).await
+    }
+
+    /// Query exactly one provider, returning its own results in its own order
+    ///
+    /// Skips the fan-out, merging and re-sorting of
+    /// [`ProviderManager::query`], for frontends with UI dedicated to one
+    /// provider. The query is routed as it would be for that provider (so
+    /// prefix overrides still apply), but a query the provider wouldn't
+    /// normally receive is still handed to it. Fails if no provider has the
+    /// name or it is disabled.
+    pub async fn query_provider(
+        &self,
+        name: &str,
+        query: &str,
+        max_results: usize,
+    ) -> anyhow::Result<Vec<Item>> {
+        let registry = self.providers.read().await;
+        let provider = registry
+            .by_name
+            .get(name)
             .cloned()
-            .collect();
+            .ok_or_else(|| anyhow::anyhow!("no provider named '{}'", name))?;
+        if !provider.enabled() {
+            anyhow::bail!("provider '{}' is disabled", name);
+        }
+        let query = self.expand_alias(query);
+        let routed = registry
+            .route(provider.as_ref(), &query)
+            .unwrap_or_else(|| query.to_string());
+        drop(registry);
 
-        debug!("Querying {} providers for '{}'", applicable.len(), query);
+        if !self.long_enough(name, &query) {
+            return Ok(Vec::new());
+        }
+        let span = debug_span!("provider", provider = %name);
+        let max_results = max_results.min(self.provider_max_results);
+        let items = stamped(catch_panics(provider.as_ref(), &routed, max_results));
+        let items = above_floor(items, self.min_score)
+            .take(max_results)
+            .collect()
+            .instrument(span)
+            .await;
+        Ok(items)
+    }
 
-        // Query all applicable providers concurrently
-        let futures: Vec<_> = applicable
+    /// Like [`ProviderManager::query`], but skipping the first `offset`
+    /// results of the merged list
+    ///
+    /// Every page queries the providers afresh, so consecutive pages only
+    /// line up while the underlying data stays the same.
+    pub async fn query_page(
+        &self,
+        query: &str,
+        offset: usize,
+        max_results: usize,
+        providers: &[String],
+    ) -> Vec<Item> {
+        self.query_page_timed(query, offset, max_results, providers)
+            .await
+            .0
+    }
+
+    /// Like [`ProviderManager::query_page`], also returning how long each
+    /// queried provider took to finish, by name
+    pub async fn query_page_timed(
+        &self,
+        query: &str,
+        offset: usize,
+        max_results: usize,
+        providers: &[String],
+    ) -> (Vec<Item>, HashMap<String, Duration>) {
+        let query = self.expand_alias(query);
+        let query = query.as_ref();
+        let registry = self.providers.read().await;
+
+        // Requested providers by name, or every provider that handles the
+        // query, each paired with the query it should see
+        let applicable: Vec<_> = if providers.is_empty() {
+            registry
+                .routes(query)
+                .into_iter()
+                .filter(|route| self.accepts(&route.provider, query))
+                .filter_map(|route| {
+                    Some((
+                        Arc::clone(registry.by_name.get(&route.provider)?),
+                        route.query,
+                    ))
+                })
+                .collect()
+        } else {
+            let mut seen = HashSet::new();
+            providers
+                .iter()
+                .filter(|name| seen.insert(name.as_str()))
+                .filter_map(|name| registry.by_name.get(name))
+                .filter(|p| p.enabled() && self.long_enough(p.name(), query))
+                .map(|p| {
+                    let routed = registry.route(p.as_ref(), query);
+                    (Arc::clone(p), routed.unwrap_or_else(|| query.to_string()))
+                })
+                .collect()
+        };
+        let priorities: HashMap<String, i32> = applicable
             .iter()
-            .map(|p| {
-                let query = query.to_string();
-                let provider = Arc::clone(p);
-                async move { provider.query(&query, max_results).await }
+            .map(|(p, _)| {
+                (
+                    p.name().to_string(),
+                    registry.effective_info(p.as_ref()).priority,
+                )
             })
             .collect();
+        drop(registry);
 
-        let results = futures::future::join_all(futures).await;
+        debug!("Querying {} providers for '{}'", applicable.len(), query);
+
+        // Query all applicable providers concurrently, reading items as they
+        // arrive and no more from each than can end up on this page, or than
+        // the per-provider limit. Each runs in a child span of the caller's
+        // (the server's per-query span carries the qid), so provider log
+        // lines can be correlated with the request. A provider that panics is
+        // cut off with an error item rather than taking the whole query down.
+        let wanted = offset
+            .saturating_add(max_results)
+            .min(self.provider_max_results);
+        let timings = Mutex::new(HashMap::new());
+        let streams = applicable.iter().map(|(provider, query)| {
+            let span = debug_span!("provider", provider = %provider.name());
+            let started = Instant::now();
+            let timings = &timings;
+            let items = stamped(catch_panics(provider.as_ref(), query, wanted));
+            let items = above_floor(items, self.min_score).take(wanted);
+            Box::pin(futures::stream::unfold(
+                (items, 0usize),
+                move |(mut items, count)| {
+                    async move {
+                        match items.next().await {
+                            Some(item) => Some((item, (items, count + 1))),
+                            None => {
+                                let elapsed = started.elapsed();
+                                debug!(
+                                    results = count,
+                                    latency_ms = elapsed.as_millis() as u64,
+                                    "Provider finished"
+                                );
+                                timings
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .insert(provider.name().to_string(), elapsed);
+                                None
+                            }
+                        }
+                    }
+                    .instrument(span.clone())
+                },
+            ))
+        });
+        let mut items: Vec<Item> = futures::stream::select_all(streams).collect().await;
+
+        // Combine and sort by score, then provider priority, then provider
+        // name; with provider groups (or empty query providers), by group
+        // first
+        let priority_of =
+            |item: &Item| priorities.get(item.provider.as_str()).copied().unwrap_or(0);
+        let groups = self.empty_query_order(query).or(self.groups.as_deref());
+        let group_of = |item: &Item| match groups {
+            Some(order) => order
+                .iter()
+                .position(|name| *name == item.provider)
+                .unwrap_or(order.len()),
+            None => 0,
+        };
 
-        // Combine and sort by score
-        let mut items: Vec<Item> = results.into_iter().flatten().collect();
         items.sort_by(|a, b| {
-            b.score
-                .partial_cmp(&a.score)
-                .unwrap_or(std::cmp::Ordering::Equal)
+            group_of(a)
+                .cmp(&group_of(b))
+                .then_with(|| {
+                    b.score
+                        .partial_cmp(&a.score)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .then_with(|| priority_of(b).cmp(&priority_of(a)))
+                .then_with(|| a.provider.cmp(&b.provider))
         });
+        items.drain(..offset.min(items.len()));
         items.truncate(max_results);
 
         debug!("Query returned {} items", items.len());
-        items
+        let timings = timings.into_inner().unwrap_or_else(PoisonError::into_inner);
+        (items, timings)
+    }
+}
+
+/// A provider's results, ending with an error item if the provider panics
+///
+/// Panics are caught both when the stream is created and while it's
+/// polled; items produced before the panic are kept.
+fn catch_panics<'a>(provider: &'a dyn Provider, query: &str, max_results: usize) -> ItemStream<'a> {
+    let name = provider.name().to_string();
+    let created = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        provider.query_stream(query, max_results)
+    }));
+    match created {
+        Ok(items) => Box::pin(
+            AssertUnwindSafe(items)
+                .catch_unwind()
+                .map(move |item| item.unwrap_or_else(|panic| panic_item(&name, panic))),
+        ),
+        Err(panic) => Box::pin(futures::stream::iter([panic_item(&name, panic)])),
     }
 }
 
+/// Items stamped with when they were produced, unless the provider did
+fn stamped(items: ItemStream<'_>) -> ItemStream<'_> {
+    Box::pin(items.map(|mut item| {
+        item.generated_at.get_or_insert_with(SystemTime::now);
+        item
+    }))
+}
+
+/// Items scoring at least `min_score`, or marked always shown
+///
+/// Filtering each provider's stream keeps weak items from using up its
+/// share of the results.
+fn above_floor(items: ItemStream<'_>, min_score: f32) -> ItemStream<'_> {
+    Box::pin(
+        items.filter(move |item| {
+            futures::future::ready(item.score >= min_score || item.always_shown())
+        }),
+    )
+}
+
+/// The error item standing in for a provider that panicked
+///
+/// It scores zero, so it sorts below every real result, but is always shown.
+fn panic_item(provider: &str, panic: Box<dyn Any + Send>) -> Item {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown error".to_string());
+    error!(provider, "Provider panicked: {}", message);
+    Item::new(format!("{} failed", provider), provider)
+        .with_stable_id("error")
+        .with_subtext(format!("Error: {}", message))
+        .with_icon("dialog-error")
+        .with_metadata("error", message)
+        .with_always_show()
+        .with_score(0.0)
+}
+
+/// Check that a provider name is usable as an identifier
+///
+/// Names travel in comma-separated lists (e.g. the CLI's `--providers`), so
+/// they are restricted to ASCII letters, digits, `-` and `_`.
+fn validate_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("provider name must not be empty");
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+    {
+        anyhow::bail!("invalid character {:?} in provider name '{}'", c, name);
+    }
+    Ok(())
+}
+
 impl Default for ProviderManager {
     fn default() -> Self {
         Self::new()
@@ -88,56 +810,93 @@ impl Default for ProviderManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::providers::Item;
+    use crate::providers::testing::{manager_with, mock, texts, MockProvider};
+    use crate::providers::{Item, ItemStream};
     use std::future::Future;
     use std::pin::Pin;
 
-    /// A configurable provider for exercising the manager's routing/sorting.
-    struct MockProvider {
-        name: String,
-        prefix: Option<String>,
-        /// (text, score) pairs returned for any query.
-        items: Vec<(&'static str, f32)>,
-    }
+    /// A streaming provider with endless results
+    struct CountingProvider;
 
-    impl Provider for MockProvider {
+    impl Provider for CountingProvider {
         fn name(&self) -> &str {
-            &self.name
+            "counting"
         }
         fn description(&self) -> &str {
-            "mock provider"
-        }
-        fn prefix(&self) -> Option<&str> {
-            self.prefix.as_deref()
+            "counts forever"
         }
         fn query(
             &self,
-            _query: &str,
-            _max_results: usize,
+            query: &str,
+            max_results: usize,
         ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
-            let name = self.name.clone();
-            let items: Vec<Item> = self
-                .items
-                .iter()
-                .map(|(text, score)| Item::new(*text, name.clone()).with_score(*score))
-                .collect();
-            Box::pin(async move { items })
+            Box::pin(
+                self.query_stream(query, max_results)
+                    .take(max_results)
+                    .collect(),
+            )
+        }
+        fn query_stream(&self, _query: &str, _max_results: usize) -> ItemStream<'_> {
+            Box::pin(
+                futures::stream::iter(0..)
+                    .map(|n| Item::new(n.to_string(), "counting").with_score(1.0 / (n + 1) as f32)),
+            )
         }
     }
 
-    fn mock(name: &str, prefix: Option<&str>, items: Vec<(&'static str, f32)>) -> MockProvider {
-        MockProvider {
-            name: name.to_string(),
-            prefix: prefix.map(String::from),
-            items,
-        }
+    #[tokio::test]
+    async fn streaming_providers_are_read_up_to_the_cap() {
+        let manager = ProviderManager::new();
+        manager.register(CountingProvider).await.unwrap();
+        manager
+            .register(mock("alpha", None, vec![("a", 0.75)]))
+            .await
+            .unwrap();
+
+        let items = manager.query("x", 3, &[]).await;
+        let texts: Vec<_> = items.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(texts, vec!["0", "a", "1"]);
+    }
+
+    #[tokio::test]
+    async fn pages_through_the_merged_results() {
+        let manager = manager_with(vec![
+            mock("alpha", None, vec![("a1", 0.9), ("a2", 0.5)]),
+            mock("beta", None, vec![("b1", 0.8), ("b2", 0.4)]),
+        ])
+        .await;
+
+        assert_eq!(
+            texts(&manager.query_page("q", 0, 2, &[]).await),
+            ["a1", "b1"]
+        );
+        assert_eq!(
+            texts(&manager.query_page("q", 2, 2, &[]).await),
+            ["a2", "b2"]
+        );
+        assert!(manager.query_page("q", 4, 2, &[]).await.is_empty());
+        // Streaming providers are read far enough to fill later pages.
+        manager.register(CountingProvider).await.unwrap();
+        assert_eq!(
+            texts(&manager.query_page("q", 5, 2, &[]).await),
+            ["b2", "2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_collects_the_stream_by_default() {
+        let items = CountingProvider.query("x", 2).await;
+        assert_eq!(items.len(), 2);
     }
 
     #[tokio::test]
     async fn registers_and_lists_providers() {
         let manager = ProviderManager::new();
-        manager.register(mock("alpha", None, vec![])).await;
-        manager.register(mock("beta", Some("="), vec![])).await;
+        manager.register(mock("alpha", None, vec![])).await.unwrap();
+        manager
+            .register(mock("beta", Some("="), vec![]))
+            .await
+            .unwrap();
 
         let providers = manager.list_providers().await;
         assert_eq!(providers.len(), 2);
@@ -147,16 +906,178 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn query_combines_and_sorts_by_score() {
+    async fn rejects_duplicate_provider_names() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("files", None, vec![("first", 0.5)]))
+            .await
+            .unwrap();
+
+        let err = manager
+            .register(mock("files", Some("/"), vec![("second", 0.5)]))
+            .await
+            .expect_err("duplicate name should be rejected");
+        assert!(err.to_string().contains("files"));
+
+        // The original registration is untouched.
+        let providers = manager.list_providers().await;
+        assert_eq!(providers.len(), 1);
+        let items = manager.query("q", 10, &[]).await;
+        assert_eq!(items[0].text, "first");
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_provider_names() {
+        let manager = ProviderManager::new();
+        assert!(manager.register(mock("", None, vec![])).await.is_err());
+        assert!(manager.register(mock("a,b", None, vec![])).await.is_err());
+        assert!(manager
+            .register(mock("two words", None, vec![]))
+            .await
+            .is_err());
+        assert!(manager
+            .register(mock("web-search_2", None, vec![]))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn unregister_removes_from_listing_and_lookup() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("alpha", None, vec![("a", 0.5)]))
+            .await
+            .unwrap();
+        manager
+            .register(mock("beta", None, vec![("b", 0.5)]))
+            .await
+            .unwrap();
+
+        assert!(manager.get("alpha").await.is_some());
+        assert!(manager.unregister("alpha").await);
+        assert!(!manager.unregister("alpha").await);
+
+        assert!(manager.get("alpha").await.is_none());
+        let names: Vec<_> = manager
+            .list_providers()
+            .await
+            .into_iter()
+            .map(|p| p.name)
+            .collect();
+        assert_eq!(names, vec!["beta"]);
+        assert!(manager
+            .query("q", 10, &["alpha".to_string()])
+            .await
+            .is_empty());
+
+        // The name is free again.
+        manager.register(mock("alpha", None, vec![])).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_provider_names() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("calc", Some("="), vec![]))
+            .await
+            .unwrap();
+
+        let names = ["calc", "typo", "typo", "other"].map(String::from);
+        assert_eq!(
+            manager.unknown_providers(&names).await,
+            vec!["typo".to_string(), "other".to_string()]
+        );
+        assert!(manager.unknown_providers(&[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn explicit_provider_names_are_deduplicated() {
         let manager = ProviderManager::new();
         manager
-            .register(mock("a", None, vec![("low", 0.1), ("high", 0.9)]))
+            .register(mock("calc", Some("="), vec![("result", 0.5)]))
+            .await
+            .unwrap();
+
+        let items = manager
+            .query("q", 10, &["calc".to_string(), "calc".to_string()])
             .await;
-        manager.register(mock("b", None, vec![("mid", 0.5)])).await;
+        assert_eq!(items.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn query_combines_and_sorts_by_score() {
+        let manager = manager_with(vec![
+            mock("a", None, vec![("low", 0.1), ("high", 0.9)]),
+            mock("b", None, vec![("mid", 0.5)]),
+        ])
+        .await;
 
         let items = manager.query("anything", 10, &[]).await;
-        let texts: Vec<_> = items.iter().map(|i| i.text.as_str()).collect();
-        assert_eq!(texts, vec!["high", "mid", "low"]);
+        assert_eq!(texts(&items), ["high", "mid", "low"]);
+    }
+
+    #[tokio::test]
+    async fn empty_queries_go_only_to_the_listed_providers() {
+        let manager = ProviderManager::new()
+            .with_empty_query_providers(vec!["recent".to_string(), "apps".to_string()]);
+        for provider in [
+            mock("apps", None, vec![("app", 0.9)]),
+            mock("calc", None, vec![("hint", 1.0)]),
+            mock("recent", None, vec![("file", 0.1)]),
+        ] {
+            manager.register(provider).await.unwrap();
+        }
+
+        // Listed order, not score order
+        assert_eq!(texts(&manager.query("", 10, &[]).await), ["file", "app"]);
+        assert_eq!(texts(&manager.query("  ", 10, &[]).await), ["file", "app"]);
+        let routed: Vec<_> = manager
+            .routes("")
+            .await
+            .into_iter()
+            .map(|route| route.provider)
+            .collect();
+        assert_eq!(routed, ["apps", "recent"]);
+
+        // Named providers are still asked
+        let named = manager.query("", 10, &["calc".to_string()]).await;
+        assert_eq!(texts(&named), ["hint"]);
+        // Other queries are unaffected
+        assert_eq!(
+            texts(&manager.query("x", 10, &[]).await),
+            ["hint", "app", "file"]
+        );
+    }
+
+    #[tokio::test]
+    async fn provider_groups_order_results_by_provider_first() {
+        let manager = ProviderManager::new()
+            .with_provider_groups(vec!["calc".to_string(), "apps".to_string()]);
+        for provider in [
+            mock("apps", None, vec![("app1", 0.9), ("app2", 0.2)]),
+            mock("other", None, vec![("other", 1.0)]),
+            mock("calc", None, vec![("calc", 0.5)]),
+        ] {
+            manager.register(provider).await.unwrap();
+        }
+
+        let items = manager.query("q", 10, &[]).await;
+        assert_eq!(texts(&items), ["calc", "app1", "app2", "other"]);
+    }
+
+    #[tokio::test]
+    async fn equal_scores_break_ties_by_priority_then_name() {
+        let manager = manager_with(vec![
+            mock("apps", None, vec![("app", 0.8)]),
+            mock("calc", None, vec![("calc", 0.8)]).with_priority(10),
+            mock("zeta", None, vec![("zeta", 0.8)]),
+            mock("best", None, vec![("best", 0.9)]),
+        ])
+        .await;
+
+        let items = manager.query("q", 10, &[]).await;
+        // Score dominates; among the 0.8 ties priority wins, then name.
+        assert_eq!(texts(&items), ["best", "calc", "app", "zeta"]);
     }
 
     #[tokio::test]
@@ -164,22 +1085,78 @@ mod tests {
         let manager = ProviderManager::new();
         manager
             .register(mock("a", None, vec![("x", 0.3), ("y", 0.2), ("z", 0.1)]))
-            .await;
+            .await
+            .unwrap();
 
         let items = manager.query("q", 2, &[]).await;
         assert_eq!(items.len(), 2);
         assert_eq!(items[0].text, "x");
     }
 
+    /// A provider that panics, either when asked or after its first item
+    struct PanickingProvider {
+        name: &'static str,
+        mid_stream: bool,
+    }
+
+    impl Provider for PanickingProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+        fn description(&self) -> &str {
+            "panics"
+        }
+        fn query(
+            &self,
+            query: &str,
+            max_results: usize,
+        ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+            Box::pin(
+                self.query_stream(query, max_results)
+                    .take(max_results)
+                    .collect(),
+            )
+        }
+        fn query_stream(&self, _query: &str, _max_results: usize) -> ItemStream<'_> {
+            if !self.mid_stream {
+                panic!("bad index");
+            }
+            Box::pin(futures::stream::iter(0..).map(|n| {
+                if n > 0 {
+                    panic!("bad regex");
+                }
+                Item::new("first", "late").with_score(0.5)
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn panicking_providers_return_an_error_item() {
+        let manager = manager_with(vec![mock("alpha", None, vec![("a", 0.9)])]).await;
+        for (name, mid_stream) in [("early", false), ("late", true)] {
+            manager
+                .register(PanickingProvider { name, mid_stream })
+                .await
+                .unwrap();
+        }
+
+        let items = manager.query("q", 10, &[]).await;
+        assert_eq!(texts(&items), ["a", "first", "early failed", "late failed"]);
+        assert_eq!(items[2].metadata["error"], "bad index");
+        assert_eq!(items[3].metadata["error"], "bad regex");
+    }
+
     #[tokio::test]
     async fn explicit_provider_filter_is_respected() {
         let manager = ProviderManager::new();
         manager
             .register(mock("apps", None, vec![("app", 0.5)]))
-            .await;
+            .await
+            .unwrap();
         manager
             .register(mock("calc", Some("="), vec![("calc-result", 0.5)]))
-            .await;
+            .await
+            .unwrap();
 
         // Even without the prefix, an explicit provider request is honoured.
         let items = manager.query("apps query", 10, &["calc".to_string()]).await;
@@ -187,15 +1164,249 @@ mod tests {
         assert_eq!(items[0].text, "calc-result");
     }
 
+    #[tokio::test]
+    async fn disabled_providers_are_skipped_even_when_named() {
+        let manager = manager_with(vec![
+            mock("on", None, vec![("on", 0.5)]),
+            mock("off", None, vec![("off", 0.5)]).with_enabled(false),
+        ])
+        .await;
+        let names = ["on".to_string(), "off".to_string(), "off".to_string()];
+
+        assert_eq!(texts(&manager.query("q", 10, &names).await), ["on"]);
+        assert_eq!(texts(&manager.query("q", 10, &[]).await), ["on"]);
+        assert_eq!(manager.disabled_providers(&names).await, ["off"]);
+        assert!(manager.unknown_providers(&names).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn known_providers_are_listed_as_disabled() {
+        let manager = manager_with(vec![mock("on", None, vec![("on", 0.5)])]).await;
+        let off = mock("off", Some("!"), vec![("off", 0.5)]);
+        manager.add_known(off.info()).await;
+        // Registering later takes precedence over the known entry.
+        manager.add_known(mock("on", None, vec![]).info()).await;
+
+        let names = |infos: Vec<ProviderInfo>| -> Vec<(String, bool)> {
+            infos.into_iter().map(|i| (i.name, i.enabled)).collect()
+        };
+        assert_eq!(
+            names(manager.list_providers().await),
+            [("on".to_string(), true)]
+        );
+        assert_eq!(
+            names(manager.list_all_providers().await),
+            [("on".to_string(), true), ("off".to_string(), false)]
+        );
+
+        let asked = ["off".to_string(), "nope".to_string()];
+        assert_eq!(manager.disabled_providers(&asked).await, ["off"]);
+        assert_eq!(manager.unknown_providers(&asked).await, ["nope"]);
+        assert_eq!(texts(&manager.query("!x", 10, &[]).await), ["on"]);
+    }
+
+    #[tokio::test]
+    async fn items_are_stamped_when_produced() {
+        let before = SystemTime::now();
+        let manager = manager_with(vec![mock("a", None, vec![("a", 0.5)])]).await;
+        let items = manager.query("q", 10, &[]).await;
+        let stamped = items[0].generated_at.unwrap();
+        assert!(stamped >= before && stamped <= SystemTime::now());
+
+        let items = manager.query_provider("a", "q", 10).await.unwrap();
+        assert!(items[0].generated_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn items_below_the_score_floor_are_dropped() {
+        let mut weak = mock(
+            "a",
+            None,
+            vec![("a good", 0.8), ("a weak", 0.1), ("a ok", 0.5)],
+        );
+        weak.items.push(
+            Item::new("a fallback", "a")
+                .with_always_show()
+                .with_score(0.01),
+        );
+        let manager = manager_with(vec![weak]).await.with_min_score(0.3);
+        assert_eq!(
+            texts(&manager.query("q", 10, &[]).await),
+            ["a good", "a ok", "a fallback"]
+        );
+        // Dropped items don't count against the provider's results
+        assert_eq!(texts(&manager.query("q", 2, &[]).await), ["a good", "a ok"]);
+        assert_eq!(
+            texts(&manager.query_provider("a", "q", 10).await.unwrap()),
+            ["a good", "a ok", "a fallback"]
+        );
+
+        // Errors are always shown
+        manager
+            .register(PanickingProvider {
+                name: "early",
+                mid_stream: false,
+            })
+            .await
+            .unwrap();
+        let items = manager.query("q", 10, &[]).await;
+        assert_eq!(texts(&items).last(), Some(&"early failed"));
+    }
+
+    #[tokio::test]
+    async fn aliases_are_expanded_before_routing() {
+        let manager = manager_with(vec![
+            mock("apps", None, vec![("app", 0.5)]),
+            mock("calc", Some("="), vec![("calc", 0.5)]),
+        ])
+        .await
+        .with_aliases(HashMap::from([
+            ("ff".to_string(), "firefox".to_string()),
+            ("ff dev".to_string(), "firefox-developer".to_string()),
+            ("calc".to_string(), "=".to_string()),
+        ]));
+
+        let queried = |items: Vec<Item>| items[0].metadata["query"].clone();
+        assert_eq!(queried(manager.query("ff", 10, &[]).await), "firefox");
+        assert_eq!(
+            queried(manager.query("ff nightly", 10, &[]).await),
+            "firefox nightly"
+        );
+        assert_eq!(
+            queried(manager.query("ff dev", 10, &[]).await),
+            "firefox-developer"
+        );
+        // Only whole words are aliases
+        assert_eq!(queried(manager.query("ffmpeg", 10, &[]).await), "ffmpeg");
+
+        // An alias can stand for a prefix
+        let items = manager.query("calc 2+2", 10, &[]).await;
+        assert_eq!(texts(&items), ["calc"]);
+        assert_eq!(queried(items), "= 2+2");
+        let routes = manager.routes("calc 1").await;
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].provider, "calc");
+        let items = manager.query_provider("apps", "ff", 10).await.unwrap();
+        assert_eq!(queried(items), "firefox");
+    }
+
+    #[tokio::test]
+    async fn factories_instantiate_providers_on_demand() {
+        let manager = ProviderManager::new();
+        let factory: ProviderFactory =
+            Arc::new(|| Box::new(mock("lazy", Some("!"), vec![("lazy", 0.5)])));
+        manager.add_factory("lazy", factory).await;
+        assert!(manager.query("!x", 10, &[]).await.is_empty());
+
+        let info = manager.instantiate("lazy").await.unwrap();
+        assert!(info.enabled);
+        assert_eq!(texts(&manager.query("!x", 10, &[]).await), ["lazy"]);
+        // Instantiating again leaves the registered provider alone.
+        manager.instantiate("lazy").await.unwrap();
+        assert_eq!(manager.list_providers().await.len(), 1);
+
+        let err = manager.instantiate("nope").await.unwrap_err();
+        assert!(err.to_string().contains("no provider named 'nope'"));
+
+        let misnamed: ProviderFactory = Arc::new(|| Box::new(mock("other", None, vec![])));
+        manager.add_factory("named", misnamed).await;
+        assert!(manager.instantiate("named").await.is_err());
+        assert!(manager.get("other").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn short_queries_skip_providers_with_a_minimum_length() {
+        let manager = manager_with(vec![
+            mock("apps", None, vec![("app", 0.5)]),
+            mock("files", None, vec![("file", 0.9)]),
+        ])
+        .await
+        .with_min_query_lens(HashMap::from([("files".to_string(), 3)]));
+
+        assert_eq!(texts(&manager.query("ab", 10, &[]).await), ["app"]);
+        assert!(manager
+            .query("ab", 10, &["files".to_string()])
+            .await
+            .is_empty());
+        assert_eq!(manager.routes("ab").await.len(), 1);
+        // Characters, not bytes
+        assert_eq!(texts(&manager.query("äb", 10, &[]).await), ["app"]);
+        assert_eq!(texts(&manager.query("abc", 10, &[]).await), ["file", "app"]);
+    }
+
+    #[tokio::test]
+    async fn provider_limit_caps_each_provider_not_the_total() {
+        let manager = manager_with(vec![
+            mock("alpha", None, vec![("a1", 0.9), ("a2", 0.8), ("a3", 0.7)]),
+            mock("beta", None, vec![("b1", 0.85), ("b2", 0.75), ("b3", 0.65)]),
+        ])
+        .await
+        .with_provider_max_results(2);
+
+        assert_eq!(
+            texts(&manager.query("q", 10, &[]).await),
+            ["a1", "b1", "a2", "b2"]
+        );
+        assert_eq!(texts(&manager.query("q", 3, &[]).await), ["a1", "b1", "a2"]);
+        assert_eq!(
+            texts(&manager.query_provider("beta", "q", 10).await.unwrap()),
+            ["b1", "b2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_provider_returns_one_providers_results_unsorted() {
+        let manager = manager_with(vec![
+            mock(
+                "alpha",
+                None,
+                vec![("low", 0.1), ("high", 0.9), ("mid", 0.5)],
+            ),
+            mock("beta", None, vec![("other", 1.0)]),
+            MockProvider::new("off").with_enabled(false),
+        ])
+        .await;
+
+        let items = manager.query_provider("alpha", "q", 2).await.unwrap();
+        assert_eq!(texts(&items), ["low", "high"]);
+
+        let unknown = manager.query_provider("nope", "q", 10).await.unwrap_err();
+        assert_eq!(unknown.to_string(), "no provider named 'nope'");
+        let disabled = manager.query_provider("off", "q", 10).await.unwrap_err();
+        assert_eq!(disabled.to_string(), "provider 'off' is disabled");
+    }
+
+    #[tokio::test]
+    async fn timed_queries_report_each_provider() {
+        let manager = manager_with(vec![
+            MockProvider::new("slow")
+                .with_item("late", 0.9)
+                .with_delay(Duration::from_millis(50)),
+            mock("fast", None, vec![("early", 0.5)]),
+            mock("calc", Some("="), vec![("sum", 1.0)]),
+        ])
+        .await;
+
+        let (items, timings) = manager.query_page_timed("q", 0, 10, &[]).await;
+        assert_eq!(texts(&items), ["late", "early"]);
+        let mut names: Vec<&str> = timings.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["fast", "slow"]);
+        assert!(timings["slow"] >= Duration::from_millis(50));
+        assert!(timings["fast"] < timings["slow"]);
+    }
+
     #[tokio::test]
     async fn prefix_provider_only_matches_with_prefix() {
         let manager = ProviderManager::new();
         manager
             .register(mock("apps", None, vec![("app", 0.5)]))
-            .await;
+            .await
+            .unwrap();
         manager
             .register(mock("calc", Some("="), vec![("calc-result", 0.9)]))
-            .await;
+            .await
+            .unwrap();
 
         // No prefix: calculator should not contribute.
         let plain = manager.query("firefox", 10, &[]).await;
@@ -205,4 +1416,342 @@ mod tests {
         let prefixed = manager.query("=2+2", 10, &[]).await;
         assert!(prefixed.iter().any(|i| i.text == "calc-result"));
     }
+
+    #[tokio::test]
+    async fn longest_matching_prefix_wins() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("apps", None, vec![("app", 0.5)]))
+            .await
+            .unwrap();
+        manager
+            .register(mock("calc", Some("="), vec![("calc-result", 0.9)]))
+            .await
+            .unwrap();
+        manager
+            .register(mock("compare", Some("=="), vec![("compared", 0.9)]))
+            .await
+            .unwrap();
+
+        let routed = |routes: Vec<Route>| -> Vec<String> {
+            routes.into_iter().map(|r| r.provider).collect()
+        };
+        assert_eq!(routed(manager.routes("==a b").await), ["compare"]);
+        assert_eq!(routed(manager.routes("=2+2").await), ["calc"]);
+        assert_eq!(routed(manager.routes("firefox").await), ["apps"]);
+
+        let items = manager.query("==a b", 10, &[]).await;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].text, "compared");
+    }
+
+    #[tokio::test]
+    async fn overlapping_prefixes_route_to_the_longest_match_only() {
+        let manager = ProviderManager::new();
+        // Registered shortest-last so order can't be what decides.
+        for (name, prefix) in [("three", "==="), ("one", "="), ("two", "==")] {
+            manager
+                .register(mock(name, Some(prefix), vec![(name, 0.5)]))
+                .await
+                .unwrap();
+        }
+        manager
+            .register(mock("also-two", Some("=="), vec![("also-two", 0.5)]))
+            .await
+            .unwrap();
+
+        let texts = |items: Vec<Item>| -> Vec<String> {
+            let mut texts: Vec<String> = items.into_iter().map(|i| i.text).collect();
+            texts.sort();
+            texts
+        };
+        assert_eq!(texts(manager.query("====", 10, &[]).await), ["three"]);
+        assert_eq!(
+            texts(manager.query("==x", 10, &[]).await),
+            ["also-two", "two"]
+        );
+        assert_eq!(texts(manager.query("=x", 10, &[]).await), ["one"]);
+        assert!(manager.query("x", 10, &[]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn prefixless_providers_fall_through_when_no_prefix_matches() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("apps", None, vec![("app", 0.5)]))
+            .await
+            .unwrap();
+        manager
+            .register(mock("calc", Some("="), vec![("calc-result", 0.9)]))
+            .await
+            .unwrap();
+
+        let items = manager.query("=2+2", 10, &[]).await;
+        assert!(items.iter().all(|i| i.text != "app"));
+        let items = manager.query("firefox", 10, &[]).await;
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].text, "app");
+
+        // Naming providers bypasses routing altogether.
+        let named = vec!["apps".to_string()];
+        assert_eq!(manager.query("=2+2", 10, &named).await[0].text, "app");
+    }
+
+    #[tokio::test]
+    async fn routes_report_matched_prefix_and_translated_query() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("calc", Some("="), vec![]))
+            .await
+            .unwrap();
+        manager
+            .set_overrides(
+                "calc",
+                ProviderOverrides {
+                    prefix: Some(Some("c ".to_string())),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            manager.routes("c 2+2").await,
+            [Route {
+                provider: "calc".to_string(),
+                prefix: Some("c ".to_string()),
+                query: "=2+2".to_string(),
+            }]
+        );
+        assert!(manager.routes("=2+2").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn priority_override_changes_order_and_listing() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("apps", None, vec![("app", 0.8)]))
+            .await
+            .unwrap();
+        manager
+            .register(mock("calc", None, vec![("calc", 0.8)]))
+            .await
+            .unwrap();
+
+        let info = manager
+            .set_overrides(
+                "calc",
+                ProviderOverrides {
+                    priority: Some(5),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(info.priority, 5);
+
+        let items = manager.query("q", 10, &[]).await;
+        assert_eq!(items[0].text, "calc");
+        let listed = manager.list_providers().await;
+        assert_eq!(
+            listed.iter().find(|p| p.name == "calc").unwrap().priority,
+            5
+        );
+
+        let info = manager.reset_overrides("calc").await.unwrap();
+        assert_eq!(info.priority, 0);
+        let items = manager.query("q", 10, &[]).await;
+        assert_eq!(items[0].text, "app");
+    }
+
+    #[tokio::test]
+    async fn prefix_override_reroutes_and_translates_query() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("calc", Some("="), vec![("calc-result", 0.9)]))
+            .await
+            .unwrap();
+
+        manager
+            .set_overrides(
+                "calc",
+                ProviderOverrides {
+                    prefix: Some(Some("c ".to_string())),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        // The old prefix no longer routes; the new one does, and the provider
+        // still sees its own prefix.
+        assert!(manager.query("=2+2", 10, &[]).await.is_empty());
+        let items = manager.query("c 2+2", 10, &[]).await;
+        assert_eq!(items[0].metadata["query"], "=2+2");
+
+        // Clearing the prefix makes it handle everything.
+        manager
+            .set_overrides(
+                "calc",
+                ProviderOverrides {
+                    prefix: Some(None),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        let items = manager.query("2+2", 10, &[]).await;
+        assert_eq!(items[0].metadata["query"], "=2+2");
+        assert_eq!(manager.list_providers().await[0].prefix, None);
+        assert!(!manager.list_providers().await[0].requires_prefix);
+    }
+
+    #[tokio::test]
+    async fn capabilities_are_listed() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("calc", Some("="), vec![]))
+            .await
+            .unwrap();
+        manager.register(mock("apps", None, vec![])).await.unwrap();
+
+        let listed = manager.list_providers().await;
+        assert!(listed[0].requires_prefix);
+        assert!(!listed[1].requires_prefix);
+        assert!(listed.iter().all(|p| p.supports_activate && !p.is_network));
+    }
+
+    #[tokio::test]
+    async fn overrides_reject_unknown_providers_and_blank_prefixes() {
+        let manager = ProviderManager::new();
+        manager
+            .register(mock("calc", Some("="), vec![]))
+            .await
+            .unwrap();
+
+        assert!(manager
+            .set_overrides("nope", ProviderOverrides::default())
+            .await
+            .is_err());
+        assert!(manager.reset_overrides("nope").await.is_err());
+        assert!(manager
+            .set_overrides(
+                "calc",
+                ProviderOverrides {
+                    prefix: Some(Some(" ".to_string())),
+                    ..Default::default()
+                },
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn register_starts_background_work_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct Background(Arc<AtomicUsize>);
+
+        impl Provider for Background {
+            fn name(&self) -> &str {
+                "background"
+            }
+            fn description(&self) -> &str {
+                "counts spawn_background calls"
+            }
+            fn spawn_background(&self, handle: tokio::runtime::Handle) {
+                let count = Arc::clone(&self.0);
+                handle.spawn(async move {
+                    count.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            fn query(
+                &self,
+                _query: &str,
+                _max_results: usize,
+            ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+                Box::pin(async { Vec::new() })
+            }
+        }
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let manager = ProviderManager::new();
+        manager
+            .register(Background(Arc::clone(&count)))
+            .await
+            .unwrap();
+        // A rejected duplicate must not start a second task.
+        assert!(manager
+            .register(Background(Arc::clone(&count)))
+            .await
+            .is_err());
+
+        tokio::task::yield_now().await;
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn warmup_runs_in_the_background() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use tokio::sync::Notify;
+
+        /// Answers only once its warmup, gated on `go`, has finished
+        struct Slow {
+            go: Arc<Notify>,
+            ready: AtomicBool,
+        }
+
+        impl Provider for Slow {
+            fn name(&self) -> &str {
+                "slow"
+            }
+            fn description(&self) -> &str {
+                "warms up on request"
+            }
+            fn warmup(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+                Box::pin(async {
+                    self.go.notified().await;
+                    self.ready.store(true, Ordering::SeqCst);
+                })
+            }
+            fn query(
+                &self,
+                _query: &str,
+                _max_results: usize,
+            ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+                let ready = self.ready.load(Ordering::SeqCst);
+                Box::pin(async move {
+                    if ready {
+                        vec![Item::new("ready", "slow")]
+                    } else {
+                        Vec::new()
+                    }
+                })
+            }
+        }
+
+        let go = Arc::new(Notify::new());
+        let manager = ProviderManager::new();
+        manager
+            .register(Slow {
+                go: Arc::clone(&go),
+                ready: AtomicBool::new(false),
+            })
+            .await
+            .unwrap();
+
+        // Warmup doesn't block, and queries are answered in the meantime.
+        manager.warmup().await;
+        assert!(manager.query("q", 10, &[]).await.is_empty());
+
+        go.notify_one();
+        for _ in 0..200 {
+            if !manager.query("q", 10, &[]).await.is_empty() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        panic!("warmup never finished");
+    }
 }
@@ -4,26 +4,67 @@
 //! implements the `Provider` trait and can respond to queries.
 
 pub mod applications;
+pub mod bookmarks;
+pub mod builtin;
 pub mod calculator;
+pub mod clipboard;
+pub mod color;
+pub mod currency;
+pub mod custom;
+pub mod external;
+pub mod generate;
+pub mod hash;
 pub mod manager;
+pub mod process;
+pub mod recent;
+pub mod systemd;
+#[cfg(test)]
+pub(crate) mod testing;
+pub mod time;
+pub mod trash;
+pub mod typing;
+pub mod web;
+pub mod websearch;
 
 pub use applications::ApplicationsProvider;
+pub use bookmarks::{BookmarkSource, BookmarksProvider};
 pub use calculator::CalculatorProvider;
-pub use manager::ProviderManager;
+pub use color::ColorProvider;
+pub use currency::CurrencyProvider;
+pub use custom::{CustomEntry, CustomProvider};
+pub use external::{ExternalItem, ExternalProvider};
+pub use generate::GenerateProvider;
+pub use hash::HashProvider;
+pub use manager::{ProviderFactory, ProviderManager, ProviderOverrides, Route};
+pub use process::ProcessProvider;
+pub use recent::RecentFilesProvider;
+pub use systemd::{SystemdProvider, SystemdScope};
+pub use time::TimeProvider;
+pub use trash::TrashProvider;
+pub use web::WebProvider;
+pub use websearch::{SearchEngine, WebSearchProvider};
 
 use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Metadata key that, set to `"true"`, keeps an item below the configured
+/// score floor (`min_score`)
+pub const ALWAYS_SHOW_KEY: &str = "always_show";
 
 /// A single result item from a provider
 #[derive(Debug, Clone)]
 pub struct Item {
-    /// Unique identifier for this item
+    /// Identifier for this item: stable across queries when set with
+    /// `with_stable_id`, otherwise random
     pub id: String,
     /// Primary display text (e.g., app name)
     pub text: String,
     /// Secondary display text (e.g., description)
     pub subtext: String,
-    /// Icon name (from .desktop file)
+    /// Icon name (from .desktop file), path or URI; see `icon_kind`
     pub icon: String,
+    /// How frontends should interpret `icon`
+    pub icon_kind: IconKind,
     /// Resolved icon file path (SVG preferred, then largest PNG)
     pub icon_path: String,
     /// Provider that generated this item
@@ -34,6 +75,12 @@ pub struct Item {
     pub metadata: HashMap<String, String>,
     /// Source of the item (e.g., "native", "flatpak", "snap")
     pub source: String,
+    /// When the item was produced; set by the manager if the provider
+    /// doesn't
+    pub generated_at: Option<SystemTime>,
+    /// How long after `generated_at` the item stays accurate (`None`: no
+    /// expiry)
+    pub ttl: Option<Duration>,
 }
 
 impl Item {
@@ -45,14 +92,26 @@ impl Item {
             text,
             subtext: String::new(),
             icon: String::new(),
+            icon_kind: IconKind::default(),
             icon_path: String::new(),
             provider: provider.into(),
             score: 0.0,
             metadata: HashMap::new(),
             source: String::new(),
+            generated_at: None,
+            ttl: None,
         }
     }
 
+    /// Derive the id from the provider name and a provider-chosen key (e.g. a
+    /// desktop id), so the same result gets the same id on every query
+    ///
+    /// Set the provider (via `new`) before calling this.
+    pub fn with_stable_id(mut self, key: impl AsRef<str>) -> Self {
+        self.id = stable_id(&self.provider, key.as_ref());
+        self
+    }
+
     pub fn with_subtext(mut self, subtext: impl Into<String>) -> Self {
         self.subtext = subtext.into();
         self
@@ -63,6 +122,11 @@ impl Item {
         self
     }
 
+    pub fn with_icon_kind(mut self, icon_kind: IconKind) -> Self {
+        self.icon_kind = icon_kind;
+        self
+    }
+
     pub fn with_icon_path(mut self, icon_path: impl Into<String>) -> Self {
         self.icon_path = icon_path.into();
         self
@@ -78,10 +142,87 @@ impl Item {
         self
     }
 
+    /// Set the text a frontend copies when the item is activated (see
+    /// [`clipboard`])
+    pub fn with_copy(self, text: impl Into<String>) -> Self {
+        self.with_metadata(clipboard::METADATA_KEY, text)
+    }
+
+    /// Set the text a frontend types into the focused window when the item's
+    /// "Type" action is chosen (see [`typing`])
+    pub fn with_type_text(self, text: impl Into<String>) -> Self {
+        self.with_metadata(typing::METADATA_KEY, text)
+    }
+
+    /// Keep the item however low it scores, e.g. a fallback that is only
+    /// meant to come last (see [`ALWAYS_SHOW_KEY`])
+    pub fn with_always_show(self) -> Self {
+        self.with_metadata(ALWAYS_SHOW_KEY, "true")
+    }
+
+    /// Whether the item is kept below the configured score floor
+    pub fn always_shown(&self) -> bool {
+        self.metadata
+            .get(ALWAYS_SHOW_KEY)
+            .is_some_and(|v| v == "true")
+    }
+
     pub fn with_source(mut self, source: impl Into<String>) -> Self {
         self.source = source.into();
         self
     }
+
+    /// Mark the item stale this long after it is produced, e.g. a conversion
+    /// at rates that are due to be refreshed
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+}
+
+/// What an item's `icon` string holds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IconKind {
+    /// An icon theme name such as "firefox"
+    #[default]
+    ThemedName,
+    /// An absolute file path
+    AbsolutePath,
+    /// A URI, e.g. `file://` or `data:`
+    Uri,
+}
+
+impl From<IconKind> for crate::proto::IconKind {
+    fn from(kind: IconKind) -> Self {
+        match kind {
+            IconKind::ThemedName => crate::proto::IconKind::ThemedName,
+            IconKind::AbsolutePath => crate::proto::IconKind::AbsolutePath,
+            IconKind::Uri => crate::proto::IconKind::Uri,
+        }
+    }
+}
+
+/// Milliseconds from the Unix epoch to `time`
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Hash `provider` and `key` into a UUID-formatted id
+///
+/// Uses 128-bit FNV-1a, which is fixed by spec, so ids also survive daemon
+/// restarts and Rust upgrades (unlike `DefaultHasher`).
+fn stable_id(provider: &str, key: &str) -> String {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    let mut hash = OFFSET_BASIS;
+    // The NUL separator keeps ("ab", "c") and ("a", "bc") apart.
+    for byte in provider.bytes().chain([0]).chain(key.bytes()) {
+        hash ^= u128::from(byte);
+        hash = hash.wrapping_mul(PRIME);
+    }
+    uuid::Uuid::from_u128(hash).to_string()
 }
 
 impl From<Item> for crate::proto::Item {
@@ -91,22 +232,30 @@ impl From<Item> for crate::proto::Item {
             text: item.text,
             subtext: item.subtext,
             icon: item.icon,
+            icon_kind: crate::proto::IconKind::from(item.icon_kind).into(),
             icon_path: item.icon_path,
             provider: item.provider,
             score: item.score,
             metadata: item.metadata,
             source: item.source,
+            generated_at_ms: item.generated_at.map_or(0, unix_millis),
+            // 0 means no expiry on the wire, so a zero ttl is rounded up.
+            ttl_ms: item.ttl.map_or(0, |ttl| (ttl.as_millis() as u64).max(1)),
         }
     }
 }
 
 /// Information about a provider
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ProviderInfo {
     pub name: String,
     pub description: String,
     pub prefix: Option<String>,
     pub enabled: bool,
+    pub priority: i32,
+    pub supports_activate: bool,
+    pub is_network: bool,
+    pub requires_prefix: bool,
 }
 
 impl From<ProviderInfo> for crate::proto::ProviderInfo {
@@ -116,13 +265,31 @@ impl From<ProviderInfo> for crate::proto::ProviderInfo {
             description: info.description,
             prefix: info.prefix.unwrap_or_default(),
             enabled: info.enabled,
+            priority: info.priority,
+            supports_activate: info.supports_activate,
+            is_network: info.is_network,
+            requires_prefix: info.requires_prefix,
+        }
+    }
+}
+
+impl From<Route> for crate::proto::ProviderRoute {
+    fn from(route: Route) -> Self {
+        crate::proto::ProviderRoute {
+            provider: route.provider,
+            prefix: route.prefix.unwrap_or_default(),
+            query: route.query,
         }
     }
 }
 
+use futures::{FutureExt, Stream};
 use std::future::Future;
 use std::pin::Pin;
 
+/// Items produced one at a time by [`Provider::query_stream`]
+pub type ItemStream<'a> = Pin<Box<dyn Stream<Item = Item> + Send + 'a>>;
+
 /// The core provider trait
 ///
 /// All data providers must implement this trait to integrate with datacube.
@@ -145,6 +312,53 @@ pub trait Provider: Send + Sync {
         true
     }
 
+    /// Returns the priority used to break score ties between providers
+    /// (higher sorts first)
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// Returns whether items do something when activated (launch, open,
+    /// restore) rather than only show information; copying an item's `copy`
+    /// value doesn't count, since frontends do that for any provider
+    fn supports_activate(&self) -> bool {
+        true
+    }
+
+    /// Returns whether queries may wait on the network, so frontends can
+    /// show a spinner
+    fn is_network(&self) -> bool {
+        false
+    }
+
+    /// Returns whether only queries starting with the prefix are answered;
+    /// providers that also recognise their input without it override this
+    fn requires_prefix(&self) -> bool {
+        self.prefix().is_some()
+    }
+
+    /// Start any long-running background work, e.g. periodic refreshes
+    ///
+    /// Called once by the manager when the provider is registered. Providers
+    /// that refresh their data on a timer spawn a task on `handle` here rather
+    /// than refreshing on the query path. Since this takes `&self`, state the
+    /// task updates must be shared with interior mutability (e.g. an
+    /// `Arc<RwLock<..>>`); holding a `Weak` lets the task end when the provider
+    /// is dropped. Tasks may be cancelled at any `.await` when the runtime shuts
+    /// down, so they must not leave shared state half-updated across one.
+    fn spawn_background(&self, _handle: tokio::runtime::Handle) {}
+
+    /// Build whatever the provider needs before it can answer in full, e.g.
+    /// an index of installed applications
+    ///
+    /// Called once by the server, in a background task, after it has bound
+    /// its socket, so slow indexing doesn't delay the daemon's startup.
+    /// Queries can arrive before warmup finishes; they should be answered
+    /// from whatever is ready so far rather than wait for it.
+    fn warmup(&self) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(async {})
+    }
+
     /// Check if this provider can handle the given query
     fn can_handle(&self, query: &str) -> bool {
         match self.prefix() {
@@ -154,12 +368,27 @@ pub trait Provider: Send + Sync {
     }
 
     /// Query the provider for matching items
+    ///
+    /// Providers that stream their results override
+    /// [`Provider::query_stream`] too, and can answer this by collecting it.
     fn query(
         &self,
         query: &str,
         max_results: usize,
     ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>>;
 
+    /// Query the provider, yielding items as they are found
+    ///
+    /// For providers with many results, so the manager can stop reading once
+    /// it has `max_results` of them. The default wraps [`Provider::query`].
+    fn query_stream(&self, query: &str, max_results: usize) -> ItemStream<'_> {
+        Box::pin(
+            self.query(query, max_results)
+                .map(futures::stream::iter)
+                .flatten_stream(),
+        )
+    }
+
     /// Get provider info
     fn info(&self) -> ProviderInfo {
         ProviderInfo {
@@ -167,6 +396,10 @@ pub trait Provider: Send + Sync {
             description: self.description().to_string(),
             prefix: self.prefix().map(String::from),
             enabled: self.enabled(),
+            priority: self.priority(),
+            supports_activate: self.supports_activate(),
+            is_network: self.is_network(),
+            requires_prefix: self.requires_prefix(),
         }
     }
 }
@@ -199,6 +432,23 @@ mod tests {
         assert!(!item.id.is_empty(), "id should be auto-generated");
     }
 
+    #[test]
+    fn stable_ids_depend_only_on_provider_and_key() {
+        let a = Item::new("Firefox", "applications").with_stable_id("firefox.desktop");
+        let b = Item::new("Firefox Web", "applications").with_stable_id("firefox.desktop");
+        assert_eq!(a.id, b.id);
+        assert_eq!(a.id.len(), 36, "formatted like a UUID");
+
+        let other_key = Item::new("Firefox", "applications").with_stable_id("chromium.desktop");
+        let other_provider = Item::new("Firefox", "bookmarks").with_stable_id("firefox.desktop");
+        assert_ne!(a.id, other_key.id);
+        assert_ne!(a.id, other_provider.id);
+        assert_ne!(stable_id("ab", "c"), stable_id("a", "bc"));
+
+        // Random ids remain the default.
+        assert_ne!(Item::new("x", "p").id, Item::new("x", "p").id);
+    }
+
     #[test]
     fn item_converts_to_proto() {
         let item = Item::new("Calc", "calculator")
@@ -212,6 +462,16 @@ mod tests {
         assert_eq!(proto.subtext, item.subtext);
         assert_eq!(proto.score, item.score);
         assert_eq!(proto.metadata.get("result").map(String::as_str), Some("4"));
+        assert_eq!(proto.icon_kind(), crate::proto::IconKind::ThemedName);
+    }
+
+    #[test]
+    fn icon_kind_converts_to_proto() {
+        let item = Item::new("Tool", "applications")
+            .with_icon("/opt/tool/icon.png")
+            .with_icon_kind(IconKind::AbsolutePath);
+        let proto: crate::proto::Item = item.into();
+        assert_eq!(proto.icon_kind(), crate::proto::IconKind::AbsolutePath);
     }
 
     #[test]
@@ -221,11 +481,19 @@ mod tests {
             description: "Evaluate expressions".to_string(),
             prefix: Some("=".to_string()),
             enabled: true,
+            priority: 10,
+            supports_activate: true,
+            is_network: false,
+            requires_prefix: true,
         };
         let proto: crate::proto::ProviderInfo = info.into();
         assert_eq!(proto.name, "calculator");
         assert_eq!(proto.prefix, "=");
         assert!(proto.enabled);
+        assert_eq!(proto.priority, 10);
+        assert!(proto.supports_activate);
+        assert!(!proto.is_network);
+        assert!(proto.requires_prefix);
     }
 
     #[test]
@@ -235,6 +503,10 @@ mod tests {
             description: "Apps".to_string(),
             prefix: None,
             enabled: true,
+            priority: 0,
+            supports_activate: true,
+            is_network: false,
+            requires_prefix: false,
         };
         let proto: crate::proto::ProviderInfo = info.into();
         assert_eq!(proto.prefix, "");
@@ -0,0 +1,379 @@
+//! Process provider - running processes, for ending them
+//!
+//! `kill firefox` lists processes whose name, command line or pid match,
+//! read straight from `/proc`, with their CPU and memory use. Only the user's
+//! own processes are listed unless configured otherwise.
+//!
+//! Datacube doesn't send signals itself: items carry the `pid` and the
+//! `signals` that can be sent ("TERM,KILL") in their metadata, and the
+//! frontend runs `kill -<signal> <pid>`. The process may have exited in the
+//! meantime, in which case `kill` fails with "No such process".
+//!
+//! CPU use is averaged over the process's lifetime, which needs only one read
+//! of `/proc` per query. The read happens on the blocking pool.
+
+use super::{Item, Provider};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::future::Future;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use tracing::warn;
+
+/// A running process
+#[derive(Debug, Clone, PartialEq)]
+struct Process {
+    pid: u32,
+    /// Executable name as the kernel reports it (at most 15 bytes)
+    name: String,
+    /// Full command line, arguments separated by spaces
+    command: String,
+    /// Average CPU use over the process's lifetime, in percent of one core
+    cpu_percent: f64,
+    /// Resident memory in bytes
+    memory: u64,
+}
+
+/// Provider for running processes
+///
+/// Cheap to clone, so a query can take a copy onto the blocking pool.
+#[derive(Clone)]
+pub struct ProcessProvider {
+    proc_dir: PathBuf,
+    all_users: bool,
+    prefix: String,
+    priority: i32,
+    matcher: Arc<SkimMatcherV2>,
+}
+
+impl ProcessProvider {
+    pub fn new() -> Self {
+        Self {
+            proc_dir: PathBuf::from("/proc"),
+            all_users: false,
+            prefix: "kill ".to_string(),
+            priority: 0,
+            matcher: Arc::new(SkimMatcherV2::default()),
+        }
+    }
+
+    /// Set the query prefix (default: "kill ")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// List every user's processes, not just the current user's (default:
+    /// off). Signalling them still needs the permission to do so.
+    pub fn with_all_users(mut self, all_users: bool) -> Self {
+        self.all_users = all_users;
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Every readable process the provider may list
+    ///
+    /// Processes can exit while `/proc` is being read; they are skipped.
+    fn processes(&self) -> Vec<Process> {
+        let Ok(entries) = std::fs::read_dir(&self.proc_dir) else {
+            return Vec::new();
+        };
+        let uid = unsafe { libc::getuid() };
+        let uptime = std::fs::read_to_string(self.proc_dir.join("uptime"))
+            .ok()
+            .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+            .unwrap_or(0.0);
+
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+                let dir = entry.path();
+                if !self.all_users && entry.metadata().ok()?.uid() != uid {
+                    return None;
+                }
+                read_process(&dir, pid, uptime)
+            })
+            .collect()
+    }
+
+    fn query_impl(&self, query: &str, max_results: usize) -> Vec<Item> {
+        let query = query
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(query)
+            .trim();
+        if query.is_empty() {
+            return vec![Item::new("End a process", "process")
+                .with_stable_id("hint")
+                .with_subtext("Type a process name or pid")
+                .with_icon("process-stop")
+                .with_score(1.0)];
+        }
+
+        let own_pid = std::process::id();
+        let mut scored: Vec<(Process, i64)> = self
+            .processes()
+            .into_iter()
+            .filter(|process| process.pid != own_pid)
+            .filter_map(|process| {
+                // An exact pid beats any name match.
+                if process.pid.to_string() == query {
+                    return Some((process, i64::MAX));
+                }
+                let by_name = self.matcher.fuzzy_match(&process.name, query);
+                let by_command = self
+                    .matcher
+                    .fuzzy_match(&process.command, query)
+                    .map(|score| score / 2);
+                let score = by_name.max(by_command)?;
+                Some((process, score))
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            b.1.cmp(&a.1)
+                .then_with(|| b.0.cpu_percent.total_cmp(&a.0.cpu_percent))
+                .then_with(|| a.0.pid.cmp(&b.0.pid))
+        });
+
+        scored
+            .into_iter()
+            .take(max_results)
+            .map(|(process, score)| {
+                let score = (score as f32 / 2000.0).clamp(0.0, 1.0);
+                process_item(process).with_score(score)
+            })
+            .collect()
+    }
+}
+
+impl Default for ProcessProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider for ProcessProvider {
+    fn name(&self) -> &str {
+        "process"
+    }
+
+    fn description(&self) -> &str {
+        "Running processes, to terminate or kill"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let provider = self.clone();
+        let query = query.to_string();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || provider.query_impl(&query, max_results))
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Listing processes failed: {}", e);
+                    Vec::new()
+                })
+        })
+    }
+}
+
+fn process_item(process: Process) -> Item {
+    let mut subtext = format!(
+        "PID {} - CPU {:.1}% - {}",
+        process.pid,
+        process.cpu_percent,
+        format_bytes(process.memory)
+    );
+    if !process.command.is_empty() {
+        subtext = format!("{} - {}", subtext, process.command);
+    }
+
+    // Pids are reused, so the name is part of the id.
+    Item::new(&process.name, "process")
+        .with_stable_id(format!("{}\0{}", process.pid, process.name))
+        .with_subtext(subtext)
+        .with_icon("utilities-system-monitor")
+        .with_metadata("pid", process.pid.to_string())
+        .with_metadata("command", &process.command)
+        .with_metadata("signals", "TERM,KILL")
+}
+
+/// Read one process from its `/proc/<pid>` directory
+///
+/// Kernel threads (which have no command line) are skipped.
+fn read_process(dir: &Path, pid: u32, uptime: f64) -> Option<Process> {
+    let cmdline = std::fs::read(dir.join("cmdline")).ok()?;
+    if cmdline.is_empty() {
+        return None;
+    }
+    let command = String::from_utf8_lossy(&cmdline)
+        .trim_end_matches('\0')
+        .replace('\0', " ");
+    let stat = parse_stat(&std::fs::read_to_string(dir.join("stat")).ok()?)?;
+    let resident_pages: u64 = std::fs::read_to_string(dir.join("statm"))
+        .ok()?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()?;
+
+    Some(Process {
+        pid,
+        name: stat.name,
+        command,
+        cpu_percent: cpu_percent(stat.cpu_ticks, stat.start_ticks, uptime, clock_ticks()),
+        memory: resident_pages * page_size(),
+    })
+}
+
+/// The fields used from `/proc/<pid>/stat`
+#[derive(Debug, PartialEq)]
+struct Stat {
+    name: String,
+    /// User plus system time, in clock ticks
+    cpu_ticks: u64,
+    /// Start time after boot, in clock ticks
+    start_ticks: u64,
+}
+
+/// Parse `/proc/<pid>/stat`
+///
+/// The name is in parentheses and may itself contain spaces and
+/// parentheses, so the fixed fields are counted from the last `)`.
+fn parse_stat(stat: &str) -> Option<Stat> {
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let name = stat.get(open + 1..close)?.to_string();
+    // Fields after the name, starting at field 3 (state)
+    let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+    let field = |n: usize| -> Option<u64> { fields.get(n - 3)?.parse().ok() };
+    Some(Stat {
+        name,
+        cpu_ticks: field(14)? + field(15)?,
+        start_ticks: field(22)?,
+    })
+}
+
+/// Lifetime-average CPU use in percent of one core
+fn cpu_percent(cpu_ticks: u64, start_ticks: u64, uptime: f64, ticks_per_second: f64) -> f64 {
+    let running = uptime - start_ticks as f64 / ticks_per_second;
+    if running <= 0.0 {
+        return 0.0;
+    }
+    100.0 * cpu_ticks as f64 / ticks_per_second / running
+}
+
+fn clock_ticks() -> f64 {
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as f64,
+        _ => 100.0,
+    }
+}
+
+fn page_size() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stat_with_awkward_names() {
+        let stat = "1234 (Web Content (x)) S 1 1234 1234 0 -1 4194560 100 0 0 0 \
+                    250 50 0 0 20 0 30 0 5000 1000000 2000 18446744073709551615";
+        assert_eq!(
+            parse_stat(stat),
+            Some(Stat {
+                name: "Web Content (x)".to_string(),
+                cpu_ticks: 300,
+                start_ticks: 5000,
+            })
+        );
+        assert_eq!(parse_stat("1234 (truncated"), None);
+    }
+
+    #[test]
+    fn cpu_is_averaged_over_the_lifetime() {
+        // Started 10s after boot, now 20s: 5s of CPU over 10s is 50%.
+        assert_eq!(cpu_percent(500, 1000, 20.0, 100.0), 50.0);
+        assert_eq!(cpu_percent(500, 2000, 20.0, 100.0), 0.0);
+    }
+
+    #[test]
+    fn formats_memory() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 / 2), "1.5 MiB");
+    }
+
+    #[tokio::test]
+    async fn lists_own_processes_by_pid() {
+        // The test harness's own process is excluded, so spawn another.
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .expect("spawn sleep");
+        let pid = child.id().to_string();
+
+        let items = ProcessProvider::new()
+            .query(&format!("kill {}", pid), 10)
+            .await;
+        let _ = child.kill();
+        let _ = child.wait();
+
+        assert_eq!(items[0].text, "sleep");
+        assert_eq!(items[0].metadata["pid"], pid);
+        assert_eq!(items[0].metadata["command"], "sleep 30");
+        assert_eq!(items[0].metadata["signals"], "TERM,KILL");
+    }
+
+    #[test]
+    fn empty_query_shows_a_hint() {
+        let items = ProcessProvider::new().query_impl("kill ", 10);
+        assert_eq!(items[0].text, "End a process");
+    }
+
+    #[test]
+    fn missing_proc_lists_nothing() {
+        let provider = ProcessProvider {
+            proc_dir: PathBuf::from("/nonexistent/proc"),
+            ..ProcessProvider::new()
+        };
+        assert!(provider.query_impl("kill sleep", 10).is_empty());
+    }
+}
@@ -0,0 +1,836 @@
+//! Recent files provider - documents from the freedesktop recent files store
+//!
+//! Reads `recently-used.xbel` (written by GTK and KDE applications) and
+//! fuzzy-matches file names. The file is re-read whenever its modification
+//! time changes, and entries whose file no longer exists are skipped. Reading
+//! and the existence checks run on the blocking pool.
+//!
+//! Items list the ways to open the file in their `actions` metadata: "open"
+//! with the default application for its MIME type (from `mimeapps.list`,
+//! named in `default_app`), "open-with" to pick another, and for directories
+//! "open-terminal".
+
+use super::time::days_from_civil;
+use super::{ApplicationsProvider, Item, Provider};
+use freedesktop_desktop_entry::DesktopEntry;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::future::Future;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+/// A recently used file
+#[derive(Debug, Clone, PartialEq)]
+struct RecentFile {
+    uri: String,
+    path: PathBuf,
+    /// File name shown and matched against
+    name: String,
+    mime_type: Option<String>,
+    /// Latest of the added/modified/visited stamps (seconds since the epoch)
+    used_at: i64,
+}
+
+/// Parsed entries and default applications, plus the mtimes they were read at
+#[derive(Default)]
+struct Cache {
+    mtime: Option<SystemTime>,
+    files: Arc<Vec<RecentFile>>,
+    /// The `mimeapps.list` mtimes; `None` until they are first read
+    lists_mtimes: Option<Vec<Option<SystemTime>>>,
+    default_apps: Arc<DefaultApps>,
+}
+
+/// Provider for recently used files
+pub struct RecentFilesProvider {
+    path: PathBuf,
+    /// The `mimeapps.list` files default applications are read from
+    lists: Vec<PathBuf>,
+    prefix: Option<String>,
+    priority: i32,
+    cache: Mutex<Cache>,
+    matcher: SkimMatcherV2,
+}
+
+impl RecentFilesProvider {
+    /// Create a provider reading the recent files store at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lists: mimeapps_paths(),
+            prefix: None,
+            priority: 0,
+            cache: Mutex::new(Cache::default()),
+            matcher: SkimMatcherV2::default(),
+        }
+    }
+
+    /// The default store location, `$XDG_DATA_HOME/recently-used.xbel`
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .or_else(|| dirs::home_dir().map(|home| home.join(".local/share")))
+            .unwrap_or_default()
+            .join("recently-used.xbel")
+    }
+
+    /// Only answer queries starting with `prefix`; an empty query after the
+    /// prefix then lists the most recent files
+    pub fn with_prefix(mut self, prefix: Option<String>) -> Self {
+        self.prefix = prefix.filter(|p| !p.is_empty());
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Current entries, newest first, and the default applications,
+    /// re-reading whichever changed on disk
+    ///
+    /// Reading happens on the blocking pool; the lock is only held to compare
+    /// mtimes and to swap in what was read.
+    async fn snapshot(&self) -> (Arc<Vec<RecentFile>>, Arc<DefaultApps>) {
+        let mtime = modified(&self.path);
+        let lists_mtimes: Vec<_> = self.lists.iter().map(|list| modified(list)).collect();
+        let (files_stale, lists_stale) = {
+            let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            (
+                cache.mtime != mtime,
+                cache.lists_mtimes.as_ref() != Some(&lists_mtimes),
+            )
+        };
+
+        if files_stale {
+            let path = self.path.clone();
+            let files = tokio::task::spawn_blocking(move || load_files(&path))
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Reading {:?} failed: {}", self.path, e);
+                    Vec::new()
+                });
+            // The cache is replaced wholesale, never left half-updated.
+            let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            cache.files = Arc::new(files);
+            cache.mtime = mtime;
+        }
+        if lists_stale {
+            let lists = self.lists.clone();
+            let default_apps = tokio::task::spawn_blocking(move || DefaultApps::load(&lists))
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Reading default applications failed: {}", e);
+                    DefaultApps::default()
+                });
+            let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            cache.default_apps = Arc::new(default_apps);
+            cache.lists_mtimes = Some(lists_mtimes);
+        }
+
+        let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        (Arc::clone(&cache.files), Arc::clone(&cache.default_apps))
+    }
+
+    /// The query after the prefix, or `None` if it isn't for this provider
+    fn strip_query<'q>(&self, query: &'q str) -> Option<&'q str> {
+        let query = match &self.prefix {
+            Some(prefix) => query.strip_prefix(prefix.as_str())?.trim(),
+            None => query.trim(),
+        };
+        // Without a prefix an empty query belongs to the applications list.
+        if query.is_empty() && self.prefix.is_none() {
+            return None;
+        }
+        Some(query)
+    }
+
+    /// Files matching `query` with their fuzzy scores, best first; an empty
+    /// query matches every file
+    fn matches(&self, files: &[RecentFile], query: &str) -> Vec<(RecentFile, i64)> {
+        let mut scored: Vec<(RecentFile, i64)> = files
+            .iter()
+            .filter_map(|file| {
+                if query.is_empty() {
+                    return Some((file.clone(), 0));
+                }
+                let score = self.matcher.fuzzy_match(&file.name, query)?;
+                Some((file.clone(), score))
+            })
+            .collect();
+        // Stable sort: equal scores stay newest first.
+        scored.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        scored
+    }
+
+    async fn query_impl(&self, query: &str, max_results: usize) -> Vec<Item> {
+        let Some(query) = self.strip_query(query) else {
+            return Vec::new();
+        };
+        let (files, default_apps) = self.snapshot().await;
+        let matches = self.matches(&files, query);
+        let listing = query.is_empty();
+        tokio::task::spawn_blocking(move || items(matches, listing, &default_apps, max_results))
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Listing recent files failed: {}", e);
+                Vec::new()
+            })
+    }
+}
+
+/// The file's modification time, if it exists
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The entries of the store at `path`, newest first
+fn load_files(path: &Path) -> Vec<RecentFile> {
+    match std::fs::read_to_string(path) {
+        Ok(xml) => {
+            let mut files = parse_xbel(&xml);
+            files.sort_by_key(|f| std::cmp::Reverse(f.used_at));
+            debug!("Loaded {} recent files from {:?}", files.len(), path);
+            files
+        }
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to read {:?}: {}", path, e);
+            }
+            Vec::new()
+        }
+    }
+}
+
+/// Items for the first `max_results` matches whose file still exists,
+/// ranked by fuzzy score or, when `listing`, by recency
+///
+/// Touches the filesystem, so it runs on the blocking pool.
+fn items(
+    matches: Vec<(RecentFile, i64)>,
+    listing: bool,
+    default_apps: &DefaultApps,
+    max_results: usize,
+) -> Vec<Item> {
+    matches
+        .into_iter()
+        .filter(|(file, _)| file.path.exists())
+        .take(max_results)
+        .enumerate()
+        .map(|(rank, (file, score))| {
+            let score = if listing {
+                1.0 - rank as f32 * 0.01
+            } else {
+                (score as f32 / 2000.0).clamp(0.0, 1.0)
+            };
+            file_item(file, default_apps).with_score(score)
+        })
+        .collect()
+}
+
+impl Provider for RecentFilesProvider {
+    fn name(&self) -> &str {
+        "recent"
+    }
+
+    fn description(&self) -> &str {
+        "Recently used files"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let query = query.to_string();
+        Box::pin(async move { self.query_impl(&query, max_results).await })
+    }
+}
+
+fn file_item(file: RecentFile, default_apps: &DefaultApps) -> Item {
+    let dir = file
+        .path
+        .parent()
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // Themed mime icons use '-' where the type has '/': "application-pdf".
+    let icon = file
+        .mime_type
+        .as_deref()
+        .map(|m| m.replace('/', "-"))
+        .unwrap_or_else(|| "text-x-generic".to_string());
+
+    let mut actions = vec!["open", "open-with"];
+    if file.path.is_dir() {
+        actions.push("open-terminal");
+    }
+
+    let mut item = Item::new(&file.name, "recent")
+        .with_stable_id(&file.uri)
+        .with_subtext(dir)
+        .with_icon(icon)
+        .with_metadata("uri", &file.uri)
+        .with_metadata("path", file.path.to_string_lossy())
+        .with_metadata("used_at", file.used_at.to_string())
+        .with_metadata("actions", actions.join(","));
+    if let Some(mime_type) = file.mime_type {
+        if let Some(app) = default_apps.lookup(&mime_type) {
+            item = item
+                .with_metadata("default_app", &app.id)
+                .with_metadata("default_app_name", &app.name);
+        }
+        item = item.with_metadata("mime_type", mime_type);
+    }
+    item
+}
+
+/// An application set as the default for a MIME type
+#[derive(Debug, Clone, PartialEq)]
+struct DefaultApp {
+    /// Desktop file id, e.g. "org.gnome.Evince.desktop"
+    id: String,
+    /// The entry's Name, for "Open with <name>"
+    name: String,
+}
+
+/// Default applications per MIME type, read from the `mimeapps.list` files
+///
+/// Lookups are resolved lazily and remembered, so only the desktop entries
+/// of the types returned are ever parsed.
+#[derive(Default)]
+struct DefaultApps {
+    /// Desktop ids per MIME type, in order of preference
+    defaults: HashMap<String, Vec<String>>,
+    /// Directories searched for desktop files, highest priority first
+    app_dirs: Vec<PathBuf>,
+    resolved: Mutex<HashMap<String, Option<DefaultApp>>>,
+}
+
+impl DefaultApps {
+    /// Read the given `mimeapps.list` files, looking entries up in the
+    /// application directories
+    fn load(lists: &[PathBuf]) -> Self {
+        let app_dirs = ApplicationsProvider::get_directories_in_precedence_order(&[]);
+        Self::from_lists(lists, app_dirs)
+    }
+
+    /// Read the given lists; earlier ones win for types set in several
+    fn from_lists(lists: &[PathBuf], app_dirs: Vec<PathBuf>) -> Self {
+        let mut defaults = HashMap::new();
+        for list in lists {
+            let Ok(contents) = std::fs::read_to_string(list) else {
+                continue;
+            };
+            for (mime_type, ids) in parse_default_applications(&contents) {
+                defaults.entry(mime_type).or_insert(ids);
+            }
+        }
+        Self {
+            defaults,
+            app_dirs,
+            resolved: Mutex::default(),
+        }
+    }
+
+    /// The first installed default application for `mime_type`
+    fn lookup(&self, mime_type: &str) -> Option<DefaultApp> {
+        // Only ever holds finished lookups, so a poisoned lock is still safe.
+        let mut resolved = self.resolved.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(app) = resolved.get(mime_type) {
+            return app.clone();
+        }
+        let app = self.defaults.get(mime_type).and_then(|ids| {
+            ids.iter().find_map(|id| {
+                let path = self
+                    .app_dirs
+                    .iter()
+                    .map(|dir| dir.join(id))
+                    .find(|path| path.is_file())?;
+                let entry = DesktopEntry::from_path::<&str>(&path, None).ok()?;
+                let locales: &[&str] = &[];
+                Some(DefaultApp {
+                    id: id.clone(),
+                    name: entry.name(locales)?.to_string(),
+                })
+            })
+        });
+        resolved.insert(mime_type.to_string(), app.clone());
+        app
+    }
+}
+
+/// `mimeapps.list` locations in the order the XDG spec gives them
+fn mimeapps_paths() -> Vec<PathBuf> {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home.join(".config"));
+    let config_dirs = std::env::var("XDG_CONFIG_DIRS").unwrap_or_else(|_| "/etc/xdg".to_string());
+    let data_home = std::env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| home.join(".local/share"));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_string());
+
+    let mut paths = vec![config_home.join("mimeapps.list")];
+    paths.extend(
+        config_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join("mimeapps.list")),
+    );
+    paths.push(data_home.join("applications/mimeapps.list"));
+    paths.extend(
+        data_dirs
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| Path::new(dir).join("applications/mimeapps.list")),
+    );
+    paths
+}
+
+/// The `[Default Applications]` group of a `mimeapps.list`
+fn parse_default_applications(contents: &str) -> Vec<(String, Vec<String>)> {
+    let mut in_group = false;
+    let mut defaults = Vec::new();
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Default Applications]";
+            continue;
+        }
+        if !in_group || line.starts_with('#') {
+            continue;
+        }
+        let Some((mime_type, ids)) = line.split_once('=') else {
+            continue;
+        };
+        let ids: Vec<String> = ids
+            .split(';')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(String::from)
+            .collect();
+        if !ids.is_empty() {
+            defaults.push((mime_type.trim().to_string(), ids));
+        }
+    }
+    defaults
+}
+
+/// Extract `file://` bookmarks from an XBEL document
+fn parse_xbel(xml: &str) -> Vec<RecentFile> {
+    let mut files = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find("<bookmark ") {
+        rest = &rest[start..];
+        let tag_end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..tag_end];
+        // A bookmark's children end at its closing tag (or it is self-closing).
+        let body_end = if tag.ends_with('/') {
+            tag_end
+        } else {
+            rest.find("</bookmark>").unwrap_or(rest.len())
+        };
+        let body = &rest[tag_end..body_end];
+        rest = &rest[body_end..];
+
+        let Some(uri) = attribute(tag, "href") else {
+            continue;
+        };
+        let Some(path) = file_uri_path(&uri) else {
+            continue;
+        };
+        let Some(name) = path.file_name().map(|n| n.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let used_at = ["added", "modified", "visited"]
+            .iter()
+            .filter_map(|name| attribute(tag, name).and_then(|v| parse_timestamp(&v)))
+            .max()
+            .unwrap_or(0);
+        let mime_type = body.find("<mime:mime-type ").and_then(|i| {
+            let tag = &body[i..];
+            attribute(&tag[..tag.find('>').unwrap_or(tag.len())], "type")
+        });
+
+        files.push(RecentFile {
+            uri,
+            path,
+            name,
+            mime_type,
+            used_at,
+        });
+    }
+    files
+}
+
+/// The unescaped value of attribute `name` in a start tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let needle = format!(" {}=", name);
+    let start = tag.find(&needle)? + needle.len();
+    let quote = tag[start..].chars().next()?;
+    if quote != '"' && quote != '\'' {
+        return None;
+    }
+    let value = &tag[start + 1..];
+    let end = value.find(quote)?;
+    Some(unescape(&value[..end]))
+}
+
+/// Replace XML entity and character references
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';') else {
+            break;
+        };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The local path of a `file://` URI, percent-decoded
+fn file_uri_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // Skip an optional host ("file://localhost/...").
+    percent_decode_path(&rest[rest.find('/')?..])
+}
+
+/// A path with `%XX` escapes decoded, as in URIs and `.trashinfo` files
+pub(super) fn percent_decode_path(path: &str) -> Option<PathBuf> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = path.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(OsString::from_vec(out)))
+}
+
+/// Parse an RFC 3339 timestamp ("2024-05-01T10:20:30.123456Z") into seconds
+/// since the epoch
+fn parse_timestamp(stamp: &str) -> Option<i64> {
+    let (date, time) = stamp.split_once('T')?;
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: u32 = date_parts.next()?.parse().ok()?;
+    let day: u32 = date_parts.next()?.parse().ok()?;
+
+    let (clock, offset) = match time.find(['Z', '+', '-']) {
+        Some(i) => (&time[..i], &time[i..]),
+        None => (time, "Z"),
+    };
+    let mut clock_parts = clock.split(':');
+    let hour: i64 = clock_parts.next()?.parse().ok()?;
+    let minute: i64 = clock_parts.next()?.parse().ok()?;
+    let second: f64 = clock_parts.next().unwrap_or("0").parse().ok()?;
+
+    let offset_secs = match offset {
+        "Z" => 0,
+        _ => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let (h, m) = offset[1..].split_once(':').unwrap_or((&offset[1..], "0"));
+            sign * (h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60)
+        }
+    };
+
+    Some(
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second as i64
+            - offset_secs,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing::TempDir;
+
+    fn bookmark(path: &Path, modified: &str, mime: &str) -> String {
+        let uri = format!("file://{}", path.display()).replace(' ', "%20");
+        format!(
+            "  <bookmark href=\"{uri}\" added=\"2024-01-01T00:00:00Z\" \
+             modified=\"{modified}\" visited=\"2024-01-01T00:00:00Z\">\n\
+             <info><metadata owner=\"http://freedesktop.org\">\
+             <mime:mime-type type=\"{mime}\"/></metadata></info>\n  </bookmark>\n"
+        )
+    }
+
+    /// A store listing `files` (name, modified stamp), creating each file
+    fn write_store(dir: &TempDir, files: &[(&str, &str)]) -> PathBuf {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<xbel version=\"1.0\" \
+             xmlns:mime=\"http://www.freedesktop.org/standards/shared-mime-info\">\n",
+        );
+        for (name, modified) in files {
+            let path = dir.path.join(name);
+            std::fs::write(&path, "").unwrap();
+            xml.push_str(&bookmark(&path, modified, "application/pdf"));
+        }
+        xml.push_str("</xbel>\n");
+        let store = dir.path.join("recently-used.xbel");
+        std::fs::write(&store, xml).unwrap();
+        store
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_timestamp("2024-05-01T10:20:30.123456Z"),
+            Some(1_714_558_830)
+        );
+        assert_eq!(
+            parse_timestamp("2024-05-01T12:20:30+02:00"),
+            Some(1_714_558_830)
+        );
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn decodes_uris_and_entities() {
+        assert_eq!(
+            file_uri_path("file:///home/me/My%20Doc.pdf"),
+            Some(PathBuf::from("/home/me/My Doc.pdf"))
+        );
+        assert_eq!(
+            file_uri_path("file://localhost/tmp/a"),
+            Some(PathBuf::from("/tmp/a"))
+        );
+        assert_eq!(file_uri_path("https://example.com/a"), None);
+        assert_eq!(
+            unescape("a &amp; b &#233; &#x41; &bogus;"),
+            "a & b é A &bogus;"
+        );
+    }
+
+    #[test]
+    fn parses_bookmarks() {
+        let xml = format!(
+            "<xbel>\n{}<bookmark href=\"https://example.com\" added=\"2024-01-01T00:00:00Z\"/>\n</xbel>",
+            bookmark(Path::new("/tmp/R&D notes.txt"), "2024-03-01T00:00:00Z", "text/plain")
+                .replace("R&D", "R&amp;D")
+        );
+        let files = parse_xbel(&xml);
+        assert_eq!(files.len(), 1, "non-file URIs are skipped");
+        assert_eq!(files[0].name, "R&D notes.txt");
+        assert_eq!(files[0].mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(
+            files[0].used_at,
+            parse_timestamp("2024-03-01T00:00:00Z").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn query_matches_names_and_skips_missing_files() {
+        let dir = TempDir::new();
+        let store = write_store(
+            &dir,
+            &[
+                ("report.pdf", "2024-02-01T00:00:00Z"),
+                ("old report.pdf", "2024-01-15T00:00:00Z"),
+                ("gone.pdf", "2024-03-01T00:00:00Z"),
+            ],
+        );
+        std::fs::remove_file(dir.path.join("gone.pdf")).unwrap();
+        let provider = RecentFilesProvider::new(store);
+
+        let items = provider.query("report", 10).await;
+        let names: Vec<_> = items.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(names.len(), 2);
+        assert!(names.contains(&"report.pdf"));
+        assert_eq!(items[0].icon, "application-pdf");
+        assert!(provider.query("gone", 10).await.is_empty());
+        assert!(provider.query("", 10).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn prefix_lists_newest_first() {
+        let dir = TempDir::new();
+        let store = write_store(
+            &dir,
+            &[
+                ("a.pdf", "2024-01-01T00:00:00Z"),
+                ("b.pdf", "2024-03-01T00:00:00Z"),
+                ("c.pdf", "2024-02-01T00:00:00Z"),
+            ],
+        );
+        let provider = RecentFilesProvider::new(store).with_prefix(Some("recent ".to_string()));
+
+        let items = provider.query("recent ", 10).await;
+        let names: Vec<_> = items.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(names, vec!["b.pdf", "c.pdf", "a.pdf"]);
+        assert!(provider.query("a.pdf", 10).await.is_empty());
+    }
+
+    #[test]
+    fn parses_default_applications() {
+        let list = "[Added Associations]\ntext/plain=other.desktop;\n\
+                    [Default Applications]\n# comment\n\
+                    application/pdf=org.gnome.Evince.desktop;okular.desktop;\n\
+                    text/plain=\n";
+        assert_eq!(
+            parse_default_applications(list),
+            vec![(
+                "application/pdf".to_string(),
+                vec![
+                    "org.gnome.Evince.desktop".to_string(),
+                    "okular.desktop".to_string()
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn default_app_is_the_first_installed_one() {
+        let dir = TempDir::new();
+        let apps = dir.path.join("applications");
+        std::fs::create_dir_all(&apps).unwrap();
+        std::fs::write(
+            apps.join("okular.desktop"),
+            "[Desktop Entry]\nType=Application\nName=Okular\nExec=okular %U\n",
+        )
+        .unwrap();
+        let user = dir.path.join("user.list");
+        let system = dir.path.join("system.list");
+        std::fs::write(
+            &user,
+            "[Default Applications]\napplication/pdf=missing.desktop;okular.desktop\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &system,
+            "[Default Applications]\napplication/pdf=evince.desktop\n\
+             text/plain=okular.desktop\n",
+        )
+        .unwrap();
+
+        let defaults = DefaultApps::from_lists(&[user, system], vec![apps]);
+        assert_eq!(
+            defaults.lookup("application/pdf"),
+            Some(DefaultApp {
+                id: "okular.desktop".to_string(),
+                name: "Okular".to_string(),
+            })
+        );
+        assert_eq!(defaults.lookup("text/plain").unwrap().name, "Okular");
+        assert_eq!(defaults.lookup("image/png"), None);
+    }
+
+    #[test]
+    fn directories_can_open_in_a_terminal() {
+        let dir = TempDir::new();
+        let file = dir.path.join("notes.txt");
+        std::fs::write(&file, "").unwrap();
+        let defaults = DefaultApps::from_lists(&[], Vec::new());
+        let recent = |path: &Path| RecentFile {
+            uri: format!("file://{}", path.display()),
+            path: path.to_path_buf(),
+            name: "x".to_string(),
+            mime_type: None,
+            used_at: 0,
+        };
+
+        let item = file_item(recent(&file), &defaults);
+        assert_eq!(item.metadata["actions"], "open,open-with");
+        assert!(!item.metadata.contains_key("default_app"));
+        let item = file_item(recent(&dir.path), &defaults);
+        assert_eq!(item.metadata["actions"], "open,open-with,open-terminal");
+    }
+
+    #[tokio::test]
+    async fn reloads_when_the_store_changes() {
+        let dir = TempDir::new();
+        let store = write_store(&dir, &[("first.txt", "2024-01-01T00:00:00Z")]);
+        let provider = RecentFilesProvider::new(&store);
+        assert_eq!(provider.query("first", 10).await.len(), 1);
+
+        write_store(&dir, &[("second.txt", "2024-01-02T00:00:00Z")]);
+        // Make sure the mtime moves even on coarse-grained filesystems.
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&store)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(provider.query("second", 10).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn default_apps_reload_when_a_list_changes() {
+        let dir = TempDir::new();
+        let store = write_store(&dir, &[("doc.pdf", "2024-01-01T00:00:00Z")]);
+        let list = dir.path.join("mimeapps.list");
+        std::fs::write(&list, "[Default Applications]\n").unwrap();
+        let mut provider = RecentFilesProvider::new(store);
+        provider.lists = vec![list.clone()];
+
+        let (_, first) = provider.snapshot().await;
+        let (_, unchanged) = provider.snapshot().await;
+        assert!(Arc::ptr_eq(&first, &unchanged));
+
+        std::fs::write(
+            &list,
+            "[Default Applications]\napplication/pdf=okular.desktop\n",
+        )
+        .unwrap();
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&list)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        let (_, reloaded) = provider.snapshot().await;
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+        assert!(reloaded.defaults.contains_key("application/pdf"));
+    }
+}
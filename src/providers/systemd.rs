@@ -0,0 +1,485 @@
+//! Systemd provider - systemd units and their state
+//!
+//! `sys nginx` lists the units whose name or description match, with their
+//! active and enabled state. Unit lists come from `systemctl list-units` and
+//! `systemctl list-unit-files`, cached for a few seconds since launchers
+//! query on every keystroke.
+//!
+//! Datacube doesn't run `systemctl` actions itself. Each item lists the
+//! actions that apply to the unit in its `actions` metadata (e.g.
+//! "stop,restart,disable"); the frontend runs `systemctl [--user] <action>
+//! <unit>`, with `--user` when the `scope` metadata is "user". Actions on
+//! system units need privilege (polkit or root), which `privileged` marks.
+
+use super::{Item, Provider};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// How long unit lists are reused before asking systemctl again
+const CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Which systemd instance a unit belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemdScope {
+    /// The per-user service manager (`systemctl --user`)
+    User,
+    /// The system service manager
+    System,
+}
+
+impl SystemdScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            SystemdScope::User => "user",
+            SystemdScope::System => "system",
+        }
+    }
+
+    fn systemctl(self) -> std::process::Command {
+        let mut command = std::process::Command::new("systemctl");
+        if self == SystemdScope::User {
+            command.arg("--user");
+        }
+        command.args(["--plain", "--no-legend", "--no-pager", "--full"]);
+        command
+    }
+}
+
+/// A unit and its state
+#[derive(Debug, Clone, PartialEq)]
+struct Unit {
+    name: String,
+    scope: SystemdScope,
+    description: String,
+    /// e.g. "active", "inactive", "failed"
+    active: String,
+    /// e.g. "running", "exited", "dead"
+    sub: String,
+    /// e.g. "enabled", "disabled", "static", "masked"; empty if unknown
+    file_state: String,
+}
+
+impl Unit {
+    /// The systemctl actions that make sense in the unit's current state
+    fn actions(&self) -> Vec<&'static str> {
+        let mut actions = Vec::new();
+        match self.active.as_str() {
+            "active" | "activating" | "reloading" => actions.extend(["stop", "restart"]),
+            _ if self.file_state != "masked" => actions.push("start"),
+            _ => {}
+        }
+        match self.file_state.as_str() {
+            "enabled" => actions.push("disable"),
+            "disabled" => actions.push("enable"),
+            _ => {}
+        }
+        actions
+    }
+}
+
+/// Provider for systemd units
+pub struct SystemdProvider {
+    scopes: Vec<SystemdScope>,
+    prefix: String,
+    priority: i32,
+    /// Units from the last listing and when it was taken
+    cache: Mutex<Option<(Instant, Vec<Unit>)>>,
+    matcher: SkimMatcherV2,
+}
+
+impl SystemdProvider {
+    pub fn new(scopes: Vec<SystemdScope>) -> Self {
+        Self {
+            scopes,
+            prefix: "sys ".to_string(),
+            priority: 0,
+            cache: Mutex::new(None),
+            matcher: SkimMatcherV2::default(),
+        }
+    }
+
+    /// Set the query prefix (default: "sys ")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// All units, listed again once the cached listing is `CACHE_TTL` old
+    ///
+    /// systemctl runs on the blocking pool, off the async workers.
+    async fn units(&self) -> Vec<Unit> {
+        // The cache is replaced wholesale, never left half-updated.
+        if let Some((listed, units)) = self
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+        {
+            if listed.elapsed() < CACHE_TTL {
+                return units.clone();
+            }
+        }
+
+        let scopes = self.scopes.clone();
+        let units = match tokio::task::spawn_blocking(move || list_all_units(&scopes)).await {
+            Ok(units) => units,
+            Err(e) => {
+                warn!("Listing units failed: {}", e);
+                Vec::new()
+            }
+        };
+        *self.cache.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some((Instant::now(), units.clone()));
+        units
+    }
+
+    fn query_impl(&self, units: Vec<Unit>, query: &str, max_results: usize) -> Vec<Item> {
+        let query = query
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(query)
+            .trim();
+
+        let mut scored: Vec<(Unit, i64)> = units
+            .into_iter()
+            .filter_map(|unit| {
+                if query.is_empty() {
+                    // List what is running (or broken) first.
+                    let score = match unit.active.as_str() {
+                        "failed" => 2,
+                        "active" => 1,
+                        _ => 0,
+                    };
+                    return Some((unit, score));
+                }
+                let by_name = self.matcher.fuzzy_match(&unit.name, query);
+                let by_description = self
+                    .matcher
+                    .fuzzy_match(&unit.description, query)
+                    .map(|score| score / 2);
+                let score = by_name.max(by_description)?;
+                Some((unit, score))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.name.cmp(&b.0.name)));
+
+        scored
+            .into_iter()
+            .take(max_results)
+            .map(|(unit, score)| {
+                let score = if query.is_empty() {
+                    0.5
+                } else {
+                    (score as f32 / 2000.0).clamp(0.0, 1.0)
+                };
+                unit_item(unit).with_score(score)
+            })
+            .collect()
+    }
+}
+
+impl Provider for SystemdProvider {
+    fn name(&self) -> &str {
+        "systemd"
+    }
+
+    fn description(&self) -> &str {
+        "Systemd units and their state"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let query = query.to_string();
+        Box::pin(async move {
+            let units = self.units().await;
+            self.query_impl(units, &query, max_results)
+        })
+    }
+}
+
+fn unit_item(unit: Unit) -> Item {
+    let mut state = format!("{} ({})", unit.active, unit.sub);
+    if !unit.file_state.is_empty() {
+        state = format!("{}, {}", state, unit.file_state);
+    }
+    let subtext = if unit.description.is_empty() {
+        state
+    } else {
+        format!("{} - {}", state, unit.description)
+    };
+    let icon = if unit.active == "failed" {
+        "dialog-error"
+    } else {
+        "preferences-system"
+    };
+
+    Item::new(&unit.name, "systemd")
+        .with_stable_id(format!("{}\0{}", unit.scope.as_str(), unit.name))
+        .with_subtext(subtext)
+        .with_icon(icon)
+        .with_metadata("unit", &unit.name)
+        .with_metadata("scope", unit.scope.as_str())
+        .with_metadata("active_state", &unit.active)
+        .with_metadata("sub_state", &unit.sub)
+        .with_metadata("unit_file_state", &unit.file_state)
+        .with_metadata("actions", unit.actions().join(","))
+        .with_metadata(
+            "privileged",
+            (unit.scope == SystemdScope::System).to_string(),
+        )
+}
+
+/// List the units of every scope, skipping scopes that fail
+fn list_all_units(scopes: &[SystemdScope]) -> Vec<Unit> {
+    let mut units = Vec::new();
+    for &scope in scopes {
+        match list_units(scope) {
+            Ok(listed) => {
+                debug!("Listed {} {} units", listed.len(), scope.as_str());
+                units.extend(listed);
+            }
+            Err(e) => warn!("Failed to list {} units: {}", scope.as_str(), e),
+        }
+    }
+    units
+}
+
+/// List a scope's units, merging runtime state with unit file state
+fn list_units(scope: SystemdScope) -> anyhow::Result<Vec<Unit>> {
+    let loaded = run(scope.systemctl().args(["list-units", "--all"]))?;
+    let files = run(scope.systemctl().arg("list-unit-files"))?;
+    Ok(merge_units(
+        scope,
+        parse_list_units(&loaded),
+        parse_list_unit_files(&files),
+    ))
+}
+
+fn run(command: &mut std::process::Command) -> anyhow::Result<String> {
+    let output = command.output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "systemctl failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Runtime state from `list-units`: (unit, active, sub, description)
+///
+/// Units that failed to load (no unit file) are left out.
+fn parse_list_units(output: &str) -> Vec<(String, String, String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            // Some versions mark failed units with a bullet even in --plain.
+            let line = line.trim_start_matches(['●', '*', ' ']);
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            let load = fields.next()?;
+            let active = fields.next()?;
+            let sub = fields.next()?;
+            if load != "loaded" {
+                return None;
+            }
+            let description = fields.collect::<Vec<_>>().join(" ");
+            Some((
+                name.to_string(),
+                active.to_string(),
+                sub.to_string(),
+                description,
+            ))
+        })
+        .collect()
+}
+
+/// Unit file state from `list-unit-files`: unit -> state
+fn parse_list_unit_files(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?.to_string(), fields.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Combine both listings. Units that aren't loaded only appear in the unit
+/// file listing and are inactive; templates (`foo@.service`) can't be acted
+/// on without an instance name, so they are skipped.
+fn merge_units(
+    scope: SystemdScope,
+    loaded: Vec<(String, String, String, String)>,
+    mut files: BTreeMap<String, String>,
+) -> Vec<Unit> {
+    let mut units: Vec<Unit> = loaded
+        .into_iter()
+        .map(|(name, active, sub, description)| Unit {
+            file_state: files.remove(&name).unwrap_or_default(),
+            name,
+            scope,
+            description,
+            active,
+            sub,
+        })
+        .collect();
+    units.extend(
+        files
+            .into_iter()
+            .filter(|(name, _)| !name.contains("@."))
+            .map(|(name, file_state)| Unit {
+                name,
+                scope,
+                description: String::new(),
+                active: "inactive".to_string(),
+                sub: "dead".to_string(),
+                file_state,
+            }),
+    );
+    units
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing::query;
+
+    const LIST_UNITS: &str = "\
+nginx.service      loaded    active   running Nginx web server
+backup.service     loaded    failed   failed  Nightly backup
+ghost.service      not-found inactive dead    ghost.service
+● broken.mount     loaded    failed   failed  /mnt/broken
+";
+
+    const LIST_UNIT_FILES: &str = "\
+nginx.service        enabled  disabled
+backup.service       static   -
+cups.service         disabled enabled
+getty@.service       enabled  enabled
+masked.service       masked   enabled
+";
+
+    fn provider_with(units: Vec<Unit>) -> SystemdProvider {
+        let provider = SystemdProvider::new(vec![]);
+        *provider.cache.lock().unwrap() = Some((Instant::now(), units));
+        provider
+    }
+
+    fn sample_units() -> Vec<Unit> {
+        merge_units(
+            SystemdScope::System,
+            parse_list_units(LIST_UNITS),
+            parse_list_unit_files(LIST_UNIT_FILES),
+        )
+    }
+
+    #[test]
+    fn merges_runtime_and_unit_file_state() {
+        let units = sample_units();
+        let names: Vec<_> = units.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "nginx.service",
+                "backup.service",
+                "broken.mount",
+                "cups.service",
+                "masked.service"
+            ]
+        );
+
+        assert_eq!(units[0].active, "active");
+        assert_eq!(units[0].sub, "running");
+        assert_eq!(units[0].file_state, "enabled");
+        assert_eq!(units[0].description, "Nginx web server");
+        assert_eq!(units[2].description, "/mnt/broken");
+        assert_eq!(units[3].active, "inactive");
+        assert_eq!(units[3].file_state, "disabled");
+    }
+
+    #[test]
+    fn actions_follow_state() {
+        let units = sample_units();
+        let actions = |name: &str| units.iter().find(|u| u.name == name).unwrap().actions();
+        assert_eq!(actions("nginx.service"), ["stop", "restart", "disable"]);
+        assert_eq!(actions("backup.service"), ["start"]);
+        assert_eq!(actions("cups.service"), ["start", "enable"]);
+        assert!(actions("masked.service").is_empty());
+    }
+
+    #[test]
+    fn query_matches_names_and_marks_privilege() {
+        let provider = provider_with(sample_units());
+        let items = query(&provider, "sys nginx", 10);
+        assert_eq!(items[0].text, "nginx.service");
+        assert_eq!(
+            items[0].subtext,
+            "active (running), enabled - Nginx web server"
+        );
+        assert_eq!(items[0].metadata["scope"], "system");
+        assert_eq!(items[0].metadata["privileged"], "true");
+        assert_eq!(items[0].metadata["actions"], "stop,restart,disable");
+    }
+
+    #[test]
+    fn bare_prefix_lists_failed_then_active_units() {
+        let provider = provider_with(sample_units());
+        let items = query(&provider, "sys ", 10);
+        let names: Vec<_> = items.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(
+            &names[..3],
+            ["backup.service", "broken.mount", "nginx.service"]
+        );
+        assert_eq!(items[0].icon, "dialog-error");
+    }
+
+    #[test]
+    fn user_units_are_not_privileged() {
+        let provider = provider_with(vec![Unit {
+            name: "syncthing.service".to_string(),
+            scope: SystemdScope::User,
+            description: "Syncthing".to_string(),
+            active: "inactive".to_string(),
+            sub: "dead".to_string(),
+            file_state: "disabled".to_string(),
+        }]);
+        let items = query(&provider, "sys sync", 10);
+        assert_eq!(items[0].metadata["scope"], "user");
+        assert_eq!(items[0].metadata["privileged"], "false");
+    }
+
+    #[tokio::test]
+    async fn stale_listings_are_refreshed() {
+        let provider = provider_with(sample_units());
+        *provider.cache.lock().unwrap() = Some((Instant::now() - CACHE_TTL, sample_units()));
+        // No scopes to list, so the refreshed listing is empty
+        assert!(provider.query("sys nginx", 10).await.is_empty());
+        let cache = provider.cache.lock().unwrap();
+        let (listed, units) = cache.as_ref().unwrap();
+        assert!(listed.elapsed() < CACHE_TTL);
+        assert!(units.is_empty());
+    }
+}
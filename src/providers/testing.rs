@@ -0,0 +1,166 @@
+//! Helpers for testing providers without a running server
+//!
+//! [`MockProvider`] answers every query with scripted items, [`manager_with`]
+//! registers providers on a fresh manager, and [`query`] runs a provider's
+//! query through the `Provider` trait the way the manager does. [`TempDir`]
+//! holds files for providers that read them.
+
+use super::{Item, Provider, ProviderManager};
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// A provider that returns the same scripted items for every query
+///
+/// Each item carries the query the provider received in its `query`
+/// metadata, so tests can check how queries were routed.
+pub(crate) struct MockProvider {
+    pub(crate) name: String,
+    pub(crate) prefix: Option<String>,
+    pub(crate) priority: i32,
+    pub(crate) enabled: bool,
+    /// Returned for every query, in order
+    pub(crate) items: Vec<Item>,
+    /// How long each query takes to answer
+    pub(crate) delay: Duration,
+}
+
+impl MockProvider {
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            prefix: None,
+            priority: 0,
+            enabled: true,
+            items: Vec::new(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub(crate) fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub(crate) fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub(crate) fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Add an item with the given text and score
+    pub(crate) fn with_item(mut self, text: &str, score: f32) -> Self {
+        self.items
+            .push(Item::new(text, self.name.as_str()).with_score(score));
+        self
+    }
+
+    pub(crate) fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl Provider for MockProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        "mock provider"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        _max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let items: Vec<Item> = self
+            .items
+            .iter()
+            .map(|item| item.clone().with_metadata("query", query))
+            .collect();
+        let delay = self.delay;
+        Box::pin(async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            items
+        })
+    }
+}
+
+/// A mock provider with an optional prefix and `(text, score)` items
+pub(crate) fn mock(name: &str, prefix: Option<&str>, items: Vec<(&str, f32)>) -> MockProvider {
+    let provider = MockProvider::new(name);
+    let provider = match prefix {
+        Some(prefix) => provider.with_prefix(prefix),
+        None => provider,
+    };
+    items.into_iter().fold(provider, |provider, (text, score)| {
+        provider.with_item(text, score)
+    })
+}
+
+/// A manager with the given providers registered, in order
+pub(crate) async fn manager_with(providers: Vec<MockProvider>) -> ProviderManager {
+    let manager = ProviderManager::new();
+    for provider in providers {
+        manager.register(provider).await.unwrap();
+    }
+    manager
+}
+
+/// Run one query through the `Provider` trait, for providers that don't
+/// need the tokio runtime
+pub(crate) fn query(provider: &dyn Provider, query: &str, max_results: usize) -> Vec<Item> {
+    futures::executor::block_on(provider.query(query, max_results))
+}
+
+/// The texts of some results, in order
+pub(crate) fn texts(items: &[Item]) -> Vec<&str> {
+    items.iter().map(|item| item.text.as_str()).collect()
+}
+
+/// A self-cleaning temporary directory (avoids pulling in a dev-dependency).
+pub(crate) struct TempDir {
+    pub(crate) path: PathBuf,
+}
+
+impl TempDir {
+    pub(crate) fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("datacube-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    /// Write `contents` to `name` in the directory, returning its path
+    pub(crate) fn write(&self, name: &str, contents: &str) -> PathBuf {
+        let path = self.path.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,869 @@
+//! Time provider - shows the current time in other timezones
+//!
+//! Answers queries like `time in tokyo` or `time utc+2`. Zones come from the
+//! system tz database (`/usr/share/zoneinfo`): zone names are listed from
+//! `zone1970.tab` and each zone's TZif file is read directly, including the
+//! POSIX TZ rule in its footer that covers dates past the last transition.
+
+use super::{Item, Provider};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+/// Where the system tz database usually lives
+pub const DEFAULT_ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Provider for world clock lookups
+pub struct TimeProvider {
+    prefix: String,
+    zoneinfo_dir: PathBuf,
+    /// Zones shown for an empty query
+    pinned: Vec<String>,
+    priority: i32,
+    /// Known zone names, e.g. "Asia/Tokyo"
+    zones: Vec<String>,
+    matcher: SkimMatcherV2,
+}
+
+impl TimeProvider {
+    pub fn new() -> Self {
+        Self {
+            prefix: "time ".to_string(),
+            zoneinfo_dir: PathBuf::new(),
+            pinned: vec!["UTC".to_string()],
+            priority: 0,
+            zones: Vec::new(),
+            matcher: SkimMatcherV2::default(),
+        }
+        .with_zoneinfo_dir(DEFAULT_ZONEINFO_DIR)
+    }
+
+    /// Set the query prefix (default: "time ")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the zones shown for an empty query (default: UTC)
+    pub fn with_pinned(mut self, pinned: Vec<String>) -> Self {
+        self.pinned = pinned;
+        self
+    }
+
+    /// Read zones from a different tz database directory
+    pub fn with_zoneinfo_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.zoneinfo_dir = dir.into();
+        self.zones = load_zone_names(&self.zoneinfo_dir);
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Resolve a zone name (or fixed offset) to something we can compute with
+    fn resolve(&self, name: &str) -> Option<Zone> {
+        if let Some(offset) = parse_fixed_offset(name) {
+            return Some(Zone::Fixed(offset));
+        }
+        // Zone names never climb out of the database directory.
+        if name.split('/').any(|part| part == "..") {
+            return None;
+        }
+        let path = self.zoneinfo_dir.join(name);
+        let data = std::fs::read(&path).ok()?;
+        match parse_tzif(&data) {
+            Some(info) => Some(Zone::Tz(name.to_string(), Box::new(info))),
+            None => {
+                warn!("Unreadable TZif file {:?}", path);
+                None
+            }
+        }
+    }
+
+    /// Zone names matching `query`, best first
+    fn search(&self, query: &str, limit: usize) -> Vec<(String, f32)> {
+        let needle = query.replace(' ', "_");
+        let mut scored: Vec<(&String, i64)> = self
+            .zones
+            .iter()
+            .filter_map(|zone| {
+                let city = zone.rsplit('/').next().unwrap_or(zone);
+                if city.eq_ignore_ascii_case(&needle) || zone.eq_ignore_ascii_case(&needle) {
+                    return Some((zone, i64::MAX));
+                }
+                let by_city = self.matcher.fuzzy_match(city, &needle);
+                let by_zone = self.matcher.fuzzy_match(zone, &needle);
+                by_city.max(by_zone).map(|score| (zone, score))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(zone, score)| {
+                let normalized = if score == i64::MAX {
+                    1.0
+                } else {
+                    (score as f32 / 200.0).clamp(0.1, 0.9)
+                };
+                (zone.clone(), normalized)
+            })
+            .collect()
+    }
+
+    fn query_impl(&self, query: &str, max_results: usize, now: i64) -> Vec<Item> {
+        let input = query
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(query)
+            .trim();
+        let input = input.strip_prefix("in ").unwrap_or(input).trim();
+
+        let zones: Vec<(String, f32)> = if input.is_empty() {
+            self.pinned
+                .iter()
+                .enumerate()
+                .map(|(i, zone)| (zone.clone(), 1.0 - i as f32 * 0.01))
+                .collect()
+        } else if parse_fixed_offset(input).is_some() {
+            vec![(input.to_string(), 1.0)]
+        } else {
+            self.search(input, max_results)
+        };
+
+        zones
+            .into_iter()
+            .filter_map(|(name, score)| {
+                let zone = self.resolve(&name)?;
+                Some(time_item(&zone, now).with_score(score))
+            })
+            .collect()
+    }
+}
+
+impl Default for TimeProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider for TimeProvider {
+    fn name(&self) -> &str {
+        "time"
+    }
+
+    fn description(&self) -> &str {
+        "Current time in other timezones"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    /// Results are values to read or copy; activating one does nothing more
+    fn supports_activate(&self) -> bool {
+        false
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let result = self.query_impl(query, max_results, now);
+        Box::pin(async move { result })
+    }
+}
+
+/// A zone resolved for computation
+enum Zone {
+    /// A fixed UTC offset in seconds, e.g. from `utc+2`
+    Fixed(i32),
+    /// A tz database zone
+    Tz(String, Box<TzInfo>),
+}
+
+/// The offset and abbreviation in effect at some instant
+#[derive(Debug, PartialEq)]
+struct LocalTime {
+    /// Seconds east of UTC
+    offset: i32,
+    abbreviation: String,
+}
+
+fn time_item(zone: &Zone, now: i64) -> Item {
+    let (name, label, local) = match zone {
+        Zone::Fixed(offset) => {
+            let label = match offset {
+                0 => "UTC".to_string(),
+                _ => format!("UTC{}", format_offset(*offset)),
+            };
+            let local = LocalTime {
+                offset: *offset,
+                abbreviation: label.clone(),
+            };
+            (label.clone(), label, local)
+        }
+        Zone::Tz(name, info) => {
+            let city = name.rsplit('/').next().unwrap_or(name).replace('_', " ");
+            (name.clone(), city, info.local_time(now))
+        }
+    };
+
+    let local_secs = now + i64::from(local.offset);
+    let days = local_secs.div_euclid(86400);
+    let secs_of_day = local_secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60);
+    let weekday = WEEKDAYS[weekday(days) as usize];
+    let offset = format_offset(local.offset);
+
+    let time = format!("{:02}:{:02}", hour, minute);
+    let date = format!("{:04}-{:02}-{:02}", year, month, day);
+    debug!("Time in {}: {} {}", name, date, time);
+
+    let mut subtext = format!(
+        "{} {} {} {}",
+        weekday,
+        day,
+        MONTHS[month as usize - 1],
+        year
+    );
+    if local.abbreviation.starts_with("UTC") {
+        subtext.push_str(&format!(" · {}", local.abbreviation));
+    } else {
+        subtext.push_str(&format!(" · {} (UTC{})", local.abbreviation, offset));
+    }
+    if name != label {
+        subtext.push_str(&format!(" · {}", name));
+    }
+
+    Item::new(format!("{} in {}", time, label), "time")
+        .with_stable_id(&name)
+        .with_subtext(subtext)
+        .with_icon("preferences-system-time")
+        .with_metadata("zone", &name)
+        .with_metadata("time", &time)
+        .with_copy(&time)
+        // Stale once the minute shown is over
+        .with_ttl(Duration::from_secs((60 - second) as u64))
+        .with_metadata("date", &date)
+        .with_metadata("utc_offset", &offset)
+        .with_metadata("abbreviation", &local.abbreviation)
+        .with_metadata(
+            "iso8601",
+            format!("{}T{}:{:02}{}", date, time, second, offset),
+        )
+}
+
+/// Parse `utc`, `gmt+1`, `UTC-5:30` or `utc+0530` into seconds east of UTC
+fn parse_fixed_offset(input: &str) -> Option<i32> {
+    let lower = input.trim().to_ascii_lowercase();
+    let rest = lower
+        .strip_prefix("utc")
+        .or_else(|| lower.strip_prefix("gmt"))?
+        .trim();
+    if rest.is_empty() {
+        return Some(0);
+    }
+
+    let (sign, digits) = match rest.as_bytes()[0] {
+        b'+' => (1, &rest[1..]),
+        b'-' => (-1, &rest[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match digits.split_once(':') {
+        Some((h, m)) => (h, m),
+        // Splitting at a byte index is only safe on ASCII
+        None if digits.len() > 2 && digits.bytes().all(|b| b.is_ascii_digit()) => {
+            digits.split_at(digits.len() - 2)
+        }
+        None => (digits, "0"),
+    };
+    let hours: i32 = hours.trim().parse().ok()?;
+    let minutes: i32 = minutes.trim().parse().ok()?;
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn format_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{}{:02}:{:02}", sign, offset / 3600, offset / 60 % 60)
+}
+
+/// List zone names from the database's `zone1970.tab` (or `zone.tab`)
+fn load_zone_names(dir: &Path) -> Vec<String> {
+    let table = ["zone1970.tab", "zone.tab"]
+        .iter()
+        .find_map(|name| std::fs::read_to_string(dir.join(name)).ok());
+    let Some(table) = table else {
+        warn!("No zone table found in {:?}", dir);
+        return vec!["UTC".to_string()];
+    };
+
+    let mut zones: Vec<String> = table
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split('\t').nth(2))
+        .map(String::from)
+        .collect();
+    zones.push("UTC".to_string());
+    zones.sort();
+    zones.dedup();
+    zones
+}
+
+/// A parsed TZif file
+#[derive(Debug)]
+struct TzInfo {
+    /// Transition instants (Unix seconds), ascending
+    transitions: Vec<i64>,
+    /// For each transition, the index of the local time type it switches to
+    indices: Vec<u8>,
+    types: Vec<LocalTime>,
+    /// Rule for instants after the last transition
+    footer: Option<PosixTz>,
+}
+
+impl TzInfo {
+    fn local_time(&self, t: i64) -> LocalTime {
+        let after_last = self.transitions.last().is_none_or(|&last| t >= last);
+        if after_last {
+            if let Some(footer) = &self.footer {
+                return footer.local_time(t);
+            }
+        }
+
+        let idx = self.transitions.partition_point(|&x| x <= t);
+        let ty = if idx == 0 {
+            // Before the first transition: the first type (RFC 8536).
+            self.types.first()
+        } else {
+            self.indices
+                .get(idx - 1)
+                .and_then(|&i| self.types.get(usize::from(i)))
+        };
+        match ty {
+            Some(ty) => LocalTime {
+                offset: ty.offset,
+                abbreviation: ty.abbreviation.clone(),
+            },
+            None => LocalTime {
+                offset: 0,
+                abbreviation: "UTC".to_string(),
+            },
+        }
+    }
+}
+
+/// Parse a TZif file (RFC 8536), preferring the 64-bit v2+ data
+fn parse_tzif(data: &[u8]) -> Option<TzInfo> {
+    if data.get(..4)? != b"TZif" {
+        return None;
+    }
+    let version = *data.get(4)?;
+    let (counts, v1_len) = tzif_counts(data, 0, 4)?;
+
+    if version < b'2' {
+        let (info, _) = parse_tzif_block(data, 44, counts, 4)?;
+        return Some(info);
+    }
+
+    let header = 44 + v1_len;
+    let (counts, v2_len) = tzif_counts(data, header, 8)?;
+    let (mut info, _) = parse_tzif_block(data, header + 44, counts, 8)?;
+
+    // Footer: "\n<POSIX TZ string>\n"
+    let footer = data.get(header + 44 + v2_len..)?;
+    if let Some(rest) = footer.strip_prefix(b"\n") {
+        let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+        info.footer = std::str::from_utf8(&rest[..end])
+            .ok()
+            .and_then(parse_posix_tz);
+    }
+    Some(info)
+}
+
+/// Header counts (isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt) at
+/// `offset`, and the length of the data block that follows
+fn tzif_counts(data: &[u8], offset: usize, time_size: usize) -> Option<([usize; 6], usize)> {
+    let mut counts = [0usize; 6];
+    for (i, count) in counts.iter_mut().enumerate() {
+        let start = offset + 20 + i * 4;
+        *count = u32::from_be_bytes(data.get(start..start + 4)?.try_into().ok()?) as usize;
+    }
+    let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts;
+    let len = timecnt * time_size
+        + timecnt
+        + typecnt * 6
+        + charcnt
+        + leapcnt * (time_size + 4)
+        + isstdcnt
+        + isutcnt;
+    Some((counts, len))
+}
+
+fn parse_tzif_block(
+    data: &[u8],
+    mut pos: usize,
+    counts: [usize; 6],
+    time_size: usize,
+) -> Option<(TzInfo, usize)> {
+    let [_, _, _, timecnt, typecnt, charcnt] = counts;
+
+    let mut transitions = Vec::with_capacity(timecnt);
+    for _ in 0..timecnt {
+        let bytes = data.get(pos..pos + time_size)?;
+        transitions.push(match time_size {
+            4 => i64::from(i32::from_be_bytes(bytes.try_into().ok()?)),
+            _ => i64::from_be_bytes(bytes.try_into().ok()?),
+        });
+        pos += time_size;
+    }
+    let indices = data.get(pos..pos + timecnt)?.to_vec();
+    pos += timecnt;
+
+    let raw_types = data.get(pos..pos + typecnt * 6)?;
+    pos += typecnt * 6;
+    let chars = data.get(pos..pos + charcnt)?;
+    pos += charcnt;
+
+    let types = raw_types
+        .chunks_exact(6)
+        .map(|ty| {
+            let offset = i32::from_be_bytes([ty[0], ty[1], ty[2], ty[3]]);
+            let start = usize::from(ty[5]).min(chars.len());
+            let end = chars[start..]
+                .iter()
+                .position(|&b| b == 0)
+                .map_or(chars.len(), |n| start + n);
+            LocalTime {
+                offset,
+                abbreviation: String::from_utf8_lossy(&chars[start..end]).into_owned(),
+            }
+        })
+        .collect();
+
+    Some((
+        TzInfo {
+            transitions,
+            indices,
+            types,
+            footer: None,
+        },
+        pos,
+    ))
+}
+
+/// A POSIX TZ rule such as `CET-1CEST,M3.5.0,M10.5.0/3`
+#[derive(Debug, PartialEq)]
+struct PosixTz {
+    std_abbr: String,
+    /// Standard offset, seconds east of UTC
+    std_offset: i32,
+    dst: Option<PosixDst>,
+}
+
+#[derive(Debug, PartialEq)]
+struct PosixDst {
+    abbr: String,
+    /// Daylight offset, seconds east of UTC
+    offset: i32,
+    start: PosixRule,
+    end: PosixRule,
+}
+
+/// When a DST change happens: a day of the year plus a local time of day
+#[derive(Debug, PartialEq)]
+struct PosixRule {
+    date: RuleDate,
+    /// Seconds after local midnight (may be negative or exceed a day)
+    time: i64,
+}
+
+#[derive(Debug, PartialEq)]
+enum RuleDate {
+    /// `Jn`: day 1-365, never counting February 29
+    Julian1(i64),
+    /// `n`: day 0-365, counting February 29
+    Julian0(i64),
+    /// `Mm.w.d`: day `d` (0 = Sunday) of week `w` (5 = last) of month `m`
+    MonthWeekDay { month: u32, week: i64, day: i64 },
+}
+
+impl PosixTz {
+    fn local_time(&self, t: i64) -> LocalTime {
+        let std = LocalTime {
+            offset: self.std_offset,
+            abbreviation: self.std_abbr.clone(),
+        };
+        let Some(dst) = &self.dst else {
+            return std;
+        };
+
+        let (year, _, _) = civil_from_days((t + i64::from(self.std_offset)).div_euclid(86400));
+        let start = dst.start.local_instant(year) - i64::from(self.std_offset);
+        let end = dst.end.local_instant(year) - i64::from(dst.offset);
+        let in_dst = if start < end {
+            start <= t && t < end
+        } else {
+            // Southern hemisphere: DST spans the new year.
+            !(end <= t && t < start)
+        };
+
+        if in_dst {
+            LocalTime {
+                offset: dst.offset,
+                abbreviation: dst.abbr.clone(),
+            }
+        } else {
+            std
+        }
+    }
+}
+
+impl PosixRule {
+    /// The rule's instant in `year`, as local wall-clock seconds
+    fn local_instant(&self, year: i64) -> i64 {
+        let days = match self.date {
+            RuleDate::Julian1(n) => {
+                let leap_day = i64::from(is_leap(year) && n >= 60);
+                days_from_civil(year, 1, 1) + n - 1 + leap_day
+            }
+            RuleDate::Julian0(n) => days_from_civil(year, 1, 1) + n,
+            RuleDate::MonthWeekDay { month, week, day } => {
+                let first = days_from_civil(year, month, 1);
+                let len = month_length(year, month);
+                let mut offset = (day - weekday(first)).rem_euclid(7) + (week - 1) * 7;
+                while offset >= len {
+                    offset -= 7;
+                }
+                first + offset
+            }
+        };
+        days * 86400 + self.time
+    }
+}
+
+/// Parse a POSIX TZ string as found in TZif footers
+fn parse_posix_tz(s: &str) -> Option<PosixTz> {
+    let mut p = Cursor(s);
+    let std_abbr = p.abbreviation()?;
+    let std_offset = -p.offset()?;
+    if p.0.is_empty() {
+        return Some(PosixTz {
+            std_abbr,
+            std_offset,
+            dst: None,
+        });
+    }
+
+    let dst_abbr = p.abbreviation()?;
+    let dst_offset = if p.0.starts_with(',') {
+        std_offset + 3600
+    } else {
+        -p.offset()?
+    };
+    let rules = p.0.strip_prefix(',')?;
+    let (start, end) = rules.split_once(',')?;
+
+    Some(PosixTz {
+        std_abbr,
+        std_offset,
+        dst: Some(PosixDst {
+            abbr: dst_abbr,
+            offset: dst_offset,
+            start: parse_rule(start)?,
+            end: parse_rule(end)?,
+        }),
+    })
+}
+
+fn parse_rule(s: &str) -> Option<PosixRule> {
+    let (date, time) = match s.split_once('/') {
+        Some((date, time)) => (date, Cursor(time).offset()?),
+        None => (s, 2 * 3600),
+    };
+    let date = if let Some(n) = date.strip_prefix('J') {
+        RuleDate::Julian1(n.parse().ok()?)
+    } else if let Some(mwd) = date.strip_prefix('M') {
+        let mut parts = mwd.split('.');
+        let month = parts.next()?.parse().ok()?;
+        let week = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&day) {
+            return None;
+        }
+        RuleDate::MonthWeekDay { month, week, day }
+    } else {
+        RuleDate::Julian0(date.parse().ok()?)
+    };
+    Some(PosixRule {
+        date,
+        time: i64::from(time),
+    })
+}
+
+/// A tiny cursor over the remainder of a POSIX TZ string
+struct Cursor<'a>(&'a str);
+
+impl Cursor<'_> {
+    /// `<+09>` or an alphabetic name like `JST`
+    fn abbreviation(&mut self) -> Option<String> {
+        if let Some(rest) = self.0.strip_prefix('<') {
+            let (abbr, rest) = rest.split_once('>')?;
+            self.0 = rest;
+            return Some(abbr.to_string());
+        }
+        let end = self
+            .0
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(self.0.len());
+        if end < 3 {
+            return None;
+        }
+        let (abbr, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(abbr.to_string())
+    }
+
+    /// `[+-]hh[:mm[:ss]]` in seconds, with POSIX's sign (positive = west)
+    fn offset(&mut self) -> Option<i32> {
+        let (sign, rest) = match self.0.as_bytes().first()? {
+            b'-' => (-1, &self.0[1..]),
+            b'+' => (1, &self.0[1..]),
+            _ => (1, self.0),
+        };
+        let end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == ':'))
+            .unwrap_or(rest.len());
+        let mut secs = 0;
+        for (part, unit) in rest[..end].split(':').zip([3600, 60, 1]) {
+            secs += part.parse::<i32>().ok()? * unit;
+        }
+        self.0 = &rest[end..];
+        Some(sign * secs)
+    }
+}
+
+fn is_leap(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn month_length(year: i64, month: u32) -> i64 {
+    match month {
+        2 if is_leap(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Day of the week for days since 1970-01-01 (0 = Sunday)
+fn weekday(days: i64) -> i64 {
+    (days + 4).rem_euclid(7)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+pub(super) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = i64::from(month);
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// (year, month, day) for days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2026-07-01T12:00:00Z
+    const JULY: i64 = 1_782_907_200;
+    /// 2026-01-15T12:00:00Z
+    const JANUARY: i64 = 1_768_478_400;
+
+    /// A v2 TZif file with no transitions, relying on its footer
+    fn tzif(footer: &str) -> Vec<u8> {
+        fn header(out: &mut Vec<u8>) {
+            out.extend_from_slice(b"TZif2");
+            out.extend_from_slice(&[0; 15]);
+            // isutcnt, isstdcnt, leapcnt, timecnt, typecnt=1, charcnt=4
+            for count in [0u32, 0, 0, 0, 1, 4] {
+                out.extend_from_slice(&count.to_be_bytes());
+            }
+        }
+        fn block(out: &mut Vec<u8>) {
+            out.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+            out.extend_from_slice(b"UTC\0");
+        }
+        let mut out = Vec::new();
+        header(&mut out);
+        block(&mut out);
+        header(&mut out);
+        block(&mut out);
+        out.push(b'\n');
+        out.extend_from_slice(footer.as_bytes());
+        out.push(b'\n');
+        out
+    }
+
+    struct ZoneDir(PathBuf);
+
+    impl ZoneDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("datacube-tz-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(dir.join("Asia")).unwrap();
+            std::fs::create_dir_all(dir.join("Europe")).unwrap();
+            std::fs::write(dir.join("Asia/Tokyo"), tzif("JST-9")).unwrap();
+            std::fs::write(
+                dir.join("Europe/Berlin"),
+                tzif("CET-1CEST,M3.5.0,M10.5.0/3"),
+            )
+            .unwrap();
+            std::fs::write(dir.join("UTC"), tzif("UTC0")).unwrap();
+            std::fs::write(
+                dir.join("zone1970.tab"),
+                "# comment\nJP\t+353916+1394441\tAsia/Tokyo\n\
+                 DE\t+5230+01322\tEurope/Berlin\n",
+            )
+            .unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for ZoneDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn civil_date_round_trip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(days_from_civil(2026, 7, 1), JULY / 86400);
+        assert_eq!(civil_from_days(JANUARY / 86400), (2026, 1, 15));
+        assert_eq!(weekday(0), 4, "1970-01-01 was a Thursday");
+    }
+
+    #[test]
+    fn parses_fixed_offsets() {
+        assert_eq!(parse_fixed_offset("UTC"), Some(0));
+        assert_eq!(parse_fixed_offset("utc+2"), Some(7200));
+        assert_eq!(parse_fixed_offset("GMT-5:30"), Some(-19800));
+        assert_eq!(parse_fixed_offset("utc+0545"), Some(20700));
+        assert_eq!(parse_fixed_offset("utc+20"), None);
+        assert_eq!(parse_fixed_offset("tokyo"), None);
+        assert_eq!(parse_fixed_offset("utc+€"), None);
+        assert_eq!(parse_fixed_offset("gmt-é1"), None);
+        assert_eq!(parse_fixed_offset("utc+1é5"), None);
+    }
+
+    #[test]
+    fn posix_rules_switch_dst() {
+        let berlin = parse_posix_tz("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        assert_eq!(berlin.local_time(JULY).offset, 7200);
+        assert_eq!(berlin.local_time(JULY).abbreviation, "CEST");
+        assert_eq!(berlin.local_time(JANUARY).offset, 3600);
+
+        // Southern hemisphere: DST over the new year.
+        let sydney = parse_posix_tz("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.local_time(JANUARY).offset, 11 * 3600);
+        assert_eq!(sydney.local_time(JULY).offset, 10 * 3600);
+
+        let quoted = parse_posix_tz("<+0530>-5:30").unwrap();
+        assert_eq!(quoted.std_abbr, "+0530");
+        assert_eq!(quoted.local_time(JULY).offset, 19800);
+    }
+
+    #[test]
+    fn dst_starts_on_the_last_sunday_of_march() {
+        let berlin = parse_posix_tz("CET-1CEST,M3.5.0,M10.5.0/3").unwrap();
+        // 2026-03-29 is the last Sunday; the switch is at 01:00 UTC.
+        let switch = days_from_civil(2026, 3, 29) * 86400 + 3600;
+        assert_eq!(berlin.local_time(switch - 1).offset, 3600);
+        assert_eq!(berlin.local_time(switch).offset, 7200);
+    }
+
+    #[test]
+    fn reads_tzif_footer() {
+        let info = parse_tzif(&tzif("JST-9")).unwrap();
+        assert_eq!(info.local_time(JULY).offset, 9 * 3600);
+        assert_eq!(info.local_time(JULY).abbreviation, "JST");
+        assert!(parse_tzif(b"not a tzif file").is_none());
+    }
+
+    #[test]
+    fn query_finds_zone_by_city() {
+        let dir = ZoneDir::new();
+        let provider = TimeProvider::new().with_zoneinfo_dir(&dir.0);
+
+        let items = provider.query_impl("time in tokyo", 5, JULY);
+        assert_eq!(items[0].text, "21:00 in Tokyo");
+        assert_eq!(items[0].metadata["zone"], "Asia/Tokyo");
+        assert_eq!(items[0].metadata["iso8601"], "2026-07-01T21:00:00+09:00");
+        assert_eq!(items[0].metadata["copy"], "21:00");
+        assert_eq!(items[0].ttl, Some(Duration::from_secs(60)));
+        assert!(items[0].subtext.starts_with("Wed 1 Jul 2026"));
+
+        let items = provider.query_impl("time berl", 5, JULY);
+        assert_eq!(items[0].metadata["abbreviation"], "CEST");
+    }
+
+    #[test]
+    fn query_handles_offsets_and_pinned_zones() {
+        let dir = ZoneDir::new();
+        let provider = TimeProvider::new()
+            .with_zoneinfo_dir(&dir.0)
+            .with_pinned(vec!["UTC".to_string(), "Asia/Tokyo".to_string()]);
+
+        let items = provider.query_impl("time utc+2", 5, JULY);
+        assert_eq!(items[0].text, "14:00 in UTC+02:00");
+
+        let items = provider.query_impl("time ", 5, JULY);
+        let texts: Vec<_> = items.iter().map(|i| i.text.as_str()).collect();
+        assert_eq!(texts, vec!["12:00 in UTC", "21:00 in Tokyo"]);
+
+        assert!(provider
+            .query_impl("time ../../etc/passwd", 5, JULY)
+            .is_empty());
+    }
+}
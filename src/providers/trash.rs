@@ -0,0 +1,427 @@
+//! Trash provider - files in the freedesktop trash, for restoring them
+//!
+//! `trash report` lists trashed files whose original name matches, newest
+//! deletion first. Each file in `Trash/files` has a `Trash/info/<name>.trashinfo`
+//! recording where it came from and when it was deleted; files without one
+//! are skipped. The parsed entries are cached until the `info` directory
+//! changes, and reading them happens on the blocking pool.
+//!
+//! Datacube doesn't move or delete files itself: items carry the trashed
+//! file's `path`, its `info_path`, the `restore_path` and the `actions`
+//! ("restore,delete") in their metadata. Restoring moves `path` to
+//! `restore_path` and removes `info_path`; deleting removes both. When the
+//! original folder no longer exists, `restore_path` is in the home directory
+//! instead and `restore_warning` says so.
+
+use super::recent::percent_decode_path;
+use super::{Item, Provider};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+use tracing::warn;
+
+/// A trashed file
+#[derive(Debug, Clone, PartialEq)]
+struct TrashedFile {
+    /// The file inside `Trash/files`
+    path: PathBuf,
+    /// Its `.trashinfo` file
+    info_path: PathBuf,
+    /// Where it was deleted from
+    original_path: PathBuf,
+    /// Local time of deletion, as written: "2024-05-01T10:20:30"
+    deleted_at: String,
+}
+
+impl TrashedFile {
+    /// The original file name, shown and matched against
+    fn name(&self) -> String {
+        self.original_path
+            .file_name()
+            .unwrap_or(self.path.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Trashed files plus the `info` directory's mtime they were read at
+struct Cache {
+    mtime: Option<SystemTime>,
+    files: Arc<Vec<TrashedFile>>,
+}
+
+/// Provider for the user's trash
+pub struct TrashProvider {
+    trash_dir: PathBuf,
+    home_dir: PathBuf,
+    prefix: String,
+    priority: i32,
+    /// `None` until the trash is first read
+    cache: Mutex<Option<Cache>>,
+    matcher: SkimMatcherV2,
+}
+
+impl TrashProvider {
+    /// Create a provider for the trash at `trash_dir` (holding `files` and
+    /// `info`)
+    pub fn new(trash_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            trash_dir: trash_dir.into(),
+            home_dir: dirs::home_dir().unwrap_or_else(|| PathBuf::from("/")),
+            prefix: "trash ".to_string(),
+            priority: 0,
+            cache: Mutex::new(None),
+            matcher: SkimMatcherV2::default(),
+        }
+    }
+
+    /// The default trash location, `$XDG_DATA_HOME/Trash`
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .or_else(|| dirs::home_dir().map(|home| home.join(".local/share")))
+            .unwrap_or_default()
+            .join("Trash")
+    }
+
+    /// Set the query prefix (default: "trash ")
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Every trashed file with readable info, newest deletion first,
+    /// re-reading the trash if its `info` directory changed
+    ///
+    /// Trashing, restoring and deleting all add or remove a `.trashinfo`, so
+    /// the directory's mtime moves with every change.
+    async fn files(&self) -> Arc<Vec<TrashedFile>> {
+        let mtime = std::fs::metadata(self.trash_dir.join("info"))
+            .and_then(|m| m.modified())
+            .ok();
+        {
+            let cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(cache) = cache.as_ref().filter(|cache| cache.mtime == mtime) {
+                return Arc::clone(&cache.files);
+            }
+        }
+
+        let trash_dir = self.trash_dir.clone();
+        let files = tokio::task::spawn_blocking(move || load_files(&trash_dir))
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Reading the trash failed: {}", e);
+                Vec::new()
+            });
+        let files = Arc::new(files);
+        // The cache is replaced wholesale, never left half-updated.
+        *self.cache.lock().unwrap_or_else(PoisonError::into_inner) = Some(Cache {
+            mtime,
+            files: Arc::clone(&files),
+        });
+        files
+    }
+
+    async fn query_impl(&self, query: &str, max_results: usize) -> Vec<Item> {
+        let query = query
+            .strip_prefix(self.prefix.as_str())
+            .unwrap_or(query)
+            .trim();
+
+        let files = self.files().await;
+        let ranked: Vec<(TrashedFile, f32)> = if query.is_empty() {
+            files
+                .iter()
+                .take(max_results)
+                .enumerate()
+                .map(|(rank, file)| (file.clone(), 1.0 - rank as f32 * 0.01))
+                .collect()
+        } else {
+            let mut scored: Vec<(&TrashedFile, i64)> = files
+                .iter()
+                .filter_map(|file| Some((file, self.matcher.fuzzy_match(&file.name(), query)?)))
+                .collect();
+            // Stable, so equal scores stay newest first
+            scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+            scored
+                .into_iter()
+                .take(max_results)
+                .map(|(file, score)| (file.clone(), (score as f32 / 2000.0).clamp(0.0, 1.0)))
+                .collect()
+        };
+
+        // Finding where each file restores to checks the filesystem.
+        let home_dir = self.home_dir.clone();
+        tokio::task::spawn_blocking(move || {
+            ranked
+                .iter()
+                .map(|(file, score)| trash_item(file, &home_dir).with_score(*score))
+                .collect()
+        })
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Listing the trash failed: {}", e);
+            Vec::new()
+        })
+    }
+}
+
+/// Every file in `trash_dir` with readable info, newest deletion first
+fn load_files(trash_dir: &Path) -> Vec<TrashedFile> {
+    let Ok(entries) = std::fs::read_dir(trash_dir.join("files")) else {
+        return Vec::new();
+    };
+    let mut files: Vec<TrashedFile> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let mut info_name = entry.file_name();
+            info_name.push(".trashinfo");
+            let info_path = trash_dir.join("info").join(info_name);
+            let (original_path, deleted_at) =
+                parse_trashinfo(&std::fs::read_to_string(&info_path).ok()?)?;
+            Some(TrashedFile {
+                path: entry.path(),
+                info_path,
+                original_path,
+                deleted_at,
+            })
+        })
+        .collect();
+    files.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    files
+}
+
+/// Where restoring puts a file: back where it was, or in `home_dir` if that
+/// folder is gone
+fn restore_path(file: &TrashedFile, home_dir: &Path) -> (PathBuf, Option<String>) {
+    match file.original_path.parent() {
+        Some(dir) if dir.is_dir() => (file.original_path.clone(), None),
+        parent => {
+            let warning = format!(
+                "{} no longer exists; restoring to {}",
+                parent.unwrap_or(Path::new("/")).display(),
+                home_dir.display()
+            );
+            (home_dir.join(file.name()), Some(warning))
+        }
+    }
+}
+
+fn trash_item(file: &TrashedFile, home_dir: &Path) -> Item {
+    let (restore_path, warning) = restore_path(file, home_dir);
+    let from = file
+        .original_path
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut item = Item::new(file.name(), "trash")
+        .with_stable_id(file.path.to_string_lossy())
+        .with_subtext(format!(
+            "Deleted {} from {}",
+            file.deleted_at.replacen('T', " ", 1),
+            from
+        ))
+        .with_icon("user-trash-full")
+        .with_metadata("path", file.path.to_string_lossy())
+        .with_metadata("info_path", file.info_path.to_string_lossy())
+        .with_metadata("original_path", file.original_path.to_string_lossy())
+        .with_metadata("restore_path", restore_path.to_string_lossy())
+        .with_metadata("deleted_at", &file.deleted_at)
+        .with_metadata("actions", "restore,delete");
+    if let Some(warning) = warning {
+        item = item.with_metadata("restore_warning", warning);
+    }
+    item
+}
+
+impl Provider for TrashProvider {
+    fn name(&self) -> &str {
+        "trash"
+    }
+
+    fn description(&self) -> &str {
+        "Files in the trash, to restore or delete"
+    }
+
+    fn prefix(&self) -> Option<&str> {
+        Some(&self.prefix)
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let query = query.to_string();
+        Box::pin(async move { self.query_impl(&query, max_results).await })
+    }
+}
+
+/// The original path and deletion date from a `.trashinfo` file
+fn parse_trashinfo(info: &str) -> Option<(PathBuf, String)> {
+    let mut in_section = false;
+    let mut path = None;
+    let mut deleted_at = String::new();
+    for line in info.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_section = line == "[Trash Info]";
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        match line.split_once('=') {
+            Some(("Path", value)) => path = percent_decode_path(value),
+            Some(("DeletionDate", value)) => deleted_at = value.to_string(),
+            _ => {}
+        }
+    }
+    Some((path?, deleted_at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::testing::{texts, TempDir};
+    use std::fs;
+
+    /// A temporary directory holding an empty `Trash`
+    fn trash_dir() -> TempDir {
+        let dir = TempDir::new();
+        fs::create_dir_all(dir.path.join("Trash/files")).unwrap();
+        fs::create_dir_all(dir.path.join("Trash/info")).unwrap();
+        dir
+    }
+
+    /// Put a file in the trash as if deleted from `original`
+    fn trash(dir: &TempDir, name: &str, original: &str, deleted_at: &str) {
+        fs::write(dir.path.join("Trash/files").join(name), "").unwrap();
+        fs::write(
+            dir.path.join(format!("Trash/info/{}.trashinfo", name)),
+            format!(
+                "[Trash Info]\nPath={}\nDeletionDate={}\n",
+                original, deleted_at
+            ),
+        )
+        .unwrap();
+    }
+
+    fn provider(dir: &TempDir) -> TrashProvider {
+        TrashProvider {
+            home_dir: dir.path.join("home"),
+            ..TrashProvider::new(dir.path.join("Trash"))
+        }
+    }
+
+    #[test]
+    fn parses_trashinfo() {
+        let info = "[Trash Info]\nPath=/home/me/My%20Notes.txt\nDeletionDate=2024-05-01T10:20:30\n";
+        assert_eq!(
+            parse_trashinfo(info),
+            Some((
+                PathBuf::from("/home/me/My Notes.txt"),
+                "2024-05-01T10:20:30".to_string()
+            ))
+        );
+        assert_eq!(parse_trashinfo("[Other]\nPath=/x\n"), None);
+    }
+
+    #[tokio::test]
+    async fn lists_newest_first_and_matches_by_name() {
+        let dir = trash_dir();
+        let tmp = std::env::temp_dir();
+        let tmp = tmp.to_string_lossy();
+        trash(
+            &dir,
+            "report.pdf",
+            &format!("{}/report.pdf", tmp),
+            "2024-05-01T10:00:00",
+        );
+        trash(
+            &dir,
+            "notes.txt",
+            &format!("{}/notes.txt", tmp),
+            "2024-06-01T09:30:00",
+        );
+        fs::write(dir.path.join("Trash/files/orphan"), "").unwrap();
+        let provider = provider(&dir);
+
+        let items = provider.query("trash ", 10).await;
+        assert_eq!(texts(&items), ["notes.txt", "report.pdf"]);
+        assert_eq!(
+            items[0].subtext,
+            format!("Deleted 2024-06-01 09:30:00 from {}", tmp)
+        );
+        assert_eq!(items[0].metadata["actions"], "restore,delete");
+        assert_eq!(
+            items[0].metadata["restore_path"],
+            format!("{}/notes.txt", tmp)
+        );
+        assert!(!items[0].metadata.contains_key("restore_warning"));
+
+        assert_eq!(
+            texts(&provider.query("trash rep", 10).await),
+            ["report.pdf"]
+        );
+    }
+
+    #[tokio::test]
+    async fn restores_to_home_when_the_folder_is_gone() {
+        let dir = trash_dir();
+        trash(
+            &dir,
+            "old.txt",
+            "/nonexistent/dir/old.txt",
+            "2024-05-01T10:00:00",
+        );
+
+        let items = provider(&dir).query("trash old", 10).await;
+        assert_eq!(
+            items[0].metadata["restore_path"],
+            dir.path.join("home/old.txt").to_string_lossy()
+        );
+        assert!(
+            items[0].metadata["restore_warning"].starts_with("/nonexistent/dir no longer exists")
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_trash_lists_nothing() {
+        let provider = TrashProvider::new("/nonexistent/Trash");
+        assert!(provider.query("trash ", 10).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn rereads_only_when_the_info_dir_changes() {
+        let dir = trash_dir();
+        trash(&dir, "a.txt", "/tmp/a.txt", "2024-05-01T10:00:00");
+        let provider = provider(&dir);
+        let first = provider.files().await;
+        assert!(Arc::ptr_eq(&first, &provider.files().await));
+
+        trash(&dir, "b.txt", "/tmp/b.txt", "2024-05-02T10:00:00");
+        // Make sure the mtime moves even on coarse-grained filesystems.
+        let later = SystemTime::now() + std::time::Duration::from_secs(5);
+        fs::File::open(dir.path.join("Trash/info"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(
+            texts(&provider.query("trash ", 10).await),
+            ["b.txt", "a.txt"]
+        );
+    }
+}
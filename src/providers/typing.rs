@@ -0,0 +1,100 @@
+//! Typing into the focused window
+//!
+//! Items whose value is better typed than pasted (a generated token, a
+//! snippet) carry it under the `type_text` metadata key, set with
+//! [`Item::with_type_text`](super::Item::with_type_text); a frontend offers a
+//! "Type" action for them. [`type_text`] does the typing with `wtype` under
+//! Wayland or `xdotool` under X11, or with the tool the user picks.
+
+use super::clipboard::pipe_to;
+use crate::display::{self, DisplayServer};
+use clap::ValueEnum;
+
+/// The metadata key holding the text to type when an item is activated
+pub const METADATA_KEY: &str = "type_text";
+
+/// A tool that types its stdin into the focused window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TypingTool {
+    /// wtype, for Wayland compositors with the virtual keyboard protocol
+    Wtype,
+    /// xdotool, for X11
+    Xdotool,
+}
+
+impl TypingTool {
+    fn program(self) -> &'static str {
+        match self {
+            TypingTool::Wtype => "wtype",
+            TypingTool::Xdotool => "xdotool",
+        }
+    }
+
+    /// Arguments that make the tool type what it reads from stdin
+    fn args(self) -> &'static [&'static str] {
+        match self {
+            TypingTool::Wtype => &["-"],
+            TypingTool::Xdotool => &["type", "--clearmodifiers", "--file", "-"],
+        }
+    }
+}
+
+/// Type `text` into the focused window, with `tool` or else whichever suits
+/// the session
+///
+/// Fails if the session has no display, or the tool isn't installed.
+pub fn type_text(text: &str, tool: Option<TypingTool>) -> anyhow::Result<()> {
+    let tools = match tool {
+        Some(tool) => vec![tool],
+        None => tools(display::current()),
+    };
+    if tools.is_empty() {
+        anyhow::bail!("no display to type into (neither WAYLAND_DISPLAY nor DISPLAY is set)");
+    }
+    for tool in &tools {
+        match pipe_to(tool.program(), tool.args(), text) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            result => {
+                return result.map_err(|e| anyhow::anyhow!("{} failed: {}", tool.program(), e))
+            }
+        }
+    }
+    let names: Vec<_> = tools.iter().map(|tool| tool.program()).collect();
+    anyhow::bail!(
+        "no typing tool found (tried {}); install it to type into windows",
+        names.join(", ")
+    )
+}
+
+/// The tools to try, in order, on `server`
+///
+/// xdotool can't type into native Wayland windows, so it isn't a fallback
+/// under Wayland.
+fn tools(server: DisplayServer) -> Vec<TypingTool> {
+    match server {
+        DisplayServer::Wayland { .. } => vec![TypingTool::Wtype],
+        DisplayServer::X11 => vec![TypingTool::Xdotool],
+        DisplayServer::None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tools_follow_the_session() {
+        assert_eq!(
+            tools(DisplayServer::Wayland { xwayland: true }),
+            [TypingTool::Wtype]
+        );
+        assert_eq!(tools(DisplayServer::X11), [TypingTool::Xdotool]);
+        assert!(tools(DisplayServer::None).is_empty());
+    }
+
+    #[test]
+    fn tools_read_the_text_from_stdin() {
+        assert_eq!(TypingTool::Wtype.args(), ["-"]);
+        assert!(TypingTool::Xdotool.args().ends_with(&["--file", "-"]));
+    }
+}
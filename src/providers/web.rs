@@ -0,0 +1,150 @@
+//! Web provider - open typed URLs
+//!
+//! A query that looks like a URL or domain (`github.com`, `https://...`) is
+//! offered as "Open in browser". Items carry the URL in their `url` metadata;
+//! the frontend opens it, e.g. with `xdg-open`. Searching the web is the
+//! websearch provider's job.
+
+use super::{Item, Provider};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Provider for typed URLs
+pub struct WebProvider {
+    priority: i32,
+}
+
+impl WebProvider {
+    pub fn new() -> Self {
+        Self { priority: 0 }
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn query_impl(&self, query: &str) -> Vec<Item> {
+        as_url(query.trim())
+            .map(|url| {
+                Item::new(&url, "web")
+                    .with_stable_id("open")
+                    .with_subtext("Open in browser")
+                    .with_icon("web-browser")
+                    .with_metadata("url", &url)
+                    .with_copy(&url)
+                    .with_score(0.9)
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+impl Default for WebProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider for WebProvider {
+    fn name(&self) -> &str {
+        "web"
+    }
+
+    fn description(&self) -> &str {
+        "Open typed URLs and domains"
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        _max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let result = self.query_impl(query);
+        Box::pin(async move { result })
+    }
+}
+
+/// The URL `input` names, if it looks like one: it has a scheme
+/// (`https://...`), or is a domain with an alphabetic top-level part and
+/// maybe a path (`github.com/rust-lang`), which is given `https://`
+fn as_url(input: &str) -> Option<String> {
+    if input.contains(char::is_whitespace) {
+        return None;
+    }
+    if let Some((scheme, rest)) = input.split_once("://") {
+        let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        return (valid_scheme && !rest.is_empty()).then(|| input.to_string());
+    }
+
+    let host = input.split(['/', '?', '#']).next().unwrap_or(input);
+    let host = host.rsplit_once(':').map_or(host, |(host, port)| {
+        if port.chars().all(|c| c.is_ascii_digit()) {
+            host
+        } else {
+            ""
+        }
+    });
+    let labels: Vec<&str> = host.split('.').collect();
+    let valid_label =
+        |label: &&str| !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-');
+    let tld = labels.last()?;
+    let is_domain = labels.len() >= 2
+        && labels.iter().all(valid_label)
+        && tld.len() >= 2
+        && tld.chars().all(char::is_alphabetic);
+    is_domain.then(|| format!("https://{}", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_recognized() {
+        assert_eq!(as_url("github.com").as_deref(), Some("https://github.com"));
+        assert_eq!(
+            as_url("docs.rs/tokio/latest").as_deref(),
+            Some("https://docs.rs/tokio/latest")
+        );
+        assert_eq!(
+            as_url("example.org:8080/x").as_deref(),
+            Some("https://example.org:8080/x")
+        );
+        assert_eq!(
+            as_url("http://localhost:3000").as_deref(),
+            Some("http://localhost:3000")
+        );
+
+        for not_a_url in [
+            "firefox",
+            "1.5",
+            "notes .txt",
+            "a..b",
+            ".com",
+            "foo.c",
+            "https://",
+            "1http://x",
+        ] {
+            assert_eq!(as_url(not_a_url), None, "{}", not_a_url);
+        }
+    }
+
+    #[test]
+    fn urls_are_offered_to_open() {
+        let items = WebProvider::new().query_impl("github.com ");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].metadata["url"], "https://github.com");
+        assert_eq!(items[0].metadata["copy"], "https://github.com");
+        assert!(WebProvider::new().query_impl("rust async").is_empty());
+        assert!(WebProvider::new().query_impl("").is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! Web search provider - search with a chosen engine
+//!
+//! The first word of a query can pick an engine by its keyword: `ddg rust
+//! async` searches DuckDuckGo for "rust async". Any other query gets a
+//! search with the default engine, scored below everything else so it is the
+//! launcher's last resort. Items carry the search URL in their `url`
+//! metadata; the frontend opens it, e.g. with `xdg-open`.
+
+use super::hash::url_encode;
+use super::{Item, Provider};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+
+/// The keyword of the default engine
+pub const DEFAULT_ENGINE: &str = "ddg";
+
+/// A search engine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchEngine {
+    /// Name shown in results
+    pub name: String,
+
+    /// Search URL; `{query}` is replaced by the URL-encoded query
+    pub url: String,
+}
+
+impl SearchEngine {
+    fn new(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    /// The URL searching for `query`
+    fn search_url(&self, query: &str) -> String {
+        self.url.replace("{query}", &url_encode(query))
+    }
+}
+
+/// The engines available unless configured otherwise, by keyword
+pub fn default_engines() -> HashMap<String, SearchEngine> {
+    HashMap::from([
+        (
+            "g".to_string(),
+            SearchEngine::new("Google", "https://www.google.com/search?q={query}"),
+        ),
+        (
+            "ddg".to_string(),
+            SearchEngine::new("DuckDuckGo", "https://duckduckgo.com/?q={query}"),
+        ),
+        (
+            "w".to_string(),
+            SearchEngine::new(
+                "Wikipedia",
+                "https://en.wikipedia.org/w/index.php?search={query}",
+            ),
+        ),
+    ])
+}
+
+/// Provider for web searches
+pub struct WebSearchProvider {
+    /// Keyword -> engine
+    engines: BTreeMap<String, SearchEngine>,
+    /// Keyword of the engine searching queries that pick none
+    default_engine: Option<String>,
+    priority: i32,
+}
+
+impl WebSearchProvider {
+    pub fn new() -> Self {
+        Self {
+            engines: default_engines().into_iter().collect(),
+            default_engine: Some(DEFAULT_ENGINE.to_string()),
+            priority: 0,
+        }
+    }
+
+    /// Replace the engines, by keyword
+    pub fn with_engines(mut self, engines: HashMap<String, SearchEngine>) -> Self {
+        self.engines = engines.into_iter().collect();
+        self
+    }
+
+    /// Set the keyword of the engine for queries that pick none (`None`: no
+    /// search for those)
+    pub fn with_default_engine(mut self, keyword: Option<String>) -> Self {
+        self.default_engine = keyword;
+        self
+    }
+
+    /// Set the tie-break priority reported to the manager
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    fn query_impl(&self, query: &str) -> Vec<Item> {
+        let query = query.trim();
+        if let Some((keyword, rest)) = query.split_once(char::is_whitespace) {
+            let rest = rest.trim_start();
+            if let Some(engine) = self.engines.get(keyword) {
+                return vec![search_item(keyword, engine, rest).with_score(0.95)];
+            }
+        }
+
+        if query.is_empty() {
+            return Vec::new();
+        }
+        self.default_engine
+            .as_ref()
+            .and_then(|keyword| Some((keyword, self.engines.get(keyword)?)))
+            .map(|(keyword, engine)| {
+                search_item(keyword, engine, query)
+                    .with_always_show()
+                    .with_score(0.01)
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+impl Default for WebSearchProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Provider for WebSearchProvider {
+    fn name(&self) -> &str {
+        "websearch"
+    }
+
+    fn description(&self) -> &str {
+        "Search the web, choosing the engine by keyword"
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn query(
+        &self,
+        query: &str,
+        _max_results: usize,
+    ) -> Pin<Box<dyn Future<Output = Vec<Item>> + Send + '_>> {
+        let result = self.query_impl(query);
+        Box::pin(async move { result })
+    }
+}
+
+fn search_item(keyword: &str, engine: &SearchEngine, query: &str) -> Item {
+    let url = engine.search_url(query);
+    Item::new(
+        format!("Search {} for '{}'", engine.name, query),
+        "websearch",
+    )
+    .with_stable_id(keyword)
+    .with_subtext(&url)
+    .with_icon("system-search")
+    .with_metadata("engine", keyword)
+    .with_metadata("url", url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_pick_the_engine() {
+        let items = WebSearchProvider::new().query_impl("w  rust (language)");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].text, "Search Wikipedia for 'rust (language)'");
+        assert_eq!(
+            items[0].metadata["url"],
+            "https://en.wikipedia.org/w/index.php?search=rust%20%28language%29"
+        );
+        assert_eq!(items[0].metadata["engine"], "w");
+        assert_eq!(items[0].score, 0.95);
+    }
+
+    #[test]
+    fn other_queries_use_the_default_engine() {
+        let provider = WebSearchProvider::new();
+        let items = provider.query_impl("rust & tokio");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].text, "Search DuckDuckGo for 'rust & tokio'");
+        assert_eq!(
+            items[0].metadata["url"],
+            "https://duckduckgo.com/?q=rust%20%26%20tokio"
+        );
+        assert_eq!(items[0].score, 0.01);
+        // A keyword alone is searched for, not an engine pick
+        assert_eq!(provider.query_impl("g")[0].metadata["engine"], "ddg");
+        assert!(provider.query_impl("  ").is_empty());
+    }
+
+    #[test]
+    fn engines_and_default_are_configurable() {
+        let engines = HashMap::from([(
+            "rs".to_string(),
+            SearchEngine::new("docs.rs", "https://docs.rs/releases/search?query={query}"),
+        )]);
+        let provider = WebSearchProvider::new().with_engines(engines);
+        // The default engine is gone with the defaults
+        assert!(provider.query_impl("serde").is_empty());
+        assert_eq!(
+            provider.query_impl("rs serde")[0].metadata["url"],
+            "https://docs.rs/releases/search?query=serde"
+        );
+
+        let provider = provider.with_default_engine(Some("rs".to_string()));
+        assert_eq!(provider.query_impl("serde")[0].metadata["engine"], "rs");
+        let provider = WebSearchProvider::new().with_default_engine(None);
+        assert!(provider.query_impl("serde").is_empty());
+    }
+}
@@ -3,22 +3,68 @@
 //! Handles client connections and dispatches requests to providers.
 
 use crate::config::Config;
-use crate::proto::{ListProvidersResponse, QueryRequest, QueryResponse};
-use crate::providers::ProviderManager;
+use crate::daemon::Readiness;
+use crate::logging::LogLevel;
+use crate::proto::{
+    BatchQueryRequest, BatchQueryResponse, Framing, Hello, HelloResponse, ListProvidersRequest,
+    ListProvidersResponse, QueryRequest, QueryResponse, ResolveItemRequest, ResolveItemResponse,
+    RoutingRequest, RoutingResponse, SetLogLevelRequest, SetLogLevelResponse,
+    SetProviderConfigRequest, SetProviderConfigResponse,
+};
+use crate::providers::{unix_millis, IconKind, Item, ProviderManager, ProviderOverrides};
 use prost::Message;
-use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, Interest};
 use tokio::net::{UnixListener, UnixStream};
-use tracing::{debug, error, info, warn};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::{JoinError, JoinSet};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+use tracing_subscriber::filter::LevelFilter;
 
-/// Message types for the protocol
+/// Protocol version spoken by this server, exchanged in `Hello`
+///
+/// Bumped when a change would make older clients misread responses.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version this server still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Largest message body accepted, in bytes
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// How long shutdown waits for connections to finish their requests
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Most pipelined requests one connection may have running at once; the
+/// connection isn't read from again until one finishes
+const MAX_PIPELINED: usize = 32;
+
+/// Message types for the protocol, the first byte of every message
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
-enum MessageType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
     Query = 1,
     QueryResponse = 2,
     ListProviders = 5,
     ListProvidersResponse = 6,
+    SetProviderConfig = 7,
+    SetProviderConfigResponse = 8,
+    Routing = 9,
+    RoutingResponse = 10,
+    Hello = 11,
+    HelloResponse = 12,
+    BatchQuery = 13,
+    BatchQueryResponse = 14,
+    ResolveItem = 15,
+    ResolveItemResponse = 16,
+    SetLogLevel = 17,
+    SetLogLevelResponse = 18,
 }
 
 impl TryFrom<u8> for MessageType {
@@ -30,15 +76,54 @@ impl TryFrom<u8> for MessageType {
             2 => Ok(MessageType::QueryResponse),
             5 => Ok(MessageType::ListProviders),
             6 => Ok(MessageType::ListProvidersResponse),
+            7 => Ok(MessageType::SetProviderConfig),
+            8 => Ok(MessageType::SetProviderConfigResponse),
+            9 => Ok(MessageType::Routing),
+            10 => Ok(MessageType::RoutingResponse),
+            11 => Ok(MessageType::Hello),
+            12 => Ok(MessageType::HelloResponse),
+            13 => Ok(MessageType::BatchQuery),
+            14 => Ok(MessageType::BatchQueryResponse),
+            15 => Ok(MessageType::ResolveItem),
+            16 => Ok(MessageType::ResolveItemResponse),
+            17 => Ok(MessageType::SetLogLevel),
+            18 => Ok(MessageType::SetLogLevelResponse),
             _ => Err(()),
         }
     }
 }
 
+impl MessageType {
+    /// Whether this is a request [`handle_request`] answers
+    fn is_request(self) -> bool {
+        matches!(
+            self,
+            MessageType::Query
+                | MessageType::ListProviders
+                | MessageType::SetProviderConfig
+                | MessageType::Routing
+                | MessageType::BatchQuery
+                | MessageType::ResolveItem
+                | MessageType::SetLogLevel
+        )
+    }
+}
+
+/// The `request_id` every request carries as field 15, so it can be read
+/// without knowing the message type
+#[derive(Clone, PartialEq, prost::Message)]
+struct RequestTag {
+    #[prost(uint32, tag = "15")]
+    request_id: u32,
+}
+
 /// The datacube server
 pub struct Server {
     config: Config,
     provider_manager: Arc<ProviderManager>,
+    replace: bool,
+    log_level: Option<LogLevel>,
+    readiness: Readiness,
 }
 
 impl Server {
@@ -47,15 +132,64 @@ impl Server {
         Self {
             config,
             provider_manager: Arc::new(provider_manager),
+            replace: false,
+            log_level: None,
+            readiness: Readiness::default(),
         }
     }
 
-    /// Run the server
+    /// Take over the socket even if another daemon is listening on it
+    /// (default: refuse to start)
+    pub fn with_replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
+    /// Switch the log level on SIGUSR1 (debug) and SIGUSR2 (back to the
+    /// starting level) while running
+    pub fn with_log_level(mut self, log_level: LogLevel) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    /// Report to the launching process once the socket is bound
+    pub fn with_readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    /// Run the server until SIGTERM or SIGINT
     pub async fn run(&self) -> anyhow::Result<()> {
+        let log_signals = self
+            .log_level
+            .clone()
+            .map(|log_level| tokio::spawn(log_level_signals(log_level)));
+        let result = self.run_with_shutdown(termination_signal()).await;
+        if let Some(log_signals) = log_signals {
+            log_signals.abort();
+        }
+        result
+    }
+
+    /// Run the server until `shutdown` completes
+    ///
+    /// Fails to start if another daemon answers on the socket, unless
+    /// replacing; a socket nothing listens on is stale and removed.
+    ///
+    /// On shutdown the server stops accepting connections and removes its
+    /// socket, if it is still the one it bound. Open connections finish the
+    /// requests they are running, are closed, and are waited for (up to
+    /// [`SHUTDOWN_GRACE`]) before this returns.
+    pub async fn run_with_shutdown(
+        &self,
+        shutdown: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
         let socket_path = &self.config.socket_path;
 
-        // Remove existing socket file if it exists
         if socket_path.exists() {
+            if !self.replace {
+                ensure_socket_unused(socket_path).await?;
+            }
             std::fs::remove_file(socket_path)?;
         }
 
@@ -65,18 +199,78 @@ impl Server {
         }
 
         let listener = UnixListener::bind(socket_path)?;
+        // A daemon that replaces this one binds a new socket at the same path,
+        // which must outlive this one's shutdown.
+        let socket_inode = std::fs::metadata(socket_path)?.ino();
         info!("Server listening on {:?}", socket_path);
+        self.readiness.ready();
+
+        // Clients can connect while providers build their indexes.
+        self.provider_manager.warmup().await;
+
+        // One permit per open connection. Once they are all taken we stop
+        // accepting, so extra clients wait in the listen backlog rather than
+        // each costing a task.
+        let max_connections = self.config.max_connections.max(1);
+        let permits = Arc::new(Semaphore::new(max_connections));
+        let idle_timeout =
+            Some(Duration::from_secs(self.config.idle_timeout_secs)).filter(|t| !t.is_zero());
+        let debounce =
+            Some(Duration::from_millis(self.config.debounce_ms)).filter(|t| !t.is_zero());
+        let max_queries_per_sec = self.config.max_queries_per_sec;
+        let shared = Shared {
+            manager: Arc::clone(&self.provider_manager),
+            max_results: self.config.total_max_results,
+            icons: IconResolver::from_config(&self.config).map(Arc::new),
+            results: ResultCache::from_config(&self.config).map(Arc::new),
+            log_level: self.log_level.clone(),
+        };
 
+        let (stop_connections, stopping) = watch::channel(false);
+        tokio::pin!(shutdown);
         loop {
-            match listener.accept().await {
+            let next = async {
+                let permit = match Arc::clone(&permits).try_acquire_owned() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        warn!(
+                            "Connection limit ({}) reached, waiting for a client to disconnect",
+                            max_connections
+                        );
+                        Arc::clone(&permits).acquire_owned().await?
+                    }
+                };
+                anyhow::Ok((permit, listener.accept().await))
+            };
+            let (permit, accepted) = tokio::select! {
+                next = next => next?,
+                () = &mut shutdown => break,
+            };
+
+            match accepted {
                 Ok((stream, _addr)) => {
-                    let manager = Arc::clone(&self.provider_manager);
-                    let max_results = self.config.max_results;
+                    let shared = shared.clone();
+                    let stopping = stopping.clone();
+                    let permits = Arc::clone(&permits);
+                    debug!(
+                        active = max_connections - permits.available_permits(),
+                        "Accepted connection"
+                    );
 
                     tokio::spawn(async move {
-                        if let Err(e) = handle_connection(stream, manager, max_results).await {
+                        let timing = Timing {
+                            idle_timeout,
+                            debounce,
+                            max_queries_per_sec,
+                        };
+                        if let Err(e) = handle_connection(stream, shared, timing, stopping).await {
                             error!("Connection error: {}", e);
                         }
+                        drop(permit);
+                        debug!(
+                            active = max_connections - permits.available_permits(),
+                            "Connection closed"
+                        );
                     });
                 }
                 Err(e) => {
@@ -84,40 +278,376 @@ impl Server {
                 }
             }
         }
+
+        info!("Shutting down");
+        drop(listener);
+        let ours = std::fs::metadata(socket_path).is_ok_and(|m| m.ino() == socket_inode);
+        if !ours {
+            info!("Socket {:?} was taken over; leaving it", socket_path);
+        } else if let Err(e) = std::fs::remove_file(socket_path) {
+            warn!("Failed to remove socket {:?}: {}", socket_path, e);
+        }
+
+        // Every permit is back once every connection has closed.
+        let _ = stop_connections.send(true);
+        let open = max_connections - permits.available_permits();
+        if open > 0 {
+            debug!("Waiting for {} connections to close", open);
+            let closed = permits.acquire_many(max_connections as u32);
+            if tokio::time::timeout(SHUTDOWN_GRACE, closed).await.is_err() {
+                warn!(
+                    "{} connections still open after {:?}, closing them",
+                    max_connections - permits.available_permits(),
+                    SHUTDOWN_GRACE
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fail if a daemon answers on `socket_path`
+///
+/// Only a refused connection shows the socket is stale; any other error
+/// leaves it in doubt, so it's left alone too.
+async fn ensure_socket_unused(socket_path: &Path) -> anyhow::Result<()> {
+    match UnixStream::connect(socket_path).await {
+        Ok(_) => anyhow::bail!(
+            "datacube already running on {:?}; pass --replace to take over",
+            socket_path
+        ),
+        Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            info!("Removing stale socket {:?}", socket_path);
+            Ok(())
+        }
+        Err(e) => anyhow::bail!("can't tell whether {:?} is in use: {}", socket_path, e),
+    }
+}
+
+/// Completes on SIGTERM or SIGINT
+async fn termination_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) {
+        (Ok(mut terminate), Ok(mut interrupt)) => {
+            tokio::select! {
+                _ = terminate.recv() => info!("Received SIGTERM"),
+                _ = interrupt.recv() => info!("Received SIGINT"),
+            }
+        }
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Cannot listen for termination signals: {}", e);
+            std::future::pending().await
+        }
+    }
+}
+
+/// Switch `log_level` to debug on SIGUSR1 and back on SIGUSR2, forever
+async fn log_level_signals(log_level: LogLevel) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (mut debug_on, mut debug_off) = match (
+        signal(SignalKind::user_defined1()),
+        signal(SignalKind::user_defined2()),
+    ) {
+        (Ok(debug_on), Ok(debug_off)) => (debug_on, debug_off),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Cannot listen for log level signals: {}", e);
+            return;
+        }
+    };
+    loop {
+        let (signal, result) = tokio::select! {
+            _ = debug_on.recv() => ("SIGUSR1", log_level.set(LevelFilter::DEBUG)),
+            _ = debug_off.recv() => ("SIGUSR2", log_level.reset()),
+        };
+        match result.and_then(|()| log_level.current()) {
+            Ok(level) => info!("Received {}, logging at {} level", signal, level),
+            Err(e) => warn!(
+                "Received {}, but cannot change the log level: {}",
+                signal, e
+            ),
+        }
+    }
+}
+
+/// Rewrites themed icon names to file paths, for frontends that can't do
+/// icon theme lookups themselves (`resolve_icons` in the config)
+#[derive(Debug, Clone)]
+struct IconResolver {
+    size: u16,
+    /// Theme searched before hicolor; the GTK theme when not configured
+    theme: Option<String>,
+}
+
+impl IconResolver {
+    fn from_config(config: &Config) -> Option<Self> {
+        config.resolve_icons.then(|| Self {
+            size: config.icon_size,
+            theme: config
+                .icon_theme
+                .clone()
+                .or_else(freedesktop_icons::default_theme_gtk),
+        })
+    }
+
+    /// Replace a themed `icon` with its file path; names that can't be
+    /// resolved are left as they are
+    fn resolve(&self, item: &mut Item) {
+        if item.icon_kind != IconKind::ThemedName || item.icon.is_empty() {
+            return;
+        }
+        let mut lookup = freedesktop_icons::lookup(&item.icon)
+            .with_size(self.size)
+            .with_scale(1)
+            .with_cache();
+        if let Some(theme) = &self.theme {
+            lookup = lookup.with_theme(theme);
+        }
+        match lookup.find() {
+            Some(path) => {
+                item.icon = path.to_string_lossy().into_owned();
+                item.icon_kind = IconKind::AbsolutePath;
+            }
+            None => debug!("No icon found for '{}'", item.icon),
+        }
+    }
+}
+
+/// Recent query results, so clients can fetch an item back by the response's
+/// qid and the item's id (`result_cache_size` and `result_cache_secs` in the
+/// config)
+#[derive(Debug)]
+struct ResultCache {
+    /// Most queries kept
+    capacity: usize,
+    /// How long a query's results are kept
+    ttl: Duration,
+    /// Oldest first
+    entries: Mutex<VecDeque<CachedResults>>,
+}
+
+#[derive(Debug)]
+struct CachedResults {
+    qid: String,
+    stored: Instant,
+    items: Vec<crate::proto::Item>,
+}
+
+impl ResultCache {
+    fn from_config(config: &Config) -> Option<Self> {
+        (config.result_cache_size > 0 && config.result_cache_secs > 0).then(|| Self {
+            capacity: config.result_cache_size,
+            ttl: Duration::from_secs(config.result_cache_secs),
+            entries: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn insert(&self, qid: &str, items: &[crate::proto::Item]) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.push_back(CachedResults {
+            qid: qid.to_string(),
+            stored: Instant::now(),
+            items: items.to_vec(),
+        });
+        while entries.len() > self.capacity
+            || entries
+                .front()
+                .is_some_and(|e| e.stored.elapsed() > self.ttl)
+        {
+            entries.pop_front();
+        }
+    }
+
+    fn get(&self, qid: &str, item_id: &str) -> Result<crate::proto::Item, String> {
+        let entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        let results = entries
+            .iter()
+            .find(|e| e.qid == qid && e.stored.elapsed() <= self.ttl)
+            .ok_or_else(|| format!("no recent query with qid '{}'", qid))?;
+        let item = results
+            .items
+            .iter()
+            .find(|item| item.id == item_id)
+            .ok_or_else(|| format!("query '{}' returned no item '{}'", qid, item_id))?;
+        if is_expired(item, unix_millis(SystemTime::now())) {
+            return Err(format!(
+                "item '{}' from query '{}' is out of date",
+                item_id, qid
+            ));
+        }
+        Ok(item.clone())
+    }
+}
+
+/// Whether `item` had stopped being accurate at `now_ms`
+fn is_expired(item: &crate::proto::Item, now_ms: u64) -> bool {
+    item.ttl_ms > 0 && now_ms > item.generated_at_ms.saturating_add(item.ttl_ms)
+}
+
+/// What requests are answered from, shared by every connection
+#[derive(Clone)]
+struct Shared {
+    manager: Arc<ProviderManager>,
+    /// Results per query when the request doesn't say
+    max_results: usize,
+    icons: Option<Arc<IconResolver>>,
+    results: Option<Arc<ResultCache>>,
+    log_level: Option<LogLevel>,
+}
+
+/// Per-connection timers, from the config
+#[derive(Debug, Clone, Copy, Default)]
+struct Timing {
+    /// Close connections that send nothing for this long
+    idle_timeout: Option<Duration>,
+    /// Hold queries this long before running them, see [`run_debounced`]
+    debounce: Option<Duration>,
+    /// Average query rate allowed, see [`RateLimiter`] (0 = unlimited)
+    max_queries_per_sec: u32,
+}
+
+/// Token bucket pacing one connection's queries
+///
+/// The bucket holds up to a second's worth of queries and refills
+/// continuously. A query that finds it empty isn't refused: it borrows from
+/// the future and waits until that token would have arrived.
+#[derive(Debug)]
+struct RateLimiter {
+    per_sec: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(per_sec: u32, now: Instant) -> Self {
+        Self {
+            per_sec: f64::from(per_sec),
+            tokens: f64::from(per_sec),
+            updated: now,
+        }
+    }
+
+    /// Take tokens for `queries` queries, returning how long to hold them
+    fn delay(&mut self, queries: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.per_sec);
+        self.updated = now;
+        self.tokens -= queries as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.per_sec)
+        }
     }
 }
 
 /// Handle a single client connection
+///
+/// Requests with a `request_id` of 0 are answered in order: the next one
+/// isn't read until the response is written. Requests with a nonzero id are
+/// pipelined: they run in their own task and are answered whenever they
+/// finish, while later requests are read and handled.
+///
+/// The connection is closed if no request starts within `idle_timeout` while
+/// no pipelined request is in flight, or once `stopping` turns true (after
+/// answering the pipelined requests in flight). Once a request has begun,
+/// reading its body and answering it are never cut short.
+///
+/// A client may open with `Hello` to agree on a protocol version; if there is
+/// none in common the connection is closed after the reply.
 async fn handle_connection(
     mut stream: UnixStream,
-    manager: Arc<ProviderManager>,
-    max_results: usize,
+    shared: Shared,
+    timing: Timing,
+    mut stopping: watch::Receiver<bool>,
 ) -> anyhow::Result<()> {
     debug!("New client connection");
 
+    let mut first_message = true;
+    let mut framing = Framing::FixedHeader;
+    // A header read while a debounced query was pending
+    let mut next_header = None;
+    let mut in_flight = JoinSet::new();
+    // The latest pipelined query, while debouncing lets newer ones replace it
+    let mut pending_query: Option<tokio::task::AbortHandle> = None;
+    let mut limiter = Some(timing.max_queries_per_sec)
+        .filter(|&per_sec| per_sec > 0)
+        .map(|per_sec| RateLimiter::new(per_sec, Instant::now()));
     loop {
-        // Read message type (1 byte) and length (4 bytes big-endian)
-        let mut header = [0u8; 5];
-        match stream.read_exact(&mut header).await {
-            Ok(_) => {}
+        let read = match next_header.take() {
+            Some(read) => read,
+            None => {
+                let next = read_next_header(
+                    &mut stream,
+                    framing,
+                    timing.idle_timeout,
+                    &mut in_flight,
+                    &mut stopping,
+                );
+                match next.await {
+                    Some(read) => read,
+                    None => {
+                        finish_in_flight(&mut stream, framing, &mut in_flight).await;
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        let (msg_type, length) = match read {
+            Ok(header) => header,
             Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
                 debug!("Client disconnected");
+                finish_in_flight(&mut stream, framing, &mut in_flight).await;
                 return Ok(());
             }
             Err(e) => return Err(e.into()),
-        }
+        };
+        let is_first = std::mem::replace(&mut first_message, false);
 
-        let msg_type = header[0];
-        let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        // A length this large is a garbled header rather than a real request;
+        // there's no way to find the next message after it.
+        if length > MAX_MESSAGE_SIZE {
+            warn!(
+                "Closing connection: message type {} claims {} bytes (limit {})",
+                msg_type, length, MAX_MESSAGE_SIZE
+            );
+            return Ok(());
+        }
 
-        // Read message body
+        // Read message body. A zero length is fine: it decodes as a message
+        // with every field at its default.
         let mut body = vec![0u8; length];
-        stream.read_exact(&mut body).await?;
+        let received = read_body(&mut stream, &mut body).await?;
+        if received < length {
+            debug!(
+                "Client disconnected {} bytes into a {} byte message (type {})",
+                received, length, msg_type
+            );
+            return Ok(());
+        }
 
-        // Process message based on type
-        let response = match MessageType::try_from(msg_type) {
-            Ok(MessageType::Query) => handle_query(&body, &manager, max_results).await,
-            Ok(MessageType::ListProviders) => handle_list_providers(&body, &manager).await,
+        let msg_type = match MessageType::try_from(msg_type) {
+            Ok(MessageType::Hello) if is_first => {
+                let response = handle_hello(&body);
+                write_message(
+                    &mut stream,
+                    framing,
+                    MessageType::HelloResponse,
+                    &response.encode_to_vec(),
+                )
+                .await?;
+                if !response.error.is_empty() {
+                    info!("Closing connection from a client with an incompatible protocol");
+                    return Ok(());
+                }
+                framing = response.framing();
+                continue;
+            }
+            Ok(msg_type) if msg_type.is_request() => msg_type,
             Ok(other) => {
                 warn!("Unexpected message type: {:?}", other);
                 continue;
@@ -128,15 +658,365 @@ async fn handle_connection(
             }
         };
 
-        // Send response
+        // A malformed body gets no response either way; its handler logs it.
+        let request_id = RequestTag::decode(body.as_slice())
+            .map(|tag| tag.request_id)
+            .unwrap_or(0);
+        let debounce = timing
+            .debounce
+            .filter(|_| matches!(msg_type, MessageType::Query));
+        let delay = match (&mut limiter, msg_type) {
+            (Some(limiter), MessageType::Query) => limiter.delay(1, Instant::now()),
+            (Some(limiter), MessageType::BatchQuery) => {
+                let queries = BatchQueryRequest::decode(body.as_slice())
+                    .map(|batch| batch.queries.len())
+                    .unwrap_or(0);
+                limiter.delay(queries, Instant::now())
+            }
+            _ => Duration::ZERO,
+        };
+        if !delay.is_zero() {
+            debug!("Rate limited: holding {:?} back for {:?}", msg_type, delay);
+        }
+        let request = handle_request(msg_type, body, shared.clone());
+        let request = async move {
+            if !delay.is_zero() {
+                tokio::time::sleep(delay).await;
+            }
+            request.await
+        };
+
+        if request_id != 0 {
+            let handle = in_flight.spawn(async move {
+                if let Some(debounce) = debounce {
+                    tokio::time::sleep(debounce).await;
+                }
+                request.await
+            });
+            // As with in-order queries, a newer query replaces one that
+            // hasn't been answered yet.
+            if debounce.is_some() {
+                if let Some(previous) = pending_query.replace(handle) {
+                    previous.abort();
+                }
+            }
+            continue;
+        }
+
+        let response = match debounce {
+            Some(debounce) => {
+                let (response, next) = run_debounced(&mut stream, framing, debounce, request).await;
+                next_header = next;
+                response
+            }
+            None => request.await,
+        };
         if let Some((resp_type, data)) = response {
-            let mut response_header = vec![resp_type as u8];
-            response_header.extend_from_slice(&(data.len() as u32).to_be_bytes());
-            stream.write_all(&response_header).await?;
-            stream.write_all(&data).await?;
-            stream.flush().await?;
+            write_message(&mut stream, framing, resp_type, &data).await?;
+        }
+    }
+}
+
+/// Wait for the next request's header, writing out the responses to
+/// pipelined requests as they finish meanwhile
+///
+/// Returns `None` once the connection has been idle for `idle_timeout`, which
+/// it never is while pipelined requests are in flight, or when the server is
+/// stopping. With [`MAX_PIPELINED`] requests in flight it waits for one to
+/// finish before reading on, so a client can't queue handlers without bound.
+async fn read_next_header(
+    stream: &mut UnixStream,
+    framing: Framing,
+    idle_timeout: Option<Duration>,
+    in_flight: &mut JoinSet<Option<(MessageType, Vec<u8>)>>,
+    stopping: &mut watch::Receiver<bool>,
+) -> Option<std::io::Result<(u8, usize)>> {
+    loop {
+        let idle_timeout = idle_timeout.filter(|_| in_flight.is_empty());
+        let full = in_flight.len() >= MAX_PIPELINED;
+        let idle = async move {
+            match idle_timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        // Only wait for data here: reading the header itself inside the select
+        // could lose half-read bytes when a response is ready first.
+        tokio::select! {
+            Some(done) = in_flight.join_next() => {
+                if let Err(e) = write_finished(stream, framing, done).await {
+                    return Some(Err(e));
+                }
+            }
+            ready = wait_for_data(stream), if !full => {
+                return Some(match ready {
+                    Ok(()) => read_header(stream, framing).await,
+                    Err(e) => Err(e),
+                });
+            }
+            () = idle => {
+                debug!("Closing connection idle for {:?}", idle_timeout.unwrap_or_default());
+                return None;
+            }
+            () = server_stopping(stopping) => {
+                debug!("Closing connection for shutdown");
+                return None;
+            }
+        }
+    }
+}
+
+/// Completes once the server starts shutting down (or has gone away)
+async fn server_stopping(stopping: &mut watch::Receiver<bool>) {
+    // Not returned: the borrow it holds isn't Send.
+    let _ = stopping.wait_for(|&stop| stop).await;
+}
+
+/// Write the responses to the pipelined requests still running once the
+/// client has stopped sending, for clients that shut down their end early
+async fn finish_in_flight(
+    stream: &mut UnixStream,
+    framing: Framing,
+    in_flight: &mut JoinSet<Option<(MessageType, Vec<u8>)>>,
+) {
+    while let Some(done) = in_flight.join_next().await {
+        if let Err(e) = write_finished(stream, framing, done).await {
+            debug!(
+                "Dropping {} pipelined responses: {}",
+                in_flight.len() + 1,
+                e
+            );
+            return;
+        }
+    }
+}
+
+/// Write the response of a finished pipelined request, if it has one
+async fn write_finished(
+    stream: &mut UnixStream,
+    framing: Framing,
+    done: Result<Option<(MessageType, Vec<u8>)>, JoinError>,
+) -> std::io::Result<()> {
+    match done {
+        Ok(Some((resp_type, data))) => write_message(stream, framing, resp_type, &data).await,
+        Ok(None) => Ok(()),
+        Err(e) if e.is_cancelled() => {
+            debug!("Query superseded by a newer one");
+            Ok(())
+        }
+        Err(e) => {
+            error!("Pipelined request failed: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Answer one request of a type [`MessageType::is_request`] accepts
+async fn handle_request(
+    msg_type: MessageType,
+    body: Vec<u8>,
+    shared: Shared,
+) -> Option<(MessageType, Vec<u8>)> {
+    let icons = shared.icons.as_deref();
+    let results = shared.results.as_deref();
+    let manager = &shared.manager;
+    match msg_type {
+        MessageType::Query => {
+            handle_query(&body, manager, shared.max_results, icons, results).await
+        }
+        MessageType::ListProviders => handle_list_providers(&body, manager).await,
+        MessageType::SetProviderConfig => handle_set_provider_config(&body, manager).await,
+        MessageType::Routing => handle_routing(&body, manager).await,
+        MessageType::BatchQuery => {
+            handle_batch_query(&body, manager, shared.max_results, icons, results).await
+        }
+        MessageType::ResolveItem => handle_resolve_item(&body, results),
+        MessageType::SetLogLevel => handle_set_log_level(&body, shared.log_level.as_ref()),
+        _ => None,
+    }
+}
+
+/// Run a query after waiting out `debounce`, giving way to a newer query
+///
+/// If the client sends another Query before this one has answered, this one
+/// is dropped without a response. Any other message lets it finish first.
+/// Returns the response, if any, and the header of the message that arrived
+/// meanwhile, which the caller handles next.
+async fn run_debounced(
+    stream: &mut UnixStream,
+    framing: Framing,
+    debounce: Duration,
+    query: impl Future<Output = Option<(MessageType, Vec<u8>)>>,
+) -> (
+    Option<(MessageType, Vec<u8>)>,
+    Option<std::io::Result<(u8, usize)>>,
+) {
+    let query = async {
+        tokio::time::sleep(debounce).await;
+        query.await
+    };
+    tokio::pin!(query);
+
+    // Only wait for data here: reading the header itself inside the select
+    // could lose half-read bytes when the query wins.
+    tokio::select! {
+        response = &mut query => return (response, None),
+        ready = wait_for_data(stream) => {
+            if let Err(e) = ready {
+                return (None, Some(Err(e)));
+            }
+        }
+    }
+
+    let next = read_header(stream, framing).await;
+    match next {
+        Ok((msg_type, _)) if msg_type != MessageType::Query as u8 => (query.await, Some(next)),
+        Ok(_) => {
+            debug!("Query superseded by a newer one");
+            (None, Some(next))
         }
+        Err(_) => (None, Some(next)),
+    }
+}
+
+/// Wait until the client has sent more bytes (or hung up), without reading
+/// them
+///
+/// `readable()` alone isn't enough: its readiness is only cleared by a read
+/// that would block, so it can report bytes that were already consumed.
+async fn wait_for_data(stream: &UnixStream) -> std::io::Result<()> {
+    loop {
+        stream.readable().await?;
+        let peeked = stream.try_io(Interest::READABLE, || {
+            let mut byte = 0u8;
+            let n = unsafe {
+                libc::recv(
+                    stream.as_raw_fd(),
+                    (&mut byte as *mut u8).cast(),
+                    1,
+                    libc::MSG_PEEK | libc::MSG_DONTWAIT,
+                )
+            };
+            if n < 0 {
+                Err(std::io::Error::last_os_error())
+            } else {
+                Ok(())
+            }
+        });
+        match peeked {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Fill `body` from the stream, returning how much arrived before the
+/// client hung up (`body.len()` if it all did)
+async fn read_body(stream: &mut UnixStream, body: &mut [u8]) -> std::io::Result<usize> {
+    let mut received = 0;
+    while received < body.len() {
+        match stream.read(&mut body[received..]).await? {
+            0 => break,
+            n => received += n,
+        }
+    }
+    Ok(received)
+}
+
+/// Read the next message's type and body length
+async fn read_header(stream: &mut UnixStream, framing: Framing) -> std::io::Result<(u8, usize)> {
+    match framing {
+        Framing::FixedHeader => {
+            // Message type (1 byte) and length (4 bytes big-endian)
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).await?;
+            let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+            Ok((header[0], length as usize))
+        }
+        Framing::Varint => {
+            let msg_type = stream.read_u8().await?;
+            // Protobuf varint: 7 bits per byte, least significant first
+            let mut length = 0u64;
+            for i in 0..10 {
+                let byte = stream.read_u8().await?;
+                length |= u64::from(byte & 0x7f) << (7 * i);
+                if byte & 0x80 == 0 {
+                    return Ok((msg_type, length as usize));
+                }
+            }
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "varint length longer than 10 bytes",
+            ))
+        }
+    }
+}
+
+/// Write one message in the connection's framing
+async fn write_message(
+    stream: &mut UnixStream,
+    framing: Framing,
+    msg_type: MessageType,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let mut header = vec![msg_type as u8];
+    match framing {
+        Framing::FixedHeader => header.extend_from_slice(&(body.len() as u32).to_be_bytes()),
+        Framing::Varint => prost::encoding::encode_varint(body.len() as u64, &mut header),
+    }
+    stream.write_all(&header).await?;
+    stream.write_all(body).await?;
+    stream.flush().await
+}
+
+/// Handle a client's `Hello`, agreeing on the highest version both speak
+fn handle_hello(body: &[u8]) -> HelloResponse {
+    let negotiated = Hello::decode(body)
+        .map_err(|e| format!("malformed Hello: {}", e))
+        .and_then(|hello| {
+            let framing = Framing::try_from(hello.framing)
+                .map_err(|_| format!("unsupported framing {}", hello.framing))?;
+            Ok((negotiate_version(&hello)?, framing))
+        });
+    match negotiated {
+        Ok((version, framing)) => {
+            debug!(
+                "Negotiated protocol version {} with {:?} framing",
+                version, framing
+            );
+            HelloResponse {
+                protocol_version: PROTOCOL_VERSION,
+                negotiated_version: version,
+                error: String::new(),
+                framing: framing.into(),
+            }
+        }
+        Err(error) => {
+            warn!("Rejected client: {}", error);
+            HelloResponse {
+                protocol_version: PROTOCOL_VERSION,
+                negotiated_version: 0,
+                error,
+                framing: Framing::FixedHeader.into(),
+            }
+        }
+    }
+}
+
+fn negotiate_version(hello: &Hello) -> Result<u32, String> {
+    let version = hello.protocol_version.min(PROTOCOL_VERSION);
+    if version < MIN_PROTOCOL_VERSION.max(hello.min_protocol_version) {
+        return Err(format!(
+            "no common protocol version (client speaks {}-{}, server {}-{})",
+            hello.min_protocol_version,
+            hello.protocol_version,
+            MIN_PROTOCOL_VERSION,
+            PROTOCOL_VERSION
+        ));
     }
+    Ok(version)
 }
 
 /// Handle a query request
@@ -144,6 +1024,8 @@ async fn handle_query(
     body: &[u8],
     manager: &ProviderManager,
     default_max_results: usize,
+    icons: Option<&IconResolver>,
+    results: Option<&ResultCache>,
 ) -> Option<(MessageType, Vec<u8>)> {
     let request = match QueryRequest::decode(body) {
         Ok(r) => r,
@@ -153,68 +1035,362 @@ async fn handle_query(
         }
     };
 
+    let response = run_query(request, manager, default_max_results, icons).await;
+    if let Some(results) = results {
+        results.insert(&response.qid, &response.items);
+    }
+    Some((MessageType::QueryResponse, response.encode_to_vec()))
+}
+
+/// Handle a batch of queries, run concurrently
+async fn handle_batch_query(
+    body: &[u8],
+    manager: &ProviderManager,
+    default_max_results: usize,
+    icons: Option<&IconResolver>,
+    results: Option<&ResultCache>,
+) -> Option<(MessageType, Vec<u8>)> {
+    let request = match BatchQueryRequest::decode(body) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to decode BatchQueryRequest: {}", e);
+            return None;
+        }
+    };
+    debug!("Batch of {} queries", request.queries.len());
+
+    let responses = futures::future::join_all(
+        request
+            .queries
+            .into_iter()
+            .map(|query| run_query(query, manager, default_max_results, icons)),
+    )
+    .await;
+    if let Some(results) = results {
+        for response in &responses {
+            results.insert(&response.qid, &response.items);
+        }
+    }
+
+    let response = BatchQueryResponse {
+        responses,
+        request_id: request.request_id,
+    };
+    Some((MessageType::BatchQueryResponse, response.encode_to_vec()))
+}
+
+/// Handle a request for an item from a recent query's results
+fn handle_resolve_item(
+    body: &[u8],
+    results: Option<&ResultCache>,
+) -> Option<(MessageType, Vec<u8>)> {
+    let request = match ResolveItemRequest::decode(body) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to decode ResolveItemRequest: {}", e);
+            return None;
+        }
+    };
+
+    let found = match results {
+        Some(results) => results.get(&request.qid, &request.item_id),
+        None => Err("the result cache is disabled".to_string()),
+    };
+    let response = match found {
+        Ok(item) => ResolveItemResponse {
+            item: Some(item),
+            error: String::new(),
+            request_id: request.request_id,
+        },
+        Err(error) => {
+            debug!("Cannot resolve item: {}", error);
+            ResolveItemResponse {
+                item: None,
+                error,
+                request_id: request.request_id,
+            }
+        }
+    };
+    Some((MessageType::ResolveItemResponse, response.encode_to_vec()))
+}
+
+/// Answer one query
+async fn run_query(
+    request: QueryRequest,
+    manager: &ProviderManager,
+    default_max_results: usize,
+    icons: Option<&IconResolver>,
+) -> QueryResponse {
+    // All log lines for this query carry its qid via the span.
+    let qid = uuid::Uuid::new_v4().to_string();
+    let span = info_span!("query", qid = %qid);
+    let started = Instant::now();
+
     debug!(
+        parent: &span,
         "Query: '{}' (providers: {:?})",
         request.query, request.providers
     );
 
+    let unknown_providers = manager.unknown_providers(&request.providers).await;
+    if !unknown_providers.is_empty() {
+        warn!(parent: &span, "Unknown providers requested: {:?}", unknown_providers);
+    }
+    let disabled_providers = manager.disabled_providers(&request.providers).await;
+    if !disabled_providers.is_empty() {
+        debug!(parent: &span, "Disabled providers requested: {:?}", disabled_providers);
+    }
+
     let max_results = if request.max_results > 0 {
         request.max_results as usize
     } else {
         default_max_results
     };
 
-    let items = manager
-        .query(&request.query, max_results, &request.providers)
-        .await;
+    // A single named provider is asked directly, and its results are
+    // returned in its own order.
+    let offset = request.offset as usize;
+    let (mut items, timings) = match request.providers.as_slice() {
+        [name] => match manager
+            .query_provider(name, &request.query, offset.saturating_add(max_results))
+            .instrument(span.clone())
+            .await
+        {
+            Ok(mut items) => {
+                items.drain(..offset.min(items.len()));
+                (items, HashMap::from([(name.clone(), started.elapsed())]))
+            }
+            Err(e) => {
+                debug!(parent: &span, "Not querying: {}", e);
+                (Vec::new(), HashMap::new())
+            }
+        },
+        _ => {
+            manager
+                .query_page_timed(&request.query, offset, max_results, &request.providers)
+                .instrument(span.clone())
+                .await
+        }
+    };
+    if let Some(icons) = icons {
+        items.iter_mut().for_each(|item| icons.resolve(item));
+    }
 
-    let response = QueryResponse {
-        query: request.query,
-        items: items.into_iter().map(Into::into).collect(),
-        qid: uuid::Uuid::new_v4().to_string(),
+    debug!(
+        parent: &span,
+        results = items.len(),
+        latency_ms = started.elapsed().as_millis() as u64,
+        "Query complete"
+    );
+
+    let (elapsed_ms, provider_ms) = if request.timings {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let provider_ms = timings
+            .into_iter()
+            .map(|(name, elapsed)| (name, millis(elapsed)))
+            .collect();
+        (millis(started.elapsed()), provider_ms)
+    } else {
+        (0.0, HashMap::new())
     };
 
-    Some((MessageType::QueryResponse, response.encode_to_vec()))
+    let mut provider_counts = HashMap::new();
+    for item in &items {
+        *provider_counts.entry(item.provider.clone()).or_insert(0) += 1;
+    }
+
+    QueryResponse {
+        query: request.query,
+        items: items.into_iter().map(Into::into).collect(),
+        provider_counts,
+        elapsed_ms,
+        provider_ms,
+        qid,
+        unknown_providers,
+        disabled_providers,
+        request_id: request.request_id,
+    }
 }
 
 /// Handle a list providers request
 async fn handle_list_providers(
-    _body: &[u8],
+    body: &[u8],
     manager: &ProviderManager,
 ) -> Option<(MessageType, Vec<u8>)> {
-    let providers = manager.list_providers().await;
-
-    let response = ListProvidersResponse {
-        providers: providers.into_iter().map(Into::into).collect(),
-    };
+    let request = match ListProvidersRequest::decode(body) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to decode ListProvidersRequest: {}", e);
+            return None;
+        }
+    };
+    let providers = if request.include_disabled {
+        manager.list_all_providers().await
+    } else {
+        manager.list_providers().await
+    };
+
+    let response = ListProvidersResponse {
+        providers: providers.into_iter().map(Into::into).collect(),
+        request_id: request.request_id,
+    };
 
     Some((MessageType::ListProvidersResponse, response.encode_to_vec()))
 }
 
+/// Handle a request to override a provider's priority/prefix
+async fn handle_set_provider_config(
+    body: &[u8],
+    manager: &ProviderManager,
+) -> Option<(MessageType, Vec<u8>)> {
+    let request = match SetProviderConfigRequest::decode(body) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to decode SetProviderConfigRequest: {}", e);
+            return None;
+        }
+    };
+
+    let result = if request.reset {
+        manager.reset_overrides(&request.name).await
+    } else {
+        let overrides = ProviderOverrides {
+            priority: request.priority,
+            prefix: request
+                .prefix
+                .map(|prefix| Some(prefix).filter(|p| !p.is_empty())),
+        };
+        manager.set_overrides(&request.name, overrides).await
+    };
+
+    let response = match result {
+        Ok(info) => SetProviderConfigResponse {
+            error: String::new(),
+            provider: Some(info.into()),
+            request_id: request.request_id,
+        },
+        Err(e) => {
+            warn!("Rejected provider config change: {}", e);
+            SetProviderConfigResponse {
+                error: e.to_string(),
+                provider: None,
+                request_id: request.request_id,
+            }
+        }
+    };
+
+    Some((
+        MessageType::SetProviderConfigResponse,
+        response.encode_to_vec(),
+    ))
+}
+
+/// Handle a request to change the log level
+fn handle_set_log_level(
+    body: &[u8],
+    log_level: Option<&LogLevel>,
+) -> Option<(MessageType, Vec<u8>)> {
+    let request = match SetLogLevelRequest::decode(body) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to decode SetLogLevelRequest: {}", e);
+            return None;
+        }
+    };
+
+    let result = log_level
+        .ok_or_else(|| anyhow::anyhow!("this daemon's log level can't be changed"))
+        .and_then(|log_level| {
+            if request.level.is_empty() {
+                log_level.reset()?;
+            } else {
+                let level = request
+                    .level
+                    .parse::<LevelFilter>()
+                    .map_err(|_| anyhow::anyhow!("unknown log level '{}'", request.level))?;
+                log_level.set(level)?;
+            }
+            log_level.current()
+        });
+
+    let response = match result {
+        Ok(level) => {
+            info!("Logging at {} level", level);
+            SetLogLevelResponse {
+                error: String::new(),
+                level: level.to_string(),
+                request_id: request.request_id,
+            }
+        }
+        Err(e) => {
+            warn!("Rejected log level change: {}", e);
+            SetLogLevelResponse {
+                error: e.to_string(),
+                level: String::new(),
+                request_id: request.request_id,
+            }
+        }
+    };
+
+    Some((MessageType::SetLogLevelResponse, response.encode_to_vec()))
+}
+
+/// Handle a request to report where a query would be routed
+async fn handle_routing(body: &[u8], manager: &ProviderManager) -> Option<(MessageType, Vec<u8>)> {
+    let request = match RoutingRequest::decode(body) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("Failed to decode RoutingRequest: {}", e);
+            return None;
+        }
+    };
+
+    let response = RoutingResponse {
+        routes: manager
+            .routes(&request.query)
+            .await
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        request_id: request.request_id,
+    };
+
+    Some((MessageType::RoutingResponse, response.encode_to_vec()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::proto::ListProvidersRequest;
+    use crate::providers::testing::{manager_with, MockProvider};
     use crate::providers::CalculatorProvider;
     use std::time::Duration;
     use tokio::net::UnixStream;
 
     async fn spawn_calculator_server() -> std::path::PathBuf {
+        spawn_calculator_server_with(Config::default()).await
+    }
+
+    async fn spawn_calculator_server_with(config: Config) -> std::path::PathBuf {
+        let manager = ProviderManager::new();
+        manager.register(CalculatorProvider::new()).await.unwrap();
+        spawn_server(config, manager).await
+    }
+
+    async fn spawn_server(config: Config, manager: ProviderManager) -> std::path::PathBuf {
         let dir = std::env::temp_dir().join(format!("datacube-it-{}", uuid::Uuid::new_v4()));
         std::fs::create_dir_all(&dir).unwrap();
         let socket = dir.join("datacube.sock");
 
-        let mut config = Config::default();
-        config.socket_path = socket.clone();
+        let mut config = Config {
+            socket_path: socket.clone(),
+            ..config
+        };
         // Keep the test hermetic: don't scan the host for applications.
         config.providers.applications.enabled = false;
 
-        let manager = ProviderManager::new();
-        manager.register(CalculatorProvider::new()).await;
-
         let server = Server::new(config, manager);
         tokio::spawn(async move {
-            let _ = server.run().await;
+            let _ = server.run_with_shutdown(std::future::pending()).await;
         });
 
         // Wait for the socket to be bound.
@@ -227,6 +1403,36 @@ mod tests {
         socket
     }
 
+    #[test]
+    fn icon_resolver_leaves_unresolvable_and_non_themed_icons() {
+        let icons = IconResolver {
+            size: 48,
+            theme: None,
+        };
+
+        let mut missing = Item::new("x", "test").with_icon("datacube-test-no-such-icon");
+        icons.resolve(&mut missing);
+        assert_eq!(missing.icon, "datacube-test-no-such-icon");
+        assert_eq!(missing.icon_kind, IconKind::ThemedName);
+
+        let mut path = Item::new("x", "test")
+            .with_icon("/opt/tool/tool.png")
+            .with_icon_kind(IconKind::AbsolutePath);
+        icons.resolve(&mut path);
+        assert_eq!(path.icon, "/opt/tool/tool.png");
+    }
+
+    #[test]
+    fn icon_resolution_is_off_by_default() {
+        assert!(IconResolver::from_config(&Config::default()).is_none());
+        let config = Config {
+            resolve_icons: true,
+            icon_size: 64,
+            ..Config::default()
+        };
+        assert_eq!(IconResolver::from_config(&config).unwrap().size, 64);
+    }
+
     async fn write_frame(stream: &mut UnixStream, msg_type: u8, body: &[u8]) {
         let mut header = vec![msg_type];
         header.extend_from_slice(&(body.len() as u32).to_be_bytes());
@@ -244,6 +1450,210 @@ mod tests {
         (header[0], body)
     }
 
+    fn query_frame(query: &str) -> Vec<u8> {
+        QueryRequest {
+            query: query.to_string(),
+            max_results: 10,
+            providers: vec![],
+            exact: false,
+            offset: 0,
+            timings: false,
+            request_id: 0,
+        }
+        .encode_to_vec()
+    }
+
+    #[tokio::test]
+    async fn debounced_queries_are_superseded_by_newer_ones() {
+        let socket = spawn_calculator_server_with(Config {
+            debounce_ms: 50,
+            ..Config::default()
+        })
+        .await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        for query in ["=1", "=1+", "=1+1"] {
+            write_frame(&mut stream, MessageType::Query as u8, &query_frame(query)).await;
+        }
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::QueryResponse as u8);
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.query, "=1+1");
+
+        // Nothing else was queued: the next frame answers the next request.
+        let request = RoutingRequest {
+            query: "=1".to_string(),
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Routing as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (msg_type, _) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::RoutingResponse as u8);
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn debounced_query_finishes_before_other_requests() {
+        let socket = spawn_calculator_server_with(Config {
+            debounce_ms: 50,
+            ..Config::default()
+        })
+        .await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        write_frame(&mut stream, MessageType::Query as u8, &query_frame("=2+2")).await;
+        let request = RoutingRequest {
+            query: "=1".to_string(),
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Routing as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::QueryResponse as u8);
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.items[0].text, "4");
+        let (msg_type, _) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::RoutingResponse as u8);
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    fn tagged_query_frame(query: &str, request_id: u32) -> Vec<u8> {
+        let mut request = QueryRequest::decode(query_frame(query).as_slice()).unwrap();
+        request.request_id = request_id;
+        request.encode_to_vec()
+    }
+
+    #[tokio::test]
+    async fn pipelined_requests_are_answered_as_they_finish() {
+        let slow = MockProvider::new("slow")
+            .with_prefix("slow ")
+            .with_item("done", 1.0)
+            .with_delay(Duration::from_millis(200));
+        let manager = manager_with(vec![slow]).await;
+        manager.register(CalculatorProvider::new()).await.unwrap();
+        let socket = spawn_server(Config::default(), manager).await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        write_frame(
+            &mut stream,
+            MessageType::Query as u8,
+            &tagged_query_frame("slow x", 1),
+        )
+        .await;
+        let request = RoutingRequest {
+            query: "=1".to_string(),
+            request_id: 2,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Routing as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        // Pipelined requests are still answered after the client stops sending.
+        stream.shutdown().await.unwrap();
+
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::RoutingResponse as u8);
+        assert_eq!(
+            RoutingResponse::decode(body.as_slice()).unwrap().request_id,
+            2
+        );
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::QueryResponse as u8);
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.request_id, 1);
+        assert_eq!(response.items[0].text, "done");
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn pipelined_requests_are_capped_per_connection() {
+        let delay = Duration::from_millis(200);
+        let slow = MockProvider::new("slow")
+            .with_item("done", 1.0)
+            .with_delay(delay);
+        let socket = spawn_server(Config::default(), manager_with(vec![slow]).await).await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let started = Instant::now();
+        let count = MAX_PIPELINED + 1;
+        for request_id in 1..=count as u32 {
+            write_frame(
+                &mut stream,
+                MessageType::Query as u8,
+                &tagged_query_frame("x", request_id),
+            )
+            .await;
+        }
+        let mut answered = Vec::new();
+        for _ in 0..count {
+            let (msg_type, body) = read_frame(&mut stream).await;
+            assert_eq!(msg_type, MessageType::QueryResponse as u8);
+            answered.push(QueryResponse::decode(body.as_slice()).unwrap().request_id);
+        }
+        // The request past the cap only started once one had finished.
+        assert!(started.elapsed() >= delay * 2, "{:?}", started.elapsed());
+        answered.sort();
+        assert_eq!(answered, (1..=count as u32).collect::<Vec<_>>());
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn pipelined_queries_are_superseded_while_debouncing() {
+        let socket = spawn_calculator_server_with(Config {
+            debounce_ms: 50,
+            ..Config::default()
+        })
+        .await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        for (request_id, query) in [(1, "=1"), (2, "=1+1")] {
+            write_frame(
+                &mut stream,
+                MessageType::Query as u8,
+                &tagged_query_frame(query, request_id),
+            )
+            .await;
+        }
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::QueryResponse as u8);
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(
+            (response.request_id, response.items[0].text.as_str()),
+            (2, "2")
+        );
+
+        // The superseded query left nothing queued.
+        let request = ListProvidersRequest {
+            request_id: 0,
+            include_disabled: false,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::ListProviders as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (msg_type, _) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::ListProvidersResponse as u8);
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
     #[tokio::test]
     async fn query_round_trip_over_socket() {
         let socket = spawn_calculator_server().await;
@@ -254,6 +1664,9 @@ mod tests {
             max_results: 10,
             providers: vec![],
             exact: false,
+            offset: 0,
+            timings: false,
+            request_id: 0,
         };
         write_frame(
             &mut stream,
@@ -272,7 +1685,29 @@ mod tests {
         );
         assert_eq!(response.items[0].text, "4");
         assert_eq!(response.items[0].provider, "calculator");
+        assert_eq!(
+            response.provider_counts["calculator"] as usize,
+            response.items.len()
+        );
         assert!(!response.qid.is_empty());
+        assert!(response.provider_ms.is_empty());
+        assert_eq!(response.elapsed_ms, 0.0);
+
+        // Timings are only reported when asked for.
+        let request = QueryRequest {
+            timings: true,
+            ..request
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Query as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (_, body) = read_frame(&mut stream).await;
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert!(response.elapsed_ms > 0.0);
+        assert!(response.provider_ms["calculator"] <= response.elapsed_ms);
 
         let _ = std::fs::remove_dir_all(socket.parent().unwrap());
     }
@@ -282,7 +1717,10 @@ mod tests {
         let socket = spawn_calculator_server().await;
         let mut stream = UnixStream::connect(&socket).await.expect("connect");
 
-        let request = ListProvidersRequest {};
+        let request = ListProvidersRequest {
+            request_id: 0,
+            include_disabled: false,
+        };
         write_frame(
             &mut stream,
             MessageType::ListProviders as u8,
@@ -298,4 +1736,698 @@ mod tests {
 
         let _ = std::fs::remove_dir_all(socket.parent().unwrap());
     }
+
+    #[test]
+    fn negotiates_the_highest_common_version() {
+        let hello = |protocol_version, min_protocol_version| Hello {
+            protocol_version,
+            min_protocol_version,
+            framing: Framing::FixedHeader.into(),
+        };
+        assert_eq!(
+            negotiate_version(&hello(PROTOCOL_VERSION, 0)),
+            Ok(PROTOCOL_VERSION)
+        );
+        assert_eq!(
+            negotiate_version(&hello(PROTOCOL_VERSION + 5, 1)),
+            Ok(PROTOCOL_VERSION)
+        );
+        assert!(negotiate_version(&hello(0, 0)).is_err());
+        assert!(negotiate_version(&hello(PROTOCOL_VERSION + 5, PROTOCOL_VERSION + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn hello_over_socket() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let hello = Hello {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: PROTOCOL_VERSION,
+            framing: Framing::FixedHeader.into(),
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Hello as u8,
+            &hello.encode_to_vec(),
+        )
+        .await;
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::HelloResponse as u8);
+        let response = HelloResponse::decode(body.as_slice()).unwrap();
+        assert!(response.error.is_empty());
+        assert_eq!(response.negotiated_version, PROTOCOL_VERSION);
+
+        // The connection carries on as usual.
+        let request = RoutingRequest {
+            query: "=1".to_string(),
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Routing as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (msg_type, _) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::RoutingResponse as u8);
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn hello_switches_to_varint_framing() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let hello = Hello {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: 0,
+            framing: Framing::Varint.into(),
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Hello as u8,
+            &hello.encode_to_vec(),
+        )
+        .await;
+        // The response itself still uses the fixed header.
+        let (_, body) = read_frame(&mut stream).await;
+        let response = HelloResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.framing(), Framing::Varint);
+
+        let request = RoutingRequest {
+            query: "=2+2".to_string(),
+            request_id: 0,
+        };
+        let mut frame = vec![MessageType::Routing as u8];
+        request.encode_length_delimited(&mut frame).unwrap();
+        stream.write_all(&frame).await.unwrap();
+
+        let msg_type = stream.read_u8().await.unwrap();
+        assert_eq!(msg_type, MessageType::RoutingResponse as u8);
+        // Read byte by byte until the rest decodes as one delimited message.
+        let mut rest = Vec::new();
+        let response = loop {
+            rest.push(stream.read_u8().await.unwrap());
+            if let Ok(response) = RoutingResponse::decode_length_delimited(rest.as_slice()) {
+                break response;
+            }
+        };
+        assert_eq!(response.routes[0].provider, "calculator");
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[test]
+    fn unknown_framing_is_rejected() {
+        let hello = Hello {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: 0,
+            framing: 7,
+        };
+        let response = handle_hello(&hello.encode_to_vec());
+        assert!(response.error.contains("framing"));
+        assert_eq!(response.framing(), Framing::FixedHeader);
+    }
+
+    #[tokio::test]
+    async fn incompatible_hello_closes_the_connection() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let hello = Hello {
+            protocol_version: PROTOCOL_VERSION + 1,
+            min_protocol_version: PROTOCOL_VERSION + 1,
+            framing: Framing::FixedHeader.into(),
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Hello as u8,
+            &hello.encode_to_vec(),
+        )
+        .await;
+        let (_, body) = read_frame(&mut stream).await;
+        let response = HelloResponse::decode(body.as_slice()).unwrap();
+        assert!(!response.error.is_empty());
+        assert_eq!(response.negotiated_version, 0);
+
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn batch_query_answers_in_request_order() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let request = BatchQueryRequest {
+            queries: ["=1+1", "firefox", "=3*3"]
+                .into_iter()
+                .map(|query| QueryRequest {
+                    query: query.to_string(),
+                    max_results: 10,
+                    providers: vec![],
+                    exact: false,
+                    offset: 0,
+                    timings: false,
+                    request_id: 0,
+                })
+                .collect(),
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::BatchQuery as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::BatchQueryResponse as u8);
+        let response = BatchQueryResponse::decode(body.as_slice()).unwrap();
+        let queries: Vec<_> = response
+            .responses
+            .iter()
+            .map(|r| r.query.as_str())
+            .collect();
+        assert_eq!(queries, vec!["=1+1", "firefox", "=3*3"]);
+        assert_eq!(response.responses[0].items[0].text, "2");
+        assert!(response.responses[1].items.is_empty());
+        assert_eq!(response.responses[2].items[0].text, "9");
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    fn proto_item(id: &str) -> crate::proto::Item {
+        crate::proto::Item {
+            id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rate_limiter_allows_a_burst_then_paces_queries() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(10, start);
+        for _ in 0..10 {
+            assert_eq!(limiter.delay(1, start), Duration::ZERO);
+        }
+        // The bucket is empty: each query waits for its own token.
+        assert_eq!(limiter.delay(1, start), Duration::from_millis(100));
+        assert_eq!(limiter.delay(1, start), Duration::from_millis(200));
+
+        // A second later the debt is paid off and eight tokens are back.
+        let later = start + Duration::from_secs(1);
+        assert_eq!(limiter.delay(8, later), Duration::ZERO);
+        assert_eq!(limiter.delay(2, later), Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn queries_beyond_the_rate_limit_are_held_back() {
+        let socket = spawn_calculator_server_with(Config {
+            max_queries_per_sec: 20,
+            ..Config::default()
+        })
+        .await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        // 20 go through at once, the next 5 take a token each at 20/s.
+        let started = Instant::now();
+        for _ in 0..25 {
+            write_frame(&mut stream, MessageType::Query as u8, &query_frame("=1")).await;
+            let (msg_type, _) = read_frame(&mut stream).await;
+            assert_eq!(msg_type, MessageType::QueryResponse as u8);
+        }
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[test]
+    fn result_cache_keeps_the_latest_queries() {
+        let cache = ResultCache {
+            capacity: 2,
+            ttl: Duration::from_secs(60),
+            entries: Mutex::new(VecDeque::new()),
+        };
+        cache.insert("q1", &[proto_item("a")]);
+        cache.insert("q2", &[proto_item("b")]);
+        assert_eq!(cache.get("q1", "a").unwrap().id, "a");
+        assert!(cache.get("q1", "b").is_err());
+
+        cache.insert("q3", &[proto_item("c")]);
+        assert!(cache.get("q1", "a").is_err());
+        assert_eq!(cache.get("q3", "c").unwrap().id, "c");
+    }
+
+    #[test]
+    fn result_cache_forgets_expired_queries() {
+        let cache = ResultCache {
+            capacity: 10,
+            ttl: Duration::ZERO,
+            entries: Mutex::new(VecDeque::new()),
+        };
+        cache.insert("q1", &[proto_item("a")]);
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(cache.get("q1", "a").is_err());
+    }
+
+    #[test]
+    fn result_cache_refuses_out_of_date_items() {
+        let cache = ResultCache {
+            capacity: 10,
+            ttl: Duration::from_secs(60),
+            entries: Mutex::new(VecDeque::new()),
+        };
+        let now = unix_millis(SystemTime::now());
+        let item = |id: &str, generated_at_ms, ttl_ms| crate::proto::Item {
+            generated_at_ms,
+            ttl_ms,
+            ..proto_item(id)
+        };
+        cache.insert(
+            "q1",
+            &[
+                item("fresh", now, 60_000),
+                item("stale", now - 2_000, 1_000),
+                item("forever", now - 3_600_000, 0),
+            ],
+        );
+        assert!(cache.get("q1", "fresh").is_ok());
+        assert!(cache
+            .get("q1", "stale")
+            .unwrap_err()
+            .contains("out of date"));
+        assert!(cache.get("q1", "forever").is_ok());
+    }
+
+    #[tokio::test]
+    async fn resolves_items_from_recent_queries() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        write_frame(&mut stream, MessageType::Query as u8, &query_frame("=6*7")).await;
+        let (_, body) = read_frame(&mut stream).await;
+        let query = QueryResponse::decode(body.as_slice()).unwrap();
+
+        let request = ResolveItemRequest {
+            qid: query.qid.clone(),
+            item_id: query.items[0].id.clone(),
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::ResolveItem as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::ResolveItemResponse as u8);
+        let response = ResolveItemResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.item.as_ref(), Some(&query.items[0]));
+
+        let request = ResolveItemRequest {
+            qid: "unknown".to_string(),
+            item_id: query.items[0].id.clone(),
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::ResolveItem as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (_, body) = read_frame(&mut stream).await;
+        let response = ResolveItemResponse::decode(body.as_slice()).unwrap();
+        assert!(response.item.is_none());
+        assert!(!response.error.is_empty());
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn truncated_message_closes_only_that_connection() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        // Claim a 100 byte body, send 10 bytes of it and hang up.
+        let mut frame = vec![MessageType::Query as u8];
+        frame.extend_from_slice(&100u32.to_be_bytes());
+        frame.extend_from_slice(&[0u8; 10]);
+        stream.write_all(&frame).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+
+        // The server carries on.
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+        write_frame(&mut stream, MessageType::Query as u8, &query_frame("=1+2")).await;
+        let (_, body) = read_frame(&mut stream).await;
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.items[0].text, "3");
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn oversized_message_closes_the_connection() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let mut header = vec![MessageType::Query as u8];
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        stream.write_all(&header).await.unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn empty_bodies_are_default_messages() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        write_frame(&mut stream, MessageType::ListProviders as u8, &[]).await;
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::ListProvidersResponse as u8);
+        let response = ListProvidersResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.providers.len(), 1);
+
+        write_frame(&mut stream, MessageType::Query as u8, &[]).await;
+        let (msg_type, _) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::QueryResponse as u8);
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn routing_over_socket() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let request = RoutingRequest {
+            query: "=2+2".to_string(),
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Routing as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::RoutingResponse as u8);
+
+        let response = RoutingResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.routes.len(), 1);
+        assert_eq!(response.routes[0].provider, "calculator");
+        assert_eq!(response.routes[0].prefix, "=");
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn set_provider_config_over_socket() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let request = SetProviderConfigRequest {
+            name: "calculator".to_string(),
+            priority: Some(7),
+            prefix: Some("calc ".to_string()),
+            reset: false,
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::SetProviderConfig as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (msg_type, body) = read_frame(&mut stream).await;
+        assert_eq!(msg_type, MessageType::SetProviderConfigResponse as u8);
+        let response = SetProviderConfigResponse::decode(body.as_slice()).unwrap();
+        assert!(response.error.is_empty());
+        let provider = response.provider.unwrap();
+        assert_eq!(provider.priority, 7);
+        assert_eq!(provider.prefix, "calc ");
+
+        // The new prefix routes to the calculator.
+        let query = QueryRequest {
+            query: "calc 2+2".to_string(),
+            max_results: 10,
+            providers: vec![],
+            exact: false,
+            offset: 0,
+            timings: false,
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Query as u8,
+            &query.encode_to_vec(),
+        )
+        .await;
+        let (_, body) = read_frame(&mut stream).await;
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.items[0].text, "4");
+
+        // Unknown providers are reported, not dropped.
+        let request = SetProviderConfigRequest {
+            name: "nope".to_string(),
+            ..Default::default()
+        };
+        write_frame(
+            &mut stream,
+            MessageType::SetProviderConfig as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+        let (_, body) = read_frame(&mut stream).await;
+        let response = SetProviderConfigResponse::decode(body.as_slice()).unwrap();
+        assert!(response.error.contains("nope"));
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn connections_beyond_the_limit_wait_for_a_free_slot() {
+        let socket = spawn_calculator_server_with(Config {
+            max_connections: 1,
+            ..Config::default()
+        })
+        .await;
+        let query = QueryRequest {
+            query: "=1+1".to_string(),
+            max_results: 10,
+            providers: vec![],
+            exact: false,
+            offset: 0,
+            timings: false,
+            request_id: 0,
+        }
+        .encode_to_vec();
+
+        let mut first = UnixStream::connect(&socket).await.expect("connect");
+        write_frame(&mut first, MessageType::Query as u8, &query).await;
+        read_frame(&mut first).await;
+
+        // The second client connects (into the backlog) but isn't served...
+        let mut second = UnixStream::connect(&socket).await.expect("connect");
+        write_frame(&mut second, MessageType::Query as u8, &query).await;
+        let waiting =
+            tokio::time::timeout(Duration::from_millis(200), read_frame(&mut second)).await;
+        assert!(waiting.is_err(), "second client should wait for a slot");
+
+        // ...until the first disconnects.
+        drop(first);
+        let (msg_type, _) = tokio::time::timeout(Duration::from_secs(5), read_frame(&mut second))
+            .await
+            .expect("second client served after the first disconnects");
+        assert_eq!(msg_type, MessageType::QueryResponse as u8);
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed_but_active_ones_kept() {
+        let socket = spawn_calculator_server_with(Config {
+            idle_timeout_secs: 1,
+            ..Config::default()
+        })
+        .await;
+        let query = QueryRequest {
+            query: "=1+1".to_string(),
+            max_results: 10,
+            providers: vec![],
+            exact: false,
+            offset: 0,
+            timings: false,
+            request_id: 0,
+        }
+        .encode_to_vec();
+
+        // Traffic more often than the timeout keeps the connection open.
+        let mut active = UnixStream::connect(&socket).await.expect("connect");
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(600)).await;
+            write_frame(&mut active, MessageType::Query as u8, &query).await;
+            let (msg_type, _) = read_frame(&mut active).await;
+            assert_eq!(msg_type, MessageType::QueryResponse as u8);
+        }
+
+        // A silent connection is closed by the server (reads hit EOF).
+        let mut idle = UnixStream::connect(&socket).await.expect("connect");
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), idle.read(&mut buf))
+            .await
+            .expect("server should close the idle connection");
+        assert_eq!(read.unwrap(), 0);
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn unknown_providers_are_reported_in_the_response() {
+        let socket = spawn_calculator_server().await;
+        let mut stream = UnixStream::connect(&socket).await.expect("connect");
+
+        let request = QueryRequest {
+            query: "=2+2".to_string(),
+            max_results: 10,
+            providers: vec!["calculator".to_string(), "typo".to_string()],
+            exact: false,
+            offset: 0,
+            timings: false,
+            request_id: 0,
+        };
+        write_frame(
+            &mut stream,
+            MessageType::Query as u8,
+            &request.encode_to_vec(),
+        )
+        .await;
+
+        let (_, body) = read_frame(&mut stream).await;
+        let response = QueryResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(response.unknown_providers, vec!["typo".to_string()]);
+        // Known providers are still queried.
+        assert_eq!(response.items[0].text, "4");
+
+        let _ = std::fs::remove_dir_all(socket.parent().unwrap());
+    }
+
+    /// Run a server on `socket` until the returned sender fires or drops,
+    /// once it's listening
+    async fn start_server(
+        socket: &Path,
+        replace: bool,
+    ) -> (
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<anyhow::Result<()>>,
+    ) {
+        let mut config = Config {
+            socket_path: socket.to_path_buf(),
+            ..Config::default()
+        };
+        config.providers.applications.enabled = false;
+        let previous = std::fs::metadata(socket).map(|m| m.ino()).ok();
+
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = Server::new(config, ProviderManager::new()).with_replace(replace);
+        let handle = tokio::spawn(async move {
+            server
+                .run_with_shutdown(async {
+                    let _ = stopped.await;
+                })
+                .await
+        });
+        for _ in 0..200 {
+            let inode = std::fs::metadata(socket).map(|m| m.ino()).ok();
+            if (inode.is_some() && inode != previous) || handle.is_finished() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        (stop, handle)
+    }
+
+    #[tokio::test]
+    async fn running_daemons_keep_their_socket_unless_replaced() {
+        let dir = std::env::temp_dir().join(format!("datacube-it-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("datacube.sock");
+
+        let (stop_first, first) = start_server(&socket, false).await;
+        let (_, second) = start_server(&socket, false).await;
+        let err = second.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("already running"), "{}", err);
+        assert!(UnixStream::connect(&socket).await.is_ok());
+
+        // The replaced daemon leaves its successor's socket alone.
+        let (stop_replacement, replacement) = start_server(&socket, true).await;
+        stop_first.send(()).unwrap();
+        first.await.unwrap().unwrap();
+        assert!(UnixStream::connect(&socket).await.is_ok());
+
+        stop_replacement.send(()).unwrap();
+        replacement.await.unwrap().unwrap();
+        assert!(!socket.exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn stale_sockets_are_removed() {
+        let dir = std::env::temp_dir().join(format!("datacube-it-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("datacube.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        assert!(socket.exists());
+
+        let (stop, server) = start_server(&socket, false).await;
+        assert!(UnixStream::connect(&socket).await.is_ok());
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn log_level_can_be_set_and_reset() {
+        let (_subscriber, log_level) = crate::logging::subscriber(
+            crate::config::LogFormat::Human,
+            tracing::Level::INFO,
+            std::io::sink,
+        );
+        let set = |level: &str, log_level: Option<&LogLevel>| {
+            let request = SetLogLevelRequest {
+                level: level.to_string(),
+                request_id: 4,
+            };
+            let (msg_type, body) =
+                handle_set_log_level(&request.encode_to_vec(), log_level).unwrap();
+            assert_eq!(msg_type, MessageType::SetLogLevelResponse);
+            let response = SetLogLevelResponse::decode(body.as_slice()).unwrap();
+            assert_eq!(response.request_id, 4);
+            response
+        };
+
+        assert_eq!(set("debug", Some(&log_level)).level, "debug");
+        assert_eq!(log_level.current().unwrap(), LevelFilter::DEBUG);
+        assert_eq!(set("", Some(&log_level)).level, "info");
+
+        let response = set("loud", Some(&log_level));
+        assert_eq!(response.error, "unknown log level 'loud'");
+        assert_eq!(log_level.current().unwrap(), LevelFilter::INFO);
+        assert!(!set("debug", None).error.is_empty());
+    }
 }
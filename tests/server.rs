@@ -0,0 +1,190 @@
+//! End-to-end tests: a real server on a temporary socket, spoken to with the
+//! same framing helpers the CLI uses
+
+use datacube::client::{recv_message, send_message};
+use datacube::proto::{
+    BatchQueryRequest, BatchQueryResponse, ListProvidersRequest, ListProvidersResponse,
+    QueryRequest, QueryResponse,
+};
+use datacube::server::MessageType;
+use datacube::{CalculatorProvider, Config, ProviderManager, Server};
+use prost::Message;
+use std::io::Read;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// A server running on its own runtime thread, shut down when dropped
+struct TestServer {
+    dir: PathBuf,
+    socket: PathBuf,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        let dir = std::env::temp_dir().join(format!("datacube-e2e-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("datacube.sock");
+        let config = Config {
+            socket_path: socket.clone(),
+            ..Config::default()
+        };
+
+        let (shutdown, stop) = oneshot::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new()?;
+            runtime.block_on(async {
+                let manager = ProviderManager::new();
+                manager.register(CalculatorProvider::new()).await?;
+                Server::new(config, manager)
+                    .run_with_shutdown(async {
+                        let _ = stop.await;
+                    })
+                    .await
+            })
+        });
+
+        for _ in 0..200 {
+            if socket.exists() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        Self {
+            dir,
+            socket,
+            shutdown: Some(shutdown),
+            thread: Some(thread),
+        }
+    }
+
+    fn connect(&self) -> UnixStream {
+        let stream = UnixStream::connect(&self.socket).expect("connect");
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+    }
+
+    /// Stop the server and wait for `run_with_shutdown` to return
+    fn stop(&mut self) -> anyhow::Result<()> {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        match self.thread.take() {
+            Some(thread) => thread.join().expect("server thread panicked"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.stop();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn query_request(query: &str) -> QueryRequest {
+    QueryRequest {
+        query: query.to_string(),
+        max_results: 10,
+        providers: vec![],
+        exact: false,
+        offset: 0,
+        timings: false,
+        request_id: 0,
+    }
+}
+
+#[test]
+fn query_round_trip() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+
+    send_message(
+        &mut stream,
+        MessageType::Query,
+        &query_request("=2+2").encode_to_vec(),
+    )
+    .unwrap();
+    let (msg_type, body) = recv_message(&mut stream).unwrap();
+    assert_eq!(msg_type, MessageType::QueryResponse as u8);
+    let response = QueryResponse::decode(body.as_slice()).unwrap();
+    assert_eq!(response.query, "=2+2");
+    assert_eq!(response.items[0].text, "4");
+    assert!(!response.qid.is_empty());
+}
+
+#[test]
+fn several_requests_on_one_connection() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+
+    send_message(
+        &mut stream,
+        MessageType::ListProviders,
+        &ListProvidersRequest {
+            request_id: 0,
+            include_disabled: false,
+        }
+        .encode_to_vec(),
+    )
+    .unwrap();
+    let request = BatchQueryRequest {
+        queries: vec![query_request("=1+1"), query_request("=3*3")],
+        request_id: 0,
+    };
+    send_message(
+        &mut stream,
+        MessageType::BatchQuery,
+        &request.encode_to_vec(),
+    )
+    .unwrap();
+
+    let (msg_type, body) = recv_message(&mut stream).unwrap();
+    assert_eq!(msg_type, MessageType::ListProvidersResponse as u8);
+    let providers = ListProvidersResponse::decode(body.as_slice()).unwrap();
+    assert_eq!(providers.providers[0].name, "calculator");
+
+    let (msg_type, body) = recv_message(&mut stream).unwrap();
+    assert_eq!(msg_type, MessageType::BatchQueryResponse as u8);
+    let batch = BatchQueryResponse::decode(body.as_slice()).unwrap();
+    let answers: Vec<_> = batch
+        .responses
+        .iter()
+        .map(|response| response.items[0].text.as_str())
+        .collect();
+    assert_eq!(answers, ["2", "9"]);
+}
+
+#[test]
+fn shutdown_stops_accepting_connections() {
+    let mut server = TestServer::start();
+    drop(server.connect());
+
+    server.stop().unwrap();
+    assert!(!server.socket.exists());
+    assert!(UnixStream::connect(&server.socket).is_err());
+}
+
+#[test]
+fn shutdown_closes_open_connections() {
+    let mut server = TestServer::start();
+    let mut stream = server.connect();
+    send_message(
+        &mut stream,
+        MessageType::Query,
+        &query_request("=1").encode_to_vec(),
+    )
+    .unwrap();
+    recv_message(&mut stream).unwrap();
+
+    // An idle connection doesn't hold shutdown up; it's closed.
+    server.stop().unwrap();
+    assert_eq!(stream.read(&mut [0u8; 1]).unwrap(), 0);
+}